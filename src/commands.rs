@@ -10,6 +10,20 @@ pub enum Command {
     Help,
     #[command(description = "start the bot.")]
     Start,
+    #[command(description = "download links from the replied-to message or after the command: /dl [link]")]
+    Dl { links: String },
+    #[command(description = "download part of a video: /clip [link] 1:20-2:05")]
+    Clip { range: String },
+    #[command(description = "download a video with subtitles: /subs [burn] [link]")]
+    Subs { args: String },
+    #[command(description = "get new posts of a profile: /follow [link] [@channel]")]
+    Follow { args: String },
+    #[command(description = "stop following a profile: /unfollow [link]")]
+    Unfollow { target: String },
+    #[command(description = "list the profiles you follow.")]
+    Following,
+    #[command(description = "configure downloads in this group (group admins).")]
+    GroupSettings,
 }
 
 #[derive(BotCommands, Clone, Debug)]
@@ -29,4 +43,10 @@ pub enum AdminCommand {
     ResetPremium,
     #[command(description = "List available TLS fingerprints")]
     Fingerprint,
+    #[command(description = "purge media cache: /purgecache <all|expired|url>")]
+    PurgeCache { target: String },
+    #[command(description = "list running background tasks")]
+    Tasks,
+    #[command(description = "show download pipeline stage load")]
+    Stages,
 }
@@ -0,0 +1,38 @@
+use crate::database::DatabasePool;
+use crate::utils::caption::DEFAULT_CAPTION_TEMPLATE;
+
+/// Setting holding the admin-defined caption template.
+const CAPTION_TEMPLATE_KEY: &str = "caption_template";
+
+impl DatabasePool {
+    /// Template captions are rendered from; the built-in one until an admin changes it.
+    pub async fn get_caption_template(&self) -> String {
+        self.get_setting(CAPTION_TEMPLATE_KEY).await
+            .ok()
+            .filter(|t| !t.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_CAPTION_TEMPLATE.to_string())
+    }
+
+    pub async fn set_caption_template(&self, template: &str) -> Result<(), anyhow::Error> {
+        self.set_setting(CAPTION_TEMPLATE_KEY, template).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_caption_template_defaults_and_updates() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = DatabasePool::new(temp_file.path().to_str().unwrap().to_string(), 1);
+        pool.execute_with_timeout(|conn| {
+            conn.execute("CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)", ())
+        }).await.unwrap();
+
+        assert_eq!(pool.get_caption_template().await, DEFAULT_CAPTION_TEMPLATE);
+        pool.set_caption_template("{author}: {url}").await.unwrap();
+        assert_eq!(pool.get_caption_template().await, "{author}: {url}");
+    }
+}
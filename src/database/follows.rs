@@ -0,0 +1,139 @@
+use rusqlite::{params, Row};
+
+use crate::database::DatabasePool;
+
+/// A profile or channel a user follows; its new posts are delivered to `chat_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Follow {
+    pub id: i64,
+    pub user_id: i64,
+    /// The follower's private chat or a channel the bot posts in.
+    pub chat_id: i64,
+    /// Public username of a channel `chat_id`; helps resolving the MTProto peer.
+    pub chat_username: Option<String>,
+    /// Canonical collection link.
+    pub url: String,
+    /// "@user" of the followed account.
+    pub name: String,
+    /// Id of the newest post seen so far; posts listed before it are new.
+    pub last_seen_id: Option<String>,
+    /// Unix time of the next check.
+    pub next_check: i64,
+}
+
+const FOLLOW_COLUMNS: &str = "id, user_id, chat_id, chat_username, url, name, last_seen_id, next_check";
+
+fn follow_from_row(row: &Row) -> rusqlite::Result<Follow> {
+    Ok(Follow {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        chat_id: row.get(2)?,
+        chat_username: row.get(3)?,
+        url: row.get(4)?,
+        name: row.get(5)?,
+        last_seen_id: row.get(6)?,
+        next_check: row.get(7)?,
+    })
+}
+
+impl DatabasePool {
+    /// Stores a new follow; `id` is ignored. Returns false when the chat already follows the link.
+    pub async fn add_follow(&self, follow: &Follow) -> Result<bool, anyhow::Error> {
+        let follow = follow.clone();
+        self.execute_with_timeout(move |conn| {
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO follows (user_id, chat_id, chat_username, url, name, last_seen_id, next_check) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![follow.user_id, follow.chat_id, follow.chat_username, follow.url, follow.name, follow.last_seen_id, follow.next_check],
+            )?;
+            Ok(inserted > 0)
+        }).await.map_err(|e| anyhow::anyhow!("Failed to add follow: {}", e))
+    }
+
+    /// Removes one of the user's follows. Returns false when there was no such follow.
+    pub async fn remove_follow(&self, user_id: i64, follow_id: i64) -> Result<bool, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let removed = conn.execute("DELETE FROM follows WHERE id = ?1 AND user_id = ?2", params![follow_id, user_id])?;
+            Ok(removed > 0)
+        }).await.map_err(|e| anyhow::anyhow!("Failed to remove follow: {}", e))
+    }
+
+    /// The user's follows, oldest first.
+    pub async fn get_user_follows(&self, user_id: i64) -> Result<Vec<Follow>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM follows WHERE user_id = ?1 ORDER BY id", FOLLOW_COLUMNS))?;
+            let rows = stmt.query_map(params![user_id], follow_from_row)?;
+            rows.collect()
+        }).await.map_err(|e| anyhow::anyhow!("Failed to get follows: {}", e))
+    }
+
+    /// Follows whose next check is at or before `now`, most overdue first.
+    pub async fn get_due_follows(&self, now: i64, limit: usize) -> Result<Vec<Follow>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM follows WHERE next_check <= ?1 ORDER BY next_check LIMIT ?2",
+                FOLLOW_COLUMNS
+            ))?;
+            let rows = stmt.query_map(params![now, limit as i64], follow_from_row)?;
+            rows.collect()
+        }).await.map_err(|e| anyhow::anyhow!("Failed to get due follows: {}", e))
+    }
+
+    /// Records a check: the newest post seen (kept when `None`) and when to look again.
+    pub async fn update_follow_check(&self, follow_id: i64, last_seen_id: Option<String>, next_check: i64) -> Result<(), anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE follows SET last_seen_id = COALESCE(?1, last_seen_id), next_check = ?2 WHERE id = ?3",
+                params![last_seen_id, next_check, follow_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to update follow: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_follows() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = DatabasePool::new(temp_file.path().to_str().unwrap().to_string(), 1);
+        pool.execute_with_timeout(|conn| {
+            conn.execute(
+                "CREATE TABLE follows (id INTEGER PRIMARY KEY, user_id BIGINT NOT NULL, chat_id BIGINT NOT NULL, chat_username TEXT, url TEXT NOT NULL, name TEXT NOT NULL, last_seen_id TEXT, next_check INTEGER NOT NULL DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, UNIQUE (chat_id, url))",
+                (),
+            )
+        }).await.unwrap();
+
+        let follow = Follow {
+            id: 0,
+            user_id: 1,
+            chat_id: 1,
+            chat_username: None,
+            url: "https://www.tiktok.com/@a".to_string(),
+            name: "@a".to_string(),
+            last_seen_id: Some("10".to_string()),
+            next_check: 100,
+        };
+        assert!(pool.add_follow(&follow).await.unwrap());
+        assert!(!pool.add_follow(&follow).await.unwrap());
+        let other = Follow { url: "https://www.tiktok.com/@b".to_string(), name: "@b".to_string(), next_check: 50, ..follow.clone() };
+        assert!(pool.add_follow(&other).await.unwrap());
+
+        let follows = pool.get_user_follows(1).await.unwrap();
+        assert_eq!(follows.len(), 2);
+        let due = pool.get_due_follows(60, 10).await.unwrap();
+        assert_eq!(due.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["@b"]);
+
+        pool.update_follow_check(due[0].id, None, 500).await.unwrap();
+        pool.update_follow_check(follows[0].id, Some("11".to_string()), 200).await.unwrap();
+        let follows = pool.get_user_follows(1).await.unwrap();
+        assert_eq!((follows[0].last_seen_id.as_deref(), follows[0].next_check), (Some("11"), 200));
+        assert_eq!((follows[1].last_seen_id.as_deref(), follows[1].next_check), (Some("10"), 500));
+
+        assert!(!pool.remove_follow(2, follows[0].id).await.unwrap());
+        assert!(pool.remove_follow(1, follows[0].id).await.unwrap());
+        assert_eq!(pool.get_user_follows(1).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,105 @@
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashSet;
+
+use crate::database::DatabasePool;
+use crate::database::platforms::parse_platform_list;
+
+/// Download behaviour of a group chat, edited by its admins with /groupsettings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupSettings {
+    /// Quality used for every link posted in the group: h264, h265 or audio.
+    pub quality: String,
+    /// Add the source link and the requester to the media caption.
+    pub captions: bool,
+    /// Send media without a notification.
+    pub silent: bool,
+    /// Delete the link message once the media was posted (needs the delete right).
+    pub delete_links: bool,
+    /// Platforms ignored in this group on top of the globally disabled ones.
+    pub disabled_platforms: HashSet<String>,
+}
+
+impl Default for GroupSettings {
+    fn default() -> Self {
+        Self {
+            quality: "h264".to_string(),
+            captions: true,
+            silent: false,
+            delete_links: false,
+            disabled_platforms: HashSet::new(),
+        }
+    }
+}
+
+impl GroupSettings {
+    pub fn toggle_platform(&mut self, platform_id: &str) {
+        if !self.disabled_platforms.remove(platform_id) {
+            self.disabled_platforms.insert(platform_id.to_string());
+        }
+    }
+}
+
+impl DatabasePool {
+    /// Settings of a group; defaults when the admins never changed anything.
+    pub async fn get_group_settings(&self, chat_id: i64) -> Result<GroupSettings, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT quality, captions, silent, delete_links, disabled_platforms FROM group_settings WHERE chat_id = ?1",
+                params![chat_id],
+                |row| Ok(GroupSettings {
+                    quality: row.get(0)?,
+                    captions: row.get(1)?,
+                    silent: row.get(2)?,
+                    delete_links: row.get(3)?,
+                    disabled_platforms: parse_platform_list(&row.get::<_, String>(4)?),
+                }),
+            ).optional()
+        }).await
+            .map(|settings| settings.unwrap_or_default())
+            .map_err(|e| anyhow::anyhow!("Failed to get group settings: {}", e))
+    }
+
+    pub async fn set_group_settings(&self, chat_id: i64, settings: &GroupSettings) -> Result<(), anyhow::Error> {
+        let mut disabled: Vec<String> = settings.disabled_platforms.iter().cloned().collect();
+        disabled.sort();
+        let settings = settings.clone();
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO group_settings (chat_id, quality, captions, silent, delete_links, disabled_platforms) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![chat_id, settings.quality, settings.captions, settings.silent, settings.delete_links, disabled.join(",")],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to save group settings: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_group_settings_roundtrip() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = DatabasePool::new(temp_file.path().to_str().unwrap().to_string(), 1);
+        pool.execute_with_timeout(|conn| {
+            conn.execute(
+                "CREATE TABLE group_settings (chat_id BIGINT PRIMARY KEY, quality TEXT NOT NULL DEFAULT 'h264', captions INTEGER NOT NULL DEFAULT 1, silent INTEGER NOT NULL DEFAULT 0, delete_links INTEGER NOT NULL DEFAULT 0, disabled_platforms TEXT NOT NULL DEFAULT '')",
+                (),
+            )
+        }).await.unwrap();
+
+        let chat_id = -1001234567890;
+        assert_eq!(pool.get_group_settings(chat_id).await.unwrap(), GroupSettings::default());
+
+        let mut settings = GroupSettings { quality: "audio".to_string(), silent: true, delete_links: true, ..Default::default() };
+        settings.toggle_platform("reddit");
+        settings.toggle_platform("likee");
+        settings.toggle_platform("likee");
+        pool.set_group_settings(chat_id, &settings).await.unwrap();
+
+        let stored = pool.get_group_settings(chat_id).await.unwrap();
+        assert_eq!(stored, settings);
+        assert!(stored.disabled_platforms.contains("reddit") && !stored.disabled_platforms.contains("likee"));
+    }
+}
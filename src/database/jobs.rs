@@ -0,0 +1,288 @@
+use rusqlite::{params, OptionalExtension, Row};
+use std::collections::HashMap;
+
+use crate::database::DatabasePool;
+use crate::utils::scheduler::{Priority, QueuedJob};
+
+/// Stage of a download job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Downloading,
+    Uploading,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Downloading => "downloading",
+            JobStatus::Uploading => "uploading",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Parses a stored status; unknown values count as failed.
+    pub fn parse(status: &str) -> Self {
+        match status {
+            "queued" => JobStatus::Queued,
+            "downloading" => JobStatus::Downloading,
+            "uploading" => JobStatus::Uploading,
+            "done" => JobStatus::Done,
+            "cancelled" => JobStatus::Cancelled,
+            _ => JobStatus::Failed,
+        }
+    }
+}
+
+/// A download request stored in the `jobs` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub id: i64,
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub url: String,
+    /// The serialized `VideoRequest`.
+    pub request: String,
+    pub status: JobStatus,
+    /// How many times a worker has started the job.
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+/// Outcome of [`DatabasePool::recover_interrupted_jobs`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveredJobs {
+    /// Interrupted jobs that were queued again.
+    pub requeued: Vec<Job>,
+    /// Interrupted jobs given up on.
+    pub failed: Vec<Job>,
+    /// Every queued job afterwards, including the ones that never started.
+    pub queued: Vec<Job>,
+}
+
+const JOB_COLUMNS: &str = "id, user_id, chat_id, url, request, status, attempts, error";
+
+fn job_from_row(row: &Row) -> rusqlite::Result<Job> {
+    Ok(Job {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        chat_id: row.get(2)?,
+        url: row.get(3)?,
+        request: row.get(4)?,
+        status: JobStatus::parse(&row.get::<_, String>(5)?),
+        attempts: row.get(6)?,
+        error: row.get(7)?,
+    })
+}
+
+impl DatabasePool {
+    /// Queues a job and returns its id.
+    pub async fn create_job(&self, user_id: i64, chat_id: i64, url: &str, request: &str, priority: Priority) -> Result<i64, anyhow::Error> {
+        let (url, request) = (url.to_string(), request.to_string());
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "INSERT INTO jobs (user_id, chat_id, url, request, priority) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![user_id, chat_id, url, request, priority.as_i64()],
+            )?;
+            Ok(conn.last_insert_rowid())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to create job: {}", e))
+    }
+
+    /// Takes a queued job, marking it as downloading and counting the attempt. Returns None
+    /// when it isn't queued anymore.
+    pub async fn claim_job(&self, job_id: i64) -> Result<Option<Job>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                &format!(
+                    "UPDATE jobs SET status = 'downloading', attempts = attempts + 1, started_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?1 AND status = 'queued'
+                     RETURNING {}",
+                    JOB_COLUMNS
+                ),
+                params![job_id],
+                job_from_row,
+            ).optional()
+        }).await.map_err(|e| anyhow::anyhow!("Failed to claim job: {}", e))
+    }
+
+    pub async fn get_job(&self, job_id: i64) -> Result<Option<Job>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(&format!("SELECT {} FROM jobs WHERE id = ?1", JOB_COLUMNS), params![job_id], job_from_row).optional()
+        }).await.map_err(|e| anyhow::anyhow!("Failed to get job: {}", e))
+    }
+
+    /// The queued jobs, oldest first.
+    pub async fn queued_jobs(&self) -> Result<Vec<QueuedJob>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let mut stmt = conn.prepare("SELECT id, user_id, priority FROM jobs WHERE status = 'queued' ORDER BY id")?;
+            let rows = stmt.query_map((), |row| {
+                Ok(QueuedJob { id: row.get(0)?, user_id: row.get(1)?, priority: Priority::from_i64(row.get(2)?) })
+            })?;
+            rows.collect()
+        }).await.map_err(|e| anyhow::anyhow!("Failed to list queued jobs: {}", e))
+    }
+
+    /// Number of downloading or uploading jobs of each user who has any.
+    pub async fn running_jobs_per_user(&self) -> Result<HashMap<i64, usize>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let mut stmt = conn.prepare("SELECT user_id, COUNT(*) FROM jobs WHERE status IN ('downloading', 'uploading') GROUP BY user_id")?;
+            let rows = stmt.query_map((), |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?;
+            rows.collect()
+        }).await.map_err(|e| anyhow::anyhow!("Failed to count running jobs: {}", e))
+    }
+
+    /// Average seconds the last `limit` done jobs took from claim to finish, if any finished.
+    pub async fn recent_job_seconds(&self, limit: usize) -> Result<Option<f64>, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT AVG((julianday(finished_at) - julianday(started_at)) * 86400) FROM
+                 (SELECT started_at, finished_at FROM jobs WHERE status = 'done' AND started_at IS NOT NULL ORDER BY id DESC LIMIT ?1)",
+                params![limit as i64],
+                |row| row.get(0),
+            )
+        }).await.map_err(|e| anyhow::anyhow!("Failed to get job durations: {}", e))
+    }
+
+    /// Cancels a job no worker has taken yet. Returns false when it isn't queued anymore.
+    pub async fn cancel_queued_job(&self, job_id: i64) -> Result<bool, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let cancelled = conn.execute(
+                "UPDATE jobs SET status = 'cancelled', updated_at = CURRENT_TIMESTAMP, finished_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = 'queued'",
+                params![job_id],
+            )?;
+            Ok(cancelled > 0)
+        }).await.map_err(|e| anyhow::anyhow!("Failed to cancel job: {}", e))
+    }
+
+    pub async fn set_job_status(&self, job_id: i64, status: JobStatus) -> Result<(), anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE jobs SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                params![status.as_str(), job_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to update job: {}", e))
+    }
+
+    /// Marks a job done, failed or cancelled, with the reason of a failure.
+    pub async fn finish_job(&self, job_id: i64, status: JobStatus, error: Option<String>) -> Result<(), anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE jobs SET status = ?1, error = ?2, updated_at = CURRENT_TIMESTAMP, finished_at = CURRENT_TIMESTAMP WHERE id = ?3",
+                params![status.as_str(), error, job_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to finish job: {}", e))
+    }
+
+    /// Handles jobs a previous run left downloading or uploading: those started fewer than
+    /// `max_attempts` times are queued again, the rest fail.
+    pub async fn recover_interrupted_jobs(&self, max_attempts: u32) -> Result<RecoveredJobs, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let interrupted: Vec<Job> = {
+                let mut stmt = tx.prepare(&format!(
+                    "SELECT {} FROM jobs WHERE status IN ('downloading', 'uploading') ORDER BY id",
+                    JOB_COLUMNS
+                ))?;
+                let rows = stmt.query_map((), job_from_row)?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+            let (requeued, failed): (Vec<Job>, Vec<Job>) = interrupted.into_iter().partition(|job| job.attempts < max_attempts);
+            for job in &requeued {
+                tx.execute("UPDATE jobs SET status = 'queued', updated_at = CURRENT_TIMESTAMP WHERE id = ?1", params![job.id])?;
+            }
+            let queued: Vec<Job> = {
+                let mut stmt = tx.prepare(&format!("SELECT {} FROM jobs WHERE status = 'queued' ORDER BY id", JOB_COLUMNS))?;
+                let rows = stmt.query_map((), job_from_row)?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+            for job in &failed {
+                tx.execute(
+                    "UPDATE jobs SET status = 'failed', error = 'interrupted by restarts', updated_at = CURRENT_TIMESTAMP, finished_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                    params![job.id],
+                )?;
+            }
+            tx.commit()?;
+            Ok(RecoveredJobs { requeued, failed, queued })
+        }).await.map_err(|e| anyhow::anyhow!("Failed to recover jobs: {}", e))
+    }
+
+    /// Deletes done, failed and cancelled jobs finished more than `days` days ago.
+    pub async fn prune_finished_jobs(&self, days: u32) -> Result<usize, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "DELETE FROM jobs WHERE status IN ('done', 'failed', 'cancelled') AND finished_at < datetime('now', ?1)",
+                params![format!("-{} days", days)],
+            )
+        }).await.map_err(|e| anyhow::anyhow!("Failed to prune jobs: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_job_lifecycle() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = DatabasePool::new(temp_file.path().to_str().unwrap().to_string(), 1);
+        pool.execute_with_timeout(|conn| {
+            conn.execute(
+                "CREATE TABLE jobs (id INTEGER PRIMARY KEY, user_id BIGINT NOT NULL, chat_id BIGINT NOT NULL, url TEXT NOT NULL, request TEXT NOT NULL, status TEXT NOT NULL DEFAULT 'queued', attempts INTEGER NOT NULL DEFAULT 0, error TEXT, priority INTEGER NOT NULL DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, updated_at DATETIME DEFAULT CURRENT_TIMESTAMP, started_at DATETIME, finished_at DATETIME)",
+                (),
+            )
+        }).await.unwrap();
+
+        let first = pool.create_job(1, 1, "https://a/1", "{}", Priority::Free).await.unwrap();
+        let second = pool.create_job(2, 2, "https://a/2", "{}", Priority::Premium).await.unwrap();
+        let third = pool.create_job(2, 3, "https://a/3", "{}", Priority::Admin).await.unwrap();
+
+        let queued = pool.queued_jobs().await.unwrap();
+        assert_eq!(queued.iter().map(|j| (j.id, j.user_id, j.priority)).collect::<Vec<_>>(), vec![
+            (first, 1, Priority::Free),
+            (second, 2, Priority::Premium),
+            (third, 2, Priority::Admin),
+        ]);
+        assert_eq!(pool.recent_job_seconds(10).await.unwrap(), None);
+
+        let job = pool.claim_job(first).await.unwrap().unwrap();
+        assert_eq!((job.id, job.status, job.attempts), (first, JobStatus::Downloading, 1));
+        assert!(pool.claim_job(first).await.unwrap().is_none());
+        assert!(!pool.cancel_queued_job(first).await.unwrap());
+        pool.finish_job(first, JobStatus::Done, None).await.unwrap();
+        assert!(pool.recent_job_seconds(10).await.unwrap().is_some());
+        assert_eq!(pool.get_job(first).await.unwrap().unwrap().status, JobStatus::Done);
+
+        let job = pool.claim_job(second).await.unwrap().unwrap();
+        assert_eq!(job.id, second);
+        pool.set_job_status(second, JobStatus::Uploading).await.unwrap();
+        let job = pool.claim_job(third).await.unwrap().unwrap();
+        assert_eq!(job.id, third);
+        assert!(pool.queued_jobs().await.unwrap().is_empty());
+        assert_eq!(pool.running_jobs_per_user().await.unwrap(), HashMap::from([(2, 2)]));
+
+        // A restart: the second job is retried, the third has used up its attempts and the
+        // fourth never started.
+        pool.execute_with_timeout(move |conn| conn.execute("UPDATE jobs SET attempts = 3 WHERE id = ?1", [third])).await.unwrap();
+        let fourth = pool.create_job(4, 4, "https://a/4", "{}", Priority::Free).await.unwrap();
+        let recovered = pool.recover_interrupted_jobs(3).await.unwrap();
+        assert_eq!(recovered.requeued.iter().map(|j| j.id).collect::<Vec<_>>(), vec![second]);
+        assert_eq!(recovered.failed.iter().map(|j| j.id).collect::<Vec<_>>(), vec![third]);
+        assert_eq!(recovered.queued.iter().map(|j| j.id).collect::<Vec<_>>(), vec![second, fourth]);
+
+        let job = pool.claim_job(second).await.unwrap().unwrap();
+        assert_eq!((job.id, job.attempts), (second, 2));
+        assert!(pool.cancel_queued_job(fourth).await.unwrap());
+        assert_eq!(pool.get_job(fourth).await.unwrap().unwrap().status, JobStatus::Cancelled);
+        assert!(pool.claim_job(fourth).await.unwrap().is_none());
+        assert_eq!(pool.prune_finished_jobs(7).await.unwrap(), 0);
+    }
+}
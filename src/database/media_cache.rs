@@ -0,0 +1,212 @@
+use rusqlite::{params, OptionalExtension};
+
+use crate::database::DatabasePool;
+use crate::yt_dlp_interface::VideoInfo;
+
+/// Default lifetime of a cached file_id when neither the setting nor the env var is set (7 days).
+const DEFAULT_MEDIA_CACHE_TTL_HOURS: i64 = 168;
+
+/// Where a cached file reference came from, which decides how it has to be re-sent.
+pub const SOURCE_BOTAPI: &str = "botapi";
+pub const SOURCE_MTPROTO: &str = "mtproto";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedMedia {
+    pub file_id: String,
+    /// "video" or "audio"
+    pub media_type: String,
+    /// SOURCE_BOTAPI (Bot API file_id) or SOURCE_MTPROTO (serialized InputDocument)
+    pub source: String,
+    /// Post metadata, so re-sent files get the same caption as the first upload.
+    pub info: Option<VideoInfo>,
+}
+
+impl DatabasePool {
+    /// Cache lifetime in hours: `media_cache_ttl_hours` setting, then MEDIA_CACHE_TTL_HOURS, then the default.
+    pub async fn media_cache_ttl_hours(&self) -> i64 {
+        self.get_setting("media_cache_ttl_hours").await
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .or_else(|| std::env::var("MEDIA_CACHE_TTL_HOURS").ok().and_then(|v| v.trim().parse().ok()))
+            .unwrap_or(DEFAULT_MEDIA_CACHE_TTL_HOURS)
+    }
+
+    /// Look up a non-expired cached upload for the URL + quality pair and count the hit.
+    pub async fn get_cached_media(&self, url: &str, quality: &str) -> Result<Option<CachedMedia>, anyhow::Error> {
+        let ttl_hours = self.media_cache_ttl_hours().await;
+        let url = url.to_string();
+        let quality = quality.to_string();
+        self.execute_with_timeout(move |conn| {
+            let cached = conn.query_row(
+                "SELECT file_id, media_type, source, info FROM media_cache
+                 WHERE url = ?1 AND quality = ?2 AND created_at > datetime('now', '-' || ?3 || ' hours')",
+                params![url, quality, ttl_hours],
+                |row| Ok(CachedMedia {
+                    file_id: row.get(0)?,
+                    media_type: row.get(1)?,
+                    source: row.get(2)?,
+                    info: row.get::<_, Option<String>>(3)?.and_then(|json| VideoInfo::from_json(&json).ok()),
+                })
+            ).optional()?;
+
+            if cached.is_some() {
+                conn.execute(
+                    "UPDATE media_cache SET hits = hits + 1 WHERE url = ?1 AND quality = ?2",
+                    params![url, quality],
+                )?;
+            }
+            Ok(cached)
+        }).await.map_err(|e| anyhow::anyhow!("Failed to read media cache: {}", e))
+    }
+
+    /// Store (or refresh) the Telegram file reference produced by an upload.
+    pub async fn store_cached_media(&self, url: &str, quality: &str, media: &CachedMedia) -> Result<(), anyhow::Error> {
+        let url = url.to_string();
+        let quality = quality.to_string();
+        let media = media.clone();
+        let info = media.info.as_ref().and_then(|info| serde_json::to_string(info).ok());
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO media_cache (url, quality, file_id, media_type, source, info, hits, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, CURRENT_TIMESTAMP)",
+                params![url, quality, media.file_id, media.media_type, media.source, info],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to store media cache entry: {}", e))
+    }
+
+    /// Drop a single entry, e.g. after Telegram rejected its file_id.
+    pub async fn invalidate_cached_media(&self, url: &str, quality: &str) -> Result<(), anyhow::Error> {
+        let url = url.to_string();
+        let quality = quality.to_string();
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "DELETE FROM media_cache WHERE url = ?1 AND quality = ?2",
+                params![url, quality],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to invalidate media cache entry: {}", e))
+    }
+
+    /// Purge cache entries. `target` is "all", "expired" or a URL (all qualities).
+    /// Returns the number of removed rows.
+    pub async fn purge_media_cache(&self, target: &str) -> Result<usize, anyhow::Error> {
+        let ttl_hours = self.media_cache_ttl_hours().await;
+        let target = target.trim().to_string();
+        self.execute_with_timeout(move |conn| {
+            match target.as_str() {
+                "all" => conn.execute("DELETE FROM media_cache", []),
+                "expired" => conn.execute(
+                    "DELETE FROM media_cache WHERE created_at <= datetime('now', '-' || ?1 || ' hours')",
+                    params![ttl_hours],
+                ),
+                url => conn.execute("DELETE FROM media_cache WHERE url = ?1", params![url]),
+            }
+        }).await.map_err(|e| anyhow::anyhow!("Failed to purge media cache: {}", e))
+    }
+
+    /// Number of cached entries and total cache hits, for the admin panel.
+    pub async fn media_cache_stats(&self) -> Result<(i64, i64), anyhow::Error> {
+        self.execute_with_timeout(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(hits), 0) FROM media_cache",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?))
+            )
+        }).await.map_err(|e| anyhow::anyhow!("Failed to get media cache stats: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    async fn setup_test_db() -> (DatabasePool, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db_path = temp_file.path().to_str().unwrap().to_string();
+        let pool = DatabasePool::new(db_path, 1);
+
+        pool.execute_with_timeout(|conn| {
+            conn.execute(
+                "CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+                (),
+            )?;
+            conn.execute(
+                "CREATE TABLE media_cache (url TEXT NOT NULL, quality TEXT NOT NULL, file_id TEXT NOT NULL, media_type TEXT NOT NULL, source TEXT NOT NULL DEFAULT 'botapi', info TEXT, hits INTEGER DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (url, quality))",
+                (),
+            )?;
+            Ok(())
+        }).await.unwrap();
+
+        (pool, temp_file)
+    }
+
+    fn video(file_id: &str) -> CachedMedia {
+        CachedMedia {
+            file_id: file_id.to_string(),
+            media_type: "video".to_string(),
+            source: SOURCE_BOTAPI.to_string(),
+            info: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_and_get_cached_media() {
+        let (pool, _file) = setup_test_db().await;
+        let url = "https://www.tiktok.com/@user/video/1";
+
+        pool.store_cached_media(url, "h264", &video("FILE_A")).await.unwrap();
+
+        assert_eq!(pool.get_cached_media(url, "h264").await.unwrap(), Some(video("FILE_A")));
+        // Quality is part of the key
+        assert_eq!(pool.get_cached_media(url, "h265").await.unwrap(), None);
+
+        let (entries, hits) = pool.media_cache_stats().await.unwrap();
+        assert_eq!((entries, hits), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_cached_media_keeps_info() {
+        let (pool, _file) = setup_test_db().await;
+        let url = "https://www.tiktok.com/@user/video/4";
+        let media = CachedMedia {
+            info: Some(VideoInfo { uploader: Some("Ann".to_string()), like_count: Some(5), ..Default::default() }),
+            ..video("WITH_INFO")
+        };
+
+        pool.store_cached_media(url, "h264", &media).await.unwrap();
+        assert_eq!(pool.get_cached_media(url, "h264").await.unwrap(), Some(media));
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_ignored_and_purged() {
+        let (pool, _file) = setup_test_db().await;
+        let url = "https://www.tiktok.com/@user/video/2";
+
+        pool.set_setting("media_cache_ttl_hours", "1").await.unwrap();
+        pool.store_cached_media(url, "h264", &video("OLD")).await.unwrap();
+        pool.execute_with_timeout(|conn| {
+            conn.execute("UPDATE media_cache SET created_at = datetime('now', '-2 hours')", [])
+        }).await.unwrap();
+
+        assert_eq!(pool.get_cached_media(url, "h264").await.unwrap(), None);
+        assert_eq!(pool.purge_media_cache("expired").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_and_purge_by_url() {
+        let (pool, _file) = setup_test_db().await;
+        let url = "https://www.tiktok.com/@user/video/3";
+
+        pool.store_cached_media(url, "h264", &video("A")).await.unwrap();
+        pool.store_cached_media(url, "audio", &video("B")).await.unwrap();
+        pool.store_cached_media("https://other", "h264", &video("C")).await.unwrap();
+
+        pool.invalidate_cached_media(url, "h264").await.unwrap();
+        assert_eq!(pool.get_cached_media(url, "h264").await.unwrap(), None);
+
+        assert_eq!(pool.purge_media_cache(url).await.unwrap(), 1);
+        assert_eq!(pool.purge_media_cache("all").await.unwrap(), 1);
+    }
+}
@@ -1,5 +1,17 @@
 mod pool;
 mod old;
+mod media_cache;
+mod user_prefs;
+mod platforms;
+mod group_settings;
+mod captions;
+mod follows;
+mod jobs;
 
 pub use pool::DatabasePool;
-pub use old::{get_database_path, init_database};
\ No newline at end of file
+pub use old::{get_database_path, init_database};
+pub use media_cache::{CachedMedia, SOURCE_BOTAPI, SOURCE_MTPROTO};
+pub use user_prefs::{SLIDESHOW_ALBUM, SLIDESHOW_VIDEO};
+pub use group_settings::GroupSettings;
+pub use follows::Follow;
+pub use jobs::{Job, JobStatus, RecoveredJobs};
\ No newline at end of file
@@ -45,6 +45,12 @@ pub fn init_database() -> Result<()> {
     let _ = conn.execute("ALTER TABLE users ADD COLUMN created_at DATETIME DEFAULT CURRENT_TIMESTAMP", ());
     let _ = conn.execute("ALTER TABLE users ADD COLUMN quality_preference TEXT DEFAULT 'h264'", ());
     let _ = conn.execute("ALTER TABLE users ADD COLUMN premium_until DATETIME", ());
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN slideshow_mode TEXT DEFAULT 'album'", ());
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN captions INTEGER DEFAULT 1", ());
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN format_picker INTEGER DEFAULT 0", ());
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN audio_format TEXT DEFAULT 'original'", ());
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN split_videos INTEGER DEFAULT 0", ());
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN subtitles TEXT DEFAULT 'off'", ());
 
     // Create the table with the new format
     conn.execute(
@@ -121,6 +127,24 @@ pub fn init_database() -> Result<()> {
         "CREATE TABLE IF NOT EXISTS invoices (id INTEGER PRIMARY KEY, user_id BIGINT NOT NULL, amount INTEGER NOT NULL, payload TEXT, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP)",
         (),
     )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS media_cache (url TEXT NOT NULL, quality TEXT NOT NULL, file_id TEXT NOT NULL, media_type TEXT NOT NULL, source TEXT NOT NULL DEFAULT 'botapi', info TEXT, hits INTEGER DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (url, quality))",
+        (),
+    )?;
+    let _ = conn.execute("ALTER TABLE media_cache ADD COLUMN info TEXT", ());
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS group_settings (chat_id BIGINT PRIMARY KEY, quality TEXT NOT NULL DEFAULT 'h264', captions INTEGER NOT NULL DEFAULT 1, silent INTEGER NOT NULL DEFAULT 0, delete_links INTEGER NOT NULL DEFAULT 0, disabled_platforms TEXT NOT NULL DEFAULT '')",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS jobs (id INTEGER PRIMARY KEY, user_id BIGINT NOT NULL, chat_id BIGINT NOT NULL, url TEXT NOT NULL, request TEXT NOT NULL, status TEXT NOT NULL DEFAULT 'queued', attempts INTEGER NOT NULL DEFAULT 0, error TEXT, priority INTEGER NOT NULL DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, updated_at DATETIME DEFAULT CURRENT_TIMESTAMP, started_at DATETIME, finished_at DATETIME)",
+        (),
+    )?;
+    let _ = conn.execute("ALTER TABLE jobs ADD COLUMN priority INTEGER NOT NULL DEFAULT 0", ());
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS follows (id INTEGER PRIMARY KEY, user_id BIGINT NOT NULL, chat_id BIGINT NOT NULL, chat_username TEXT, url TEXT NOT NULL, name TEXT NOT NULL, last_seen_id TEXT, next_check INTEGER NOT NULL DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, UNIQUE (chat_id, url))",
+        (),
+    )?;
     
     // Add indexes for performance
     let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_users_last_active ON users(last_active)", ());
@@ -130,6 +154,10 @@ pub fn init_database() -> Result<()> {
     let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_pending_user_id ON pending_downloads(user_id)", ());
     let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_payments_date ON payments(timestamp)", ());
     let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_invoices_date ON invoices(timestamp)", ());
+    let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_media_cache_created ON media_cache(created_at)", ());
+    let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_follows_next_check ON follows(next_check)", ());
+    let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_follows_user_id ON follows(user_id)", ());
+    let _ = conn.execute("CREATE INDEX IF NOT EXISTS idx_jobs_status ON jobs(status)", ());
 
     conn.execute(
         "INSERT OR IGNORE INTO settings (key, value) VALUES ('subscription_required', 'true')",
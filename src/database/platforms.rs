@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+
+use crate::database::DatabasePool;
+
+/// Setting holding the comma-separated ids of platforms switched off by an admin.
+const DISABLED_PLATFORMS_KEY: &str = "disabled_platforms";
+/// Prefix of the settings holding a platform's download provider order, e.g. "provider_order:tiktok".
+const PROVIDER_ORDER_PREFIX: &str = "provider_order:";
+
+pub(super) fn parse_platform_list(value: &str) -> HashSet<String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+impl DatabasePool {
+    /// Ids of platforms whose links are currently ignored. Empty when nothing is disabled.
+    pub async fn get_disabled_platforms(&self) -> HashSet<String> {
+        self.get_setting(DISABLED_PLATFORMS_KEY).await
+            .map(|v| parse_platform_list(&v))
+            .unwrap_or_default()
+    }
+
+    pub async fn set_platform_enabled(&self, platform_id: &str, enabled: bool) -> Result<(), anyhow::Error> {
+        let mut disabled = self.get_disabled_platforms().await;
+        if enabled {
+            disabled.remove(platform_id);
+        } else {
+            disabled.insert(platform_id.to_string());
+        }
+        let mut ids: Vec<String> = disabled.into_iter().collect();
+        ids.sort();
+        self.set_setting(DISABLED_PLATFORMS_KEY, &ids.join(",")).await
+    }
+
+    /// Provider ids an admin put in front for this platform, in order. `None` keeps the default.
+    pub async fn get_provider_order(&self, platform_id: &str) -> Option<Vec<String>> {
+        let value = self.get_setting(&format!("{}{}", PROVIDER_ORDER_PREFIX, platform_id)).await.ok()?;
+        let order: Vec<String> = value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        (!order.is_empty()).then_some(order)
+    }
+
+    pub async fn set_provider_order(&self, platform_id: &str, order: &[String]) -> Result<(), anyhow::Error> {
+        self.set_setting(&format!("{}{}", PROVIDER_ORDER_PREFIX, platform_id), &order.join(",")).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_enable_disable_platforms() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = DatabasePool::new(temp_file.path().to_str().unwrap().to_string(), 1);
+        pool.execute_with_timeout(|conn| {
+            conn.execute("CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)", ())
+        }).await.unwrap();
+
+        assert!(pool.get_disabled_platforms().await.is_empty());
+
+        pool.set_platform_enabled("reddit", false).await.unwrap();
+        pool.set_platform_enabled("likee", false).await.unwrap();
+        assert_eq!(pool.get_setting(DISABLED_PLATFORMS_KEY).await.unwrap(), "likee,reddit");
+
+        pool.set_platform_enabled("reddit", true).await.unwrap();
+        let disabled = pool.get_disabled_platforms().await;
+        assert!(disabled.contains("likee") && !disabled.contains("reddit"));
+
+        assert_eq!(pool.get_provider_order("tiktok").await, None);
+        pool.set_provider_order("tiktok", &["tikwm".to_string(), "ytdlp".to_string()]).await.unwrap();
+        assert_eq!(pool.get_provider_order("tiktok").await, Some(vec!["tikwm".to_string(), "ytdlp".to_string()]));
+    }
+}
@@ -0,0 +1,214 @@
+use rusqlite::{params, OptionalExtension};
+
+use crate::database::DatabasePool;
+use crate::yt_dlp_interface::AudioFormat;
+use crate::yt_dlp_interface::subtitles::SubtitleMode;
+
+/// Photo carousels are sent as a media group.
+pub const SLIDESHOW_ALBUM: &str = "album";
+/// Photo carousels are rendered into a single MP4 over the post's music.
+pub const SLIDESHOW_VIDEO: &str = "video";
+
+impl DatabasePool {
+    /// How the user wants TikTok photo carousels delivered. Defaults to an album.
+    pub async fn get_user_slideshow_mode(&self, user_id: i64) -> Result<String, anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT slideshow_mode FROM users WHERE telegram_id = ?1",
+                params![user_id],
+                |row| row.get::<_, Option<String>>(0)
+            ).optional()
+        }).await
+            .map(|mode| mode.flatten().unwrap_or_else(|| SLIDESHOW_ALBUM.to_string()))
+            .map_err(|e| anyhow::anyhow!("Failed to get slideshow mode: {}", e))
+    }
+
+    pub async fn set_user_slideshow_mode(&self, user_id: i64, mode: &str) -> Result<(), anyhow::Error> {
+        let mode = mode.to_string();
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE users SET slideshow_mode = ?1 WHERE telegram_id = ?2",
+                params![mode, user_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to set slideshow mode: {}", e))
+    }
+
+    /// Whether the user wants captions (author, description, stats...) under their media.
+    /// Defaults to on.
+    pub async fn get_user_captions(&self, user_id: i64) -> bool {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT captions FROM users WHERE telegram_id = ?1",
+                params![user_id],
+                |row| row.get::<_, Option<bool>>(0)
+            ).optional()
+        }).await
+            .ok()
+            .flatten()
+            .flatten()
+            .unwrap_or(true)
+    }
+
+    pub async fn set_user_captions(&self, user_id: i64, enabled: bool) -> Result<(), anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE users SET captions = ?1 WHERE telegram_id = ?2",
+                params![enabled, user_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to set captions: {}", e))
+    }
+
+    /// Whether the user picks a format for every link instead of using their quality setting.
+    /// Defaults to off.
+    pub async fn get_user_format_picker(&self, user_id: i64) -> bool {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT format_picker FROM users WHERE telegram_id = ?1",
+                params![user_id],
+                |row| row.get::<_, Option<bool>>(0)
+            ).optional()
+        }).await
+            .ok()
+            .flatten()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    pub async fn set_user_format_picker(&self, user_id: i64, enabled: bool) -> Result<(), anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE users SET format_picker = ?1 WHERE telegram_id = ?2",
+                params![enabled, user_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to set format picker: {}", e))
+    }
+
+    /// Whether videos too long to compress reasonably are sent in parts. Defaults to off.
+    pub async fn get_user_split_videos(&self, user_id: i64) -> bool {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT split_videos FROM users WHERE telegram_id = ?1",
+                params![user_id],
+                |row| row.get::<_, Option<bool>>(0)
+            ).optional()
+        }).await
+            .ok()
+            .flatten()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    pub async fn set_user_split_videos(&self, user_id: i64, enabled: bool) -> Result<(), anyhow::Error> {
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE users SET split_videos = ?1 WHERE telegram_id = ?2",
+                params![enabled, user_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to set split videos: {}", e))
+    }
+
+    /// What the user's audio downloads are converted to. Defaults to the source's own format.
+    pub async fn get_user_audio_format(&self, user_id: i64) -> AudioFormat {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT audio_format FROM users WHERE telegram_id = ?1",
+                params![user_id],
+                |row| row.get::<_, Option<String>>(0)
+            ).optional()
+        }).await
+            .ok()
+            .flatten()
+            .flatten()
+            .map(|id| AudioFormat::from_id(&id))
+            .unwrap_or_default()
+    }
+
+    pub async fn set_user_audio_format(&self, user_id: i64, format: AudioFormat) -> Result<(), anyhow::Error> {
+        let id = format.id();
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE users SET audio_format = ?1 WHERE telegram_id = ?2",
+                params![id, user_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to set audio format: {}", e))
+    }
+
+    /// Whether and how the user's videos come with subtitles. Defaults to off.
+    pub async fn get_user_subtitles(&self, user_id: i64) -> SubtitleMode {
+        self.execute_with_timeout(move |conn| {
+            conn.query_row(
+                "SELECT subtitles FROM users WHERE telegram_id = ?1",
+                params![user_id],
+                |row| row.get::<_, Option<String>>(0)
+            ).optional()
+        }).await
+            .ok()
+            .flatten()
+            .flatten()
+            .map(|id| SubtitleMode::from_id(&id))
+            .unwrap_or_default()
+    }
+
+    pub async fn set_user_subtitles(&self, user_id: i64, mode: SubtitleMode) -> Result<(), anyhow::Error> {
+        let id = mode.id();
+        self.execute_with_timeout(move |conn| {
+            conn.execute(
+                "UPDATE users SET subtitles = ?1 WHERE telegram_id = ?2",
+                params![id, user_id],
+            )?;
+            Ok(())
+        }).await.map_err(|e| anyhow::anyhow!("Failed to set subtitles: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_slideshow_mode_roundtrip() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = DatabasePool::new(temp_file.path().to_str().unwrap().to_string(), 1);
+        pool.execute_with_timeout(|conn| {
+            conn.execute(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, telegram_id BIGINT UNIQUE NOT NULL, slideshow_mode TEXT DEFAULT 'album', captions INTEGER DEFAULT 1, format_picker INTEGER DEFAULT 0, audio_format TEXT DEFAULT 'original', split_videos INTEGER DEFAULT 0, subtitles TEXT DEFAULT 'off')",
+                (),
+            )?;
+            conn.execute("INSERT INTO users (telegram_id) VALUES (42)", ())?;
+            Ok(())
+        }).await.unwrap();
+
+        assert_eq!(pool.get_user_slideshow_mode(42).await.unwrap(), SLIDESHOW_ALBUM);
+        // Unknown users fall back to the default as well
+        assert_eq!(pool.get_user_slideshow_mode(7).await.unwrap(), SLIDESHOW_ALBUM);
+
+        pool.set_user_slideshow_mode(42, SLIDESHOW_VIDEO).await.unwrap();
+        assert_eq!(pool.get_user_slideshow_mode(42).await.unwrap(), SLIDESHOW_VIDEO);
+
+        assert!(pool.get_user_captions(42).await);
+        pool.set_user_captions(42, false).await.unwrap();
+        assert!(!pool.get_user_captions(42).await);
+
+        assert!(!pool.get_user_format_picker(42).await);
+        pool.set_user_format_picker(42, true).await.unwrap();
+        assert!(pool.get_user_format_picker(42).await);
+
+        assert!(!pool.get_user_split_videos(42).await);
+        pool.set_user_split_videos(42, true).await.unwrap();
+        assert!(pool.get_user_split_videos(42).await);
+
+        assert_eq!(pool.get_user_audio_format(42).await, AudioFormat::Original);
+        pool.set_user_audio_format(42, AudioFormat::Mp3(320)).await.unwrap();
+        assert_eq!(pool.get_user_audio_format(42).await, AudioFormat::Mp3(320));
+
+        assert_eq!(pool.get_user_subtitles(42).await, SubtitleMode::Off);
+        pool.set_user_subtitles(42, SubtitleMode::Burn).await.unwrap();
+        assert_eq!(pool.get_user_subtitles(42).await, SubtitleMode::Burn);
+    }
+}
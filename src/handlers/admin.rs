@@ -1,23 +1,92 @@
 use std::env;
 use teloxide::prelude::*;
 
+use crate::utils::stages::StageMetrics;
+use crate::utils::task_manager::{TaskInfo, TaskKind};
+
 pub async fn is_admin(msg: &Message) -> bool {
-    let admin_ids_str = env::var("ADMIN_IDS").unwrap_or_default();
-    let admin_ids: Vec<i64> = admin_ids_str
+    // Check user ID instead of chat ID
+    msg.from.as_ref().is_some_and(|user| is_admin_id(user.id.0 as i64))
+}
+
+/// Admin check for updates that carry no `Message`, e.g. callback queries.
+pub fn is_admin_id(user_id: i64) -> bool {
+    env::var("ADMIN_IDS")
+        .unwrap_or_default()
         .split(',')
-        .filter_map(|s| s.trim().parse().ok())
-        .collect();
+        .filter_map(|s| s.trim().parse::<i64>().ok())
+        .any(|id| id == user_id)
+}
 
-    // Check user ID instead of chat ID
-    if let Some(user) = msg.from.as_ref() {
-        admin_ids.contains(&(user.id.0 as i64))
-    } else {
-        false
+/// The /tasks listing: one line per background task, oldest first.
+pub fn tasks_report(tasks: &[TaskInfo]) -> String {
+    if tasks.is_empty() {
+        return "🧵 No background tasks.".to_string();
     }
+    let lines: Vec<String> = tasks
+        .iter()
+        .map(|task| {
+            let state = match (task.kind, task.running) {
+                (TaskKind::Service, _) => "service",
+                (TaskKind::Job, true) => "running",
+                (TaskKind::Job, false) => "waiting",
+            };
+            format!("#{} {} ({}, {}s)", task.id, task.name, state, task.age.as_secs())
+        })
+        .collect();
+    format!("🧵 Background tasks ({}):\n{}", tasks.len(), lines.join("\n"))
+}
+
+/// The /stages listing: load and counters of each download pipeline stage.
+pub fn stages_report(metrics: &[StageMetrics]) -> String {
+    let lines: Vec<String> = metrics
+        .iter()
+        .map(|m| {
+            format!(
+                "{}: {}/{} busy, {} waiting, {} done, avg {:.1}s",
+                m.stage.label(),
+                m.active,
+                m.limit,
+                m.waiting,
+                m.completed,
+                m.avg_busy.as_secs_f64()
+            )
+        })
+        .collect();
+    format!("🏭 Pipeline stages:\n{}", lines.join("\n"))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::utils::stages::Stage;
+    use std::time::Duration;
+
+    #[test]
+    fn test_stages_report() {
+        let metrics = vec![
+            StageMetrics { stage: Stage::Download, limit: 2, active: 2, waiting: 3, completed: 10, avg_busy: Duration::from_millis(12_340) },
+            StageMetrics { stage: Stage::MtprotoUpload, limit: 1, active: 0, waiting: 0, completed: 0, avg_busy: Duration::ZERO },
+        ];
+        assert_eq!(
+            stages_report(&metrics),
+            "🏭 Pipeline stages:\ndownload: 2/2 busy, 3 waiting, 10 done, avg 12.3s\nMTProto upload: 0/1 busy, 0 waiting, 0 done, avg 0.0s"
+        );
+    }
+
+    #[test]
+    fn test_tasks_report() {
+        assert_eq!(tasks_report(&[]), "🧵 No background tasks.");
+        let tasks = vec![
+            TaskInfo { id: 1, name: "follow poller".to_string(), kind: TaskKind::Service, running: true, age: Duration::from_secs(90) },
+            TaskInfo { id: 7, name: "job 3: https://x/1".to_string(), kind: TaskKind::Job, running: false, age: Duration::from_millis(1500) },
+        ];
+        assert_eq!(
+            tasks_report(&tasks),
+            "🧵 Background tasks (2):\n#1 follow poller (service, 90s)\n#7 job 3: https://x/1 (waiting, 1s)"
+        );
+    }
+
     // Helper function to test parsing logic without environment variables
     fn parse_admin_ids(admin_ids_str: &str) -> Vec<i64> {
         admin_ids_str
@@ -1,16 +1,23 @@
 use teloxide::prelude::*;
-use teloxide::types::{KeyboardMarkup, KeyboardButton};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, KeyboardMarkup, KeyboardButton};
 use teloxide::dispatching::dialogue::{InMemStorage, Dialogue};
-use crate::handlers::admin::is_admin;
+use crate::handlers::admin::{is_admin, is_admin_id};
 use crate::handlers::ui::{
-    BTN_ADMIN_PANEL, BTN_SUBSCRIPTION, BTN_BACK,
+    BTN_ADMIN_PANEL, BTN_SUBSCRIPTION, BTN_BACK, BTN_CAPTION_TEMPLATE, BTN_PROVIDERS,
     BTN_TOGGLE_ADS, BTN_TOGGLE_SUCCESS_NOTIFS, BTN_TOGGLE_FAIL_NOTIFS
 };
 use crate::database::DatabasePool;
 use crate::handlers::broadcast::BroadcastState;
+use crate::utils::caption::{render_caption, unknown_placeholders, CAPTION_PLACEHOLDERS, DEFAULT_CAPTION_TEMPLATE};
+use crate::yt_dlp_interface::{VideoInfo, YoutubeFetcher};
 use std::sync::Arc;
 
 pub const BTN_BROADCAST: &str = "📢 Broadcast";
+pub const BTN_PLATFORMS: &str = "🌐 Platforms";
+/// Callback data prefix of the per-platform toggle buttons.
+pub const PLATFORM_TOGGLE_PREFIX: &str = "platform_toggle:";
+/// Callback data prefix of the per-platform download source order buttons.
+pub const PROVIDER_ORDER_PREFIX: &str = "provider_order:";
 
 type MyDialogue = Dialogue<BroadcastState, InMemStorage<BroadcastState>>;
 
@@ -35,8 +42,9 @@ pub async fn admin_panel_text_handler(
         vec![KeyboardButton::new("📊 Stats"), KeyboardButton::new("📈 Daily Stats")],
         vec![KeyboardButton::new(BTN_BROADCAST), KeyboardButton::new("➕ Add Premium User")],
         vec![KeyboardButton::new("🏆 Top 10"), KeyboardButton::new("👥 All users")],
-        vec![KeyboardButton::new("💎 Premium Users")],
-        vec![KeyboardButton::new(BTN_SUBSCRIPTION)],
+        vec![KeyboardButton::new("💎 Premium Users"), KeyboardButton::new(BTN_PLATFORMS)],
+        vec![KeyboardButton::new(BTN_SUBSCRIPTION), KeyboardButton::new(BTN_CAPTION_TEMPLATE)],
+        vec![KeyboardButton::new(BTN_PROVIDERS)],
         vec![
             KeyboardButton::new(format!("{}{}", BTN_TOGGLE_ADS, if ads_enabled { "ON ✅" } else { "OFF ❌" })),
             KeyboardButton::new(format!("🔔 Admin Ads: {}", if admin_ads_enabled { "ON ✅" } else { "OFF ❌" })),
@@ -102,6 +110,258 @@ pub async fn add_premium_user_handler(
     Ok(())
 }
 
+/// Post used to preview a caption template.
+fn sample_video_info() -> VideoInfo {
+    VideoInfo {
+        title: Some("Morning routine".to_string()),
+        description: Some("Morning routine with my cat #cats #morning".to_string()),
+        uploader: Some("Ann".to_string()),
+        uploader_id: Some("ann".to_string()),
+        track: Some("original sound".to_string()),
+        artist: Some("Ann".to_string()),
+        view_count: Some(1_250_000),
+        like_count: Some(84_300),
+        comment_count: Some(912),
+        ..Default::default()
+    }
+}
+
+/// Shows the caption template with its placeholders and waits for a new one.
+pub async fn caption_template_text_handler(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !is_admin(&msg).await {
+        bot.send_message(msg.chat.id, "This option is for admins only.").await?;
+        return Ok(());
+    }
+
+    let template = db_pool.get_caption_template().await;
+    let placeholders = CAPTION_PLACEHOLDERS
+        .iter()
+        .map(|(name, about)| format!("{{{}}} — {}", name, about))
+        .collect::<Vec<_>>()
+        .join("\n");
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "📝 Current caption template:\n\n{}\n\nPlaceholders:\n{}\n\nLines whose placeholders are all empty are skipped.\nSend a new template, /reset for the default or /cancel.",
+            template, placeholders
+        ),
+    )
+    .await?;
+    dialogue.update(BroadcastState::WaitingForCaptionTemplate).await?;
+    Ok(())
+}
+
+pub async fn caption_template_handler(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !is_admin(&msg).await {
+        return Ok(());
+    }
+    let Some(text) = msg.text() else {
+        bot.send_message(msg.chat.id, "⚠️ Please send the template as text (or /cancel):").await?;
+        return Ok(());
+    };
+
+    if text == "/cancel" {
+        bot.send_message(msg.chat.id, "❌ Cancelled.")
+            .reply_markup(crate::handlers::command::get_main_reply_keyboard())
+            .await?;
+        dialogue.exit().await?;
+        return Ok(());
+    }
+
+    let template = if text == "/reset" { DEFAULT_CAPTION_TEMPLATE } else { text };
+    let unknown = unknown_placeholders(template);
+    if !unknown.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!("⚠️ Unknown placeholders: {}. Fix the template and send it again (or /cancel):", unknown.join(", ")),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if let Err(e) = db_pool.set_caption_template(template).await {
+        log::error!("Failed to save caption template: {}", e);
+        bot.send_message(msg.chat.id, "❌ Database error.").await?;
+        return Ok(());
+    }
+    let preview = render_caption(template, &sample_video_info(), "https://www.tiktok.com/@ann/video/7");
+    bot.send_message(msg.chat.id, format!("✅ Caption template saved. Preview:\n\n{}", preview))
+        .reply_markup(crate::handlers::command::get_main_reply_keyboard())
+        .await?;
+    dialogue.exit().await?;
+    Ok(())
+}
+
+async fn platforms_keyboard(db_pool: &DatabasePool) -> InlineKeyboardMarkup {
+    let disabled = db_pool.get_disabled_platforms().await;
+    let rows = crate::platforms::registry()
+        .all()
+        .map(|p| {
+            let state = if disabled.contains(p.id()) { "OFF ❌" } else { "ON ✅" };
+            vec![InlineKeyboardButton::callback(
+                format!("{}: {}", p.name(), state),
+                format!("{}{}", PLATFORM_TOGGLE_PREFIX, p.id()),
+            )]
+        })
+        .collect::<Vec<_>>();
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub async fn platforms_text_handler(
+    bot: Bot,
+    msg: Message,
+    db_pool: Arc<DatabasePool>
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !is_admin(&msg).await {
+        bot.send_message(msg.chat.id, "This option is for admins only.").await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, "🌐 Supported platforms. Tap to enable or disable:")
+        .reply_markup(platforms_keyboard(&db_pool).await)
+        .await?;
+    Ok(())
+}
+
+/// Flips a platform on/off and refreshes the inline keyboard in place.
+pub async fn platform_toggle_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    db_pool: Arc<DatabasePool>
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !is_admin_id(q.from.id.0 as i64) {
+        bot.answer_callback_query(q.id).text("This option is for admins only.").await?;
+        return Ok(());
+    }
+
+    let Some(platform_id) = q.data.as_deref().and_then(|d| d.strip_prefix(PLATFORM_TOGGLE_PREFIX)) else {
+        return Ok(());
+    };
+    let Some(platform) = crate::platforms::registry().get(platform_id) else {
+        bot.answer_callback_query(q.id).text("Unknown platform.").await?;
+        return Ok(());
+    };
+
+    let enable = db_pool.get_disabled_platforms().await.contains(platform.id());
+    db_pool.set_platform_enabled(platform.id(), enable).await?;
+    bot.answer_callback_query(q.id.clone())
+        .text(format!("{} {}", platform.name(), if enable { "enabled" } else { "disabled" }))
+        .await?;
+
+    if let Some(message) = q.regular_message() {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(platforms_keyboard(&db_pool).await)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Health of every download source, e.g. "tikwm: 🔴 skipped for 240s (12 ok, 5 failed)".
+fn providers_text(fetcher: &YoutubeFetcher) -> String {
+    let now = std::time::Instant::now();
+    let mut text = String::from("🔌 Download sources\n\n");
+    for provider in fetcher.providers.all() {
+        let health = fetcher.providers.health(provider.id());
+        let state = match health.open_until {
+            Some(until) if until > now => format!("🔴 skipped for {}s", (until - now).as_secs()),
+            _ if health.consecutive_failures > 0 => "🟡 failing".to_string(),
+            _ => "🟢 OK".to_string(),
+        };
+        text.push_str(&format!("{}: {} ({} ok, {} failed)\n", provider.name(), state, health.successes, health.failures));
+        if let Some(error) = &health.last_error {
+            text.push_str(&format!("   last error: {}\n", error.chars().take(120).collect::<String>()));
+        }
+    }
+    text.push_str("\nTap a platform to change the order its sources are tried in:");
+    text
+}
+
+/// Order of the sources that can serve the platform, the ones missing from its order last.
+fn platform_provider_order(fetcher: &YoutubeFetcher, platform_id: &str) -> Vec<String> {
+    let supporting: Vec<String> = fetcher.providers.supporting(platform_id).iter().map(|p| p.id().to_string()).collect();
+    let mut order: Vec<String> = fetcher.providers.order(platform_id).into_iter().filter(|id| supporting.contains(id)).collect();
+    order.extend(supporting.into_iter().filter(|id| !order.contains(id)).collect::<Vec<_>>());
+    order
+}
+
+fn providers_keyboard(fetcher: &YoutubeFetcher) -> InlineKeyboardMarkup {
+    let rows = crate::platforms::registry()
+        .all()
+        .filter(|p| fetcher.providers.supporting(p.id()).len() > 1)
+        .map(|p| {
+            let names: Vec<&str> = platform_provider_order(fetcher, p.id())
+                .iter()
+                .filter_map(|id| fetcher.providers.get(id).map(|provider| provider.name()))
+                .collect();
+            vec![InlineKeyboardButton::callback(
+                format!("{}: {}", p.name(), names.join(" → ")),
+                format!("{}{}", PROVIDER_ORDER_PREFIX, p.id()),
+            )]
+        })
+        .collect::<Vec<_>>();
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub async fn providers_text_handler(
+    bot: Bot,
+    msg: Message,
+    fetcher: Arc<YoutubeFetcher>
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !is_admin(&msg).await {
+        bot.send_message(msg.chat.id, "This option is for admins only.").await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, providers_text(&fetcher))
+        .reply_markup(providers_keyboard(&fetcher))
+        .await?;
+    Ok(())
+}
+
+/// Moves the platform's first download source to the end of its chain.
+pub async fn provider_order_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !is_admin_id(q.from.id.0 as i64) {
+        bot.answer_callback_query(q.id).text("This option is for admins only.").await?;
+        return Ok(());
+    }
+
+    let Some(platform) = q.data.as_deref()
+        .and_then(|d| d.strip_prefix(PROVIDER_ORDER_PREFIX))
+        .and_then(|id| crate::platforms::registry().get(id))
+    else {
+        bot.answer_callback_query(q.id).text("Unknown platform.").await?;
+        return Ok(());
+    };
+
+    let mut order = platform_provider_order(&fetcher, platform.id());
+    order.rotate_left(1);
+    db_pool.set_provider_order(platform.id(), &order).await?;
+    fetcher.providers.set_order(platform.id(), order);
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    if let Some(message) = q.regular_message() {
+        bot.edit_message_text(message.chat.id, message.id, providers_text(&fetcher))
+            .reply_markup(providers_keyboard(&fetcher))
+            .await?;
+    }
+    Ok(())
+}
+
 /// Escape special characters for Telegram MarkdownV2
 pub fn escape_markdown_v2(s: &str) -> String {
     s.replace("_", "\\_")
@@ -286,13 +546,16 @@ pub async fn stats_text_handler(
         Ok((total_users, total_downloads))
     }).await;
 
+    let (cached_files, cache_hits) = db_pool.media_cache_stats().await.unwrap_or((0, 0));
+
     match result {
         Ok((total_users, total_downloads)) => {
             let response = format!(
                 "📊 Statistics\n\n\
                  👥 Total users: {}\n\
-                 📥 Total downloads: {}",
-                total_users, total_downloads
+                 📥 Total downloads: {}\n\
+                 🗂 Cached files: {} (hits: {})",
+                total_users, total_downloads, cached_files, cache_hits
             );
             bot.send_message(msg.chat.id, response)
                 .await
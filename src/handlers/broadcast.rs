@@ -4,7 +4,9 @@ use teloxide::types::{ParseMode, ChatId, InlineKeyboardMarkup, InlineKeyboardBut
 use std::sync::Arc;
 use crate::database::DatabasePool;
 use crate::handlers::admin::is_admin;
+use crate::utils::task_manager::TaskManager;
 use tokio::time::{sleep, Duration};
+use tokio_util::sync::CancellationToken;
 
 type MyDialogue = Dialogue<BroadcastState, InMemStorage<BroadcastState>>;
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
@@ -16,6 +18,7 @@ pub enum BroadcastState {
     WaitingForMessage,
     WaitingForConfirmation { message: String },  // New state!
     WaitingForAddPremiumUserId,
+    WaitingForCaptionTemplate,
 }
 
 pub async fn start_broadcast(
@@ -97,6 +100,7 @@ pub async fn handle_broadcast_confirmation(
     dialogue: MyDialogue,
     q: CallbackQuery,
     db_pool: Arc<DatabasePool>,
+    task_manager: Arc<TaskManager>,
     message: String,
 ) -> HandlerResult {
     if let Some(data) = &q.data {
@@ -141,40 +145,15 @@ pub async fn handle_broadcast_confirmation(
 
                 match users {
                     Ok(users) => {
-                        let total = users.len();
-                        let mut sent = 0;
-                        let mut failed = 0;
-
-                        for (idx, user_id) in users.iter().enumerate() {
-                            // Rate limit: 25 msg/sec
-                            if idx > 0 && idx % 25 == 0 {
-                                sleep(Duration::from_secs(1)).await;
-                            }
-
-                            match bot.send_message(ChatId(*user_id), &message)
-                                .parse_mode(ParseMode::Html)
+                        // Runs in the background so the dispatcher isn't held up for minutes
+                        let admin_chat = msg.chat().id;
+                        let name = format!("broadcast to {} users", users.len());
+                        let sender = bot.clone();
+                        if task_manager.spawn_job(name, |token| send_broadcast(sender, admin_chat, users, message, token)).is_none() {
+                            bot.send_message(admin_chat, "⚠️ The bot is shutting down, broadcast not started.")
                                 .await
-                            {
-                                Ok(_) => sent += 1,
-                                Err(e) => {
-                                    log::warn!("Failed to send to {}: {}", user_id, e);
-                                    failed += 1;
-
-                                    if let Some(secs) = extract_flood_wait(&e.to_string()) {
-                                        log::info!("FLOOD_WAIT_{} - sleeping", secs);
-                                        sleep(Duration::from_secs(secs.min(30))).await;
-                                    }
-                                }
-                            }
+                                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
                         }
-
-                        let report = format!(
-                            "✅ Broadcast completed!\n📊 Sent: {}/{}\n❌ Failed: {}",
-                            sent, total, failed
-                        );
-                        bot.send_message(msg.chat().id, report)
-                            .await
-                            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
                     }
                     Err(e) => {
                         log::error!("DB error: {}", e);
@@ -194,6 +173,57 @@ pub async fn handle_broadcast_confirmation(
     Ok(())
 }
 
+/// Sends `message` to every user and reports the totals to `admin_chat`. Stops early when
+/// `token` is cancelled.
+async fn send_broadcast(bot: Bot, admin_chat: ChatId, users: Vec<i64>, message: String, token: CancellationToken) {
+    let total = users.len();
+    let mut sent = 0;
+    let mut failed = 0;
+
+    for (idx, user_id) in users.iter().enumerate() {
+        if token.is_cancelled() {
+            let report = format!(
+                "⚠️ Broadcast interrupted at {}/{} by a restart.\n📊 Sent: {}\n❌ Failed: {}",
+                idx, total, sent, failed
+            );
+            let _ = bot.send_message(admin_chat, report).await;
+            return;
+        }
+
+        // Rate limit: 25 msg/sec
+        if idx > 0 && idx % 25 == 0 {
+            sleep(Duration::from_secs(1)).await;
+        }
+
+        match bot.send_message(ChatId(*user_id), &message)
+            .parse_mode(ParseMode::Html)
+            .await
+        {
+            Ok(_) => sent += 1,
+            Err(e) => {
+                log::warn!("Failed to send to {}: {}", user_id, e);
+                failed += 1;
+
+                if let Some(secs) = extract_flood_wait(&e.to_string()) {
+                    log::info!("FLOOD_WAIT_{} - sleeping", secs);
+                    tokio::select! {
+                        _ = sleep(Duration::from_secs(secs.min(30))) => {}
+                        _ = token.cancelled() => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let report = format!(
+        "✅ Broadcast completed!\n📊 Sent: {}/{}\n❌ Failed: {}",
+        sent, total, failed
+    );
+    if let Err(e) = bot.send_message(admin_chat, report).await {
+        log::error!("Failed to send the broadcast report: {}", e);
+    }
+}
+
 fn extract_flood_wait(error_str: &str) -> Option<u64> {
     use regex::Regex;
     let re = Regex::new(r"FLOOD_WAIT_(\d+)").unwrap();
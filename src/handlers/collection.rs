@@ -0,0 +1,203 @@
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::database::DatabasePool;
+use crate::handlers::admin::is_admin_id;
+use crate::handlers::link::{process_batch, VideoRequest};
+use crate::handlers::queue::JobQueue;
+use crate::platforms::collections::CollectionLink;
+use crate::utils::task_manager::TaskManager;
+use crate::yt_dlp_interface::YoutubeFetcher;
+
+/// Callback data prefix of the count buttons: `col:<token>:<count>`.
+pub const COLLECTION_PREFIX: &str = "col:";
+
+/// Posts free users may fetch from one profile or playlist, unless FREE_MAX_BATCH_ITEMS says otherwise.
+const DEFAULT_FREE_MAX_BATCH_ITEMS: usize = 5;
+/// Posts Premium users may fetch from one profile or playlist, unless PREMIUM_MAX_BATCH_ITEMS says otherwise.
+const DEFAULT_PREMIUM_MAX_BATCH_ITEMS: usize = 50;
+/// Counts offered as buttons; the number of listed posts is offered as well.
+const COUNT_CHOICES: &[usize] = &[1, 3, 5, 10, 25, 50];
+/// Unanswered count questions are forgotten after this long.
+const PENDING_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A listed collection waiting for the user's count.
+struct PendingCollection {
+    /// Template for the request of every post; its `url` is replaced.
+    request: VideoRequest,
+    link: CollectionLink,
+    urls: Vec<String>,
+    created: Instant,
+}
+
+lazy_static::lazy_static! {
+    static ref PENDING: Mutex<HashMap<String, PendingCollection>> = Mutex::new(HashMap::new());
+}
+
+/// Most posts the user may fetch from one collection, from FREE_MAX_BATCH_ITEMS / PREMIUM_MAX_BATCH_ITEMS.
+pub fn max_batch_items(premium: bool) -> usize {
+    let (var, default) = if premium {
+        ("PREMIUM_MAX_BATCH_ITEMS", DEFAULT_PREMIUM_MAX_BATCH_ITEMS)
+    } else {
+        ("FREE_MAX_BATCH_ITEMS", DEFAULT_FREE_MAX_BATCH_ITEMS)
+    };
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+/// Counts to offer for `found` listed posts, each with whether it exceeds the user's `cap`.
+fn count_options(found: usize, cap: usize) -> Vec<(usize, bool)> {
+    let mut counts: Vec<usize> = COUNT_CHOICES.iter().copied().filter(|n| *n < found).chain([found]).collect();
+    counts.dedup();
+    counts.into_iter().filter(|n| *n > 0).map(|n| (n, n > cap)).collect()
+}
+
+fn count_keyboard(token: &str, found: usize, cap: usize) -> InlineKeyboardMarkup {
+    let buttons: Vec<InlineKeyboardButton> = count_options(found, cap)
+        .into_iter()
+        .map(|(count, locked)| {
+            let label = if locked { format!("🔒 {}", count) } else { count.to_string() };
+            InlineKeyboardButton::callback(label, format!("{}{}:{}", COLLECTION_PREFIX, token, count))
+        })
+        .collect();
+    InlineKeyboardMarkup::new(buttons.chunks(3).map(|row| row.to_vec()).collect::<Vec<_>>())
+}
+
+fn parse_callback(data: &str) -> Option<(&str, usize)> {
+    let (token, count) = data.strip_prefix(COLLECTION_PREFIX)?.split_once(':')?;
+    Some((token, count.parse().ok()?))
+}
+
+/// Lists the latest posts of `link` and asks how many of them to download. `request` is the
+/// template every post is requested with.
+pub async fn offer_collection(
+    bot: &Bot,
+    fetcher: &YoutubeFetcher,
+    db_pool: Arc<DatabasePool>,
+    request: VideoRequest,
+    link: CollectionLink,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let status = bot
+        .send_message(request.chat_id, format!("🔍 Looking up the latest posts of {} {}...", link.kind.noun(), link.name))
+        .await?;
+    let fingerprint = crate::handlers::fingerprint::get_current_fingerprint(db_pool.clone()).await;
+    // List as many as anybody may fetch, so the keyboard can show what Premium unlocks.
+    let limit = max_batch_items(true).max(max_batch_items(false));
+    let urls: Vec<String> = match fetcher.list_entries(&link.url, limit, fingerprint).await {
+        Ok(entries) if !entries.is_empty() => entries.into_iter().map(|e| e.url).collect(),
+        Ok(_) => {
+            bot.edit_message_text(status.chat.id, status.id, format!("❌ No posts found in {} {}.", link.kind.noun(), link.name)).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            log::error!("Failed to list {}: {}", link.url, e);
+            bot.edit_message_text(status.chat.id, status.id, format!("❌ Could not open {} {}.", link.kind.noun(), link.name)).await?;
+            return Ok(());
+        }
+    };
+
+    let premium = db_pool.is_user_premium(request.user_id).await || is_admin_id(request.user_id);
+    let token = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+    let keyboard = count_keyboard(&token, urls.len(), max_batch_items(premium));
+    let text = format!(
+        "📚 Found {} posts in {} {}. How many of the latest should I download?",
+        urls.len(),
+        link.kind.noun(),
+        link.name
+    );
+    {
+        let mut pending = PENDING.lock().unwrap();
+        pending.retain(|_, p| p.created.elapsed() < PENDING_TTL);
+        pending.insert(token, PendingCollection { request, link, urls, created: Instant::now() });
+    }
+    bot.edit_message_text(status.chat.id, status.id, text).reply_markup(keyboard).await?;
+    Ok(())
+}
+
+pub async fn collection_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+    task_manager: Arc<TaskManager>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some((token, count)) = q.data.as_deref().and_then(parse_callback) else {
+        return Ok(());
+    };
+    let user_id = q.from.id.0 as i64;
+
+    let premium = db_pool.is_user_premium(user_id).await || is_admin_id(user_id);
+    let cap = max_batch_items(premium);
+    if count > cap {
+        bot.answer_callback_query(q.id)
+            .text(format!("You can download up to {} posts at once. Premium users get more.", cap))
+            .await?;
+        crate::handlers::payments::send_premium_invoice(bot, q.from.id.into(), db_pool, None).await?;
+        return Ok(());
+    }
+
+    let picked = {
+        let mut pending = PENDING.lock().unwrap();
+        match pending.get(token) {
+            Some(p) if p.request.user_id == user_id => pending.remove(token),
+            _ => None,
+        }
+    };
+    let Some(picked) = picked else {
+        bot.answer_callback_query(q.id).text("This choice has expired, send the link again.").await?;
+        return Ok(());
+    };
+    if let Some(message) = q.regular_message() {
+        let _ = bot.delete_message(message.chat.id, message.id).await;
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    let requests: Vec<VideoRequest> = picked
+        .urls
+        .into_iter()
+        .take(count)
+        .map(|url| VideoRequest { url, ..picked.request.clone() })
+        .collect();
+    let label = format!("{} posts of {}", requests.len(), picked.link.name);
+    // Only follows the queued jobs, so it takes no job slot the downloads need. The jobs
+    // outlive a restart; the summary is given up when shutdown begins.
+    let chat_id = picked.request.chat_id;
+    let batch_bot = bot.clone();
+    let spawned = task_manager.spawn_service(format!("batch: {}", label), |token| async move {
+        if let Err(e) = process_batch(batch_bot, requests, label, true, job_queue, token).await {
+            log::error!("Collection batch failed: {}", e);
+        }
+    });
+    if spawned.is_none() {
+        bot.send_message(chat_id, "⚠️ The bot is restarting, try again in a minute.").await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_options() {
+        assert_eq!(count_options(50, 5), vec![(1, false), (3, false), (5, false), (10, true), (25, true), (50, true)]);
+        // The listed number is offered even when it isn't one of the choices.
+        assert_eq!(count_options(4, 5), vec![(1, false), (3, false), (4, false)]);
+        assert_eq!(count_options(1, 5), vec![(1, false)]);
+        assert_eq!(count_options(5, 5), vec![(1, false), (3, false), (5, false)]);
+    }
+
+    #[test]
+    fn test_parse_callback() {
+        assert_eq!(parse_callback("col:abc:10"), Some(("abc", 10)));
+        assert_eq!(parse_callback("col:abc"), None);
+        assert_eq!(parse_callback("fp:abc:1"), None);
+    }
+}
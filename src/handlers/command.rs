@@ -43,6 +43,13 @@ pub async fn command_handler(
                 .await
                 .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
         }
+        // Routed to the link::*_command_handler functions by their own branches, which have the downloader deps.
+        Command::Dl { .. } | Command::Clip { .. } | Command::Subs { .. } => {}
+        // Routed to follow::follow_command_handler, which needs the fetcher.
+        Command::Follow { .. } | Command::Unfollow { .. } | Command::Following => {}
+        Command::GroupSettings => {
+            crate::handlers::group_settings::group_settings_command(bot, msg, db_pool).await?;
+        }
     };
     Ok(())
 }
\ No newline at end of file
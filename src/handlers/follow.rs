@@ -0,0 +1,502 @@
+//! `/follow`, `/unfollow` and `/following`, plus the poller that delivers new posts of
+//! followed profiles and channels.
+//!
+//! Every follow stores the id of the newest post seen. The poller lists the profile with
+//! `--flat-playlist` when its check is due, queues what came after that id as download jobs
+//! and schedules the next check with some jitter, so follows added together
+//! don't hit the platform together.
+//!
+//! Premium status is looked up on every check: follows beyond the user's current cap are
+//! paused (oldest follows are kept), and users who see ads get the new links to download
+//! themselves instead of the media.
+
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, Recipient, User};
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::commands::Command;
+use crate::database::{DatabasePool, Follow};
+use crate::handlers::admin::is_admin_id;
+use crate::handlers::link::{ads_enabled, VideoRequest};
+use crate::handlers::queue::JobQueue;
+use crate::platforms::collections::{find_collection, CollectionKind};
+use crate::yt_dlp_interface::playlist::PlaylistEntry;
+use crate::yt_dlp_interface::YoutubeFetcher;
+
+/// Callback data prefix of the /unfollow buttons: `unf:<follow id>`.
+pub const UNFOLLOW_PREFIX: &str = "unf:";
+
+/// Follows a free user may have, unless FREE_MAX_FOLLOWS says otherwise.
+const DEFAULT_FREE_MAX_FOLLOWS: usize = 3;
+/// Follows a Premium user may have, unless PREMIUM_MAX_FOLLOWS says otherwise.
+const DEFAULT_PREMIUM_MAX_FOLLOWS: usize = 25;
+/// Minutes between checks of a free user's follows, unless FREE_FOLLOW_POLL_MINUTES says otherwise.
+const DEFAULT_FREE_POLL_MINUTES: u64 = 360;
+/// Minutes between checks of a Premium user's follows, unless PREMIUM_FOLLOW_POLL_MINUTES says otherwise.
+const DEFAULT_PREMIUM_POLL_MINUTES: u64 = 30;
+/// Posts listed per check; more new posts than this between two checks are partly skipped.
+const CHECK_DEPTH: usize = 10;
+/// New posts delivered per check, so a burst of uploads doesn't flood the chat.
+const MAX_NEW_PER_CHECK: usize = 5;
+/// How often the poller looks for due follows, and how many it checks at once.
+const POLL_TICK: Duration = Duration::from_secs(60);
+const POLL_BATCH: usize = 20;
+
+fn env_or<T: std::str::FromStr + PartialOrd + Default>(var: &str, default: T) -> T {
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|v| *v > T::default())
+        .unwrap_or(default)
+}
+
+/// Follows the user may have, from FREE_MAX_FOLLOWS / PREMIUM_MAX_FOLLOWS.
+pub fn max_follows(premium: bool) -> usize {
+    if premium {
+        env_or("PREMIUM_MAX_FOLLOWS", DEFAULT_PREMIUM_MAX_FOLLOWS)
+    } else {
+        env_or("FREE_MAX_FOLLOWS", DEFAULT_FREE_MAX_FOLLOWS)
+    }
+}
+
+/// Time between checks of the user's follows, from FREE_FOLLOW_POLL_MINUTES / PREMIUM_FOLLOW_POLL_MINUTES.
+pub fn poll_interval(premium: bool) -> Duration {
+    let minutes = if premium {
+        env_or("PREMIUM_FOLLOW_POLL_MINUTES", DEFAULT_PREMIUM_POLL_MINUTES)
+    } else {
+        env_or("FREE_FOLLOW_POLL_MINUTES", DEFAULT_FREE_POLL_MINUTES)
+    };
+    Duration::from_secs(minutes * 60)
+}
+
+/// Unix time of the next check: `interval` from `now`, give or take 20%.
+fn next_check(now: i64, interval: Duration) -> i64 {
+    now + interval.mul_f64(rand::random_range(0.8..1.2)).as_secs() as i64
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Numeric post ids (TikTok) grow with time, so they are compared by value; that also copes
+/// with pinned posts listed above newer ones. Other ids are compared by listing position.
+fn numeric_ids(entries: &[PlaylistEntry]) -> Option<Vec<u64>> {
+    entries.iter().map(|e| e.id.parse().ok()).collect()
+}
+
+/// Id of the newest post of a listing (newest first).
+fn newest_id(entries: &[PlaylistEntry]) -> Option<String> {
+    match numeric_ids(entries) {
+        Some(ids) => ids.into_iter().max().map(|id| id.to_string()),
+        None => entries.first().map(|e| e.id.clone()),
+    }
+}
+
+/// Posts of a listing (newest first) that came after `last_seen`, oldest first and at most
+/// `max`. Without a `last_seen` nothing is new; when it isn't listed anymore, the newest
+/// `max` posts are.
+fn new_entries(entries: &[PlaylistEntry], last_seen: Option<&str>, max: usize) -> Vec<PlaylistEntry> {
+    let Some(last_seen) = last_seen else {
+        return Vec::new();
+    };
+    // An empty baseline comes from a profile that had no posts yet.
+    let numeric_seen = if last_seen.is_empty() { Some(0) } else { last_seen.parse::<u64>().ok() };
+    let mut new: Vec<PlaylistEntry> = match (numeric_ids(entries), numeric_seen) {
+        (Some(ids), Some(seen)) => {
+            let mut newer: Vec<(u64, &PlaylistEntry)> = ids.into_iter().zip(entries).filter(|(id, _)| *id > seen).collect();
+            newer.sort_by_key(|(id, _)| std::cmp::Reverse(*id));
+            newer.into_iter().map(|(_, e)| e.clone()).collect()
+        }
+        _ => match entries.iter().position(|e| e.id == last_seen) {
+            Some(idx) => entries[..idx].to_vec(),
+            None => entries.to_vec(),
+        },
+    };
+    new.truncate(max);
+    new.reverse();
+    new
+}
+
+/// Whether `follow_id` is among the user's oldest `limit` follows; newer ones are paused
+/// while the user is over the cap, e.g. after Premium ran out.
+fn within_cap(follows: &[Follow], follow_id: i64, limit: usize) -> bool {
+    follows.iter().take(limit).any(|f| f.id == follow_id)
+}
+
+/// Where new posts of a follow go, as shown to the user.
+fn target_label(follow: &Follow) -> String {
+    if follow.chat_id == follow.user_id {
+        "here".to_string()
+    } else {
+        match &follow.chat_username {
+            Some(username) => format!("to @{}", username),
+            None => format!("to channel {}", follow.chat_id),
+        }
+    }
+}
+
+/// Checks that `target` (`@channel` or its id) is a channel both the bot and the user
+/// administer, and returns its id and username.
+async fn resolve_channel(bot: &Bot, target: &str, user: UserId) -> Result<(ChatId, Option<String>), String> {
+    let recipient: Recipient = match target.parse::<i64>() {
+        Ok(id) => ChatId(id).into(),
+        Err(_) => Recipient::ChannelUsername(format!("@{}", target.trim_start_matches('@'))),
+    };
+    let chat = bot.get_chat(recipient).await.map_err(|_| "❌ I can't find that channel. Add me to it as an admin first.".to_string())?;
+    if !chat.is_channel() {
+        return Err("❌ New posts can only go to you or to a channel.".to_string());
+    }
+    let me = bot.get_me().await.map_err(|e| format!("❌ {}", e))?;
+    let can_post = bot.get_chat_member(chat.id, me.id).await.is_ok_and(|m| m.can_post_messages());
+    if !can_post {
+        return Err("❌ Make me an admin of the channel who can post messages first.".to_string());
+    }
+    if !bot.get_chat_member(chat.id, user).await.is_ok_and(|m| m.is_privileged()) {
+        return Err("❌ Only admins of the channel can send posts to it.".to_string());
+    }
+    Ok((chat.id, chat.username().map(|s| s.to_string())))
+}
+
+/// `/follow <profile> [@channel]`, `/unfollow [profile]` and `/following`.
+pub async fn follow_command_handler(
+    bot: Bot,
+    msg: Message,
+    cmd: Command,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(user) = msg.from.clone() else {
+        return Ok(());
+    };
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "Follows are managed in a private chat with me.").await?;
+        return Ok(());
+    }
+    match cmd {
+        Command::Follow { args } => follow(&bot, &msg, &user, &args, &fetcher, &db_pool).await,
+        Command::Unfollow { target } => unfollow(&bot, &msg, &user, &target, &db_pool).await,
+        Command::Following => following(&bot, &msg, &user, &db_pool).await,
+        _ => Ok(()),
+    }
+}
+
+async fn follow(
+    bot: &Bot,
+    msg: &Message,
+    user: &User,
+    args: &str,
+    fetcher: &YoutubeFetcher,
+    db_pool: &Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let user_id = user.id.0 as i64;
+    let disabled = db_pool.get_disabled_platforms().await;
+    let Some(link) = find_collection(args, &disabled) else {
+        bot.send_message(msg.chat.id, "Send /follow <profile link> to get new posts here, or /follow <profile link> @channel to have them posted in your channel. TikTok and Instagram profiles and YouTube channels can be followed.").await?;
+        return Ok(());
+    };
+    if link.kind == CollectionKind::YoutubePlaylist {
+        bot.send_message(msg.chat.id, "❌ Playlists can't be followed, only profiles and channels.").await?;
+        return Ok(());
+    }
+
+    let premium = db_pool.is_user_premium(user_id).await || is_admin_id(user_id);
+    let follows = db_pool.get_user_follows(user_id).await?;
+    let limit = max_follows(premium);
+    if follows.len() >= limit {
+        let more = if premium { "" } else { " Premium users can follow more." };
+        bot.send_message(msg.chat.id, format!("❌ You can follow up to {} accounts.{}", limit, more)).await?;
+        return Ok(());
+    }
+
+    let (chat_id, chat_username) = match args.split_whitespace().find(|w| *w != link.url && !w.starts_with("http")) {
+        Some(target) => match resolve_channel(bot, target, user.id).await {
+            Ok(channel) => channel,
+            Err(text) => {
+                bot.send_message(msg.chat.id, text).await?;
+                return Ok(());
+            }
+        },
+        None => (msg.chat.id, None),
+    };
+
+    // The newest post right now is the baseline; only what comes after it is delivered.
+    let fingerprint = crate::handlers::fingerprint::get_current_fingerprint(db_pool.clone()).await;
+    let entries = match fetcher.list_entries(&link.url, CHECK_DEPTH, fingerprint).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("Failed to list {} for a follow: {}", link.url, e);
+            bot.send_message(msg.chat.id, format!("❌ Could not open {} {}.", link.kind.noun(), link.name)).await?;
+            return Ok(());
+        }
+    };
+    let follow = Follow {
+        id: 0,
+        user_id,
+        chat_id: chat_id.0,
+        chat_username,
+        url: link.url.clone(),
+        name: link.name.clone(),
+        // An empty profile still needs a baseline, or its first post would never count as new.
+        last_seen_id: Some(newest_id(&entries).unwrap_or_default()),
+        next_check: next_check(now(), poll_interval(premium)),
+    };
+    if !db_pool.add_follow(&follow).await? {
+        bot.send_message(msg.chat.id, format!("You already follow {} there.", link.name)).await?;
+        return Ok(());
+    }
+    bot.send_message(
+        msg.chat.id,
+        format!("🔔 Following {}. New posts will be sent {}.", link.name, target_label(&follow)),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn unfollow(
+    bot: &Bot,
+    msg: &Message,
+    user: &User,
+    target: &str,
+    db_pool: &Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let user_id = user.id.0 as i64;
+    let follows = db_pool.get_user_follows(user_id).await?;
+    if follows.is_empty() {
+        bot.send_message(msg.chat.id, "You don't follow anybody yet. Use /follow <profile link>.").await?;
+        return Ok(());
+    }
+
+    let target = target.trim();
+    if target.is_empty() {
+        let rows: Vec<Vec<InlineKeyboardButton>> = follows
+            .iter()
+            .map(|f| vec![InlineKeyboardButton::callback(format!("❌ {} ({})", f.name, target_label(f)), format!("{}{}", UNFOLLOW_PREFIX, f.id))])
+            .collect();
+        bot.send_message(msg.chat.id, "Which account should I stop following?")
+            .reply_markup(InlineKeyboardMarkup::new(rows))
+            .await?;
+        return Ok(());
+    }
+
+    let url = find_collection(target, &Default::default()).map(|link| link.url);
+    let matching: Vec<&Follow> = follows
+        .iter()
+        .filter(|f| url.as_ref().is_some_and(|url| *url == f.url) || f.name.eq_ignore_ascii_case(target))
+        .collect();
+    if matching.is_empty() {
+        bot.send_message(msg.chat.id, "❌ You don't follow that account. /following lists your follows.").await?;
+        return Ok(());
+    }
+    for f in &matching {
+        db_pool.remove_follow(user_id, f.id).await?;
+    }
+    bot.send_message(msg.chat.id, format!("🔕 Stopped following {}.", matching[0].name)).await?;
+    Ok(())
+}
+
+async fn following(
+    bot: &Bot,
+    msg: &Message,
+    user: &User,
+    db_pool: &Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let user_id = user.id.0 as i64;
+    let follows = db_pool.get_user_follows(user_id).await?;
+    if follows.is_empty() {
+        bot.send_message(msg.chat.id, "You don't follow anybody yet. Use /follow <profile link>.").await?;
+        return Ok(());
+    }
+    let premium = db_pool.is_user_premium(user_id).await || is_admin_id(user_id);
+    let mut text = format!(
+        "🔔 You follow {} of {} accounts, checked about every {} minutes:\n",
+        follows.len(),
+        max_follows(premium),
+        poll_interval(premium).as_secs() / 60
+    );
+    let limit = max_follows(premium);
+    for f in &follows {
+        let paused = if within_cap(&follows, f.id, limit) { "" } else { " (paused)" };
+        text.push_str(&format!("\n• {} → {}{}", f.name, target_label(f), paused));
+    }
+    if follows.len() > limit {
+        text.push_str("\n\nPaused follows are over your limit; unfollow some or get Premium to resume them.");
+    }
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+pub async fn unfollow_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(follow_id) = q.data.as_deref().and_then(|d| d.strip_prefix(UNFOLLOW_PREFIX)).and_then(|id| id.parse().ok()) else {
+        return Ok(());
+    };
+    let removed = db_pool.remove_follow(q.from.id.0 as i64, follow_id).await?;
+    if let Some(message) = q.regular_message() {
+        let text = if removed { "🔕 Stopped following." } else { "This follow was already removed." };
+        let _ = bot.edit_message_text(message.chat.id, message.id, text).await;
+    }
+    bot.answer_callback_query(q.id).await?;
+    Ok(())
+}
+
+/// Lists one follow and queues its new posts, or sends their links when the user sees ads.
+async fn check_follow(bot: &Bot, follow: Follow, fetcher: &YoutubeFetcher, db_pool: &Arc<DatabasePool>, job_queue: &JobQueue) {
+    let is_user_admin = is_admin_id(follow.user_id);
+    let premium = db_pool.is_user_premium(follow.user_id).await || is_user_admin;
+    let next = next_check(now(), poll_interval(premium));
+    let follows = match db_pool.get_user_follows(follow.user_id).await {
+        Ok(follows) => follows,
+        Err(e) => {
+            log::warn!("Failed to load the follows of user {}: {}", follow.user_id, e);
+            let _ = db_pool.update_follow_check(follow.id, None, next).await;
+            return;
+        }
+    };
+    if !within_cap(&follows, follow.id, max_follows(premium)) {
+        // Keeps the old baseline, so at most MAX_NEW_PER_CHECK posts arrive once it resumes.
+        let _ = db_pool.update_follow_check(follow.id, None, next).await;
+        return;
+    }
+
+    let fingerprint = crate::handlers::fingerprint::get_current_fingerprint(db_pool.clone()).await;
+    let entries = match fetcher.list_entries(&follow.url, CHECK_DEPTH, fingerprint).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Failed to check follow {} ({}): {}", follow.id, follow.url, e);
+            let _ = db_pool.update_follow_check(follow.id, None, next).await;
+            return;
+        }
+    };
+
+    let new = new_entries(&entries, follow.last_seen_id.as_deref(), MAX_NEW_PER_CHECK);
+    if let Err(e) = db_pool.update_follow_check(follow.id, newest_id(&entries), next).await {
+        // Without the new baseline the same posts would be sent again next time.
+        log::error!("Failed to update follow {}: {}", follow.id, e);
+        return;
+    }
+    if new.is_empty() {
+        return;
+    }
+    log::info!("{} new posts of {} for chat {}", new.len(), follow.url, follow.chat_id);
+
+    if ads_enabled(db_pool, follow.user_id, is_user_admin, premium).await {
+        // Downloads of ad-supported users go through the ad, which only they can watch.
+        let links: Vec<String> = new.iter().map(|entry| entry.url.clone()).collect();
+        let text = format!(
+            "🔔 New posts from {}:\n{}\n\nSend me any of them to download it. With Premium new posts are sent {} automatically.",
+            follow.name,
+            links.join("\n"),
+            target_label(&follow)
+        );
+        let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback("💎 Get Premium", "buy_premium")]]);
+        if let Err(e) = bot.send_message(ChatId(follow.user_id), text).reply_markup(keyboard).await {
+            log::warn!("Failed to send new posts of {} to user {}: {}", follow.url, follow.user_id, e);
+        }
+        return;
+    }
+
+    for entry in new {
+        let mut request = VideoRequest::new(follow.user_id, ChatId(follow.chat_id), entry.url);
+        request.username = follow.chat_username.clone();
+        request.show_progress = false;
+        if follow.chat_id == follow.user_id {
+            request.send_options.caption = Some(format!("🔔 New post from {}", follow.name));
+        }
+        if let Err(e) = job_queue.enqueue(&request).await {
+            log::error!("Failed to queue a new post of {}: {}", follow.url, e);
+        }
+    }
+}
+
+/// Checks due follows until `stop` is cancelled; started once at startup.
+pub async fn run_follow_poller(
+    bot: Bot,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+    stop: CancellationToken,
+) {
+    while !stop.is_cancelled() {
+        match db_pool.get_due_follows(now(), POLL_BATCH).await {
+            Ok(due) => {
+                for follow in due {
+                    check_follow(&bot, follow, &fetcher, &db_pool, &job_queue).await;
+                }
+            }
+            Err(e) => log::error!("Failed to load due follows: {}", e),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_TICK) => {}
+            _ = stop.cancelled() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(ids: &[&str]) -> Vec<PlaylistEntry> {
+        ids.iter().map(|id| PlaylistEntry { id: id.to_string(), url: format!("https://x/{}", id) }).collect()
+    }
+
+    fn ids(entries: &[PlaylistEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_new_entries_by_position() {
+        let listed = entries(&["e", "d", "c", "b"]);
+        assert_eq!(ids(&new_entries(&listed, Some("c"), 5)), vec!["d", "e"]);
+        assert!(new_entries(&listed, Some("e"), 5).is_empty());
+        assert!(new_entries(&listed, None, 5).is_empty());
+        // The last seen post is gone: the newest ones are sent, oldest first.
+        assert_eq!(ids(&new_entries(&listed, Some("zz"), 2)), vec!["d", "e"]);
+        assert_eq!(newest_id(&listed).as_deref(), Some("e"));
+    }
+
+    #[test]
+    fn test_new_entries_by_numeric_id() {
+        // A pinned old post is listed first.
+        let listed = entries(&["100", "300", "250", "200"]);
+        assert_eq!(ids(&new_entries(&listed, Some("200"), 5)), vec!["250", "300"]);
+        assert_eq!(newest_id(&listed).as_deref(), Some("300"));
+        // A baseline taken from an empty profile.
+        assert_eq!(ids(&new_entries(&listed, Some(""), 1)), vec!["300"]);
+    }
+
+    #[test]
+    fn test_within_cap_keeps_oldest_follows() {
+        let follows: Vec<Follow> = (1..=4)
+            .map(|id| Follow {
+                id,
+                user_id: 1,
+                chat_id: 1,
+                chat_username: None,
+                url: format!("https://www.tiktok.com/@{}", id),
+                name: format!("@{}", id),
+                last_seen_id: None,
+                next_check: 0,
+            })
+            .collect();
+        assert!(within_cap(&follows, 1, 3));
+        assert!(within_cap(&follows, 3, 3));
+        assert!(!within_cap(&follows, 4, 3));
+        assert!(!within_cap(&follows, 9, 25));
+    }
+
+    #[test]
+    fn test_next_check_is_jittered() {
+        let interval = Duration::from_secs(1000);
+        for _ in 0..20 {
+            let next = next_check(0, interval);
+            assert!((800..=1200).contains(&next), "{}", next);
+        }
+    }
+}
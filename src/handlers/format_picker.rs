@@ -0,0 +1,170 @@
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::database::DatabasePool;
+use crate::handlers::admin::is_admin_id;
+use crate::handlers::link::VideoRequest;
+use crate::handlers::queue::JobQueue;
+use crate::yt_dlp_interface::formats::{format_quality, FormatOption};
+use crate::yt_dlp_interface::YoutubeFetcher;
+
+/// Callback data prefix of the picker buttons: `fp:<token>:<index>`.
+pub const FORMAT_PICKER_PREFIX: &str = "fp:";
+
+/// Resolutions above this need Premium, unless FREE_MAX_HEIGHT says otherwise.
+const DEFAULT_FREE_MAX_HEIGHT: u32 = 720;
+/// Unanswered pickers are forgotten after this long.
+const PICKER_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A picker waiting for the user's choice.
+struct PendingPick {
+    request: VideoRequest,
+    options: Vec<FormatOption>,
+    created: Instant,
+}
+
+lazy_static::lazy_static! {
+    static ref PENDING: Mutex<HashMap<String, PendingPick>> = Mutex::new(HashMap::new());
+}
+
+fn free_max_height() -> u32 {
+    std::env::var("FREE_MAX_HEIGHT")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_FREE_MAX_HEIGHT)
+}
+
+/// Whether the option is reserved for Premium users.
+fn is_locked(option: &FormatOption, premium: bool, free_max_height: u32) -> bool {
+    !premium && option.height.is_some_and(|h| h > free_max_height)
+}
+
+fn picker_keyboard(token: &str, options: &[FormatOption], premium: bool, free_max_height: u32) -> InlineKeyboardMarkup {
+    let rows = options
+        .iter()
+        .enumerate()
+        .map(|(idx, option)| {
+            let label = if is_locked(option, premium, free_max_height) {
+                format!("🔒 {} (Premium)", option.label())
+            } else {
+                option.label()
+            };
+            vec![InlineKeyboardButton::callback(label, format!("{}{}:{}", FORMAT_PICKER_PREFIX, token, idx))]
+        })
+        .collect::<Vec<_>>();
+    InlineKeyboardMarkup::new(rows)
+}
+
+fn parse_callback(data: &str) -> Option<(&str, usize)> {
+    let (token, idx) = data.strip_prefix(FORMAT_PICKER_PREFIX)?.split_once(':')?;
+    Some((token, idx.parse().ok()?))
+}
+
+/// Looks up the formats of `request.url` and asks the user to pick one. Returns false when the
+/// formats couldn't be listed, so the caller downloads with the usual quality instead.
+pub async fn offer_formats(
+    bot: &Bot,
+    fetcher: &YoutubeFetcher,
+    db_pool: Arc<DatabasePool>,
+    request: VideoRequest,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let status = bot.send_message(request.chat_id, "🔍 Looking up available formats...").await?;
+    let fingerprint = crate::handlers::fingerprint::get_current_fingerprint(db_pool.clone()).await;
+    let options = match fetcher.list_formats(&request.url, fingerprint).await {
+        Ok(options) if !options.is_empty() => options,
+        Ok(_) | Err(_) => {
+            log::warn!("No formats listed for {}, downloading with the default quality", request.url);
+            let _ = bot.delete_message(request.chat_id, status.id).await;
+            return Ok(false);
+        }
+    };
+
+    let premium = db_pool.is_user_premium(request.user_id).await || is_admin_id(request.user_id);
+    let token = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+    let keyboard = picker_keyboard(&token, &options, premium, free_max_height());
+    {
+        let mut pending = PENDING.lock().unwrap();
+        pending.retain(|_, pick| pick.created.elapsed() < PICKER_TTL);
+        pending.insert(token, PendingPick { request, options, created: Instant::now() });
+    }
+    bot.edit_message_text(status.chat.id, status.id, "🎚 Choose a format:")
+        .reply_markup(keyboard)
+        .await?;
+    Ok(true)
+}
+
+pub async fn format_picker_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some((token, idx)) = q.data.as_deref().and_then(parse_callback) else {
+        return Ok(());
+    };
+    let user_id = q.from.id.0 as i64;
+
+    let picked = {
+        let pending = PENDING.lock().unwrap();
+        pending
+            .get(token)
+            .filter(|pick| pick.request.user_id == user_id)
+            .and_then(|pick| Some((pick.request.clone(), pick.options.get(idx)?.clone())))
+    };
+    let Some((mut request, option)) = picked else {
+        bot.answer_callback_query(q.id).text("This choice has expired, send the link again.").await?;
+        return Ok(());
+    };
+
+    let premium = db_pool.is_user_premium(user_id).await || is_admin_id(user_id);
+    if is_locked(&option, premium, free_max_height()) {
+        bot.answer_callback_query(q.id).text("This resolution is available with Premium.").await?;
+        crate::handlers::payments::send_premium_invoice(bot, q.from.id.into(), db_pool, None).await?;
+        return Ok(());
+    }
+
+    PENDING.lock().unwrap().remove(token);
+    if let Some(message) = q.regular_message() {
+        let _ = bot.edit_message_text(message.chat.id, message.id, format!("🎚 {}", option.label())).await;
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    request.quality = Some(if option.is_audio() { "audio".to_string() } else { format_quality(&option.selector) });
+    if let Err(e) = job_queue.enqueue(&request).await {
+        log::error!("Failed to queue {} with a picked format: {}", request.url, e);
+        bot.send_message(request.chat_id, "❌ Error initializing download.").await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(height: Option<u32>) -> FormatOption {
+        FormatOption { selector: "1".to_string(), height, codec: "H.264".to_string(), size: None }
+    }
+
+    #[test]
+    fn test_premium_resolutions_are_locked() {
+        assert!(is_locked(&option(Some(1080)), false, 720));
+        assert!(!is_locked(&option(Some(720)), false, 720));
+        assert!(!is_locked(&option(Some(1080)), true, 720));
+        assert!(!is_locked(&option(None), false, 720));
+
+        let keyboard = picker_keyboard("abc", &[option(Some(1080)), option(None)], false, 720);
+        let labels: Vec<&str> = keyboard.inline_keyboard.iter().map(|row| row[0].text.as_str()).collect();
+        assert_eq!(labels, vec!["🔒 1080p H.264 (Premium)", "🎵 Audio"]);
+    }
+
+    #[test]
+    fn test_parse_callback() {
+        assert_eq!(parse_callback("fp:abc:2"), Some(("abc", 2)));
+        assert_eq!(parse_callback("fp:abc"), None);
+        assert_eq!(parse_callback("gs:quality"), None);
+    }
+}
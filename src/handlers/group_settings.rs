@@ -0,0 +1,158 @@
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::database::{DatabasePool, GroupSettings};
+
+/// Callback data prefix of the /groupsettings keyboard.
+pub const GROUP_SETTINGS_PREFIX: &str = "gs:";
+
+const QUALITIES: [&str; 3] = ["h264", "h265", "audio"];
+
+fn next_quality(current: &str) -> &'static str {
+    let idx = QUALITIES.iter().position(|q| *q == current).unwrap_or(QUALITIES.len() - 1);
+    QUALITIES[(idx + 1) % QUALITIES.len()]
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "ON ✅" } else { "OFF ❌" }
+}
+
+fn button(text: String, action: &str) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, format!("{}{}", GROUP_SETTINGS_PREFIX, action))
+}
+
+fn settings_keyboard(settings: &GroupSettings) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![
+        vec![button(format!("🎬 Quality: {}", settings.quality), "quality")],
+        vec![button(format!("📝 Captions: {}", on_off(settings.captions)), "captions")],
+        vec![button(format!("🔕 Silent: {}", on_off(settings.silent)), "silent")],
+        vec![button(format!("🗑 Delete links: {}", on_off(settings.delete_links)), "delete")],
+        vec![button("🌐 Platforms".to_string(), "platforms")],
+    ])
+}
+
+/// Platforms the group can toggle; the ones an admin switched off globally are hidden.
+fn group_platforms_keyboard(settings: &GroupSettings, globally_disabled: &HashSet<String>) -> InlineKeyboardMarkup {
+    let mut rows = crate::platforms::registry()
+        .all()
+        .filter(|p| !globally_disabled.contains(p.id()))
+        .map(|p| {
+            let enabled = !settings.disabled_platforms.contains(p.id());
+            vec![button(format!("{}: {}", p.name(), on_off(enabled)), &format!("pf:{}", p.id()))]
+        })
+        .collect::<Vec<_>>();
+    rows.push(vec![button("⬅️ Back".to_string(), "back")]);
+    InlineKeyboardMarkup::new(rows)
+}
+
+async fn is_group_admin(bot: &Bot, chat_id: ChatId, user_id: UserId) -> bool {
+    bot.get_chat_member(chat_id, user_id).await.is_ok_and(|member| member.is_privileged())
+}
+
+/// `/groupsettings`: shows the group's download settings to its admins.
+pub async fn group_settings_command(
+    bot: Bot,
+    msg: Message,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !(msg.chat.is_group() || msg.chat.is_supergroup()) {
+        bot.send_message(msg.chat.id, "This command only works in groups.").await?;
+        return Ok(());
+    }
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    if !is_group_admin(&bot, msg.chat.id, user.id).await {
+        bot.send_message(msg.chat.id, "Only group admins can change these settings.").await?;
+        return Ok(());
+    }
+
+    let settings = db_pool.get_group_settings(msg.chat.id.0).await.unwrap_or_default();
+    bot.send_message(
+        msg.chat.id,
+        "⚙️ Group settings. Links posted here are answered with the media using these options:",
+    )
+    .reply_markup(settings_keyboard(&settings))
+    .await?;
+    Ok(())
+}
+
+pub async fn group_settings_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(message) = q.regular_message() else {
+        return Ok(());
+    };
+    let Some(action) = q.data.as_deref().and_then(|d| d.strip_prefix(GROUP_SETTINGS_PREFIX)) else {
+        return Ok(());
+    };
+    let chat_id = message.chat.id;
+    if !is_group_admin(&bot, chat_id, q.from.id).await {
+        bot.answer_callback_query(q.id.clone()).text("Only group admins can change these settings.").await?;
+        return Ok(());
+    }
+
+    let mut settings = db_pool.get_group_settings(chat_id.0).await.unwrap_or_default();
+    let globally_disabled = db_pool.get_disabled_platforms().await;
+    let mut show_platforms = false;
+    match action {
+        "quality" => settings.quality = next_quality(&settings.quality).to_string(),
+        "captions" => settings.captions = !settings.captions,
+        "silent" => settings.silent = !settings.silent,
+        "delete" => settings.delete_links = !settings.delete_links,
+        "platforms" => show_platforms = true,
+        "back" => {}
+        other => match other.strip_prefix("pf:") {
+            Some(platform_id) if crate::platforms::registry().get(platform_id).is_some() => {
+                settings.toggle_platform(platform_id);
+                show_platforms = true;
+            }
+            _ => {
+                bot.answer_callback_query(q.id.clone()).text("Unknown option.").await?;
+                return Ok(());
+            }
+        },
+    }
+
+    db_pool.set_group_settings(chat_id.0, &settings).await?;
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let keyboard = if show_platforms {
+        group_platforms_keyboard(&settings, &globally_disabled)
+    } else {
+        settings_keyboard(&settings)
+    };
+    bot.edit_message_reply_markup(chat_id, message.id).reply_markup(keyboard).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_quality_cycles() {
+        assert_eq!(next_quality("h264"), "h265");
+        assert_eq!(next_quality("h265"), "audio");
+        assert_eq!(next_quality("audio"), "h264");
+        assert_eq!(next_quality("best"), "h264");
+    }
+
+    #[test]
+    fn test_platforms_keyboard_hides_globally_disabled() {
+        let mut settings = GroupSettings::default();
+        settings.toggle_platform("tiktok");
+        let globally_disabled: HashSet<String> = ["reddit".to_string()].into_iter().collect();
+        let keyboard = group_platforms_keyboard(&settings, &globally_disabled);
+        let labels: Vec<&str> = keyboard.inline_keyboard.iter().map(|row| row[0].text.as_str()).collect();
+        assert!(labels.contains(&"TikTok: OFF ❌"));
+        assert!(labels.contains(&"Instagram: ON ✅"));
+        assert!(!labels.iter().any(|l| l.starts_with("Reddit")));
+        assert_eq!(labels.last(), Some(&"⬅️ Back"));
+    }
+}
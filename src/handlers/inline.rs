@@ -0,0 +1,349 @@
+use teloxide::prelude::*;
+use teloxide::types::{
+    ChosenInlineResult, FileId, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery, InlineQueryResult,
+    InlineQueryResultArticle, InlineQueryResultsButton, InlineQueryResultsButtonKind, InlineQueryResultCachedAudio, InlineQueryResultCachedVideo, InputFile, InputMedia,
+    InputMediaAudio, InputMediaVideo, InputMessageContent, InputMessageContentText,
+};
+
+use std::fs;
+use std::sync::Arc;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+use crate::database::{CachedMedia, DatabasePool, SOURCE_BOTAPI};
+use crate::handlers::admin::is_admin_id;
+use crate::handlers::link::{ads_enabled, log_download, missing_subscription, DOWNLOAD_TIMEOUT, SLIDESHOW_VIDEO_CACHE_KEY, TELEGRAM_BOT_API_FILE_LIMIT};
+use crate::platforms::canonicalizer::canonical_url;
+use crate::telegram_bot_api_uploader::{send_audio_with_progress_botapi, send_video_with_progress_botapi};
+use crate::utils::caption::render_caption;
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::stages::{Stage, Stages};
+use crate::utils::task_manager::TaskManager;
+use crate::utils::SendOptions;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::{AudioFormat, VideoInfo, YoutubeFetcher};
+use crate::yt_dlp_interface::slideshow::{Downloaded, SlideshowRenderOptions};
+use crate::yt_dlp_interface::video_modes::download_quality;
+
+/// Result id of the "download and send" placeholder; its choice triggers the background job.
+pub const INLINE_DOWNLOAD_RESULT_ID: &str = "download";
+
+/// Chat where inline downloads are uploaded to obtain a Bot API file_id
+/// (inline messages can't receive new uploads, only existing file_ids).
+fn inline_storage_chat() -> Option<ChatId> {
+    std::env::var("INLINE_STORAGE_CHAT_ID").ok()?.trim().parse().ok().map(ChatId)
+}
+
+/// Quality used for a user's inline request and whether it yields audio.
+async fn inline_quality(db_pool: &DatabasePool, user_id: i64, url: &str) -> String {
+    if crate::platforms::registry().for_url(url).is_some_and(|p| p.audio_only()) {
+        return "audio".to_string();
+    }
+    let quality = db_pool.get_user_quality(user_id).await.unwrap_or_else(|_| "best".to_string());
+    // Inline messages can't be video notes; GIF users get the plain video as well.
+    download_quality(&quality).to_string()
+}
+
+/// The user's audio format for inline requests. An inline message can't hold a voice
+/// note, so voice users get the original audio there.
+async fn inline_audio_format(db_pool: &DatabasePool, user_id: i64) -> AudioFormat {
+    match db_pool.get_user_audio_format(user_id).await {
+        AudioFormat::Voice => AudioFormat::Original,
+        format => format,
+    }
+}
+
+/// media_cache key of an inline request: the quality, or the audio format's key for audio.
+async fn inline_cache_key(db_pool: &DatabasePool, user_id: i64, quality: &str) -> String {
+    if quality == "audio" {
+        inline_audio_format(db_pool, user_id).await.cache_key()
+    } else {
+        quality.to_string()
+    }
+}
+
+/// Cached Bot API upload for the link. Video requests check the rendered-slideshow key first,
+/// since inline messages hold a single media and carousels are always rendered for them.
+async fn cached_botapi_media(db_pool: &DatabasePool, url: &str, cache_key: &str) -> Option<CachedMedia> {
+    let keys: &[&str] = if cache_key.starts_with("audio") { &[cache_key] } else { &[SLIDESHOW_VIDEO_CACHE_KEY, cache_key] };
+    for key in keys {
+        if let Ok(Some(cached)) = db_pool.get_cached_media(url, key).await
+            && cached.source == SOURCE_BOTAPI
+        {
+            return Some(cached);
+        }
+    }
+    None
+}
+
+/// Caption of the inline media, unless the user turned captions off.
+async fn inline_caption(db_pool: &DatabasePool, user_id: i64, info: Option<&VideoInfo>, url: &str) -> Option<String> {
+    if !db_pool.get_user_captions(user_id).await {
+        return None;
+    }
+    let template = db_pool.get_caption_template().await;
+    Some(render_caption(&template, info.unwrap_or(&VideoInfo::default()), url))
+}
+
+fn text_result(id: &str, title: &str, text: String) -> InlineQueryResultArticle {
+    InlineQueryResultArticle::new(
+        id,
+        title,
+        InputMessageContent::Text(InputMessageContentText::new(text)),
+    )
+}
+
+/// Why `user_id` can't download inline: the same subscription and ad gates as in private
+/// chat, which inline mode has no way to show. `None` if nothing stands in the way.
+async fn inline_gate(bot: &Bot, db_pool: &DatabasePool, user_id: i64) -> Option<&'static str> {
+    if missing_subscription(bot, db_pool, user_id).await {
+        return Some("📢 Subscribe to our channels to use the bot");
+    }
+    let is_premium = db_pool.is_user_premium(user_id).await;
+    if ads_enabled(db_pool, user_id, is_admin_id(user_id), is_premium).await {
+        return Some("💎 Inline mode needs Premium, open the bot");
+    }
+    None
+}
+
+pub async fn inline_query_handler(
+    bot: Bot,
+    q: InlineQuery,
+    db_pool: Arc<DatabasePool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let disabled_platforms = db_pool.get_disabled_platforms().await;
+    let Some((_, url)) = crate::platforms::registry().find_url(&q.query, &disabled_platforms) else {
+        bot.answer_inline_query(q.id, Vec::<InlineQueryResult>::new()).cache_time(0).await?;
+        return Ok(());
+    };
+    let url = canonical_url(&url).await;
+
+    let user_id = q.from.id.0 as i64;
+    if let Some(reason) = inline_gate(&bot, &db_pool, user_id).await {
+        bot.answer_inline_query(q.id, Vec::<InlineQueryResult>::new())
+            .cache_time(0)
+            .is_personal(true)
+            .button(InlineQueryResultsButton {
+                text: reason.to_string(),
+                kind: InlineQueryResultsButtonKind::StartParameter("inline".to_string()),
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let quality = inline_quality(&db_pool, user_id, &url).await;
+    let cache_key = inline_cache_key(&db_pool, user_id, &quality).await;
+
+    let result = match cached_botapi_media(&db_pool, &url, &cache_key).await {
+        Some(cached) if cached.media_type == "audio" => {
+            let mut audio = InlineQueryResultCachedAudio::new("cached", FileId(cached.file_id));
+            if let Some(caption) = inline_caption(&db_pool, user_id, cached.info.as_ref(), &url).await {
+                audio = audio.caption(caption);
+            }
+            InlineQueryResult::CachedAudio(audio)
+        }
+        Some(cached) => {
+            let mut video = InlineQueryResultCachedVideo::new("cached", FileId(cached.file_id), "📤 Send video")
+                .description(url.clone());
+            if let Some(caption) = inline_caption(&db_pool, user_id, cached.info.as_ref(), &url).await {
+                video = video.caption(caption);
+            }
+            InlineQueryResult::CachedVideo(video)
+        }
+        None if inline_storage_chat().is_some() => {
+            // The keyboard is required: without it Telegram doesn't report an inline_message_id.
+            let keyboard = match reqwest::Url::parse(&url) {
+                Ok(link) => InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::url("🔗 Original", link)]]),
+                Err(_) => InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::switch_inline_query_current_chat("🔁 Retry", url.clone())]]),
+            };
+            InlineQueryResult::Article(
+                text_result(INLINE_DOWNLOAD_RESULT_ID, "⬇️ Download and send", format!("⏳ Downloading...\n{}", url))
+                    .description(url.clone())
+                    .reply_markup(keyboard),
+            )
+        }
+        None => InlineQueryResult::Article(
+            text_result("unavailable", "Not downloaded yet", url.clone())
+                .description("Send this link to the bot in private chat first, then try again."),
+        ),
+    };
+
+    bot.answer_inline_query(q.id, vec![result])
+        .cache_time(0)
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
+
+/// Starts the background download for a chosen placeholder. Requires inline feedback to be
+/// enabled for the bot in @BotFather (/setinlinefeedback).
+pub async fn chosen_inline_result_handler(
+    bot: Bot,
+    result: ChosenInlineResult,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+    stages: Arc<Stages>,
+    task_manager: Arc<TaskManager>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if result.result_id != INLINE_DOWNLOAD_RESULT_ID {
+        return Ok(());
+    }
+    let (Some(inline_message_id), Some(storage_chat)) = (result.inline_message_id.clone(), inline_storage_chat()) else {
+        return Ok(());
+    };
+    let disabled_platforms = db_pool.get_disabled_platforms().await;
+    let Some((_, url)) = crate::platforms::registry().find_url(&result.query, &disabled_platforms) else {
+        return Ok(());
+    };
+    let url = canonical_url(&url).await;
+
+    if task_manager.is_stopping() {
+        bot.edit_message_text_inline(&inline_message_id, format!("⚠️ The bot is restarting, try again in a minute.\n{}", url)).await?;
+        return Ok(());
+    }
+
+    let user_id = result.from.id.0 as i64;
+    if let Some(reason) = inline_gate(&bot, &db_pool, user_id).await {
+        bot.edit_message_text_inline(&inline_message_id, format!("{}\n{}", reason, url)).await?;
+        return Ok(());
+    }
+
+    task_manager.spawn_job(format!("inline: {}", url), |token| async move {
+        let res = tokio::select! {
+            res = deliver_inline(&bot, &fetcher, &db_pool, &stages, user_id, &url, &inline_message_id, storage_chat) => res,
+            _ = token.cancelled() => Err("interrupted by shutdown".into()),
+        };
+        if let Err(e) = res {
+            log::error!("Inline delivery failed for {}: {:?}", url, e);
+            let _ = bot
+                .edit_message_text_inline(&inline_message_id, format!("❌ Could not download this link.\n{}", url))
+                .await;
+        }
+    });
+    Ok(())
+}
+
+/// Downloads the link, uploads it to the storage chat and swaps the placeholder for the media.
+#[allow(clippy::too_many_arguments)]
+async fn deliver_inline(
+    bot: &Bot,
+    fetcher: &Arc<YoutubeFetcher>,
+    db_pool: &Arc<DatabasePool>,
+    stages: &Stages,
+    user_id: i64,
+    url: &str,
+    inline_message_id: &str,
+    storage_chat: ChatId,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let quality = inline_quality(db_pool, user_id, url).await;
+    let is_audio = quality == "audio";
+    let fingerprint = crate::handlers::fingerprint::get_current_fingerprint(db_pool.clone()).await;
+
+    // Nobody watches a progress message for inline requests.
+    let file_stem = format!("output/{}", Uuid::new_v4());
+    let download = {
+        let (fetcher, url, file_stem, quality) = (fetcher.clone(), url.to_string(), file_stem.clone(), quality.clone());
+        async move { fetcher.download(url, &file_stem, &quality, fingerprint, &mut ProgressBar::new_silent()).await }
+    };
+    let downloaded = stages
+        .run(Stage::Download, timeout(DOWNLOAD_TIMEOUT, download))
+        .await
+    .map_err(|_| anyhow::anyhow!("Download timeout"));
+    let ytdlp_info = fetcher.take_video_info(&file_stem).await;
+    let downloaded = downloaded??;
+    let info = match &downloaded {
+        Downloaded::Slideshow(show) => Some(show.info.clone()),
+        Downloaded::Media(_) => ytdlp_info,
+    };
+
+    let mut cache_quality = inline_cache_key(db_pool, user_id, &quality).await;
+    let path = match downloaded {
+        Downloaded::Media(path) => path,
+        Downloaded::Slideshow(show) => {
+            // An inline message holds a single media, so carousels are always rendered.
+            let _guards = show.guards();
+            cache_quality = SLIDESHOW_VIDEO_CACHE_KEY.to_string();
+            let (fetcher, file_stem) = (fetcher.clone(), file_stem.clone());
+            stages
+                .run(Stage::Ffmpeg, async move {
+                    fetcher
+                        .render_slideshow_video(&show, &file_stem, &SlideshowRenderOptions::from_env(), &mut ProgressBar::new_silent())
+                        .await
+                })
+                .await?
+        }
+    };
+    let _guard = TempFileGuard::new(path.clone());
+
+    let is_audio = is_audio && cache_quality != SLIDESHOW_VIDEO_CACHE_KEY;
+    let audio_format = if is_audio { inline_audio_format(db_pool, user_id).await } else { AudioFormat::Original };
+    // Videos and original-format audio skip the ffmpeg stage.
+    let (path, _converted_guard) = if audio_format != AudioFormat::Original {
+        let (fetcher, input) = (fetcher.clone(), path.clone());
+        match stages.run(Stage::Ffmpeg, async move { fetcher.convert_audio(&input, audio_format).await }).await {
+            Ok(converted) if converted != path => (converted.clone(), Some(TempFileGuard::new(converted))),
+            Ok(_) => (path, None),
+            Err(e) => {
+                log::error!("Failed to convert {} to {}: {:?}", url, audio_format.label(), e);
+                cache_quality = AudioFormat::Original.cache_key();
+                (path, None)
+            }
+        }
+    } else {
+        (path, None)
+    };
+
+    if fs::metadata(&path)?.len() > TELEGRAM_BOT_API_FILE_LIMIT {
+        bot.edit_message_text_inline(
+            inline_message_id,
+            format!("⚠️ This file is too large to send inline. Send the link to the bot instead.\n{}", url),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let storage_options = SendOptions { caption: Some(url.to_string()), ..Default::default() };
+    let (token, upload_path) = (bot.token().to_string(), path.clone());
+    let file_id = if is_audio {
+        let audio_meta = fetcher.prepare_audio(&path, info.as_ref()).await;
+        let _cover_guard = audio_meta.thumbnail.clone().map(TempFileGuard::new);
+        let upload = async move {
+            send_audio_with_progress_botapi(&token, storage_chat, &upload_path, &audio_meta, &storage_options, &mut ProgressBar::new_silent()).await
+        };
+        stages.run(Stage::BotApiUpload, upload).await?
+    } else {
+        let upload = async move {
+            send_video_with_progress_botapi(&token, storage_chat, &upload_path, &storage_options, &mut ProgressBar::new_silent()).await
+        };
+        stages.run(Stage::BotApiUpload, upload).await?
+    };
+    // The placeholder can only be swapped for media Telegram already has.
+    let file_id = file_id.ok_or_else(|| anyhow::anyhow!("Bot API response has no file_id"))?;
+
+    let cached = CachedMedia {
+        file_id: file_id.clone(),
+        media_type: if is_audio { "audio" } else { "video" }.to_string(),
+        source: SOURCE_BOTAPI.to_string(),
+        info: info.clone(),
+    };
+    let _ = db_pool.store_cached_media(url, &cache_quality, &cached).await;
+
+    let caption = inline_caption(db_pool, user_id, info.as_ref(), url).await;
+    let media = if is_audio {
+        let mut audio = InputMediaAudio::new(InputFile::file_id(FileId(file_id)));
+        if let Some(caption) = caption {
+            audio = audio.caption(caption);
+        }
+        InputMedia::Audio(audio)
+    } else {
+        let mut video = InputMediaVideo::new(InputFile::file_id(FileId(file_id))).supports_streaming(true);
+        if let Some(caption) = caption {
+            video = video.caption(caption);
+        }
+        InputMedia::Video(video)
+    };
+    bot.edit_message_media_inline(inline_message_id, media).await?;
+
+    log_download(db_pool, user_id, url).await;
+    Ok(())
+}
@@ -1,26 +1,41 @@
-use regex::Regex;
 use teloxide::prelude::*;
-use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, WebAppInfo};
+use teloxide::types::{
+    FileId, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageEntityKind, MessageId, ReplyParameters, User,
+    WebAppInfo,
+};
 
 use std::collections::HashMap;
 use std::fs;
 use std::sync::Arc;
 use tokio::time::Instant;
 use tokio::time::{Duration, timeout};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-use crate::database::DatabasePool;
-use crate::handlers::admin::is_admin;
+use crate::database::{CachedMedia, DatabasePool, GroupSettings, JobStatus, SLIDESHOW_VIDEO, SOURCE_BOTAPI, SOURCE_MTPROTO};
+use crate::handlers::admin::{is_admin, is_admin_id};
+use crate::handlers::queue::{cancel_keyboard, JobQueue};
 use crate::handlers::subscription::check_subscription;
 use crate::handlers::ui::is_menu_button;
 use crate::mtproto_uploader::MTProtoUploader;
+use crate::mtproto_uploader::cached::DocumentRef;
+use crate::platforms::canonicalizer::canonical_url;
+use crate::platforms::collections::find_collection;
 use crate::telegram_bot_api_uploader::{
-    send_audio_with_progress_botapi, send_video_with_progress_botapi,
+    send_audio_with_progress_botapi, send_photo_album_botapi, send_video_with_progress_botapi,
+    send_animation_botapi, send_video_note_botapi, send_voice_with_progress_botapi,
 };
+use crate::utils::caption::{render_caption, truncate_chars, MAX_CAPTION_CHARS};
 use crate::utils::progress_bar::ProgressBar;
-use crate::utils::task_manager::TaskManager;
+use crate::utils::stages::{Stage, Stages};
+use crate::utils::{AudioMeta, SendOptions};
 use crate::utils::temp_file::TempFileGuard;
-use crate::yt_dlp_interface::YoutubeFetcher;
+use crate::yt_dlp_interface::{AudioFormat, ClipRange, VideoInfo, YoutubeFetcher};
+use crate::yt_dlp_interface::clip::{find_clip_range, max_clip_seconds};
+use crate::yt_dlp_interface::subtitles::{SubtitleMode, FALLBACK_SUBTITLE_LANGUAGE};
+use crate::yt_dlp_interface::slideshow::{Downloaded, Slideshow, SlideshowRenderOptions};
+use crate::yt_dlp_interface::video_info::Chapter;
+use crate::yt_dlp_interface::video_modes::{download_quality, VideoMode};
 
 // To track active link processing and avoid double-triggering
 lazy_static::lazy_static! {
@@ -28,27 +43,101 @@ lazy_static::lazy_static! {
     static ref URL_PROCESSING: Arc<tokio::sync::Mutex<std::collections::HashSet<String>>> = Arc::new(tokio::sync::Mutex::new(std::collections::HashSet::new()));
 }
 
-const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300); // 5 minutes
-const TELEGRAM_BOT_API_FILE_LIMIT: u64 = 48 * 1024 * 1024; // 48MB
+pub(crate) const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300); // 5 minutes
+pub(crate) const TELEGRAM_BOT_API_FILE_LIMIT: u64 = 48 * 1024 * 1024; // 48MB
+const MTPROTO_FILE_LIMIT: u64 = 2000 * 1024 * 1024; // 2GB
+/// media_cache quality key for slideshows rendered into a video.
+pub(crate) const SLIDESHOW_VIDEO_CACHE_KEY: &str = "slideshow_video";
+/// Links taken from a single message; the rest are ignored.
+const MAX_BATCH_LINKS: usize = 10;
 
-// Add this function at the beginning of the file
-fn extract_url_from_text(text: &str) -> Option<String> {
-    // Regex for searching TikTok, Instagram or YouTube URL
-    let tiktok_re = Regex::new(r"https?://(?:www\.|vm\.|vt\.)?tiktok\.com/[^\s]+").unwrap();
-    let instagram_re = Regex::new(r"https?://(?:www\.)?instagram\.com/(?:reels?|p|tv)/[^\s]+").unwrap();
-    let youtube_re = Regex::new(r"https?://(?:www\.)?(?:youtube\.com/shorts/|youtube\.com/watch\?v=|youtu\.be/)[^\s]+").unwrap();
+/// One link to deliver: who asked for it, where it goes and how it is posted. Stored as JSON
+/// in the `jobs` table while it waits for a worker.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VideoRequest {
+    /// The requester; quality preference, subscription checks and stats use this id.
+    pub user_id: i64,
+    /// Chat the media is sent to: the requester's private chat or a group.
+    pub chat_id: ChatId,
+    pub url: String,
+    /// Public username of `chat_id`, if any; helps resolving the MTProto peer.
+    pub username: Option<String>,
+    /// Quality forced for this request (group settings) instead of the user's preference.
+    pub quality: Option<String>,
+    /// Captions forced on/off for this request (group settings) instead of the user's preference.
+    pub captions: Option<bool>,
+    /// Audio format forced for this request (groups) instead of the user's preference.
+    pub audio_format: Option<AudioFormat>,
+    /// Only this part of the post is delivered (`<link> 1:20-2:05` or `/clip`).
+    pub clip: Option<ClipRange>,
+    /// Subtitle mode forced for this request (`/subs`, groups) instead of the user's preference.
+    pub subtitles: Option<SubtitleMode>,
+    /// Telegram language of the requester; subtitles are fetched in it.
+    pub language: Option<String>,
+    /// `caption` is appended below the rendered caption template.
+    pub send_options: SendOptions,
+    /// Message deleted once the media was delivered (the link message in groups).
+    pub delete_after: Option<MessageId>,
+    /// Show a progress message; batches report through one combined status message instead.
+    pub show_progress: bool,
+    /// Queue status message; it becomes the progress message once a worker takes the job.
+    pub status_message: Option<MessageId>,
+    /// Row of the `jobs` table this request is processed for.
+    #[serde(skip)]
+    pub job_id: Option<i64>,
+}
 
-    if let Some(mat) = tiktok_re.find(text) {
-        Some(mat.as_str().to_string())
-    } else if let Some(mat) = instagram_re.find(text) {
-        Some(mat.as_str().to_string())
-    } else if let Some(mat) = youtube_re.find(text) {
-        Some(mat.as_str().to_string())
-    } else {
-        None
+impl VideoRequest {
+    pub fn new(user_id: i64, chat_id: ChatId, url: String) -> Self {
+        Self {
+            user_id,
+            chat_id,
+            url,
+            username: None,
+            quality: None,
+            captions: None,
+            audio_format: None,
+            clip: None,
+            subtitles: None,
+            language: None,
+            send_options: SendOptions::default(),
+            delete_after: None,
+            show_progress: true,
+            status_message: None,
+            job_id: None,
+        }
     }
 }
 
+/// Last caption line of media posted in a group: who asked for it.
+fn requester_caption(sender: &User) -> String {
+    let requester = match &sender.username {
+        Some(username) => format!("@{}", username),
+        None => sender.full_name(),
+    };
+    format!("🙋 Requested by {}", requester)
+}
+
+/// Caption of a delivery: the rendered template (`None` when captions are off) followed by
+/// the request's own caption. The template is shortened to leave room for the request's
+/// caption within Telegram's limit.
+fn build_caption(template: Option<&str>, info: Option<&VideoInfo>, request: &VideoRequest) -> Option<String> {
+    let own = request.send_options.caption.as_deref().filter(|s| !s.is_empty());
+    let room = own.map_or(MAX_CAPTION_CHARS, |own| MAX_CAPTION_CHARS.saturating_sub(own.chars().count() + 2));
+    let rendered = template.map(|t| truncate_chars(&render_caption(t, info.unwrap_or(&VideoInfo::default()), &request.url), room));
+    let parts: Vec<&str> = rendered
+        .iter()
+        .map(|s| s.as_str())
+        .chain(own)
+        .filter(|s| !s.is_empty())
+        .collect();
+    (!parts.is_empty()).then(|| truncate_chars(&parts.join("\n\n"), MAX_CAPTION_CHARS))
+}
+
+fn is_group_chat(msg: &Message) -> bool {
+    msg.chat.is_group() || msg.chat.is_supergroup()
+}
+
 fn get_localized_ad_button_text(lang_code: Option<&str>) -> &'static str {
     match lang_code {
         Some("ru") => "🚀 Скачать видео (Бесплатно)",
@@ -97,16 +186,400 @@ async fn get_subscription_required(
     Ok(result)
 }
 
+/// Whether the subscription requirement keeps `user_id` from downloading. Admins are exempt.
+pub(crate) async fn missing_subscription(bot: &Bot, db_pool: &DatabasePool, user_id: i64) -> bool {
+    get_subscription_required(db_pool).await.unwrap_or(true)
+        && !is_admin_id(user_id)
+        && !check_subscription(bot, user_id).await.unwrap_or(false)
+}
+
+/// Re-sends a cached upload for this URL + quality by its Telegram reference, skipping
+/// yt-dlp and ffmpeg entirely. Returns true when the user got the media. A reference that
+/// Telegram rejects is dropped from the cache so the caller falls back to a fresh download.
+async fn try_send_cached(
+    bot: &Bot,
+    db_pool: &DatabasePool,
+    mtproto_uploader: &MTProtoUploader,
+    request: &VideoRequest,
+    quality: &str,
+    caption_template: Option<&str>,
+) -> bool {
+    let (url, chat_id) = (request.url.as_str(), request.chat_id);
+    let cached = match db_pool.get_cached_media(url, quality).await {
+        Ok(Some(cached)) => cached,
+        Ok(None) => return false,
+        Err(e) => {
+            log::warn!("Media cache lookup failed for {}: {}", url, e);
+            return false;
+        }
+    };
+    let options = &SendOptions {
+        caption: build_caption(caption_template, cached.info.as_ref(), request),
+        ..request.send_options.clone()
+    };
+
+    let result: Result<(), String> = if cached.source == SOURCE_MTPROTO {
+        match DocumentRef::decode(&cached.file_id) {
+            Some(doc) => mtproto_uploader
+                .send_cached_document(chat_id.0, request.username.clone(), &doc, options)
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("malformed document reference".to_string()),
+        }
+    } else {
+        let file = InputFile::file_id(FileId(cached.file_id.clone()));
+        let reply = options.reply_to.map(|id| ReplyParameters::new(MessageId(id)).allow_sending_without_reply());
+        if cached.media_type == "video_note" {
+            let mut req = bot.send_video_note(chat_id, file).disable_notification(options.silent);
+            if let Some(r) = reply { req = req.reply_parameters(r); }
+            match req.await {
+                Ok(_) => {
+                    send_detached_caption(bot, chat_id, options).await;
+                    Ok(())
+                }
+                Err(e) => Err(e.to_string()),
+            }
+        } else if cached.media_type == "animation" {
+            let mut req = bot.send_animation(chat_id, file).disable_notification(options.silent);
+            if let Some(c) = &options.caption { req = req.caption(c.clone()); }
+            if let Some(r) = reply { req = req.reply_parameters(r); }
+            req.await.map(|_| ()).map_err(|e| e.to_string())
+        } else if cached.media_type == "voice" {
+            let mut req = bot.send_voice(chat_id, file).disable_notification(options.silent);
+            if let Some(c) = &options.caption { req = req.caption(c.clone()); }
+            if let Some(r) = reply { req = req.reply_parameters(r); }
+            req.await.map(|_| ()).map_err(|e| e.to_string())
+        } else if cached.media_type == "audio" {
+            let mut req = bot.send_audio(chat_id, file).disable_notification(options.silent);
+            if let Some(c) = &options.caption { req = req.caption(c.clone()); }
+            if let Some(r) = reply { req = req.reply_parameters(r); }
+            req.await.map(|_| ()).map_err(|e| e.to_string())
+        } else {
+            let mut req = bot.send_video(chat_id, file).disable_notification(options.silent);
+            if let Some(c) = &options.caption { req = req.caption(c.clone()); }
+            if let Some(r) = reply { req = req.reply_parameters(r); }
+            req.await.map(|_| ()).map_err(|e| e.to_string())
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            log::info!("Served {} ({}) from media cache", url, quality);
+            true
+        }
+        Err(e) => {
+            log::warn!("Cached file for {} ({}) was rejected, invalidating: {}", url, quality, e);
+            let _ = db_pool.invalidate_cached_media(url, quality).await;
+            false
+        }
+    }
+}
+
+/// Video notes can't carry a caption, so it follows them as a message of its own.
+async fn send_detached_caption(bot: &Bot, chat_id: ChatId, options: &SendOptions) {
+    if let Some(caption) = options.caption.as_deref().filter(|c| !c.is_empty())
+        && let Err(e) = bot.send_message(chat_id, caption).disable_notification(options.silent).await
+    {
+        log::warn!("Failed to send the caption of a video note: {}", e);
+    }
+}
+
+/// Sends a photo carousel as albums (Bot API first, MTProto if that fails), then the
+/// background music as a separate audio message. Caption and reply go to the album only.
+async fn deliver_slideshow(
+    bot: &Bot,
+    mtproto_uploader: &Arc<MTProtoUploader>,
+    fetcher: &YoutubeFetcher,
+    request: &VideoRequest,
+    show: &Slideshow,
+    stages: &Stages,
+    progress_bar: &ProgressBar,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let chat_id = request.chat_id;
+    let options = &request.send_options;
+    let album = {
+        let (token, images, options, mut progress_bar) = (bot.token().to_string(), show.images.clone(), options.clone(), progress_bar.clone());
+        stages
+            .run(Stage::BotApiUpload, async move { send_photo_album_botapi(&token, chat_id, &images, &options, &mut progress_bar).await })
+            .await
+    };
+    if let Err(e) = album {
+        log::warn!("Bot API album upload failed, sending the remaining photos over MTProto: {:?}", e);
+        // Photos that already went out aren't sent twice; the caption and reply went with them.
+        let rest_options = if e.sent == 0 { options.clone() } else { SendOptions { silent: options.silent, ..Default::default() } };
+        let rest = show.images[e.sent..].to_vec();
+        let (uploader, username, mut progress_bar) = (mtproto_uploader.clone(), request.username.clone(), progress_bar.clone());
+        stages
+            .run(Stage::MtprotoUpload, async move { uploader.upload_album(chat_id.0, username, &rest, &rest_options, &mut progress_bar).await })
+            .await?;
+    }
+
+    if let Some(audio) = &show.audio {
+        let music_options = SendOptions { silent: options.silent, ..Default::default() };
+        let music = fetcher.prepare_audio(audio, Some(&show.info)).await;
+        let _cover_guard = music.thumbnail.clone().map(TempFileGuard::new);
+        let sent = {
+            let (token, audio, music, music_options) = (bot.token().to_string(), audio.clone(), music.clone(), music_options.clone());
+            stages
+                .run(Stage::BotApiUpload, async move {
+                    send_audio_with_progress_botapi(&token, chat_id, &audio, &music, &music_options, &mut ProgressBar::new_silent()).await
+                })
+                .await
+        };
+        if let Err(e) = sent {
+            log::warn!("Bot API slideshow music upload failed, retrying over MTProto: {:?}", e);
+            let (uploader, username, audio) = (mtproto_uploader.clone(), request.username.clone(), audio.clone());
+            stages
+                .run(Stage::MtprotoUpload, async move {
+                    uploader.upload_audio(chat_id.0, username, &audio, &music, &music_options, &mut ProgressBar::new_silent()).await
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn log_download(db_pool: &DatabasePool, user_id: i64, url: &str) {
+    let video_url = url.to_string();
+    let _ = db_pool.execute_with_timeout(move |conn| {
+        conn.execute("INSERT OR IGNORE INTO users (telegram_id) VALUES (?1)", [user_id])?;
+        conn.execute("INSERT INTO downloads (user_telegram_id, video_url) VALUES (?1, ?2)", (user_id, video_url))?;
+        Ok(())
+    }).await;
+}
+
+
+/// Deletes the link message once its media was posted, if the group asked for it.
+async fn delete_source_message(bot: &Bot, request: &VideoRequest) {
+    if let Some(message_id) = request.delete_after
+        && let Err(e) = bot.delete_message(request.chat_id, message_id).await
+    {
+        log::warn!("Could not delete link message in {}: {}", request.chat_id, e);
+    }
+}
+
+/// Text searched for links: the message text or media caption (forwarded posts included),
+/// followed by the targets of `text_link` entities, which are hidden behind other words.
+pub fn message_link_text(msg: &Message) -> Option<String> {
+    let (text, entities) = match msg.text() {
+        Some(text) => (text, msg.entities()),
+        None => (msg.caption()?, msg.caption_entities()),
+    };
+    let mut combined = text.to_string();
+    for entity in entities.unwrap_or_default() {
+        if let MessageEntityKind::TextLink { url } = &entity.kind {
+            combined.push('\n');
+            combined.push_str(url.as_str());
+        }
+    }
+    Some(combined)
+}
+
 pub async fn link_handler(
     bot: Bot,
     msg: Message,
     fetcher: Arc<YoutubeFetcher>,
-    mtproto_uploader: Arc<MTProtoUploader>,
     db_pool: Arc<DatabasePool>,
-    task_manager: Arc<tokio::sync::Mutex<TaskManager>>,
-    upload_semaphore: Arc<tokio::sync::Semaphore>,
+    job_queue: Arc<JobQueue>,
+    token: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(text) = message_link_text(&msg) else {
+        return Ok(());
+    };
+    if is_menu_button(&text) {
+        return Ok(());
+    }
+
+    handle_links(bot, &msg, vec![text], None, None, fetcher, db_pool, job_queue, &token).await?;
+    Ok(())
+}
+
+/// `/dl`: downloads the links given after the command and those in the replied-to message.
+pub async fn dl_command_handler(
+    bot: Bot,
+    msg: Message,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+    token: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let texts: Vec<String> = std::iter::once(&msg)
+        .chain(msg.reply_to_message())
+        .filter_map(message_link_text)
+        .collect();
+
+    let found = handle_links(bot.clone(), &msg, texts, None, None, fetcher, db_pool, job_queue, &token).await?;
+    if !found {
+        bot.send_message(msg.chat.id, "Reply to a message that contains a link with /dl, or send /dl <link>.")
+            .reply_parameters(ReplyParameters::new(msg.id).allow_sending_without_reply())
+            .await?;
+    }
+    Ok(())
+}
+
+/// `/clip <link> 1:20-2:05`: downloads part of a video. The link can also come from the
+/// replied-to message.
+pub async fn clip_command_handler(
+    bot: Bot,
+    msg: Message,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+    token: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let range = msg.text().and_then(|text| text.split_whitespace().skip(1).find_map(ClipRange::parse));
+    let texts: Vec<String> = std::iter::once(&msg)
+        .chain(msg.reply_to_message())
+        .filter_map(message_link_text)
+        .collect();
+
+    let found = match range {
+        Some(range) => {
+            handle_links(bot.clone(), &msg, texts, Some(range), None, fetcher, db_pool, job_queue, &token).await?
+        }
+        None => false,
+    };
+    if !found {
+        bot.send_message(msg.chat.id, "Send /clip <link> 1:20-2:05, or reply to a message that contains a link with /clip 1:20-2:05.")
+            .reply_parameters(ReplyParameters::new(msg.id).allow_sending_without_reply())
+            .await?;
+    }
+    Ok(())
+}
+
+/// `/subs [burn] <link>`: downloads a video with subtitles in the sender's language, as an
+/// .srt file or, with `burn`, rendered into the video. The link can also come from the
+/// replied-to message.
+pub async fn subs_command_handler(
+    bot: Bot,
+    msg: Message,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+    token: CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let user_id = msg.chat.id.0;
+    let burn = msg.text().is_some_and(|text| text.split_whitespace().skip(1).any(|w| w.eq_ignore_ascii_case("burn")));
+    let mode = if burn { SubtitleMode::Burn } else { SubtitleMode::File };
+    let texts: Vec<String> = std::iter::once(&msg)
+        .chain(msg.reply_to_message())
+        .filter_map(message_link_text)
+        .collect();
+
+    let found = handle_links(bot.clone(), &msg, texts, None, Some(mode), fetcher, db_pool, job_queue, &token).await?;
+    if !found {
+        bot.send_message(msg.chat.id, "Send /subs <link> for an .srt file or /subs burn <link> for subtitles in the video, or reply to a message that contains a link.")
+            .reply_parameters(ReplyParameters::new(msg.id).allow_sending_without_reply())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Sends the post's subtitles as an .srt document after its video.
+async fn send_subtitle_file(bot: &Bot, fetcher: &YoutubeFetcher, request: &VideoRequest, fingerprint: Option<String>) {
+    let stem = fetcher.output_dir.join(Uuid::new_v4().to_string()).to_string_lossy().into_owned();
+    let path = match fetcher.fetch_subtitles(&request.url, &stem, request.language.as_deref(), fingerprint).await {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            let _ = bot.send_message(request.chat_id, "💬 This video has no subtitles in your language or English.").await;
+            return;
+        }
+        Err(e) => {
+            log::warn!("Failed to fetch subtitles of {}: {}", request.url, e);
+            let _ = bot.send_message(request.chat_id, "💬 Could not download the subtitles.").await;
+            return;
+        }
+    };
+    let _guard = TempFileGuard::new(path.clone());
+    let mut req = bot.send_document(request.chat_id, InputFile::file(path)).disable_notification(request.send_options.silent);
+    if let Some(id) = request.send_options.reply_to {
+        req = req.reply_parameters(ReplyParameters::new(MessageId(id)).allow_sending_without_reply());
+    }
+    if let Err(e) = req.await {
+        log::warn!("Failed to send the subtitles of {}: {}", request.url, e);
+    }
+}
+
+/// Whether `user_id` has to watch an ad before each download.
+pub(crate) async fn ads_enabled(db_pool: &DatabasePool, user_id: i64, is_user_admin: bool, is_premium: bool) -> bool {
+    let module_enabled = std::env::var("MONETAG_MODULE_ENABLED").map(|v| v.to_lowercase() == "true").unwrap_or(true);
+    let global_ads = db_pool.get_setting("ads_enabled").await.map(|val| val == "true").unwrap_or(true);
+    let is_test_mode = std::env::var("TEST_MODE").map(|v| v.to_lowercase() == "true").unwrap_or(false);
+    let admin_ads = db_pool.get_setting("admin_ads_enabled").await.map(|val| val == "true").unwrap_or(false);
+
+    if is_user_admin && (admin_ads || is_test_mode) {
+        log::info!("Ads enabled for admin (forced by setting or test mode)");
+        true
+    } else if !module_enabled || !global_ads {
+        log::info!("Ads disabled globally or by module flag");
+        false
+    } else if is_user_admin {
+        // Admin but has personal ads OFF and not in test mode
+        false
+    } else if is_premium {
+        log::info!("Ads disabled: User {} has Premium", user_id);
+        false
+    } else {
+        true
+    }
+}
+
+/// Request for `url` posted by `sender` in the chat of `msg`, with the group's settings
+/// applied; `subtitles` overrides the subtitle mode.
+fn build_request(
+    url: String,
+    msg: &Message,
+    sender: &User,
+    group_settings: Option<&GroupSettings>,
+    subtitles: Option<SubtitleMode>,
+) -> VideoRequest {
+    let mut request = VideoRequest::new(sender.id.0 as i64, msg.chat.id, url);
+    request.language = sender.language_code.clone();
+    if let Some(settings) = group_settings {
+        request.username = msg.chat.username().map(|s| s.to_string());
+        request.quality = Some(settings.quality.clone());
+        request.captions = Some(settings.captions);
+        // Somebody's voice-message setting shouldn't decide how a group gets its audio.
+        request.audio_format = Some(AudioFormat::Original);
+        request.subtitles = Some(SubtitleMode::Off);
+        request.send_options = SendOptions {
+            caption: settings.captions.then(|| requester_caption(sender)),
+            reply_to: Some(msg.id.0),
+            silent: settings.silent,
+        };
+        if settings.delete_links {
+            request.delete_after = Some(msg.id);
+        }
+    } else {
+        request.username = msg.chat.username().map(|s| s.to_string()).or_else(|| sender.username.clone());
+    }
+    if subtitles.is_some() {
+        request.subtitles = subtitles;
+    }
+    request
+}
+
+/// Shared entry for links found in `texts`, requested by the sender of `msg` in its chat.
+/// A single link is cut to `clip`, or to the range written after it; `subtitles` overrides
+/// the subtitle mode of every link. Returns false when the texts contain no supported link.
+#[allow(clippy::too_many_arguments)]
+async fn handle_links(
+    bot: Bot,
+    msg: &Message,
+    texts: Vec<String>,
+    clip: Option<ClipRange>,
+    subtitles: Option<SubtitleMode>,
+    fetcher: Arc<YoutubeFetcher>,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+    token: &CancellationToken,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    // Ads, premium and subscriptions belong to whoever posted the link, not to the chat
+    // (they only differ in groups).
+    let Some(sender) = msg.from.clone() else {
+        return Ok(false);
+    };
+    let user_id = sender.id.0 as i64;
+    let in_group = is_group_chat(msg);
 
     // Update user activity
     let _ = db_pool.execute_with_timeout(move |conn| {
@@ -115,162 +588,526 @@ pub async fn link_handler(
         Ok(())
     }).await;
 
-    let text = match msg.text() {
-        Some(text) => text,
-        None => return Ok(()),
+    let group_settings = if in_group {
+        Some(db_pool.get_group_settings(msg.chat.id.0).await.unwrap_or_default())
+    } else {
+        None
     };
 
-    if is_menu_button(text) {
-        return Ok(());
+    let mut disabled_platforms = db_pool.get_disabled_platforms().await;
+    if let Some(settings) = &group_settings {
+        disabled_platforms.extend(settings.disabled_platforms.iter().cloned());
     }
+    let is_user_admin = is_admin(msg).await;
+    let is_premium = db_pool.is_user_premium(user_id).await;
+    let ads_enabled = ads_enabled(&db_pool, user_id, is_user_admin, is_premium).await;
 
-    let url = match extract_url_from_text(text) {
-        Some(url) => url,
-        None => return Ok(()),
-    };
+    // Profiles and playlists become a batch of their latest posts.
+    if let Some(collection) = texts.iter().find_map(|text| find_collection(text, &disabled_platforms)) {
+        if ads_enabled {
+            // Every post would need its own ad view; batches are a Premium perk instead.
+            let lang = sender.language_code.as_deref();
+            let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+                get_localized_premium_button_text(lang),
+                "buy_premium",
+            )]]);
+            bot.send_message(msg.chat.id, "📚 Downloading profiles and playlists is available with Premium.")
+                .reply_markup(keyboard)
+                .await?;
+            return Ok(true);
+        }
+        let request = build_request(collection.url.clone(), msg, &sender, group_settings.as_ref(), subtitles);
+        crate::handlers::collection::offer_collection(&bot, &fetcher, db_pool, request, collection).await?;
+        return Ok(true);
+    }
+
+    // Short links are resolved here, so everything downstream (dedupe, cache, pending
+    // downloads, stats) sees one canonical URL per post.
+    let mut found_urls: Vec<String> = Vec::new();
+    for text in &texts {
+        for (_, url) in crate::platforms::registry().find_urls(text, &disabled_platforms) {
+            let url = canonical_url(&url).await;
+            if !found_urls.contains(&url) {
+                found_urls.push(url);
+            }
+        }
+    }
+    if found_urls.is_empty() {
+        return Ok(false);
+    }
+    if found_urls.len() > MAX_BATCH_LINKS {
+        bot.send_message(msg.chat.id, format!("⚠️ Only the first {} links are downloaded.", MAX_BATCH_LINKS)).await?;
+        found_urls.truncate(MAX_BATCH_LINKS);
+    }
 
     // Deduplication
-    {
-        let mut urls = URL_PROCESSING.lock().await;
-        if urls.contains(&url) {
+    let urls: Vec<String> = {
+        let mut processing = URL_PROCESSING.lock().await;
+        let fresh: Vec<String> = found_urls.into_iter().filter(|url| !processing.contains(url)).collect();
+        processing.extend(fresh.iter().cloned());
+        fresh
+    };
+    // Ranges only make sense for one link; with several it's unclear which one is meant.
+    let clip = clip
+        .or_else(|| texts.iter().find_map(|text| find_clip_range(text)))
+        .filter(|_| urls.len() == 1);
+    if urls.is_empty() {
+        // Groups often repost the same link; don't answer every copy.
+        if !in_group {
             bot.send_message(msg.chat.id, "⏳ This video is already being processed.").await?;
-            return Ok(());
         }
-        urls.insert(url.clone());
+        return Ok(true);
     }
 
     // Mini App Ad invitation logic
-    let is_user_admin = is_admin(&msg).await;
-    let is_premium = db_pool.is_user_premium(user_id as i64).await;
-
-    let ads_enabled = {
-        let module_enabled = std::env::var("MONETAG_MODULE_ENABLED").map(|v| v.to_lowercase() == "true").unwrap_or(true);
-        let global_ads = db_pool.get_setting("ads_enabled").await.map(|val| val == "true").unwrap_or(true);
-        let is_test_mode = std::env::var("TEST_MODE").map(|v| v.to_lowercase() == "true").unwrap_or(false);
-        let admin_ads = db_pool.get_setting("admin_ads_enabled").await.map(|val| val == "true").unwrap_or(false);
-
-        if is_user_admin && (admin_ads || is_test_mode) {
-            log::info!("Ads enabled for admin (forced by setting or test mode)");
-            true
-        } else if !module_enabled || !global_ads {
-            log::info!("Ads disabled globally or by module flag");
-            false
-        } else if is_user_admin {
-            // Admin but has personal ads OFF and not in test mode
-            false
-        } else if is_premium {
-            log::info!("Ads disabled: User {} has Premium", user_id);
-            false
-        } else {
-            true
-        }
-    };
-
     if ads_enabled {
         let webapp_url = std::env::var("WEBAPP_URL").unwrap_or_default();
         if !webapp_url.is_empty() {
             if let Ok(url_obj) = webapp_url.parse::<reqwest::Url>() {
-                let ymid = match db_pool.create_pending_download(user_id as i64, &url).await {
-                    Ok(id) => id,
-                    Err(e) => {
-                        log::error!("Failed to create pending download: {}", e);
-                        bot.send_message(msg.chat.id, "❌ Error initializing download.").await?;
-                        return Ok(());
-                    }
-                };
+                // Every link needs its own verified ad view, so each gets its own offer.
+                let mut sent_privately = true;
+                for url in &urls {
+                    // The range travels with the stored link until the ad is watched.
+                    let stored_url = match clip {
+                        Some(range) => range.tag_url(url),
+                        None => url.clone(),
+                    };
+                    let ymid = match db_pool.create_pending_download(user_id, &stored_url).await {
+                        Ok(id) => id,
+                        Err(e) => {
+                            log::error!("Failed to create pending download: {}", e);
+                            bot.send_message(msg.chat.id, "❌ Error initializing download.").await?;
+                            break;
+                        }
+                    };
+
+                    let mut final_url = url_obj.clone();
+                    final_url.query_pairs_mut().append_pair("ymid", &ymid);
+
+                    let lang = sender.language_code.as_deref();
 
-                let mut final_url = url_obj;
-                final_url.query_pairs_mut().append_pair("ymid", &ymid);
+                    let ad_btn_text = get_localized_ad_button_text(lang);
+                    let prem_btn_text = get_localized_premium_button_text(lang);
+                    let choice_text = get_localized_choice_text(lang);
 
-                let lang = msg.from.as_ref().and_then(|u| u.language_code.as_deref());
-                
-                let ad_btn_text = get_localized_ad_button_text(lang);
-                let prem_btn_text = get_localized_premium_button_text(lang);
-                let choice_text = get_localized_choice_text(lang);
+                    let keyboard = InlineKeyboardMarkup::new(vec![
+                        vec![InlineKeyboardButton::web_app(ad_btn_text, WebAppInfo { url: final_url })],
+                        vec![InlineKeyboardButton::callback(prem_btn_text, "buy_premium")],
+                    ]);
 
-                let keyboard = InlineKeyboardMarkup::new(vec![
-                    vec![InlineKeyboardButton::web_app(ad_btn_text, WebAppInfo { url: final_url })],
-                    vec![InlineKeyboardButton::callback(prem_btn_text, "buy_premium")],
-                ]);
+                    if in_group {
+                        // Mini App buttons only work in private chats: offer the download to the
+                        // sender directly; the claimed video is delivered there too.
+                        sent_privately &= bot.send_message(sender.id, choice_text)
+                            .reply_markup(keyboard)
+                            .await
+                            .is_ok();
+                    } else {
+                        // Send a friendly choice message instead of an invoice
+                        let _ = bot.send_message(msg.chat.id, choice_text)
+                            .reply_markup(keyboard)
+                            .await;
+                    }
+                }
 
-                // Send a friendly choice message instead of an invoice
-                let _ = bot.send_message(msg.chat.id, choice_text)
-                    .reply_markup(keyboard)
-                    .await;
+                if in_group {
+                    let reply = if sent_privately {
+                        "📩 I sent you the download in a private message.".to_string()
+                    } else {
+                        "👋 Start the bot in a private chat to download this link, or get Premium to receive videos right here.".to_string()
+                    };
+                    let mut req = bot.send_message(msg.chat.id, reply)
+                        .reply_parameters(ReplyParameters::new(msg.id).allow_sending_without_reply());
+                    if !sent_privately && let Ok(me) = bot.get_me().await {
+                        req = req.reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                            InlineKeyboardButton::url("🤖 Open bot", me.tme_url()),
+                        ]]));
+                    }
+                    let _ = req.await;
+                }
 
                 // Stop processing
                 {
-                    let mut urls = URL_PROCESSING.lock().await;
-                    urls.remove(&url);
+                    let mut processing = URL_PROCESSING.lock().await;
+                    for url in &urls {
+                        processing.remove(url);
+                    }
                 }
-                return Ok(());
+                return Ok(true);
             }
         }
     }
 
+    let requests: Vec<VideoRequest> = urls
+        .into_iter()
+        .map(|url| VideoRequest { clip, ..build_request(url, msg, &sender, group_settings.as_ref(), subtitles) })
+        .collect();
+
+    // Users who pick a format per link get a picker instead of an immediate download.
+    if !in_group
+        && let [request] = requests.as_slice()
+        && !crate::platforms::registry().for_url(&request.url).is_some_and(|p| p.audio_only())
+        && db_pool.get_user_format_picker(user_id).await
+        && crate::handlers::format_picker::offer_formats(&bot, &fetcher, db_pool.clone(), request.clone()).await?
+    {
+        URL_PROCESSING.lock().await.remove(&request.url);
+        return Ok(true);
+    }
+
     // Proceed to download
-    process_video_request(
-        bot,
-        user_id as i64,
-        url,
-        fetcher,
-        mtproto_uploader,
-        db_pool,
-        task_manager,
-        upload_semaphore,
-        msg.chat.username().map(|s| s.to_string()).or_else(|| msg.from.as_ref().and_then(|u| u.username.clone())),
-        msg.chat.id,
-    ).await
+    if requests.len() == 1 {
+        let request = requests.into_iter().next().expect("one request");
+        if let Err(e) = job_queue.enqueue(&request).await {
+            log::error!("Failed to queue {}: {}", request.url, e);
+            URL_PROCESSING.lock().await.remove(&request.url);
+            bot.send_message(request.chat_id, "❌ Error initializing download.").await?;
+        }
+    } else {
+        let label = format!("{} links", requests.len());
+        process_batch(bot, requests, label, false, job_queue, token.clone()).await?;
+    }
+    Ok(true)
+}
+
+/// Lets the link be requested again when its processing ended without getting that far
+/// (cancelled, crashed or never started).
+pub(crate) async fn release_url(url: &str) {
+    URL_PROCESSING.lock().await.remove(url);
+}
+
+/// Marks links of jobs queued before a restart as in progress, so sending one again doesn't
+/// queue a second job; the job releases it when it ends.
+pub(crate) async fn claim_urls(urls: impl IntoIterator<Item = String>) {
+    URL_PROCESSING.lock().await.extend(urls);
+}
+
+fn batch_status_text(label: &str, done: usize, sent: usize, total: usize) -> String {
+    format!("📦 Downloading {}: {}/{} done, {} sent", label, done, total, sent)
 }
 
+/// Final status of a batch: how many were sent and which links failed.
+fn batch_summary_text(sent: usize, total: usize, failed: &[String]) -> String {
+    let mut text = format!("📦 Sent {} of {}.", sent, total);
+    if !failed.is_empty() {
+        text.push_str("\n\n❌ Failed:");
+        for url in failed {
+            text.push('\n');
+            text.push_str(url);
+        }
+    }
+    text
+}
+
+/// Queues several links at once and follows them with a single status message (`label` names
+/// what is downloaded) instead of a progress bar per link, ending with a summary. A fully
+/// sent batch just removes the status unless `keep_summary` is set. The link message is
+/// deleted (if requested) only after the last one. Cancelling `token` stops following the
+/// batch; its jobs stay queued.
+pub(crate) async fn process_batch(
+    bot: Bot,
+    mut requests: Vec<VideoRequest>,
+    label: String,
+    keep_summary: bool,
+    job_queue: Arc<JobQueue>,
+    token: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let total = requests.len();
+    let Some(first) = requests.first() else {
+        return Ok(());
+    };
+    let (chat_id, delete_after) = (first.chat_id, first.delete_after);
+    let status = bot.send_message(chat_id, batch_status_text(&label, 0, 0, total)).await?;
+
+    let mut queued = Vec::with_capacity(total);
+    for mut request in requests.drain(..) {
+        request.show_progress = false;
+        request.delete_after = None;
+        let outcome = match job_queue.enqueue_waiting(&request).await {
+            Ok(outcome) => Some(outcome),
+            Err(e) => {
+                log::error!("Failed to queue batch item {}: {}", request.url, e);
+                URL_PROCESSING.lock().await.remove(&request.url);
+                None
+            }
+        };
+        queued.push((request.url, outcome));
+    }
+
+    let mut sent = 0;
+    let mut failed = Vec::new();
+    for (idx, (url, outcome)) in queued.into_iter().enumerate() {
+        let delivered = match outcome {
+            Some(outcome) => tokio::select! {
+                delivered = outcome => delivered.unwrap_or(false),
+                _ = token.cancelled() => {
+                    let text = format!("⚠️ The bot is restarting, the rest of the {} will be sent after the restart.", label);
+                    let _ = bot.edit_message_text(chat_id, status.id, text).await;
+                    return Ok(());
+                }
+            },
+            None => false,
+        };
+        if delivered {
+            sent += 1;
+        } else {
+            failed.push(url);
+        }
+        let _ = bot.edit_message_text(chat_id, status.id, batch_status_text(&label, idx + 1, sent, total)).await;
+    }
+
+    if sent == total && !keep_summary {
+        let _ = bot.delete_message(chat_id, status.id).await;
+    } else {
+        let _ = bot.edit_message_text(chat_id, status.id, batch_summary_text(sent, total, &failed)).await;
+    }
+    if sent > 0 && let Some(message_id) = delete_after {
+        let _ = bot.delete_message(chat_id, message_id).await;
+    }
+    Ok(())
+}
+
+/// Caption of part `index` (0-based) of a split video; the post's caption goes on the first
+/// part, shortened to fit behind the label.
+fn part_caption(index: usize, total: usize, caption: Option<&str>) -> String {
+    let label = format!("Part {}/{}", index + 1, total);
+    match caption {
+        Some(caption) if index == 0 => {
+            let room = MAX_CAPTION_CHARS.saturating_sub(label.chars().count() + 2);
+            format!("{}\n\n{}", label, truncate_chars(caption, room))
+        }
+        _ => label,
+    }
+}
+
+/// Splits a video that can't be compressed reasonably into parts under `limit` and sends
+/// them in order, each captioned "Part i/N". Parts aren't cached. Returns whether every
+/// part was delivered.
+#[allow(clippy::too_many_arguments)]
+async fn deliver_in_parts(
+    bot: &Bot,
+    mtproto_uploader: &Arc<MTProtoUploader>,
+    fetcher: &Arc<YoutubeFetcher>,
+    request: &VideoRequest,
+    path: &std::path::Path,
+    limit: u64,
+    chapters: &[Chapter],
+    stages: &Stages,
+    progress_bar: &mut ProgressBar,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let chat_id = request.chat_id;
+    progress_bar.update(80, Some("✂️ Splitting into parts...")).await?;
+    let split = {
+        let (fetcher, path, chapters, mut progress_bar) = (fetcher.clone(), path.to_path_buf(), chapters.to_vec(), progress_bar.clone());
+        stages.run(Stage::Ffmpeg, async move { fetcher.split_video(&path, limit, &chapters, &mut progress_bar).await }).await
+    };
+    let parts = match split {
+        Ok(parts) => parts,
+        Err(e) => {
+            log::error!("Failed to split {}: {:?}", request.url, e);
+            progress_bar.delete().await?;
+            bot.send_message(chat_id, "❌ The video is too large to upload, even in parts.").await?;
+            return Ok(false);
+        }
+    };
+    let _guards: Vec<TempFileGuard> = parts.iter().cloned().map(TempFileGuard::new).collect();
+
+    let total = parts.len();
+    for (idx, part) in parts.iter().enumerate() {
+        let options = SendOptions {
+            caption: Some(part_caption(idx, total, request.send_options.caption.as_deref())),
+            ..request.send_options.clone()
+        };
+        let (part, mut part_progress) = (part.clone(), progress_bar.for_part(idx, total));
+        let res = if fs::metadata(&part)?.len() > TELEGRAM_BOT_API_FILE_LIMIT {
+            let (uploader, username) = (mtproto_uploader.clone(), request.username.clone());
+            stages
+                .run(Stage::MtprotoUpload, async move { uploader.upload_video(chat_id.0, username, &part, &options, &mut part_progress).await })
+                .await
+                .map(|_| ())
+        } else {
+            let token = bot.token().to_string();
+            stages
+                .run(Stage::BotApiUpload, async move {
+                    send_video_with_progress_botapi(&token, chat_id, &part, &options, &mut part_progress).await
+                })
+                .await
+                .map(|_| ())
+                .map_err(|e| e.into())
+        };
+        if let Err(e) = res {
+            log::error!("Failed to upload part {}/{} of {}: {:?}", idx + 1, total, request.url, e);
+            progress_bar.delete().await?;
+            bot.send_message(chat_id, format!("❌ Upload failed at part {}/{}.", idx + 1, total)).await?;
+            return Ok(false);
+        }
+    }
+
+    progress_bar.update(100, Some("✅ Done!")).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    progress_bar.delete().await?;
+    delete_source_message(bot, request).await;
+    Ok(true)
+}
+
+/// Largest file the bot uploads: MTProto's 2 GB, or less with MAX_UPLOAD_MB (e.g. 48 to keep
+/// every upload on the Bot API). Bigger videos are compressed first.
+fn max_upload_size() -> u64 {
+    std::env::var("MAX_UPLOAD_MB")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|mb| *mb > 0)
+        .map(|mb| mb.saturating_mul(1024 * 1024).min(MTPROTO_FILE_LIMIT))
+        .unwrap_or(MTPROTO_FILE_LIMIT)
+}
+
+/// Downloads and delivers one link. Returns whether the media reached the chat. Each step
+/// runs on a worker of its [`Stage`].
 pub async fn process_video_request(
     bot: Bot,
-    user_id: i64,
-    url: String,
+    mut request: VideoRequest,
     fetcher: Arc<YoutubeFetcher>,
     mtproto_uploader: Arc<MTProtoUploader>,
     db_pool: Arc<DatabasePool>,
-    _task_manager: Arc<tokio::sync::Mutex<TaskManager>>,
-    upload_semaphore: Arc<tokio::sync::Semaphore>,
-    username: Option<String>,
-    chat_id: ChatId,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Get user quality preference
-    let quality_preference = db_pool.get_user_quality(user_id).await.unwrap_or_else(|_| "best".to_string());
+    stages: Arc<Stages>,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let (user_id, chat_id, url) = (request.user_id, request.chat_id, request.url.clone());
+
+    // Quality: forced by the request (groups) or the user's preference; audio-only
+    // platforms always download audio.
+    let audio_only_platform = crate::platforms::registry().for_url(&url).is_some_and(|p| p.audio_only());
+    let quality_preference = if audio_only_platform {
+        "audio".to_string()
+    } else if let Some(quality) = &request.quality {
+        quality.clone()
+    } else {
+        db_pool.get_user_quality(user_id).await.unwrap_or_else(|_| "best".to_string())
+    };
     let fingerprint = crate::handlers::fingerprint::get_current_fingerprint(db_pool.clone()).await;
     let is_audio = quality_preference == "audio";
+    let audio_format = match request.audio_format {
+        Some(format) => format,
+        None if is_audio => db_pool.get_user_audio_format(user_id).await,
+        None => AudioFormat::Original,
+    };
+    let video_mode = VideoMode::from_quality(&quality_preference);
+    // Subtitles only go with regular videos.
+    let subtitle_mode = match request.subtitles {
+        _ if is_audio || video_mode.is_some() => SubtitleMode::Off,
+        Some(mode) => mode,
+        None => db_pool.get_user_subtitles(user_id).await,
+    };
+    // Audio is cached per output format; the original format keeps the plain "audio" key.
+    // Clips are cached per range.
+    let mut quality_key = if is_audio { audio_format.cache_key() } else { quality_preference.clone() };
+    if let Some(range) = request.clip {
+        quality_key.push_str(&range.cache_suffix());
+    }
+    // Videos with burned-in subtitles are cached per language.
+    let unsubtitled_key = quality_key.clone();
+    if subtitle_mode == SubtitleMode::Burn {
+        let language = request.language.as_deref().unwrap_or(FALLBACK_SUBTITLE_LANGUAGE);
+        quality_key.push_str(&format!("+subs:{}", language.to_lowercase()));
+    }
+    let render_slideshows = !is_audio
+        && db_pool.get_user_slideshow_mode(user_id).await.map(|m| m == SLIDESHOW_VIDEO).unwrap_or(false);
+    let captions = match request.captions {
+        Some(captions) => captions,
+        None => db_pool.get_user_captions(user_id).await,
+    };
+    let caption_template = if captions { Some(db_pool.get_caption_template().await) } else { None };
+    let caption_template = caption_template.as_deref();
 
-    // Acquire upload permit
-    let _permit = upload_semaphore.acquire().await.map_err(|e| anyhow::anyhow!("Semaphore error: {}", e))?;
+    if missing_subscription(&bot, &db_pool, user_id).await {
+        bot.send_message(chat_id, "To use the bot, please subscribe to our channels.").await?;
+        {
+            let mut urls = URL_PROCESSING.lock().await;
+            urls.remove(&url);
+        }
+        return Ok(false);
+    }
 
-    let subscription_required = get_subscription_required(&db_pool).await.unwrap_or(true);
-    if subscription_required {
-        if !check_subscription(&bot, user_id).await.unwrap_or(false) {
-            let admins: Vec<i64> = std::env::var("ADMIN_IDS").unwrap_or_default()
-                .split(',').filter_map(|s| s.trim().parse().ok()).collect();
+    // The clip limit holds for cached clips too, so the length is checked before the fast path.
+    // Admins get the Premium limit.
+    let clip_premium = db_pool.is_user_premium(user_id).await || is_admin_id(user_id);
+    if let Some(range) = request.clip
+        && let Err(e) = range.validate(None, max_clip_seconds(clip_premium), clip_premium)
+    {
+        bot.send_message(chat_id, format!("❌ {}", e)).await?;
+        {
+            let mut urls = URL_PROCESSING.lock().await;
+            urls.remove(&url);
+        }
+        return Ok(false);
+    }
 
-            if !admins.contains(&user_id) {
-                bot.send_message(chat_id, "To use the bot, please subscribe to our channels.").await?;
-                {
-                    let mut urls = URL_PROCESSING.lock().await;
-                    urls.remove(&url);
-                }
-                return Ok(());
+    // Fast path: this exact URL + quality was already uploaded, re-send it by file_id.
+    // Rendered slideshows are cached under their own key so album users never get the video.
+    let sent_cached = (render_slideshows
+        && try_send_cached(&bot, &db_pool, &mtproto_uploader, &request, SLIDESHOW_VIDEO_CACHE_KEY, caption_template).await)
+        || try_send_cached(&bot, &db_pool, &mtproto_uploader, &request, &quality_key, caption_template).await;
+    if sent_cached {
+        if subtitle_mode == SubtitleMode::File {
+            send_subtitle_file(&bot, &fetcher, &request, fingerprint.clone()).await;
+        }
+        log_download(&db_pool, user_id, &url).await;
+        delete_source_message(&bot, &request).await;
+        {
+            let mut urls = URL_PROCESSING.lock().await;
+            urls.remove(&url);
+        }
+        return Ok(true);
+    }
+
+    // Clips are checked against the post's length before anything is downloaded. A duration
+    // yt-dlp can't probe (e.g. tikwm-only posts) skips the check.
+    if let Some(range) = request.clip {
+        let duration = fetcher.probe_duration(&url, fingerprint.clone()).await.unwrap_or_else(|e| {
+            log::warn!("Could not probe the duration of {}: {}", url, e);
+            None
+        });
+        if let Err(e) = range.validate(duration, max_clip_seconds(clip_premium), clip_premium) {
+            bot.send_message(chat_id, format!("❌ {}", e)).await?;
+            {
+                let mut urls = URL_PROCESSING.lock().await;
+                urls.remove(&url);
             }
+            return Ok(false);
         }
     }
 
-    let mut progress_bar = ProgressBar::new(bot.clone(), chat_id);
-    progress_bar.start("🎬 Starting...").await?;
-    progress_bar.update(5, Some("⬇️ Downloading...")).await?;
+    let mut progress_bar = if request.show_progress {
+        let mut progress_bar = match (request.status_message, request.job_id) {
+            (Some(message_id), Some(job_id)) => ProgressBar::for_message(bot.clone(), chat_id, message_id, Some(cancel_keyboard(job_id))),
+            _ => ProgressBar::new(bot.clone(), chat_id),
+        };
+        progress_bar.start("🎬 Starting...").await?;
+        progress_bar.update(5, Some("⬇️ Downloading...")).await?;
+        progress_bar
+    } else {
+        ProgressBar::new_silent()
+    };
 
     let mut retries = 0;
+    let file_stem = format!("output/{}", Uuid::new_v4());
     let download_result = loop {
-        let file_stem = format!("output/{}", Uuid::new_v4());
-        let fut = fetcher.download_video_from_url(url.clone(), &file_stem, &quality_preference, fingerprint.clone(), &mut progress_bar);
+        let quality = download_quality(&quality_preference).to_string();
+        let (fetcher, url, file_stem, fingerprint, clip) =
+            (fetcher.clone(), url.clone(), file_stem.clone(), fingerprint.clone(), request.clip);
+        let mut progress_bar = progress_bar.clone();
+        let fut = async move {
+            match clip {
+                Some(range) => fetcher
+                    .download_clip(url, &file_stem, &quality, fingerprint, range, &mut progress_bar)
+                    .await
+                    .map(Downloaded::Media),
+                None => fetcher.download(url, &file_stem, &quality, fingerprint, &mut progress_bar).await,
+            }
+        };
 
-        match timeout(DOWNLOAD_TIMEOUT, fut).await {
-            Ok(Ok(path)) => break Ok(path),
+        // Each attempt is its own step, so no worker waits out the backoff between them.
+        let attempt = stages.run(Stage::Download, timeout(DOWNLOAD_TIMEOUT, fut)).await;
+        match attempt {
+            Ok(Ok(downloaded)) => break Ok(downloaded),
             Ok(Err(e)) => {
                 retries += 1;
                 if retries >= 3 { break Err(e); }
@@ -283,9 +1120,70 @@ pub async fn process_video_request(
             }
         }
     };
+    if download_result.is_ok() && let Some(job_id) = request.job_id {
+        let _ = db_pool.set_job_status(job_id, JobStatus::Uploading).await;
+    }
 
+    // Taken even when the download failed, so no info file is left behind.
+    let ytdlp_info = fetcher.take_video_info(&file_stem).await;
+    let info = match &download_result {
+        Ok(Downloaded::Slideshow(show)) => Some(show.info.clone()),
+        _ => ytdlp_info,
+    };
+    request.send_options.caption = build_caption(caption_template, info.as_ref(), &request);
+    let send_options = &request.send_options;
+
+    let mut cache_quality = quality_key;
     let path = match download_result {
-        Ok(p) => p,
+        Ok(Downloaded::Media(p)) => p,
+        Ok(Downloaded::Slideshow(show)) if render_slideshows => {
+            let _guards = show.guards();
+            let rendered = {
+                let (fetcher, file_stem, mut progress_bar) = (fetcher.clone(), file_stem.clone(), progress_bar.clone());
+                stages
+                    .run(Stage::Ffmpeg, async move {
+                        fetcher.render_slideshow_video(&show, &file_stem, &SlideshowRenderOptions::from_env(), &mut progress_bar).await
+                    })
+                    .await
+            };
+            match rendered {
+                Ok(p) => {
+                    cache_quality = SLIDESHOW_VIDEO_CACHE_KEY.to_string();
+                    p
+                }
+                Err(e) => {
+                    log::error!("Failed to render slideshow {}: {:?}", url, e);
+                    progress_bar.delete().await?;
+                    bot.send_message(chat_id, "❌ Error: could not render the slideshow video.").await?;
+                    {
+                        let mut urls = URL_PROCESSING.lock().await;
+                        urls.remove(&url);
+                    }
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(Downloaded::Slideshow(show)) => {
+            let _guards = show.guards();
+            let delivered = match deliver_slideshow(&bot, &mtproto_uploader, &fetcher, &request, &show, &stages, &progress_bar).await {
+                Ok(()) => {
+                    log_download(&db_pool, user_id, &url).await;
+                    delete_source_message(&bot, &request).await;
+                    true
+                }
+                Err(e) => {
+                    log::error!("Failed to deliver slideshow {}: {:?}", url, e);
+                    progress_bar.delete().await?;
+                    bot.send_message(chat_id, "❌ Upload failed.").await?;
+                    false
+                }
+            };
+            {
+                let mut urls = URL_PROCESSING.lock().await;
+                urls.remove(&url);
+            }
+            return Ok(delivered);
+        }
         Err(e) => {
             progress_bar.delete().await?;
             bot.send_message(chat_id, format!("❌ Error: {}", e)).await?;
@@ -293,35 +1191,214 @@ pub async fn process_video_request(
                 let mut urls = URL_PROCESSING.lock().await;
                 urls.remove(&url);
             }
-            return Ok(());
+            return Ok(false);
         }
     };
 
     let _guard = TempFileGuard::new(path.clone());
+
+    // Video notes and animations are re-encoded from the download; rendered slideshows stay videos.
+    let video_mode = video_mode.filter(|_| cache_quality != SLIDESHOW_VIDEO_CACHE_KEY);
+    let (path, _mode_guard) = match video_mode {
+        Some(mode) => {
+            let text = if mode == VideoMode::VideoNote { "⭕ Making a video note..." } else { "🔁 Making a GIF..." };
+            progress_bar.update(80, Some(text)).await?;
+            let (fetcher, input) = (fetcher.clone(), path.clone());
+            match stages.run(Stage::Ffmpeg, async move { fetcher.apply_video_mode(&input, mode).await }).await {
+                Ok(converted) => (converted.clone(), Some(TempFileGuard::new(converted))),
+                Err(e) => {
+                    log::error!("Failed to make a {} of {}: {:?}", mode.media_type(), url, e);
+                    progress_bar.delete().await?;
+                    bot.send_message(chat_id, "❌ Error: could not convert the video.").await?;
+                    {
+                        let mut urls = URL_PROCESSING.lock().await;
+                        urls.remove(&url);
+                    }
+                    return Ok(false);
+                }
+            }
+        }
+        None => (path, None),
+    };
+
+    // Burned-in subtitles; without any in the user's language or English the video goes out as is.
+    let burn = subtitle_mode == SubtitleMode::Burn && cache_quality != SLIDESHOW_VIDEO_CACHE_KEY;
+    let (path, _subtitled_guard) = if burn {
+        progress_bar.update(80, Some("💬 Adding subtitles...")).await?;
+        let burned = match fetcher.fetch_subtitles(&url, &file_stem, request.language.as_deref(), fingerprint.clone()).await {
+            Ok(Some(subtitles)) => {
+                let _subtitles_guard = TempFileGuard::new(subtitles.clone());
+                let (fetcher, input) = (fetcher.clone(), path.clone());
+                stages.run(Stage::Ffmpeg, async move { fetcher.burn_subtitles(&input, &subtitles).await }).await.map(Some)
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        match burned {
+            Ok(Some(subtitled)) => (subtitled.clone(), Some(TempFileGuard::new(subtitled))),
+            Ok(None) => (path, None),
+            Err(e) => {
+                // Don't cache the plain video as the subtitled one; the next try may work.
+                log::error!("Failed to add subtitles to {}: {:?}", url, e);
+                cache_quality = unsubtitled_key.clone();
+                (path, None)
+            }
+        }
+    } else {
+        (path, None)
+    };
+    let file_size = fs::metadata(&path)?.len();
+
+    // Videos over the upload limit are compressed to fit instead of failing the upload. Users
+    // who split long videos get numbered parts when compression would ruin the picture.
+    let upload_limit = max_upload_size();
+    let oversized = !is_audio && file_size > upload_limit;
+    let split = oversized && video_mode.is_none() && db_pool.get_user_split_videos(user_id).await;
+    let chapters = info.as_ref().and_then(|i| i.chapters.clone()).unwrap_or_default();
+    if split && fetcher.should_split(&path, upload_limit).await {
+        let delivered =
+            deliver_in_parts(&bot, &mtproto_uploader, &fetcher, &request, &path, upload_limit, &chapters, &stages, &mut progress_bar).await?;
+        log_download(&db_pool, user_id, &url).await;
+        {
+            let mut urls = URL_PROCESSING.lock().await;
+            urls.remove(&url);
+        }
+        return Ok(delivered);
+    }
+    let (path, _compressed_guard) = if oversized {
+        progress_bar.update(80, Some("🗜 Compressing to fit the upload limit...")).await?;
+        let compressed = {
+            let (fetcher, input, mut progress_bar) = (fetcher.clone(), path.clone(), progress_bar.clone());
+            stages.run(Stage::Ffmpeg, async move { fetcher.fit_to_size(&input, upload_limit, &mut progress_bar).await }).await
+        };
+        match compressed {
+            Ok(compressed) => (compressed.clone(), Some(TempFileGuard::new(compressed))),
+            Err(e) if split => {
+                log::warn!("Failed to compress {} ({} bytes), sending it in parts: {:?}", url, file_size, e);
+                let delivered =
+                    deliver_in_parts(&bot, &mtproto_uploader, &fetcher, &request, &path, upload_limit, &chapters, &stages, &mut progress_bar).await?;
+                log_download(&db_pool, user_id, &url).await;
+                {
+                    let mut urls = URL_PROCESSING.lock().await;
+                    urls.remove(&url);
+                }
+                return Ok(delivered);
+            }
+            Err(e) => {
+                log::error!("Failed to compress {} ({} bytes): {:?}", url, file_size, e);
+                progress_bar.delete().await?;
+                bot.send_message(chat_id, "❌ The video is too large to upload, even compressed.").await?;
+                {
+                    let mut urls = URL_PROCESSING.lock().await;
+                    urls.remove(&url);
+                }
+                return Ok(false);
+            }
+        }
+    } else {
+        (path, None)
+    };
+
+    // Audio is converted to the user's format; when that fails the original is sent instead.
+    let (path, audio_format, _converted_guard) = if is_audio && audio_format != AudioFormat::Original {
+        progress_bar.update(80, Some(&format!("🎵 Converting to {}...", audio_format.label()))).await?;
+        let (fetcher, input) = (fetcher.clone(), path.clone());
+        match stages.run(Stage::Ffmpeg, async move { fetcher.convert_audio(&input, audio_format).await }).await {
+            Ok(converted) if converted != path => (converted.clone(), audio_format, Some(TempFileGuard::new(converted))),
+            Ok(_) => (path, audio_format, None),
+            Err(e) => {
+                log::error!("Failed to convert {} to {}: {:?}", url, audio_format.label(), e);
+                cache_quality = AudioFormat::Original.cache_key();
+                (path, AudioFormat::Original, None)
+            }
+        }
+    } else {
+        (path, audio_format, None)
+    };
+    let is_voice = is_audio && audio_format.is_voice();
+    let media_type = match video_mode {
+        Some(mode) => mode.media_type(),
+        None if is_voice => "voice",
+        None if is_audio => "audio",
+        None => "video",
+    };
+
+    let audio_meta = if is_voice {
+        // Voice messages have no title or cover, only their duration.
+        fetcher.prepare_audio(&path, None).await
+    } else if is_audio {
+        progress_bar.update(80, Some("🏷 Tagging audio...")).await?;
+        fetcher.prepare_audio(&path, info.as_ref()).await
+    } else {
+        AudioMeta::default()
+    };
+    let _cover_guard = audio_meta.thumbnail.clone().map(TempFileGuard::new);
     let file_size = fs::metadata(&path)?.len();
 
-    if file_size > TELEGRAM_BOT_API_FILE_LIMIT {
+    let delivered = if file_size > TELEGRAM_BOT_API_FILE_LIMIT {
         progress_bar.update(85, Some("📤 Uploading (Large)...")).await?;
-        let res = if is_audio {
-            mtproto_uploader.upload_audio(user_id, username, &path, "", &mut progress_bar).await
-        } else {
-            mtproto_uploader.upload_video(user_id, username, &path, "", &mut progress_bar).await
+        let res = {
+            let (uploader, username, path, audio_meta, options, mut progress_bar) = (
+                mtproto_uploader.clone(),
+                request.username.clone(),
+                path.clone(),
+                audio_meta.clone(),
+                send_options.clone(),
+                progress_bar.clone(),
+            );
+            let upload = async move {
+                if is_voice {
+                    uploader.upload_voice(chat_id.0, username, &path, audio_meta.duration, &options, &mut progress_bar).await
+                } else if is_audio {
+                    uploader.upload_audio(chat_id.0, username, &path, &audio_meta, &options, &mut progress_bar).await
+                } else {
+                    match video_mode {
+                        Some(VideoMode::VideoNote) => uploader.upload_video_note(chat_id.0, username, &path, &options, &mut progress_bar).await,
+                        Some(VideoMode::Animation) => uploader.upload_animation(chat_id.0, username, &path, &options, &mut progress_bar).await,
+                        None => uploader.upload_video(chat_id.0, username, &path, &options, &mut progress_bar).await,
+                    }
+                }
+            };
+            stages.run(Stage::MtprotoUpload, upload).await
         };
+        if let Ok(Some(doc)) = &res {
+            let cached = CachedMedia {
+                file_id: doc.encode(),
+                media_type: media_type.to_string(),
+                source: SOURCE_MTPROTO.to_string(),
+                info: info.clone(),
+            };
+            let _ = db_pool.store_cached_media(&url, &cache_quality, &cached).await;
+        }
         if res.is_ok() {
             progress_bar.update(100, Some("✅ Done!")).await?;
             tokio::time::sleep(Duration::from_millis(500)).await;
             progress_bar.delete().await?;
+            delete_source_message(&bot, &request).await;
         }
+        res.is_ok()
     } else {
         let mut retries = 0;
         let send_res = loop {
-            let res = if is_audio {
-                send_audio_with_progress_botapi(&bot.token(), chat_id, &path, None, &mut progress_bar).await
-            } else {
-                send_video_with_progress_botapi(&bot.token(), chat_id, &path, None, &mut progress_bar).await
+            let (token, path, audio_meta, options, mut progress_bar) =
+                (bot.token().to_string(), path.clone(), audio_meta.clone(), send_options.clone(), progress_bar.clone());
+            // Each attempt is its own step, so no worker waits out the back-off.
+            let upload = async move {
+                if is_voice {
+                    send_voice_with_progress_botapi(&token, chat_id, &path, audio_meta.duration, &options, &mut progress_bar).await
+                } else if is_audio {
+                    send_audio_with_progress_botapi(&token, chat_id, &path, &audio_meta, &options, &mut progress_bar).await
+                } else {
+                    match video_mode {
+                        Some(VideoMode::VideoNote) => send_video_note_botapi(&token, chat_id, &path, &options, &mut progress_bar).await,
+                        Some(VideoMode::Animation) => send_animation_botapi(&token, chat_id, &path, &options, &mut progress_bar).await,
+                        None => send_video_with_progress_botapi(&token, chat_id, &path, &options, &mut progress_bar).await,
+                    }
+                }
             };
+            let res = stages.run(Stage::BotApiUpload, upload).await;
             match res {
-                Ok(_) => break Ok(()),
+                Ok(file_id) => break Ok(file_id),
                 Err(e) => {
                     retries += 1;
                     if retries >= 3 { break Err(e); }
@@ -329,23 +1406,151 @@ pub async fn process_video_request(
                 }
             }
         };
-        if send_res.is_err() {
-            progress_bar.delete().await?;
-            bot.send_message(chat_id, "❌ Upload failed.").await?;
+        match send_res {
+            Ok(file_id) => {
+                if let Some(file_id) = file_id {
+                    let cached = CachedMedia {
+                        file_id,
+                        media_type: media_type.to_string(),
+                        source: SOURCE_BOTAPI.to_string(),
+                        info: info.clone(),
+                    };
+                    let _ = db_pool.store_cached_media(&url, &cache_quality, &cached).await;
+                }
+                delete_source_message(&bot, &request).await;
+                true
+            }
+            Err(_) => {
+                progress_bar.delete().await?;
+                bot.send_message(chat_id, "❌ Upload failed.").await?;
+                false
+            }
         }
+    };
+
+    if delivered && video_mode == Some(VideoMode::VideoNote) {
+        send_detached_caption(&bot, chat_id, send_options).await;
+    }
+    if delivered && subtitle_mode == SubtitleMode::File {
+        send_subtitle_file(&bot, &fetcher, &request, fingerprint.clone()).await;
     }
 
     // Final logging
-    let video_url = url.clone();
-    let _ = db_pool.execute_with_timeout(move |conn| {
-        conn.execute("INSERT OR IGNORE INTO users (telegram_id) VALUES (?1)", [user_id])?;
-        conn.execute("INSERT INTO downloads (user_telegram_id, video_url) VALUES (?1, ?2)", (user_id, video_url))?;
-        Ok(())
-    }).await;
+    log_download(&db_pool, user_id, &url).await;
 
     {
         let mut urls = URL_PROCESSING.lock().await;
         urls.remove(&url);
     }
-    Ok(())
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::types::UserId;
+
+    fn user(username: Option<&str>) -> User {
+        User {
+            id: UserId(42),
+            is_bot: false,
+            first_name: "Ann".to_string(),
+            last_name: Some("Lee".to_string()),
+            username: username.map(|s| s.to_string()),
+            language_code: None,
+            is_premium: false,
+            added_to_attachment_menu: false,
+        }
+    }
+
+    fn message(json: serde_json::Value) -> Message {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_message_link_text_includes_hidden_links() {
+        let msg = message(serde_json::json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": 7, "type": "private", "first_name": "Ann" },
+            "from": { "id": 7, "is_bot": false, "first_name": "Ann" },
+            "text": "look at this",
+            "entities": [{ "type": "text_link", "offset": 8, "length": 4, "url": "https://vimeo.com/123" }]
+        }));
+        let text = message_link_text(&msg).unwrap();
+        assert!(text.starts_with("look at this"));
+        assert!(text.contains("https://vimeo.com/123"));
+    }
+
+    #[test]
+    fn test_message_link_text_reads_captions() {
+        let msg = message(serde_json::json!({
+            "message_id": 2,
+            "date": 0,
+            "chat": { "id": -1001, "type": "channel", "title": "News" },
+            "photo": [{ "file_id": "a", "file_unique_id": "b", "width": 1, "height": 1 }],
+            "caption": "clip https://www.tiktok.com/@u/video/1"
+        }));
+        assert_eq!(message_link_text(&msg).as_deref(), Some("clip https://www.tiktok.com/@u/video/1"));
+
+        let no_text = message(serde_json::json!({
+            "message_id": 3,
+            "date": 0,
+            "chat": { "id": 7, "type": "private", "first_name": "Ann" },
+            "photo": [{ "file_id": "a", "file_unique_id": "b", "width": 1, "height": 1 }]
+        }));
+        assert_eq!(message_link_text(&no_text), None);
+    }
+
+    #[test]
+    fn test_batch_status_text() {
+        assert_eq!(batch_status_text("3 links", 1, 1, 3), "📦 Downloading 3 links: 1/3 done, 1 sent");
+        assert_eq!(batch_summary_text(3, 3, &[]), "📦 Sent 3 of 3.");
+        assert_eq!(batch_summary_text(1, 2, &["https://a.b/1".to_string()]), "📦 Sent 1 of 2.\n\n❌ Failed:\nhttps://a.b/1");
+    }
+
+    #[test]
+    fn test_requester_caption_names_the_requester() {
+        assert_eq!(requester_caption(&user(Some("ann"))), "🙋 Requested by @ann");
+        assert_eq!(requester_caption(&user(None)), "🙋 Requested by Ann Lee");
+    }
+
+    #[test]
+    fn test_build_caption_appends_request_caption() {
+        let mut request = VideoRequest::new(42, ChatId(-100), "https://x.com/i/status/1".to_string());
+        let info = VideoInfo { uploader: Some("Ann".to_string()), ..Default::default() };
+        assert_eq!(
+            build_caption(Some("👤 {author}\n🔗 {url}"), Some(&info), &request).as_deref(),
+            Some("👤 Ann\n🔗 https://x.com/i/status/1")
+        );
+        assert_eq!(build_caption(None, Some(&info), &request), None);
+
+        request.send_options.caption = Some(requester_caption(&user(Some("ann"))));
+        assert_eq!(
+            build_caption(Some("{author}"), None, &request).as_deref(),
+            Some("🙋 Requested by @ann")
+        );
+        assert_eq!(
+            build_caption(Some("{author}"), Some(&info), &request).as_deref(),
+            Some("Ann\n\n🙋 Requested by @ann")
+        );
+
+        // A full-length template still leaves the whole request caption within the limit.
+        let long = "x".repeat(MAX_CAPTION_CHARS);
+        let caption = build_caption(Some(&long), None, &request).unwrap();
+        assert_eq!(caption.chars().count(), MAX_CAPTION_CHARS);
+        assert!(caption.ends_with("…\n\n🙋 Requested by @ann"));
+    }
+
+    #[test]
+    fn test_part_caption() {
+        assert_eq!(part_caption(0, 3, Some("👤 Ann")), "Part 1/3\n\n👤 Ann");
+        assert_eq!(part_caption(1, 3, Some("👤 Ann")), "Part 2/3");
+        assert_eq!(part_caption(0, 2, None), "Part 1/2");
+
+        let long = "x".repeat(MAX_CAPTION_CHARS);
+        let caption = part_caption(0, 12, Some(&long));
+        assert_eq!(caption.chars().count(), MAX_CAPTION_CHARS);
+        assert!(caption.starts_with("Part 1/12\n\nxxx") && caption.ends_with('…'));
+    }
 }
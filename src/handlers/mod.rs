@@ -1,9 +1,15 @@
 pub mod admin;
 pub mod admin_panel;
 pub mod broadcast;
+pub mod collection;
 pub mod command;
 pub mod fingerprint;
+pub mod follow;
+pub mod format_picker;
+pub mod group_settings;
+pub mod inline;
 pub mod link;
+pub mod queue;
 pub mod subscription;
 pub mod text;
 pub mod ui;
@@ -12,7 +18,8 @@ pub mod payments;
 pub use admin_panel::{
     BTN_BROADCAST, admin_panel_text_handler, all_users_text_handler, stats_text_handler,
     top10_text_handler, premium_users_text_handler, add_premium_user_handler,
-    daily_stats_text_handler, admin_ads_text_handler,
+    daily_stats_text_handler, admin_ads_text_handler, platforms_text_handler, platform_toggle_callback,
+    caption_template_text_handler, caption_template_handler, providers_text_handler, provider_order_callback,
 };
 pub use broadcast::{
     BroadcastState, handle_broadcast_confirmation, receive_broadcast_message, start_broadcast,
@@ -20,5 +27,6 @@ pub use broadcast::{
 pub use command::command_handler;
 pub use link::link_handler;
 pub use text::{
-    back_text_handler, format_text_handler, settings_text_handler, subscription_text_handler,
+    audio_settings_text_handler, back_text_handler, format_text_handler, settings_text_handler,
+    subscription_text_handler,
 };
@@ -0,0 +1,573 @@
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId};
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex, Notify};
+use tokio_util::sync::CancellationToken;
+
+use crate::database::{DatabasePool, Job, JobStatus};
+use crate::handlers::admin::is_admin_id;
+use crate::handlers::link::{claim_urls, process_video_request, release_url, VideoRequest};
+use crate::mtproto_uploader::MTProtoUploader;
+use crate::utils::scheduler::{Priority, Scheduler};
+use crate::utils::stages::Stages;
+use crate::utils::task_manager::TaskManager;
+use crate::yt_dlp_interface::YoutubeFetcher;
+
+/// Jobs a restart interrupted this many times are given up instead of started again.
+const MAX_ATTEMPTS: u32 = 3;
+/// Jobs one user may have running at once, unless MAX_JOBS_PER_USER says otherwise.
+const DEFAULT_MAX_JOBS_PER_USER: usize = 2;
+/// Idle workers look for jobs this often even without being woken up.
+const IDLE_POLL: Duration = Duration::from_secs(30);
+/// Finished jobs are deleted after this many days.
+const KEEP_FINISHED_DAYS: u32 = 7;
+/// Links listed in a restart notice; the rest are only counted.
+const MAX_NOTICE_LINKS: usize = 10;
+/// Done jobs the wait estimate is averaged over.
+const ESTIMATE_JOBS: usize = 20;
+/// Assumed length of a job before any has finished.
+const DEFAULT_JOB_SECS: f64 = 60.0;
+/// Queue changes within this time after a status update are shown together in the next one.
+const STATUS_DEBOUNCE: Duration = Duration::from_secs(2);
+/// Pause between two status message edits, to stay clear of Telegram's flood limits.
+const STATUS_EDIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Callback data prefix of the Cancel button: `cxl:<job id>`.
+pub const CANCEL_PREFIX: &str = "cxl:";
+
+/// Queue status message of a waiting job and the text it shows.
+struct StatusMessage {
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+}
+
+/// Download jobs stored in the `jobs` table, processed by a pool of workers. Jobs left
+/// unfinished by a restart are picked up again on startup.
+pub struct JobQueue {
+    bot: Bot,
+    fetcher: Arc<YoutubeFetcher>,
+    mtproto_uploader: Arc<MTProtoUploader>,
+    db_pool: Arc<DatabasePool>,
+    task_manager: Arc<TaskManager>,
+    stages: Arc<Stages>,
+    workers: usize,
+    wakeup: Notify,
+    /// Wakes the status updater when the queue changed.
+    statuses_changed: Notify,
+    /// Jobs whose outcome somebody waits for (batches), by job id.
+    waiters: Mutex<HashMap<i64, oneshot::Sender<bool>>>,
+    /// Status messages of queued jobs, by job id; a worker takes them over with the job.
+    statuses: Mutex<HashMap<i64, StatusMessage>>,
+    /// Tokens of the running jobs, by job id.
+    running: std::sync::Mutex<HashMap<i64, CancellationToken>>,
+    /// Picks the next job; held while a worker claims it.
+    scheduler: Mutex<Scheduler>,
+}
+
+/// Number of workers, from DOWNLOAD_WORKERS. By default there is one per step the pipeline
+/// holds, so the stages rather than the workers limit how many jobs make progress.
+fn worker_count(stages: &Stages) -> usize {
+    std::env::var("DOWNLOAD_WORKERS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or_else(|| stages.capacity())
+}
+
+/// Per-user cap on running jobs, from MAX_JOBS_PER_USER.
+fn max_jobs_per_user() -> usize {
+    std::env::var("MAX_JOBS_PER_USER")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_JOBS_PER_USER)
+}
+
+pub fn cancel_keyboard(job_id: i64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback("❌ Cancel", format!("{}{}", CANCEL_PREFIX, job_id))]])
+}
+
+/// Expected seconds until the job at `position` (1-based) is done, when `workers` take one
+/// job at a time and a job takes `job_secs`.
+fn estimated_wait(position: usize, workers: usize, job_secs: f64) -> f64 {
+    position.div_ceil(workers.max(1)) as f64 * job_secs
+}
+
+fn queue_status_text(position: usize, wait_secs: f64) -> String {
+    let minutes = ((wait_secs / 60.0).ceil() as u64).max(1);
+    format!("⏳ #{} in queue, ~{} min", position, minutes)
+}
+
+/// "link1\nlink2\n…and 3 more" for a restart notice.
+fn link_list(urls: &[&str]) -> String {
+    let mut text = urls.iter().take(MAX_NOTICE_LINKS).copied().collect::<Vec<_>>().join("\n");
+    if urls.len() > MAX_NOTICE_LINKS {
+        text.push_str(&format!("\n…and {} more", urls.len() - MAX_NOTICE_LINKS));
+    }
+    text
+}
+
+/// Messages telling each chat which of the downloads requested there went on after a
+/// restart and which were given up, in chat id order.
+fn restart_notices(resumed: &[Job], failed: &[Job]) -> Vec<(i64, String)> {
+    let mut by_chat: BTreeMap<i64, (Vec<&str>, Vec<&str>)> = BTreeMap::new();
+    for job in resumed {
+        by_chat.entry(job.chat_id).or_default().0.push(&job.url);
+    }
+    for job in failed {
+        by_chat.entry(job.chat_id).or_default().1.push(&job.url);
+    }
+    by_chat
+        .into_iter()
+        .map(|(chat_id, (resumed, failed))| {
+            let mut parts = Vec::new();
+            if !resumed.is_empty() {
+                parts.push(format!("🔄 The bot was restarted, your downloads are resumed:\n{}", link_list(&resumed)));
+            }
+            if !failed.is_empty() {
+                parts.push(format!("❌ These downloads were interrupted too often, please send them again:\n{}", link_list(&failed)));
+            }
+            (chat_id, parts.join("\n\n"))
+        })
+        .collect()
+}
+
+impl JobQueue {
+    pub fn new(
+        bot: Bot,
+        fetcher: Arc<YoutubeFetcher>,
+        mtproto_uploader: Arc<MTProtoUploader>,
+        db_pool: Arc<DatabasePool>,
+        task_manager: Arc<TaskManager>,
+        stages: Arc<Stages>,
+    ) -> Self {
+        Self {
+            bot,
+            fetcher,
+            mtproto_uploader,
+            db_pool,
+            task_manager,
+            workers: worker_count(&stages),
+            stages,
+            wakeup: Notify::new(),
+            statuses_changed: Notify::new(),
+            waiters: Mutex::new(HashMap::new()),
+            statuses: Mutex::new(HashMap::new()),
+            running: std::sync::Mutex::new(HashMap::new()),
+            scheduler: Mutex::new(Scheduler::new(max_jobs_per_user())),
+        }
+    }
+
+    /// Stores `request` as a job and wakes a worker. Requests with a progress bar get a
+    /// status message with their place in the queue and a Cancel button. Returns the job id.
+    pub async fn enqueue(&self, request: &VideoRequest) -> anyhow::Result<i64> {
+        self.enqueue_with(request, None).await
+    }
+
+    /// [`JobQueue::enqueue`], registering `waiter` for the job's outcome.
+    async fn enqueue_with(&self, request: &VideoRequest, waiter: Option<oneshot::Sender<bool>>) -> anyhow::Result<i64> {
+        let mut request = request.clone();
+        if request.show_progress && request.status_message.is_none() {
+            request.status_message = Some(self.bot.send_message(request.chat_id, "⏳ Queued...").await?.id);
+        }
+        let json = serde_json::to_string(&request)?;
+        let priority = self.priority(request.user_id).await;
+        // Held only while the row is written, so a worker can't finish the job before its
+        // waiter is there.
+        let mut waiters = match waiter {
+            Some(_) => Some(self.waiters.lock().await),
+            None => None,
+        };
+        let job_id = match self.db_pool.create_job(request.user_id, request.chat_id.0, &request.url, &json, priority).await {
+            Ok(job_id) => job_id,
+            Err(e) => {
+                drop(waiters);
+                if let Some(message_id) = request.status_message {
+                    let _ = self.bot.delete_message(request.chat_id, message_id).await;
+                }
+                return Err(e);
+            }
+        };
+        if let (Some(waiters), Some(waiter)) = (waiters.as_mut(), waiter) {
+            waiters.insert(job_id, waiter);
+        }
+        drop(waiters);
+        if let Some(message_id) = request.status_message {
+            let status = StatusMessage { chat_id: request.chat_id, message_id, text: String::new() };
+            self.statuses.lock().await.insert(job_id, status);
+        }
+        self.wakeup.notify_one();
+        self.refresh_statuses();
+        Ok(job_id)
+    }
+
+    async fn priority(&self, user_id: i64) -> Priority {
+        if is_admin_id(user_id) {
+            Priority::Admin
+        } else if self.db_pool.is_user_premium(user_id).await {
+            Priority::Premium
+        } else {
+            Priority::Free
+        }
+    }
+
+    /// Asks the status updater to show the waiting jobs their new places in the queue.
+    fn refresh_statuses(&self) {
+        self.statuses_changed.notify_one();
+    }
+
+    /// Updates the status messages whenever the queue changed, until `stop` is cancelled.
+    async fn run_status_updater(self: Arc<Self>, stop: CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = stop.cancelled() => return,
+                _ = self.statuses_changed.notified() => {}
+            }
+            self.update_statuses().await;
+            tokio::select! {
+                _ = stop.cancelled() => return,
+                _ = tokio::time::sleep(STATUS_DEBOUNCE) => {}
+            }
+        }
+    }
+
+    /// Shows every waiting job with a status message its place in the queue and the
+    /// expected wait, editing only messages whose text changed. The edits are sent after
+    /// the lock is released, one at a time.
+    async fn update_statuses(&self) {
+        if self.statuses.lock().await.is_empty() {
+            return;
+        }
+        let queued = match self.db_pool.queued_jobs().await {
+            Ok(jobs) => self.scheduler.lock().await.queue_order(&jobs),
+            Err(e) => {
+                log::warn!("{}", e);
+                return;
+            }
+        };
+        let job_secs = self.db_pool.recent_job_seconds(ESTIMATE_JOBS).await.ok().flatten().unwrap_or(DEFAULT_JOB_SECS);
+        let edits: Vec<(i64, ChatId, MessageId, String)> = {
+            let statuses = self.statuses.lock().await;
+            queued
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, job_id)| {
+                    let status = statuses.get(job_id)?;
+                    let text = queue_status_text(idx + 1, estimated_wait(idx + 1, self.workers, job_secs));
+                    (text != status.text).then_some((*job_id, status.chat_id, status.message_id, text))
+                })
+                .collect()
+        };
+        for (idx, (job_id, chat_id, message_id, text)) in edits.into_iter().enumerate() {
+            if idx > 0 {
+                tokio::time::sleep(STATUS_EDIT_INTERVAL).await;
+            }
+            let edited = self.bot
+                .edit_message_text(chat_id, message_id, &text)
+                .reply_markup(cancel_keyboard(job_id))
+                .await;
+            // A job a worker took meanwhile keeps its message out of the map.
+            if edited.is_ok()
+                && let Some(status) = self.statuses.lock().await.get_mut(&job_id)
+            {
+                status.text = text;
+            }
+        }
+    }
+
+    /// Cancels `job`: a queued job is dropped, a running one is stopped, which kills its
+    /// yt-dlp process or stops the upload and removes its temp files. Returns false when the
+    /// job already finished.
+    pub async fn cancel(&self, job: &Job) -> anyhow::Result<bool> {
+        if self.db_pool.cancel_queued_job(job.id).await? {
+            if let Some(status) = self.statuses.lock().await.remove(&job.id) {
+                let _ = self.bot.edit_message_text(status.chat_id, status.message_id, "❌ Download cancelled.").await;
+            }
+            release_url(&job.url).await;
+            if let Some(waiter) = self.waiters.lock().await.remove(&job.id) {
+                let _ = waiter.send(false);
+            }
+            self.refresh_statuses();
+            return Ok(true);
+        }
+        match self.running.lock().unwrap().get(&job.id) {
+            Some(token) => {
+                token.cancel();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Like [`JobQueue::enqueue`], with a receiver for whether the media was delivered.
+    pub async fn enqueue_waiting(&self, request: &VideoRequest) -> anyhow::Result<oneshot::Receiver<bool>> {
+        let (tx, rx) = oneshot::channel();
+        self.enqueue_with(request, Some(tx)).await?;
+        Ok(rx)
+    }
+
+    /// Queues the jobs a previous run left downloading or uploading again (or gives up on
+    /// them) and tells the chats they were requested in. Run before the workers start.
+    pub async fn recover(&self) {
+        match self.db_pool.prune_finished_jobs(KEEP_FINISHED_DAYS).await {
+            Ok(0) => {}
+            Ok(pruned) => log::info!("Deleted {} finished jobs", pruned),
+            Err(e) => log::warn!("{}", e),
+        }
+        let recovered = match self.db_pool.recover_interrupted_jobs(MAX_ATTEMPTS).await {
+            Ok(recovered) => recovered,
+            Err(e) => {
+                log::error!("{}", e);
+                return;
+            }
+        };
+        if recovered.queued.is_empty() && recovered.failed.is_empty() {
+            return;
+        }
+        log::info!(
+            "Resuming {} interrupted jobs, giving up {}, {} queued in total",
+            recovered.requeued.len(),
+            recovered.failed.len(),
+            recovered.queued.len()
+        );
+        claim_urls(recovered.queued.iter().map(|job| job.url.clone())).await;
+        // Background deliveries (follows) stay quiet, like they do while running.
+        let noticed = |jobs: &[Job]| -> Vec<Job> {
+            jobs.iter()
+                .filter(|job| serde_json::from_str::<VideoRequest>(&job.request).is_ok_and(|request| request.show_progress))
+                .cloned()
+                .collect()
+        };
+        for (chat_id, text) in restart_notices(&noticed(&recovered.requeued), &noticed(&recovered.failed)) {
+            if let Err(e) = self.bot.send_message(ChatId(chat_id), text).await {
+                log::warn!("Failed to tell chat {} about resumed jobs: {}", chat_id, e);
+            }
+        }
+        // Status messages of queued jobs show their place in the queue again.
+        {
+            let mut statuses = self.statuses.lock().await;
+            for job in &recovered.queued {
+                if let Ok(request) = serde_json::from_str::<VideoRequest>(&job.request)
+                    && let Some(message_id) = request.status_message
+                {
+                    statuses.insert(job.id, StatusMessage { chat_id: request.chat_id, message_id, text: String::new() });
+                }
+            }
+        }
+        self.refresh_statuses();
+    }
+
+    /// Starts DOWNLOAD_WORKERS workers that process jobs until shutdown, and the status updater.
+    pub fn start_workers(self: &Arc<Self>) {
+        log::info!("Starting {} download workers", self.workers);
+        for idx in 0..self.workers {
+            let queue = self.clone();
+            self.task_manager.spawn_service(format!("download worker {}", idx + 1), |stop| queue.run_worker(stop));
+        }
+        let queue = self.clone();
+        self.task_manager.spawn_service("queue status updater", |stop| queue.run_status_updater(stop));
+    }
+
+    /// Takes jobs until `stop` is cancelled; a job in hand is finished first.
+    async fn run_worker(self: Arc<Self>, stop: CancellationToken) {
+        while !stop.is_cancelled() {
+            match self.claim_next().await {
+                Ok(Some(job)) => self.run_job(job).await,
+                Ok(None) => {
+                    tokio::select! {
+                        _ = stop.cancelled() => {}
+                        _ = self.wakeup.notified() => {}
+                        _ = tokio::time::sleep(IDLE_POLL) => {}
+                    }
+                }
+                Err(e) => {
+                    log::error!("{}", e);
+                    tokio::select! {
+                        _ = stop.cancelled() => {}
+                        _ = tokio::time::sleep(IDLE_POLL) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Claims the job the scheduler picks, if any may start now.
+    async fn claim_next(&self) -> anyhow::Result<Option<Job>> {
+        let mut scheduler = self.scheduler.lock().await;
+        loop {
+            let queued = self.db_pool.queued_jobs().await?;
+            if queued.is_empty() {
+                return Ok(None);
+            }
+            let running = self.db_pool.running_jobs_per_user().await?;
+            let Some(job_id) = scheduler.pick(&queued, &running) else {
+                return Ok(None);
+            };
+            // None when the job was cancelled in the meantime; look again.
+            if let Some(job) = self.db_pool.claim_job(job_id).await? {
+                return Ok(Some(job));
+            }
+        }
+    }
+
+    async fn run_job(&self, job: Job) {
+        // The status message is the worker's now; the jobs behind move up.
+        self.statuses.lock().await.remove(&job.id);
+        self.refresh_statuses();
+
+        let request = match serde_json::from_str::<VideoRequest>(&job.request) {
+            Ok(request) => request,
+            Err(e) => {
+                self.finish(&job, JobStatus::Failed, Some(format!("unreadable request: {}", e))).await;
+                return;
+            }
+        };
+        let status_message = request.status_message.map(|message_id| (request.chat_id, message_id));
+        // Dropping the download on cancel kills yt-dlp and removes its temp files; a panic
+        // fails only this job.
+        let process = process_video_request(
+            self.bot.clone(),
+            VideoRequest { job_id: Some(job.id), ..request },
+            self.fetcher.clone(),
+            self.mtproto_uploader.clone(),
+            self.db_pool.clone(),
+            self.stages.clone(),
+        );
+        // The stages cap the job's steps, so it takes no TaskManager slot.
+        let task = self.task_manager.spawn_unslotted_job(format!("job {}: {}", job.id, job.url), |token| async move {
+            tokio::select! {
+                processed = process => Some(processed),
+                _ = token.cancelled() => None,
+            }
+        });
+        let Some(task) = task else {
+            // Shutdown began after the job was claimed; the next start picks it up.
+            let _ = self.db_pool.set_job_status(job.id, JobStatus::Queued).await;
+            return;
+        };
+        self.running.lock().unwrap().insert(job.id, task.token());
+        let processed = task.join().await;
+        self.running.lock().unwrap().remove(&job.id);
+
+        let (status, error) = match processed {
+            Ok(Some(Ok(true))) => (JobStatus::Done, None),
+            Ok(Some(Ok(false))) => (JobStatus::Failed, Some("not delivered".to_string())),
+            Ok(Some(Err(e))) => (JobStatus::Failed, Some(e.to_string())),
+            Ok(None) if self.task_manager.is_aborting() => {
+                self.report_interrupted(&job, status_message).await;
+                return;
+            }
+            Ok(None) => (JobStatus::Cancelled, None),
+            Err(e) => (JobStatus::Failed, Some(e.to_string())),
+        };
+        // The progress bar removes the message when it ends normally; early exits (cached
+        // media, errors) and cancels leave it behind.
+        if let Some((chat_id, message_id)) = status_message {
+            if status == JobStatus::Cancelled {
+                let _ = self.bot.edit_message_text(chat_id, message_id, "❌ Download cancelled.").await;
+            } else {
+                let _ = self.bot.delete_message(chat_id, message_id).await;
+            }
+        }
+        self.finish(&job, status, error).await;
+    }
+
+    /// Tells the requester that shutdown cut their download short. The job keeps its status,
+    /// so the next start queues it again.
+    async fn report_interrupted(&self, job: &Job, status_message: Option<(ChatId, MessageId)>) {
+        let text = format!("⚠️ The bot is restarting and interrupted your download. It continues once the bot is back:\n{}", job.url);
+        let edited = match status_message {
+            Some((chat_id, message_id)) => self.bot.edit_message_text(chat_id, message_id, &text).await.is_ok(),
+            None => false,
+        };
+        if !edited && let Err(e) = self.bot.send_message(ChatId(job.user_id), text).await {
+            log::warn!("Failed to tell user {} about the interrupted job {}: {}", job.user_id, job.id, e);
+        }
+    }
+
+    async fn finish(&self, job: &Job, status: JobStatus, error: Option<String>) {
+        if let Some(error) = &error {
+            log::error!("Job {} ({}) failed: {}", job.id, job.url, error);
+        }
+        if let Err(e) = self.db_pool.finish_job(job.id, status, error).await {
+            log::error!("{}", e);
+        }
+        release_url(&job.url).await;
+        if let Some(waiter) = self.waiters.lock().await.remove(&job.id) {
+            let _ = waiter.send(status == JobStatus::Done);
+        }
+        // The requester's other jobs may start now.
+        self.wakeup.notify_one();
+    }
+}
+
+/// The Cancel button under queue and progress messages; only the requester can press it.
+pub async fn cancel_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    db_pool: Arc<DatabasePool>,
+    job_queue: Arc<JobQueue>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(job_id) = q.data.as_deref().and_then(|d| d.strip_prefix(CANCEL_PREFIX)).and_then(|id| id.parse().ok()) else {
+        return Ok(());
+    };
+    let job = db_pool.get_job(job_id).await?;
+    let answer = match job {
+        Some(job) if job.user_id != q.from.id.0 as i64 => "Only the person who sent the link can cancel it.",
+        Some(job) => match job_queue.cancel(&job).await? {
+            true => "Cancelled.",
+            false => "This download already finished.",
+        },
+        None => "This download already finished.",
+    };
+    bot.answer_callback_query(q.id).text(answer).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(user_id: i64, url: &str) -> Job {
+        Job {
+            id: 0,
+            user_id,
+            chat_id: user_id,
+            url: url.to_string(),
+            request: String::new(),
+            status: JobStatus::Queued,
+            attempts: 1,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_status() {
+        assert_eq!(queue_status_text(1, estimated_wait(1, 2, 45.0)), "⏳ #1 in queue, ~1 min");
+        assert_eq!(queue_status_text(3, estimated_wait(3, 2, 45.0)), "⏳ #3 in queue, ~2 min");
+        assert_eq!(queue_status_text(5, estimated_wait(5, 1, 100.0)), "⏳ #5 in queue, ~9 min");
+        assert_eq!(estimated_wait(4, 0, 10.0), 40.0);
+    }
+
+    #[test]
+    fn test_restart_notices() {
+        let notices = restart_notices(&[job(2, "https://a/1"), job(1, "https://a/2"), job(2, "https://a/3")], &[job(2, "https://a/4")]);
+        assert_eq!(notices.len(), 2);
+        assert_eq!(notices[0], (1, "🔄 The bot was restarted, your downloads are resumed:\nhttps://a/2".to_string()));
+        assert_eq!(
+            notices[1].1,
+            "🔄 The bot was restarted, your downloads are resumed:\nhttps://a/1\nhttps://a/3\n\n❌ These downloads were interrupted too often, please send them again:\nhttps://a/4"
+        );
+
+        // A job requested in a group is reported there.
+        let group_job = Job { chat_id: -100, ..job(3, "https://a/5") };
+        assert_eq!(restart_notices(&[group_job], &[])[0].0, -100);
+
+        let many: Vec<Job> = (0..12).map(|i| job(1, &format!("https://a/{}", i))).collect();
+        assert!(restart_notices(&many, &[])[0].1.ends_with("https://a/9\n…and 2 more"));
+    }
+}
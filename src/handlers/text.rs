@@ -1,16 +1,24 @@
 use teloxide::prelude::*;
 use teloxide::types::{KeyboardMarkup, KeyboardButton};
 use crate::handlers::admin::is_admin;
-use crate::handlers::ui::{BTN_ADMIN_PANEL, BTN_FORMAT, BTN_SETTINGS, BTN_BACK};
+use crate::handlers::ui::{BTN_ADMIN_PANEL, BTN_ANIMATION, BTN_AUDIO_FORMAT_PREFIX, BTN_AUDIO_SETTINGS, BTN_FORMAT, BTN_VIDEO_NOTE, BTN_SETTINGS, BTN_BACK, BTN_SLIDESHOW_ALBUM, BTN_SLIDESHOW_VIDEO, BTN_TOGGLE_CAPTIONS, BTN_TOGGLE_FORMAT_PICKER, BTN_TOGGLE_SPLIT_VIDEOS, BTN_SUBTITLES};
+use crate::yt_dlp_interface::AudioFormat;
 use std::sync::Arc;
 use crate::database::DatabasePool;
 
 pub async fn settings_text_handler(
     bot: Bot,
-    msg: Message
+    msg: Message,
+    db_pool: Arc<DatabasePool>
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let captions = db_pool.get_user_captions(msg.chat.id.0).await;
+    let split_videos = db_pool.get_user_split_videos(msg.chat.id.0).await;
+    let subtitles = db_pool.get_user_subtitles(msg.chat.id.0).await;
     let mut rows = vec![
         vec![KeyboardButton::new(BTN_FORMAT)],
+        vec![KeyboardButton::new(format!("{}{}", BTN_TOGGLE_CAPTIONS, if captions { "ON ✅" } else { "OFF ❌" }))],
+        vec![KeyboardButton::new(format!("{}{}", BTN_TOGGLE_SPLIT_VIDEOS, if split_videos { "ON ✅" } else { "OFF ❌" }))],
+        vec![KeyboardButton::new(format!("{}{}", BTN_SUBTITLES, subtitles.label()))],
     ];
 
     if is_admin(&msg).await {
@@ -31,19 +39,75 @@ pub async fn settings_text_handler(
 
 pub async fn format_text_handler(
     bot: Bot,
-    msg: Message
+    msg: Message,
+    db_pool: Arc<DatabasePool>
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let picker = db_pool.get_user_format_picker(msg.chat.id.0).await;
     let keyboard = KeyboardMarkup::new(vec![
         vec![
             KeyboardButton::new("h265"),
             KeyboardButton::new("h264"),
             KeyboardButton::new("audio"),
         ],
+        vec![
+            KeyboardButton::new(BTN_VIDEO_NOTE),
+            KeyboardButton::new(BTN_ANIMATION),
+        ],
+        vec![
+            KeyboardButton::new(BTN_SLIDESHOW_ALBUM),
+            KeyboardButton::new(BTN_SLIDESHOW_VIDEO),
+        ],
+        vec![KeyboardButton::new(format!("{}{}", BTN_TOGGLE_FORMAT_PICKER, if picker { "ON ✅" } else { "OFF ❌" }))],
+        vec![KeyboardButton::new(BTN_AUDIO_SETTINGS)],
         vec![KeyboardButton::new(BTN_BACK)],
     ])
     .resize_keyboard();
 
-    let text = "h265: best quality, but may not work on some devices.\nh264: worse quality, but works on many devices.\naudio: audio only";
+    let text = "h265: best quality, but may not work on some devices.\nh264: worse quality, but works on many devices.\naudio: audio only\nvideo note: round video of up to a minute\nGIF: silent looping animation\n\nTikTok photo slideshows:\nalbum: send the photos as an album plus the music\nvideo: render the photos into one video over the music\n\nPick format per link: list the real resolutions of every link you send and choose one.\n\nAudio format: what audio downloads are converted to.";
+
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .await
+        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+
+    Ok(())
+}
+
+/// Button of an audio format choice; the current one is checked.
+pub fn audio_format_button(format: AudioFormat, current: AudioFormat) -> String {
+    let check = if format == current { " ✅" } else { "" };
+    format!("{}{}{}", BTN_AUDIO_FORMAT_PREFIX, format.label(), check)
+}
+
+/// The format an audio format button stands for.
+pub fn parse_audio_format_button(text: &str) -> Option<AudioFormat> {
+    let label = text.strip_prefix(BTN_AUDIO_FORMAT_PREFIX)?.trim_end_matches(" ✅");
+    AudioFormat::ALL.into_iter().find(|f| f.label() == label)
+}
+
+pub async fn audio_settings_text_handler(
+    bot: Bot,
+    msg: Message,
+    db_pool: Arc<DatabasePool>
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let current = db_pool.get_user_audio_format(msg.chat.id.0).await;
+    let mut rows: Vec<Vec<KeyboardButton>> = vec![
+        vec![KeyboardButton::new(audio_format_button(AudioFormat::Original, current))],
+        vec![
+            KeyboardButton::new(audio_format_button(AudioFormat::Mp3(128), current)),
+            KeyboardButton::new(audio_format_button(AudioFormat::Mp3(192), current)),
+            KeyboardButton::new(audio_format_button(AudioFormat::Mp3(320), current)),
+        ],
+        vec![
+            KeyboardButton::new(audio_format_button(AudioFormat::M4a, current)),
+            KeyboardButton::new(audio_format_button(AudioFormat::Opus, current)),
+        ],
+        vec![KeyboardButton::new(audio_format_button(AudioFormat::Voice, current))],
+    ];
+    rows.push(vec![KeyboardButton::new(BTN_BACK)]);
+    let keyboard = KeyboardMarkup::new(rows).resize_keyboard();
+
+    let text = "Audio downloads are converted to the format you pick:\nOriginal: the source's own codec, no re-encoding\nMP3: plays everywhere, car stereos included\nM4A / Opus: smaller files at the same quality\nVoice message: sent as a voice note you can play at 2x";
 
     bot.send_message(msg.chat.id, text)
         .reply_markup(keyboard)
@@ -109,3 +173,17 @@ pub async fn subscription_text_handler(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_format_buttons_roundtrip() {
+        for format in AudioFormat::ALL {
+            assert_eq!(parse_audio_format_button(&audio_format_button(format, format)), Some(format));
+            assert_eq!(parse_audio_format_button(&audio_format_button(format, AudioFormat::Voice)), Some(format));
+        }
+        assert_eq!(parse_audio_format_button("🎵 Audio: FLAC"), None);
+    }
+}
@@ -5,7 +5,21 @@ pub const BTN_SUBSCRIPTION: &str = "Subscription";
 pub const BTN_TOGGLE_ADS: &str = "Ads: ";
 pub const BTN_TOGGLE_SUCCESS_NOTIFS: &str = "Notify Success: ";
 pub const BTN_TOGGLE_FAIL_NOTIFS: &str = "Notify Fail: ";
+pub const BTN_PROVIDERS: &str = "🔌 Download Sources";
+pub const BTN_TOGGLE_CAPTIONS: &str = "📝 Captions: ";
+pub const BTN_TOGGLE_FORMAT_PICKER: &str = "🎚 Pick format per link: ";
+pub const BTN_TOGGLE_SPLIT_VIDEOS: &str = "✂️ Split long videos: ";
+/// Cycles the subtitle mode; followed by the current mode's label.
+pub const BTN_SUBTITLES: &str = "💬 Subtitles: ";
+pub const BTN_CAPTION_TEMPLATE: &str = "📝 Caption Template";
+pub const BTN_VIDEO_NOTE: &str = "⭕ Video note";
+pub const BTN_ANIMATION: &str = "🔁 GIF";
+pub const BTN_AUDIO_SETTINGS: &str = "🎵 Audio format";
+/// Prefix of the choices in the audio format menu, followed by the format's label.
+pub const BTN_AUDIO_FORMAT_PREFIX: &str = "🎵 Audio: ";
 pub const BTN_BACK: &str = "Back";
+pub const BTN_SLIDESHOW_ALBUM: &str = "🖼 Slideshow: album";
+pub const BTN_SLIDESHOW_VIDEO: &str = "🎞 Slideshow: video";
 
 pub fn is_menu_button(text: &str) -> bool {
     matches!(text,
@@ -21,9 +35,15 @@ pub fn is_system_button(text: &str) -> bool {
     matches!(
         text,
         BTN_ADMIN_PANEL | BTN_SETTINGS | BTN_FORMAT | BTN_SUBSCRIPTION | BTN_BACK |
-        "📢 Broadcast" | "📊 Stats" | "🏆 Top 10" | "👥 All users" | "💎 Premium Users" | "➕ Add Premium User" |
-        "h265" | "h264" | "audio"
+        "📢 Broadcast" | "📊 Stats" | "🏆 Top 10" | "👥 All users" | "💎 Premium Users" | "➕ Add Premium User" | "🌐 Platforms" |
+        "h265" | "h264" | "audio" | BTN_SLIDESHOW_ALBUM | BTN_SLIDESHOW_VIDEO | BTN_CAPTION_TEMPLATE | BTN_PROVIDERS | BTN_AUDIO_SETTINGS |
+        BTN_VIDEO_NOTE | BTN_ANIMATION
     ) || text.starts_with(BTN_TOGGLE_ADS)
+      || text.starts_with(BTN_AUDIO_FORMAT_PREFIX)
+      || text.starts_with(BTN_TOGGLE_CAPTIONS)
+      || text.starts_with(BTN_TOGGLE_FORMAT_PICKER)
+      || text.starts_with(BTN_TOGGLE_SPLIT_VIDEOS)
+      || text.starts_with(BTN_SUBTITLES)
       || text.starts_with(BTN_TOGGLE_SUCCESS_NOTIFS)
       || text.starts_with(BTN_TOGGLE_FAIL_NOTIFS)
 }
@@ -41,4 +61,17 @@ mod tests {
         assert!(is_menu_button(BTN_BACK));
         assert!(!is_menu_button("some other text"));
     }
+
+    #[test]
+    fn test_slideshow_buttons_are_system_buttons() {
+        assert!(is_system_button(BTN_SLIDESHOW_ALBUM));
+        assert!(is_system_button(BTN_SLIDESHOW_VIDEO));
+        assert!(!is_menu_button(BTN_SLIDESHOW_VIDEO));
+    }
+
+    #[test]
+    fn test_audio_format_buttons_are_system_buttons() {
+        assert!(is_system_button(BTN_AUDIO_SETTINGS));
+        assert!(is_system_button(&format!("{}MP3 320k ✅", BTN_AUDIO_FORMAT_PREFIX)));
+    }
 }
\ No newline at end of file
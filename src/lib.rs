@@ -5,6 +5,7 @@ pub mod database;
 pub mod handlers;
 pub mod mtproto_uploader;
 pub mod peers;
+pub mod platforms;
 pub mod telegram_bot_api_uploader;
 pub mod utils;
 pub mod web_server;
@@ -23,8 +24,10 @@ use handlers::{
 };
 use handlers::ui::{BTN_ADMIN_PANEL, BTN_BACK, BTN_FORMAT, BTN_SETTINGS, BTN_SUBSCRIPTION};
 use database::DatabasePool;
-use mtproto_uploader::MTProtoUploader;
 use yt_dlp_interface::YoutubeFetcher;
+use yt_dlp_interface::video_modes::{QUALITY_ANIMATION, QUALITY_VIDEO_NOTE};
+use handlers::queue::JobQueue;
+use utils::stages::Stages;
 use utils::task_manager::TaskManager;
 use std::sync::Arc;
 use std::collections::HashSet;
@@ -40,6 +43,9 @@ pub type MyDialogue = dialogue::Dialogue<
     dialogue::InMemStorage<BroadcastState>,
 >;
 
+/// Reply to commands that arrive after shutdown began.
+const RESTARTING_TEXT: &str = "⚠️ The bot is restarting, try again in a minute.";
+
 // For deduplication
 lazy_static::lazy_static! {
     static ref PROCESSING: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
@@ -51,12 +57,19 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
         // Payment handlers must be outside dialogue::enter because PreCheckoutQuery has no ChatId
         .branch(Update::filter_pre_checkout_query().endpoint(handlers::payments::handle_pre_checkout))
         .branch(Update::filter_message().filter(|msg: Message| msg.successful_payment().is_some()).endpoint(handlers::payments::handle_successful_payment))
+        // Inline updates carry no chat either, so they also bypass the dialogue
+        .branch(Update::filter_inline_query().endpoint(handlers::inline::inline_query_handler))
+        .branch(Update::filter_chosen_inline_result().endpoint(handlers::inline::chosen_inline_result_handler))
         .branch(
             dialogue::enter::<Update, dialogue::InMemStorage<BroadcastState>, BroadcastState, _>()
                 .branch(
                     Update::filter_message()
                         .branch(dptree::case![BroadcastState::WaitingForMessage].endpoint(receive_broadcast_message))
                         .branch(dptree::case![BroadcastState::WaitingForAddPremiumUserId].endpoint(add_premium_user_handler))
+                        .branch(dptree::case![BroadcastState::WaitingForCaptionTemplate].endpoint(handlers::caption_template_handler))
+                        .branch(dptree::case![BroadcastState::Idle]
+                                .filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_CAPTION_TEMPLATE))
+                                .endpoint(handlers::caption_template_text_handler))
                         .branch(dptree::case![BroadcastState::Idle]
                                 .filter(|msg: Message| msg.text().map(|t| t == BTN_BROADCAST).unwrap_or(false))
                                 .endpoint(start_broadcast))
@@ -71,9 +84,9 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
                 .branch(
                     Update::filter_callback_query()
                         .filter(|q: CallbackQuery| q.data.as_ref().map_or(false, |data| data == "broadcast_confirm" || data == "broadcast_cancel"))
-                        .endpoint(|bot: Bot, dialogue: MyDialogue, q: CallbackQuery, db_pool: Arc<DatabasePool>| async move {
+                        .endpoint(|bot: Bot, dialogue: MyDialogue, q: CallbackQuery, db_pool: Arc<DatabasePool>, task_manager: Arc<TaskManager>| async move {
                             if let Ok(Some(BroadcastState::WaitingForConfirmation { message })) = dialogue.get().await {
-                                handle_broadcast_confirmation(bot, dialogue, q, db_pool, message).await
+                                handle_broadcast_confirmation(bot, dialogue, q, db_pool, task_manager, message).await
                             } else {
                                 Ok(())
                             }
@@ -82,7 +95,7 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
                 .branch(
                     Update::filter_message()
                         .filter_command::<AdminCommand>()
-                        .endpoint(|bot: Bot, msg: Message, cmd: AdminCommand, db_pool: Arc<DatabasePool>| async move {
+                        .endpoint(|bot: Bot, msg: Message, cmd: AdminCommand, db_pool: Arc<DatabasePool>, task_manager: Arc<TaskManager>, stages: Arc<Stages>| async move {
                             if !handlers::admin::is_admin(&msg).await {
                                 bot.send_message(msg.chat.id, "This command is for admins only.").await?;
                                 return Ok(());
@@ -140,6 +153,28 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
                                         bot.send_message(msg.chat.id, "✅ [TEST] Premium activated!").await?;
                                     }
                                 }
+                                AdminCommand::PurgeCache { target } => {
+                                    let target = target.trim().to_string();
+                                    if target.is_empty() {
+                                        bot.send_message(msg.chat.id, "Usage: /purgecache <all|expired|url>").await?;
+                                    } else {
+                                        match db_pool.purge_media_cache(&target).await {
+                                            Ok(removed) => {
+                                                bot.send_message(msg.chat.id, format!("🗑 Media cache: removed {} entries.", removed)).await?;
+                                            }
+                                            Err(e) => {
+                                                log::error!("PurgeCache failed: {}", e);
+                                                bot.send_message(msg.chat.id, "❌ Failed to purge media cache.").await?;
+                                            }
+                                        }
+                                    }
+                                }
+                                AdminCommand::Tasks => {
+                                    bot.send_message(msg.chat.id, handlers::admin::tasks_report(&task_manager.tasks())).await?;
+                                }
+                                AdminCommand::Stages => {
+                                    bot.send_message(msg.chat.id, handlers::admin::stages_report(&stages.metrics())).await?;
+                                }
                                 AdminCommand::ResetPremium => {
                                     if let Some(user) = msg.from {
                                         let user_id = user.id.0 as i64;
@@ -159,12 +194,61 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
                     let ytdlp = exe_dir.join("lib").join("yt-dlp").to_string_lossy().to_string();
                     handlers::fingerprint::set_fingerprint_handler(bot, msg, db_pool, fp, &ytdlp).await
                 }))
+                .branch(Update::filter_message().filter_command::<Command>().filter(|cmd: Command| matches!(cmd, Command::Dl { .. })).endpoint(|bot: Bot, msg: Message, fetcher: Arc<YoutubeFetcher>, db_pool: Arc<DatabasePool>, job_queue: Arc<JobQueue>, task_manager: Arc<TaskManager>| async move {
+                    let chat_id = msg.chat.id;
+                    let spawned = task_manager.spawn_service(format!("/dl: chat {}", chat_id), {
+                        let bot = bot.clone();
+                        |token| async move {
+                            let _ = handlers::link::dl_command_handler(bot, msg, fetcher, db_pool, job_queue, token).await;
+                        }
+                    });
+                    if spawned.is_none() {
+                        bot.send_message(chat_id, RESTARTING_TEXT).await?;
+                    }
+                    Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                .branch(Update::filter_message().filter_command::<Command>().filter(|cmd: Command| matches!(cmd, Command::Clip { .. })).endpoint(|bot: Bot, msg: Message, fetcher: Arc<YoutubeFetcher>, db_pool: Arc<DatabasePool>, job_queue: Arc<JobQueue>, task_manager: Arc<TaskManager>| async move {
+                    let chat_id = msg.chat.id;
+                    let spawned = task_manager.spawn_service(format!("/clip: chat {}", chat_id), {
+                        let bot = bot.clone();
+                        |token| async move {
+                            let _ = handlers::link::clip_command_handler(bot, msg, fetcher, db_pool, job_queue, token).await;
+                        }
+                    });
+                    if spawned.is_none() {
+                        bot.send_message(chat_id, RESTARTING_TEXT).await?;
+                    }
+                    Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                .branch(Update::filter_message().filter_command::<Command>().filter(|cmd: Command| matches!(cmd, Command::Follow { .. } | Command::Unfollow { .. } | Command::Following)).endpoint(handlers::follow::follow_command_handler))
+                .branch(Update::filter_message().filter_command::<Command>().filter(|cmd: Command| matches!(cmd, Command::Subs { .. })).endpoint(|bot: Bot, msg: Message, fetcher: Arc<YoutubeFetcher>, db_pool: Arc<DatabasePool>, job_queue: Arc<JobQueue>, task_manager: Arc<TaskManager>| async move {
+                    let chat_id = msg.chat.id;
+                    let spawned = task_manager.spawn_service(format!("/subs: chat {}", chat_id), {
+                        let bot = bot.clone();
+                        |token| async move {
+                            let _ = handlers::link::subs_command_handler(bot, msg, fetcher, db_pool, job_queue, token).await;
+                        }
+                    });
+                    if spawned.is_none() {
+                        bot.send_message(chat_id, RESTARTING_TEXT).await?;
+                    }
+                    Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                }))
                 .branch(Update::filter_message().filter_command::<Command>().endpoint(command_handler))
                 .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(BTN_SETTINGS)).endpoint(settings_text_handler))
                 .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(BTN_FORMAT)).endpoint(format_text_handler))
                 .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(BTN_ADMIN_PANEL)).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
                     admin_panel_text_handler(bot, msg, db_pool).await
                 }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::admin_panel::BTN_PLATFORMS)).endpoint(handlers::platforms_text_handler))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::admin_panel::PLATFORM_TOGGLE_PREFIX))).endpoint(handlers::platform_toggle_callback))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_PROVIDERS)).endpoint(handlers::providers_text_handler))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::admin_panel::PROVIDER_ORDER_PREFIX))).endpoint(handlers::provider_order_callback))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::group_settings::GROUP_SETTINGS_PREFIX))).endpoint(handlers::group_settings::group_settings_callback))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::format_picker::FORMAT_PICKER_PREFIX))).endpoint(handlers::format_picker::format_picker_callback))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::queue::CANCEL_PREFIX))).endpoint(handlers::queue::cancel_callback))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::collection::COLLECTION_PREFIX))).endpoint(handlers::collection::collection_callback))
+                .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with(handlers::follow::UNFOLLOW_PREFIX))).endpoint(handlers::follow::unfollow_callback))
                 .branch(Update::filter_callback_query().filter(|q: CallbackQuery| q.data == Some("buy_premium".to_string())).endpoint(|bot: Bot, q: CallbackQuery, db_pool: Arc<DatabasePool>| async move {
                     let _ = bot.answer_callback_query(q.id).await;
                     handlers::payments::send_premium_invoice(bot, q.from.id.into(), db_pool, None).await
@@ -175,6 +259,30 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
                     admin_panel_text_handler(bot, msg, db_pool).await
                 }))
                 .branch(Update::filter_message().filter(|msg: Message| msg.text().map_or(false, |t| t.starts_with("🔔 Admin Ads:"))).endpoint(admin_ads_text_handler))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with(handlers::ui::BTN_TOGGLE_CAPTIONS))).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let id = msg.chat.id.0;
+                    let enabled = !db_pool.get_user_captions(id).await;
+                    let _ = db_pool.set_user_captions(id, enabled).await;
+                    settings_text_handler(bot, msg, db_pool).await
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with(handlers::ui::BTN_SUBTITLES))).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let id = msg.chat.id.0;
+                    let mode = db_pool.get_user_subtitles(id).await.next();
+                    let _ = db_pool.set_user_subtitles(id, mode).await;
+                    settings_text_handler(bot, msg, db_pool).await
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with(handlers::ui::BTN_TOGGLE_SPLIT_VIDEOS))).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let id = msg.chat.id.0;
+                    let enabled = !db_pool.get_user_split_videos(id).await;
+                    let _ = db_pool.set_user_split_videos(id, enabled).await;
+                    settings_text_handler(bot, msg, db_pool).await
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with(handlers::ui::BTN_TOGGLE_FORMAT_PICKER))).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let id = msg.chat.id.0;
+                    let enabled = !db_pool.get_user_format_picker(id).await;
+                    let _ = db_pool.set_user_format_picker(id, enabled).await;
+                    format_text_handler(bot, msg, db_pool).await
+                }))
                 .branch(Update::filter_message().filter(|msg: Message| msg.text().map_or(false, |t| t.starts_with(handlers::ui::BTN_TOGGLE_SUCCESS_NOTIFS))).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
                     let curr = db_pool.get_setting("notify_success").await.map(|v| v == "true").unwrap_or(true);
                     let _ = db_pool.set_setting("notify_success", if !curr { "true" } else { "false" }).await;
@@ -215,17 +323,59 @@ pub fn build_handler() -> Handler<'static, Result<(), Box<dyn std::error::Error
                     bot.send_message(msg.chat.id, "Quality: audio").reply_markup(handlers::command::get_main_reply_keyboard()).await?;
                     Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
                 }))
-                .branch(Update::filter_message().filter(|msg: Message| msg.text().map(|t| !handlers::ui::is_system_button(t)).unwrap_or(false)).endpoint(|bot: Bot, msg: Message, fetcher: Arc<YoutubeFetcher>, mtproto_uploader: Arc<MTProtoUploader>, db_pool: Arc<DatabasePool>, task_manager: Arc<tokio::sync::Mutex<TaskManager>>, upload_semaphore: Arc<tokio::sync::Semaphore>| async move {
-                    let key = format!("{}:{}:{}", msg.chat.id.0, msg.id.0, msg.text().unwrap_or(""));
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_VIDEO_NOTE)).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let id = msg.chat.id.0;
+                    let _ = db_pool.execute_with_timeout(move |c| c.execute("UPDATE users SET quality_preference = ?1 WHERE telegram_id = ?2", rusqlite::params![QUALITY_VIDEO_NOTE, id])).await;
+                    db_pool.invalidate_user_quality_cache(id).await;
+                    bot.send_message(msg.chat.id, "Quality: video note").reply_markup(handlers::command::get_main_reply_keyboard()).await?;
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_ANIMATION)).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let id = msg.chat.id.0;
+                    let _ = db_pool.execute_with_timeout(move |c| c.execute("UPDATE users SET quality_preference = ?1 WHERE telegram_id = ?2", rusqlite::params![QUALITY_ANIMATION, id])).await;
+                    db_pool.invalidate_user_quality_cache(id).await;
+                    bot.send_message(msg.chat.id, "Quality: GIF").reply_markup(handlers::command::get_main_reply_keyboard()).await?;
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_AUDIO_SETTINGS)).endpoint(handlers::audio_settings_text_handler))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with(handlers::ui::BTN_AUDIO_FORMAT_PREFIX))).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let Some(format) = msg.text().and_then(handlers::text::parse_audio_format_button) else {
+                        return handlers::audio_settings_text_handler(bot, msg, db_pool).await;
+                    };
+                    let _ = db_pool.set_user_audio_format(msg.chat.id.0, format).await;
+                    bot.send_message(msg.chat.id, format!("Audio format: {}", format.label())).reply_markup(handlers::command::get_main_reply_keyboard()).await?;
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_SLIDESHOW_ALBUM)).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let _ = db_pool.set_user_slideshow_mode(msg.chat.id.0, database::SLIDESHOW_ALBUM).await;
+                    bot.send_message(msg.chat.id, "Slideshows: album").reply_markup(handlers::command::get_main_reply_keyboard()).await?;
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                .branch(Update::filter_message().filter(|msg: Message| msg.text() == Some(handlers::ui::BTN_SLIDESHOW_VIDEO)).endpoint(|bot: Bot, msg: Message, db_pool: Arc<DatabasePool>| async move {
+                    let _ = db_pool.set_user_slideshow_mode(msg.chat.id.0, database::SLIDESHOW_VIDEO).await;
+                    bot.send_message(msg.chat.id, "Slideshows: video").reply_markup(handlers::command::get_main_reply_keyboard()).await?;
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+                }))
+                // Text messages and media captions (forwarded posts included) may carry links
+                .branch(Update::filter_message().filter(|msg: Message| msg.text().or(msg.caption()).is_some_and(|t| !handlers::ui::is_system_button(t))).endpoint(|bot: Bot, msg: Message, fetcher: Arc<YoutubeFetcher>, db_pool: Arc<DatabasePool>, job_queue: Arc<JobQueue>, task_manager: Arc<TaskManager>| async move {
+                    let key = format!("{}:{}:{}", msg.chat.id.0, msg.id.0, msg.text().or(msg.caption()).unwrap_or(""));
                     {
                         let mut p = PROCESSING.lock().await;
                         if p.contains(&key) { return Ok(()); }
                         p.insert(key.clone());
                     }
-                    tokio::spawn(async move {
-                        let _ = link_handler(bot.clone(), msg.clone(), fetcher, mtproto_uploader, db_pool, task_manager, upload_semaphore).await;
-                        PROCESSING.lock().await.remove(&key);
+                    let chat_id = msg.chat.id;
+                    let spawned = task_manager.spawn_service(format!("links: chat {}", chat_id), {
+                        let key = key.clone();
+                        |token| async move {
+                            let _ = link_handler(bot, msg, fetcher, db_pool, job_queue, token).await;
+                            PROCESSING.lock().await.remove(&key);
+                        }
                     });
+                    // Shutting down: the link is dropped quietly, a group may not even expect an answer.
+                    if spawned.is_none() {
+                        PROCESSING.lock().await.remove(&key);
+                    }
                     Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
                 }))
         )
@@ -2,13 +2,16 @@ use anyhow::Error;
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::env;
+use std::time::Duration;
 use teloxide::prelude::*;
 use tokio::sync::Mutex;
 
 use tiktokdownloader::database::DatabasePool;
 use tiktokdownloader::handlers::broadcast::BroadcastState;
 use tiktokdownloader::mtproto_uploader::MTProtoUploader;
+use tiktokdownloader::utils::stages::Stages;
 use tiktokdownloader::utils::task_manager::TaskManager;
+use tiktokdownloader::handlers::queue::JobQueue;
 use tiktokdownloader::yt_dlp_interface::{ensure_binaries, is_executable_present, YoutubeFetcher};
 use tiktokdownloader::build_handler;
 use teloxide::dispatching::dialogue;
@@ -78,12 +81,22 @@ async fn main() -> Result<(), Error> {
         return Err(anyhow::Error::msg("yt-dlp not available"));
     }
 
+    // All background work runs through the task manager, which drains it on shutdown
+    let max_background_tasks: usize = env::var("MAX_BACKGROUND_TASKS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8);
+    let task_manager = Arc::new(TaskManager::new(max_background_tasks));
+
     let auto_updater = Arc::new(tiktokdownloader::auto_update::AutoUpdater::new(libraries_dir.clone(), 30));
     let _ = auto_updater.check_for_updates().await;
 
     let updater_clone = Arc::clone(&auto_updater);
-    tokio::spawn(async move {
-        let _ = updater_clone.start_periodic_checks().await;
+    task_manager.spawn_service("auto updater", |stop| async move {
+        tokio::select! {
+            _ = updater_clone.start_periodic_checks() => {}
+            _ = stop.cancelled() => {}
+        }
     });
 
     if let Err(e) = tiktokdownloader::database::init_database() {
@@ -128,6 +141,13 @@ async fn main() -> Result<(), Error> {
     log::info!("🗄️ Using database at: {}", db_path);
     let db_pool = Arc::new(DatabasePool::new(db_path, 3));
 
+    // Download source orders chosen in the admin panel
+    for platform in tiktokdownloader::platforms::registry().all() {
+        if let Some(order) = db_pool.get_provider_order(platform.id()).await {
+            fetcher.providers.set_order(platform.id(), order);
+        }
+    }
+
     // Sync settings from .env to database (only as initial defaults, don't overwrite admin panel values)
     if let Ok(sub_req) = env::var("SUBSCRIPTION_REQUIRED") {
         if db_pool.get_setting("subscription_required").await.is_err() {
@@ -142,17 +162,26 @@ async fn main() -> Result<(), Error> {
         }
     }
 
-    let task_manager = Arc::new(tokio::sync::Mutex::new(TaskManager::new(2)));
-    let upload_semaphore = Arc::new(tokio::sync::Semaphore::new(2));
+    // Downloads, ffmpeg work and uploads are limited separately
+    let stages = Arc::new(Stages::from_env());
+
+    // Downloads run as jobs; the ones a restart interrupted are picked up again first.
+    let job_queue = Arc::new(JobQueue::new(
+        bot.clone(),
+        fetcher.clone(),
+        mtproto_uploader.clone(),
+        db_pool.clone(),
+        task_manager.clone(),
+        stages.clone(),
+    ));
+    job_queue.recover().await;
+    job_queue.start_workers();
 
     // --- Web Server Configuration ---
     let web_server_state = tiktokdownloader::web_server::AppState {
         db: db_pool.clone(),
         bot: bot.clone(),
-        fetcher: fetcher.clone(),
-        mtproto_uploader: mtproto_uploader.clone(),
-        task_manager: task_manager.clone(),
-        upload_semaphore: upload_semaphore.clone(),
+        job_queue: job_queue.clone(),
     };
 
     let web_port: u16 = env::var("WEB_SERVER_PORT")
@@ -160,8 +189,17 @@ async fn main() -> Result<(), Error> {
         .parse()
         .unwrap_or(8088);
 
-    tokio::spawn(async move {
-        tiktokdownloader::web_server::start_web_server(web_server_state, web_port).await;
+    task_manager.spawn_service("web server", |stop| async move {
+        tokio::select! {
+            _ = tiktokdownloader::web_server::start_web_server(web_server_state, web_port) => {}
+            _ = stop.cancelled() => {}
+        }
+    });
+
+    // New posts of followed profiles
+    let (poller_bot, poller_fetcher, poller_db, poller_queue) = (bot.clone(), fetcher.clone(), db_pool.clone(), job_queue.clone());
+    task_manager.spawn_service("follow poller", |stop| {
+        tiktokdownloader::handlers::follow::run_follow_poller(poller_bot, poller_fetcher, poller_db, poller_queue, stop)
     });
 
     let handler = build_handler();
@@ -174,17 +212,50 @@ async fn main() -> Result<(), Error> {
             mtproto_uploader, 
             db_pool, 
             task_manager.clone(), 
-            upload_semaphore
+            stages,
+            job_queue
         ])
         .enable_ctrlc_handler()
         .build();
 
     tokio::select! {
         _ = dispatcher.dispatch() => {},
-        _ = tokio::signal::ctrl_c() => log::info!("Received Ctrl+C, shutting down..."),
+        _ = shutdown_signal() => log::info!("Received shutdown signal, shutting down..."),
+    }
+
+    // Uploads in progress get SHUTDOWN_GRACE_SECS to finish; the rest is requeued on the next start
+    let grace = env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    let interrupted = task_manager.shutdown(Duration::from_secs(grace)).await;
+    for name in &interrupted {
+        log::warn!("Interrupted by shutdown: {}", name);
     }
-    let mut tm = task_manager.lock().await;
-    tm.shutdown().await;
     log::info!("Bot shutdown complete");
     Ok(())
 }
+
+/// Resolves on Ctrl+C or, on Unix, SIGTERM (sent by `docker stop` and systemd).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -0,0 +1,197 @@
+use grammers_client::{Client, InvocationError};
+use grammers_tl_types as tl;
+use std::path::{Path, PathBuf};
+
+use crate::peers::resolve_peer;
+use crate::mtproto_uploader::uploader::MTProtoUploader;
+use crate::mtproto_uploader::message_sender::{input_reply_to, send_input_media_with_retry};
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::SendOptions;
+use crate::yt_dlp_interface::slideshow::MEDIA_GROUP_LIMIT;
+
+/// Photos are at most 10 MB, so they always go through `upload.saveFilePart` (a small file
+/// in Telegram's terms) rather than `saveBigFilePart`, which photo uploads reject.
+async fn upload_photo_file(
+    client: &Client,
+    file_path: &Path,
+) -> Result<tl::enums::InputFile, Box<dyn std::error::Error + Send + Sync>> {
+    const PART_SIZE: usize = 512 * 1024;
+
+    let bytes = tokio::fs::read(file_path).await?;
+    let file_id: i64 = rand::random();
+    let mut parts = 0;
+    for (idx, chunk) in bytes.chunks(PART_SIZE).enumerate() {
+        client.invoke(&tl::functions::upload::SaveFilePart {
+            file_id,
+            file_part: idx as i32,
+            bytes: chunk.to_vec(),
+        }).await.map_err(|e| anyhow::anyhow!("saveFilePart failed: {:?}", e))?;
+        parts += 1;
+    }
+
+    Ok(tl::enums::InputFile::File(tl::types::InputFile {
+        id: file_id,
+        parts,
+        name: file_path
+            .file_name()
+            .and_then(|os_str| os_str.to_str())
+            .unwrap_or("photo.jpg")
+            .to_string(),
+        md5_checksum: String::new(),
+    }))
+}
+
+impl MTProtoUploader {
+    /// Uploads a photo with `messages.uploadMedia` and returns it as a sendable `InputMedia`.
+    async fn upload_album_photo(
+        &self,
+        input_peer: &tl::enums::InputPeer,
+        file_path: &Path,
+    ) -> Result<tl::enums::InputMedia, Box<dyn std::error::Error + Send + Sync>> {
+        let file_path = file_path.to_path_buf();
+        let input_peer = input_peer.clone();
+
+        let uploaded = self.with_reconnect_retry(|| {
+            let uploader = self.clone();
+            let file_path = file_path.clone();
+            let input_peer = input_peer.clone();
+
+            Box::pin(async move {
+                let client = uploader.client.lock().await;
+                let file = upload_photo_file(&client, &file_path).await?;
+                let media = client.invoke(&tl::functions::messages::UploadMedia {
+                    business_connection_id: None,
+                    peer: input_peer,
+                    media: tl::enums::InputMedia::UploadedPhoto(tl::types::InputMediaUploadedPhoto {
+                        spoiler: false,
+                        file,
+                        stickers: None,
+                        ttl_seconds: None,
+                    }),
+                }).await.map_err(|e| anyhow::anyhow!("uploadMedia failed: {:?}", e))?;
+                Ok(media)
+            })
+        }).await?;
+
+        match uploaded {
+            tl::enums::MessageMedia::Photo(tl::types::MessageMediaPhoto {
+                photo: Some(tl::enums::Photo::Photo(photo)),
+                ..
+            }) => Ok(tl::enums::InputMedia::Photo(tl::types::InputMediaPhoto {
+                spoiler: false,
+                id: tl::enums::InputPhoto::Photo(tl::types::InputPhoto {
+                    id: photo.id,
+                    access_hash: photo.access_hash,
+                    file_reference: photo.file_reference,
+                }),
+                ttl_seconds: None,
+            })),
+            other => Err(anyhow::anyhow!("uploadMedia returned unexpected media: {:?}", other).into()),
+        }
+    }
+
+    /// Sends the images as albums with `messages.sendMultiMedia`, `MEDIA_GROUP_LIMIT` per
+    /// message. A trailing single image goes through `messages.sendMedia`. The caption is
+    /// attached to the first photo and only the first message replies.
+    pub async fn upload_album(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        images: &[PathBuf],
+        options: &SendOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let input_peer = resolve_peer(&self.client, chat_id, username.as_deref()).await.map_err(|e| {
+            log::error!("Failed to resolve peer for chat_id {}: {:?}", chat_id, e);
+            e
+        })?;
+
+        let total = images.len();
+        let mut uploaded_count = 0;
+
+        for (chunk_idx, chunk) in images.chunks(MEDIA_GROUP_LIMIT).enumerate() {
+            let mut media = Vec::with_capacity(chunk.len());
+            for path in chunk {
+                media.push(self.upload_album_photo(&input_peer, path).await.map_err(|e| {
+                    log::error!("Failed to upload album photo {:?}: {:?}", path, e);
+                    e
+                })?);
+                uploaded_count += 1;
+                let overall = 80 + ((uploaded_count as f64 / total as f64) * 19.0) as u8;
+                let info = format!("📤 Uploading photos... {}/{}", uploaded_count, total);
+                let _ = progress_bar.update(overall, Some(&info)).await;
+            }
+
+            let chunk_options = if chunk_idx == 0 {
+                options.clone()
+            } else {
+                SendOptions { silent: options.silent, ..Default::default() }
+            };
+            if media.len() == 1 {
+                send_input_media_with_retry(&self.client, input_peer.clone(), media.remove(0), &chunk_options).await?;
+                continue;
+            }
+
+            let multi_media: Vec<tl::enums::InputSingleMedia> = media
+                .into_iter()
+                .enumerate()
+                .map(|(i, m)| tl::enums::InputSingleMedia::Media(tl::types::InputSingleMedia {
+                    media: m,
+                    random_id: rand::random(),
+                    message: if i == 0 { chunk_options.caption_or_empty().to_string() } else { String::new() },
+                    entities: None,
+                }))
+                .collect();
+
+            self.send_multi_media_with_retry(&input_peer, multi_media, &chunk_options).await?;
+        }
+
+        progress_bar.delete().await?;
+        Ok(())
+    }
+
+    async fn send_multi_media_with_retry(
+        &self,
+        input_peer: &tl::enums::InputPeer,
+        multi_media: Vec<tl::enums::InputSingleMedia>,
+        options: &SendOptions,
+    ) -> Result<tl::enums::Updates, Box<dyn std::error::Error + Send + Sync>> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match {
+                let client = self.client.lock().await;
+                client.invoke(&tl::functions::messages::SendMultiMedia {
+                    silent: options.silent,
+                    background: false,
+                    clear_draft: false,
+                    noforwards: false,
+                    update_stickersets_order: false,
+                    invert_media: false,
+                    peer: input_peer.clone(),
+                    reply_to: input_reply_to(options),
+                    multi_media: multi_media.clone(),
+                    schedule_date: None,
+                    send_as: None,
+                    quick_reply_shortcut: None,
+                    effect: None,
+                }).await
+            } {
+                Ok(updates) => return Ok(updates),
+                Err(InvocationError::Rpc(e)) if e.name.starts_with("FLOOD_WAIT_") => {
+                    let secs = e.code as u64;
+                    log::warn!("sendMultiMedia FLOOD_WAIT: waiting {} seconds", secs);
+                    tokio::time::sleep(std::time::Duration::from_secs(secs.min(30))).await;
+                }
+                Err(e) if attempts < 3 => {
+                    log::warn!("sendMultiMedia attempt {} failed: {:?}. Retrying...", attempts, e);
+                    tokio::time::sleep(std::time::Duration::from_millis(500 * attempts as u64)).await;
+                }
+                Err(e) => {
+                    log::error!("sendMultiMedia failed after {} attempts: {:?}", attempts, e);
+                    return Err(anyhow::anyhow!("sendMultiMedia failed after {} attempts: {:?}", attempts, e).into());
+                }
+            }
+        }
+    }
+}
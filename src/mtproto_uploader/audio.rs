@@ -2,15 +2,16 @@ use crate::peers::resolve_peer;
 
 use anyhow;
 use grammers_tl_types as tl;
-use rand::SeedableRng;
-use rand_chacha::ChaCha8Rng;
 use std::path::Path;
 use log;
 
 use crate::utils::progress_bar::ProgressBar;
+use crate::utils::{AudioMeta, SendOptions};
 
 use crate::mtproto_uploader::uploader::MTProtoUploader; // Import MTProtoUploader
-use crate::mtproto_uploader::file_uploader::upload_file_in_parts_with_reconnect;
+use crate::mtproto_uploader::file_uploader::{upload_file_in_parts_with_reconnect, upload_small_file_with_reconnect};
+use crate::mtproto_uploader::message_sender::send_input_media_with_retry;
+use crate::mtproto_uploader::cached::{extract_document, DocumentRef};
 
 impl MTProtoUploader {
     pub async fn upload_audio(
@@ -18,18 +19,44 @@ impl MTProtoUploader {
         chat_id: i64,
         username: Option<String>,
         file_path: &Path,
-        caption: &str,
+        audio: &AudioMeta,
+        options: &SendOptions,
         progress_bar: &mut ProgressBar,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
+        self.upload_audio_document(chat_id, username, file_path, audio, false, options, progress_bar).await
+    }
+
+    /// Sends an OGG/Opus file as a voice message.
+    pub async fn upload_voice(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        file_path: &Path,
+        duration: u32,
+        options: &SendOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
+        let audio = AudioMeta { duration, ..Default::default() };
+        self.upload_audio_document(chat_id, username, file_path, &audio, true, options, progress_bar).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_audio_document(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        file_path: &Path,
+        audio: &AudioMeta,
+        voice: bool,
+        options: &SendOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
         // Upload the audio file using reconnect mechanism
         let (file_id, total_parts) = upload_file_in_parts_with_reconnect(self, file_path, progress_bar, "audio").await.map_err(|e| {
             log::error!("Failed to upload audio file {:?}: {:?}", file_path, e);
             e
         })?;
 
-        // Access the actual client through the mutex
-        let client = self.client.lock().await;
-        
         let input_peer = resolve_peer(&self.client, chat_id, username.as_deref()).await.map_err(|e| {
             log::error!("Failed to resolve peer: {:?}", e);
             e
@@ -53,23 +80,52 @@ impl MTProtoUploader {
             "mp3" => "audio/mpeg",
             "m4a" => "audio/mp4",
             "aac" => "audio/aac",
-            "ogg" => "audio/ogg",
+            "ogg" | "opus" => "audio/ogg",
+            "flac" => "audio/flac",
             _ => "audio/mpeg",
         }.to_string();
 
         let audio_attr = tl::enums::DocumentAttribute::Audio(tl::types::DocumentAttributeAudio {
-            voice: false,
-            duration: 0,              // optionally calculate beforehand
-            title: None,
-            performer: None,
+            voice,
+            duration: audio.duration as i32,
+            title: audio.title.clone(),
+            performer: audio.performer.clone(),
             waveform: None,
         });
 
+        // The cover is optional: without it Telegram just shows a generic note icon.
+        let thumb = match &audio.thumbnail {
+            Some(thumbnail_path) => match upload_small_file_with_reconnect(self, thumbnail_path).await {
+                Ok((thumb_id, thumb_parts)) => {
+                    let name = thumbnail_path
+                        .file_name()
+                        .and_then(|os_str| os_str.to_str())
+                        .unwrap_or("cover.jpg")
+                        .to_string();
+                    Some(if thumb_parts == 1 {
+                        tl::enums::InputFile::File(tl::types::InputFile {
+                            id: thumb_id,
+                            parts: 1,
+                            name,
+                            md5_checksum: String::new(),
+                        })
+                    } else {
+                        tl::enums::InputFile::Big(tl::types::InputFileBig { id: thumb_id, parts: thumb_parts, name })
+                    })
+                }
+                Err(e) => {
+                    log::warn!("Failed to upload audio cover {:?}: {:?}", thumbnail_path, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
         let media = tl::enums::InputMedia::UploadedDocument(tl::types::InputMediaUploadedDocument {
             nosound_video: false,
             spoiler: false,
             file: input_file,
-            thumb: None,
+            thumb,
             mime_type: mime,
             force_file: false,
             attributes: vec![audio_attr],
@@ -77,34 +133,11 @@ impl MTProtoUploader {
             ttl_seconds: None,
         });
 
-        let mut rng = ChaCha8Rng::from_os_rng();
-        
-        // Sending message
-        let request = tl::functions::messages::SendMedia {
-            silent: false,
-            background: false,
-            clear_draft: false,
-            noforwards: false,
-            update_stickersets_order: false,
-            peer: input_peer,
-            reply_to: None,
-            media,
-            message: caption.to_string(),
-            random_id: rand::Rng::random(&mut rng),
-            reply_markup: None,
-            entities: Some(Vec::new()),
-            schedule_date: None,
-            send_as: None,
-            effect: None,
-            invert_media: false,
-            quick_reply_shortcut: None,
-        };
-        
-        client.invoke(&request).await.map_err(|e| {
+        let updates = send_input_media_with_retry(&self.client, input_peer, media, options).await.map_err(|e| {
             log::error!("Failed to send audio: {:?}", e);
             e
         })?;
-        
-        Ok(())
+
+        Ok(extract_document(&updates))
     }
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+use grammers_tl_types as tl;
+
+use crate::peers::resolve_peer;
+use crate::mtproto_uploader::uploader::MTProtoUploader;
+use crate::mtproto_uploader::message_sender::send_input_media_with_retry;
+use crate::utils::SendOptions;
+
+/// A document that Telegram already stores, enough to re-send it with `InputMediaDocument`
+/// without uploading the bytes again. Serialized as `id:access_hash:file_reference_hex`
+/// so it fits into the `media_cache.file_id` column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentRef {
+    pub id: i64,
+    pub access_hash: i64,
+    pub file_reference: Vec<u8>,
+}
+
+impl DocumentRef {
+    pub fn encode(&self) -> String {
+        let hex: String = self.file_reference.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}:{}:{}", self.id, self.access_hash, hex)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, ':');
+        let id = parts.next()?.parse().ok()?;
+        let access_hash = parts.next()?.parse().ok()?;
+        let hex = parts.next()?;
+        if hex.len() % 2 != 0 {
+            return None;
+        }
+        let file_reference = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Self { id, access_hash, file_reference })
+    }
+
+    fn input_document(&self) -> tl::enums::InputDocument {
+        tl::enums::InputDocument::Document(tl::types::InputDocument {
+            id: self.id,
+            access_hash: self.access_hash,
+            file_reference: self.file_reference.clone(),
+        })
+    }
+}
+
+/// Finds the document of the message created by a `messages.sendMedia` call.
+pub fn extract_document(updates: &tl::enums::Updates) -> Option<DocumentRef> {
+    let list: Vec<&tl::enums::Update> = match updates {
+        tl::enums::Updates::Updates(u) => u.updates.iter().collect(),
+        tl::enums::Updates::Combined(u) => u.updates.iter().collect(),
+        tl::enums::Updates::UpdateShort(u) => vec![&u.update],
+        _ => Vec::new(),
+    };
+
+    list.into_iter().find_map(|update| {
+        let message = match update {
+            tl::enums::Update::NewMessage(u) => &u.message,
+            tl::enums::Update::NewChannelMessage(u) => &u.message,
+            _ => return None,
+        };
+        let tl::enums::Message::Message(message) = message else {
+            return None;
+        };
+        let Some(tl::enums::MessageMedia::Document(media)) = message.media.as_ref() else {
+            return None;
+        };
+        match media.document.as_ref()? {
+            tl::enums::Document::Document(d) => Some(DocumentRef {
+                id: d.id,
+                access_hash: d.access_hash,
+                file_reference: d.file_reference.clone(),
+            }),
+            _ => None,
+        }
+    })
+}
+
+impl MTProtoUploader {
+    /// Re-sends a previously uploaded document by reference. Fails with FILE_REFERENCE_EXPIRED
+    /// (or similar) when Telegram no longer accepts the reference; callers should then drop
+    /// the cache entry and upload again.
+    pub async fn send_cached_document(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        document: &DocumentRef,
+        options: &SendOptions,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let input_peer = resolve_peer(&self.client, chat_id, username.as_deref()).await.map_err(|e| {
+            log::error!("Failed to resolve peer for chat_id {}: {:?}", chat_id, e);
+            e
+        })?;
+
+        let media = tl::enums::InputMedia::Document(tl::types::InputMediaDocument {
+            spoiler: false,
+            id: document.input_document(),
+            ttl_seconds: None,
+            query: None,
+        });
+
+        send_input_media_with_retry(&self.client, input_peer, media, options).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_ref_roundtrip() {
+        let doc = DocumentRef {
+            id: 5_123_456_789,
+            access_hash: -42,
+            file_reference: vec![0x00, 0x0f, 0xab, 0xff],
+        };
+        let encoded = doc.encode();
+        assert_eq!(encoded, "5123456789:-42:000fabff");
+        assert_eq!(DocumentRef::decode(&encoded), Some(doc));
+    }
+
+    #[test]
+    fn test_document_ref_decode_rejects_garbage() {
+        assert_eq!(DocumentRef::decode("not-a-ref"), None);
+        assert_eq!(DocumentRef::decode("1:2:abc"), None);
+        assert_eq!(DocumentRef::decode("1:2:zz"), None);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::peers::resolve_peer;
+
+use grammers_tl_types as tl;
+use std::path::Path;
+
+use crate::mtproto_uploader::cached::{extract_document, DocumentRef};
+use crate::mtproto_uploader::file_uploader::{upload_file_in_parts_with_reconnect, upload_small_file_with_reconnect};
+use crate::mtproto_uploader::message_sender::send_input_media_with_retry;
+use crate::mtproto_uploader::metadata::get_video_metadata;
+use crate::mtproto_uploader::thumbnail::generate_thumbnail;
+use crate::mtproto_uploader::uploader::MTProtoUploader;
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::temp_file::TempFileGuard;
+use crate::utils::SendOptions;
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|os_str| os_str.to_str())
+        .unwrap_or("video.mp4")
+        .to_string()
+}
+
+impl MTProtoUploader {
+    /// Sends a square MP4 as a round video note. Video notes can't carry a caption, so
+    /// `options.caption` is ignored.
+    pub async fn upload_video_note(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        file_path: &Path,
+        options: &SendOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
+        let options = SendOptions { caption: None, ..options.clone() };
+        self.upload_clip(chat_id, username, file_path, true, &options, progress_bar).await
+    }
+
+    /// Sends a silent MP4 as an animation (GIF).
+    pub async fn upload_animation(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        file_path: &Path,
+        options: &SendOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
+        self.upload_clip(chat_id, username, file_path, false, options, progress_bar).await
+    }
+
+    /// Uploads a video note (`round`) or an animation with its thumbnail.
+    async fn upload_clip(
+        &self,
+        chat_id: i64,
+        username: Option<String>,
+        file_path: &Path,
+        round: bool,
+        options: &SendOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
+        let metadata = get_video_metadata(self.ffprobe_path.to_string_lossy().as_ref(), file_path).await.map_err(|e| {
+            log::error!("Failed to get video metadata for {:?}: {:?}", file_path, e);
+            e
+        })?;
+
+        let (file_id, file_parts) = upload_file_in_parts_with_reconnect(self, file_path, progress_bar, "video").await.map_err(|e| {
+            log::error!("Failed to upload clip {:?}: {:?}", file_path, e);
+            e
+        })?;
+
+        let thumbnail_path = file_path.with_extension("jpg");
+        let thumb = match generate_thumbnail(&self.ffmpeg_path, file_path, &thumbnail_path).await {
+            Ok(()) => {
+                let _thumbnail_guard = TempFileGuard::new(thumbnail_path.clone());
+                match upload_small_file_with_reconnect(self, &thumbnail_path).await {
+                    Ok((id, 1)) => Some(tl::enums::InputFile::File(tl::types::InputFile {
+                        id,
+                        parts: 1,
+                        name: file_name(&thumbnail_path),
+                        md5_checksum: String::new(),
+                    })),
+                    Ok((id, parts)) => Some(tl::enums::InputFile::Big(tl::types::InputFileBig {
+                        id,
+                        parts,
+                        name: file_name(&thumbnail_path),
+                    })),
+                    Err(e) => {
+                        log::warn!("Failed to upload clip thumbnail {:?}: {:?}", thumbnail_path, e);
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to generate clip thumbnail for {:?}: {:?}", file_path, e);
+                None
+            }
+        };
+
+        let input_peer = resolve_peer(&self.client, chat_id, username.as_deref()).await.map_err(|e| {
+            log::error!("Failed to resolve peer: {:?}", e);
+            e
+        })?;
+
+        let video_attr = tl::enums::DocumentAttribute::Video(tl::types::DocumentAttributeVideo {
+            round_message: round,
+            supports_streaming: !round,
+            nosound: !round,
+            duration: metadata.duration,
+            w: metadata.width as i32,
+            h: metadata.height as i32,
+            preload_prefix_size: None,
+            video_start_ts: None,
+        });
+        let mut attributes = vec![video_attr];
+        if !round {
+            // Marks the MP4 as a GIF, so clients loop it without sound controls.
+            attributes.push(tl::enums::DocumentAttribute::Animated);
+        }
+
+        let media = tl::enums::InputMedia::UploadedDocument(tl::types::InputMediaUploadedDocument {
+            nosound_video: !round,
+            spoiler: false,
+            file: tl::enums::InputFile::Big(tl::types::InputFileBig {
+                id: file_id,
+                parts: file_parts,
+                name: file_name(file_path),
+            }),
+            thumb,
+            mime_type: "video/mp4".to_string(),
+            force_file: false,
+            attributes,
+            stickers: None,
+            ttl_seconds: None,
+        });
+
+        let updates = send_input_media_with_retry(&self.client, input_peer, media, options).await.map_err(|e| {
+            log::error!("Failed to send clip: {:?}", e);
+            e
+        })?;
+
+        Ok(extract_document(&updates))
+    }
+}
@@ -5,6 +5,21 @@ use anyhow;
 use tokio::sync::Mutex;
 
 use crate::peers::resolve_peer;
+use crate::utils::SendOptions;
+
+/// `reply_to` for an MTProto send built from the Bot API style options.
+pub fn input_reply_to(options: &SendOptions) -> Option<tl::enums::InputReplyTo> {
+    options.reply_to.map(|id| {
+        tl::enums::InputReplyTo::Message(tl::types::InputReplyToMessage {
+            reply_to_msg_id: id,
+            top_msg_id: None,
+            reply_to_peer_id: None,
+            quote_text: None,
+            quote_entities: None,
+            quote_offset: None,
+        })
+    })
+}
 
 pub async fn send_media_with_retry(
     client: &Arc<Mutex<Client>>,
@@ -19,8 +34,8 @@ pub async fn send_media_with_retry(
     duration: f64,
     width: u32,
     height: u32,
-    caption: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    options: &SendOptions,
+) -> Result<tl::enums::Updates, Box<dyn std::error::Error + Send + Sync>> {
     // Get input peer
     let input_peer = resolve_peer(client, chat_id, username.as_deref()).await.map_err(|e| {
         log::error!("Failed to resolve peer for chat_id {}: {:?}", chat_id, e);
@@ -96,7 +111,17 @@ pub async fn send_media_with_retry(
         ttl_seconds: None,
     });
 
-    // Sending message with retry logic
+    send_input_media_with_retry(client, input_peer, media, options).await
+}
+
+/// Sends an already-built `InputMedia` to the peer, retrying on FLOOD_WAIT and transient
+/// errors. Returns the raw `Updates` so callers can pull the stored document out of it.
+pub async fn send_input_media_with_retry(
+    client: &Arc<Mutex<Client>>,
+    input_peer: tl::enums::InputPeer,
+    media: tl::enums::InputMedia,
+    options: &SendOptions,
+) -> Result<tl::enums::Updates, Box<dyn std::error::Error + Send + Sync>> {
     let mut attempts = 0;
     loop {
         attempts += 1;
@@ -104,15 +129,15 @@ pub async fn send_media_with_retry(
         match {
             let actual_client = client.lock().await;
             actual_client.invoke(&tl::functions::messages::SendMedia {
-                silent: false,
+                silent: options.silent,
                 background: false,
                 clear_draft: false,
                 noforwards: false,
                 update_stickersets_order: false,
                 peer: input_peer.clone(), // Clone input_peer for retries
-                reply_to: None,
+                reply_to: input_reply_to(options),
                 media: media.clone(), // Clone media for retries
-                message: caption.to_string(),
+                message: options.caption_or_empty().to_string(),
                 random_id,
                 reply_markup: None,
                 entities: Some(Vec::new()),
@@ -123,7 +148,7 @@ pub async fn send_media_with_retry(
                 quick_reply_shortcut: None,
             }).await
         } {
-            Ok(_) => break,
+            Ok(updates) => return Ok(updates),
             Err(InvocationError::Rpc(e)) if e.name.starts_with("FLOOD_WAIT_") => {
                 let secs = e.code as u64;
                 eprintln!("FLOOD_WAIT_X: Waiting for {} seconds", secs);
@@ -146,6 +171,4 @@ pub async fn send_media_with_retry(
             }
         }
     }
-
-    Ok(())
 }
\ No newline at end of file
@@ -34,4 +34,25 @@ pub async fn get_video_metadata(ffprobe_path: &str, file_path: &Path) -> Result<
         }
     }
     Ok(s)
-}
\ No newline at end of file
+}
+
+/// Duration in seconds of any media file (audio included), from the container.
+pub async fn get_media_duration(ffprobe_path: &str, file_path: &Path) -> Result<f64, Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new(ffprobe_path)
+        .arg("-v")
+        .arg("error")
+        .arg("-show_entries")
+        .arg("format=duration")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1:nokey=1")
+        .arg(file_path)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("ffprobe failed: {}", stderr).into());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.trim().parse::<f64>()?)
+}
@@ -7,5 +7,8 @@ pub mod video_metadata;
 pub mod file_uploader;
 pub mod message_sender;
 pub mod video_upload;
+pub mod cached;
+pub mod album;
+pub mod clip_upload;
 
 pub use uploader::MTProtoUploader;
\ No newline at end of file
@@ -8,7 +8,9 @@ use crate::mtproto_uploader::thumbnail::generate_thumbnail;
 use crate::mtproto_uploader::metadata::get_video_metadata;
 use crate::mtproto_uploader::file_uploader::{upload_file_in_parts_with_reconnect, upload_small_file_with_reconnect};
 use crate::mtproto_uploader::message_sender::send_media_with_retry;
+use crate::mtproto_uploader::cached::{extract_document, DocumentRef};
 use crate::utils::temp_file::TempFileGuard;
+use crate::utils::SendOptions;
 
 impl MTProtoUploader {
     async fn ensure_faststart_video(&self, file_path: &Path) -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
@@ -44,9 +46,9 @@ impl MTProtoUploader {
         chat_id: i64,
         username: Option<String>,
         file_path: &Path,
-        caption: &str,
+        options: &SendOptions,
         progress_bar: &mut ProgressBar,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<Option<DocumentRef>, Box<dyn std::error::Error + Send + Sync>> {
         // Create temporary faststart file with guard
         let (video_path, _video_guard) = if file_path.extension().map_or(false, |ext| ext == "mp4") {
             match self.ensure_faststart_video(file_path).await {
@@ -91,7 +93,7 @@ impl MTProtoUploader {
         })?;
 
         // Send the media with retry logic
-        send_media_with_retry(
+        let updates = send_media_with_retry(
             &self.client, // Pass the Arc<Mutex<Client>> directly
             chat_id,
             username,
@@ -104,12 +106,12 @@ impl MTProtoUploader {
             video_metadata.duration,
             video_metadata.width,
             video_metadata.height,
-            caption,
+            options,
         ).await.map_err(|e| {
             log::error!("Failed to send media: {:?}", e);
             e
         })?;
 
-        Ok(())
+        Ok(extract_document(&updates))
     }
 }
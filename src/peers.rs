@@ -56,5 +56,51 @@ pub async fn resolve_peer(
                             }
                         }
                         return Err(anyhow!("Failed to map ResolvedPeer to InputPeer for @{}", un));    }
+
+    // 3) No username: bots may look up chats they are in (and users who wrote to them)
+    //    with access_hash 0, which yields the real access_hash.
+    if let Some(channel_id) = channel_id_from_chat_id(chat_id) {
+        let res = actual_client.invoke(&tl::functions::channels::GetChannels {
+            id: vec![tl::enums::InputChannel::Channel(tl::types::InputChannel { channel_id, access_hash: 0 })],
+        }).await.map_err(|e| anyhow!("channels.getChannels failed for {}: {:?}", chat_id, e))?;
+        let chats = match res {
+            tl::enums::messages::Chats::Chats(c) => c.chats,
+            tl::enums::messages::Chats::Slice(c) => c.chats,
+        };
+        if let Some(hash) = chats.into_iter().find_map(|c| match c {
+            tl::enums::Chat::Channel(c) if c.id == channel_id => c.access_hash,
+            _ => None,
+        }) {
+            return Ok(tl::enums::InputPeer::Channel(tl::types::InputPeerChannel { channel_id, access_hash: hash }));
+        }
+    } else if chat_id > 0 {
+        let res = actual_client.invoke(&tl::functions::users::GetUsers {
+            id: vec![tl::enums::InputUser::User(tl::types::InputUser { user_id: chat_id, access_hash: 0 })],
+        }).await.map_err(|e| anyhow!("users.getUsers failed for {}: {:?}", chat_id, e))?;
+        if let Some(hash) = res.into_iter().find_map(|u| match u {
+            tl::enums::User::User(u) if u.id == chat_id => u.access_hash,
+            _ => None,
+        }) {
+            return Ok(tl::enums::InputPeer::User(tl::types::InputPeerUser { user_id: chat_id, access_hash: hash }));
+        }
+    }
     Err(anyhow!("Cannot resolve peer {} as bot without username; dialogs are forbidden for bots", chat_id))
+}
+
+/// Bot API ids of channels and supergroups are `-100` followed by the MTProto channel id.
+pub fn channel_id_from_chat_id(chat_id: i64) -> Option<i64> {
+    const CHANNEL_OFFSET: i64 = 1_000_000_000_000;
+    (chat_id < -CHANNEL_OFFSET).then(|| -chat_id - CHANNEL_OFFSET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_id_from_chat_id() {
+        assert_eq!(channel_id_from_chat_id(-1001234567890), Some(1234567890));
+        assert_eq!(channel_id_from_chat_id(-123456), None);
+        assert_eq!(channel_id_from_chat_id(123456), None);
+    }
 }
\ No newline at end of file
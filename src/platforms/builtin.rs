@@ -0,0 +1,296 @@
+use regex::Regex;
+
+use crate::platforms::{strip_query, Fallback, Platform};
+
+/// A platform described entirely by data; all built-in entries use this.
+struct BuiltinPlatform {
+    id: &'static str,
+    name: &'static str,
+    pattern: Regex,
+    /// Links that only redirect to the real post (vm.tiktok.com, pin.it, ...).
+    short_links: Option<Regex>,
+    canonicalize: fn(&str) -> String,
+    yt_dlp_args: &'static [&'static str],
+    fallbacks: &'static [Fallback],
+    audio_only: bool,
+    download_sections: bool,
+}
+
+impl BuiltinPlatform {
+    fn new(id: &'static str, name: &'static str, pattern: &str) -> Self {
+        Self {
+            id,
+            name,
+            pattern: Regex::new(pattern).expect("invalid built-in platform pattern"),
+            short_links: None,
+            canonicalize: strip_query,
+            yt_dlp_args: &[],
+            fallbacks: &[],
+            audio_only: false,
+            download_sections: false,
+        }
+    }
+
+    fn canonicalize_with(mut self, f: fn(&str) -> String) -> Self {
+        self.canonicalize = f;
+        self
+    }
+
+    fn short_links(mut self, pattern: &str) -> Self {
+        self.short_links = Some(Regex::new(pattern).expect("invalid built-in short link pattern"));
+        self
+    }
+
+    fn yt_dlp_args(mut self, args: &'static [&'static str]) -> Self {
+        self.yt_dlp_args = args;
+        self
+    }
+
+    fn fallbacks(mut self, fallbacks: &'static [Fallback]) -> Self {
+        self.fallbacks = fallbacks;
+        self
+    }
+
+    fn audio_only(mut self) -> Self {
+        self.audio_only = true;
+        self
+    }
+
+    fn download_sections(mut self) -> Self {
+        self.download_sections = true;
+        self
+    }
+}
+
+impl Platform for BuiltinPlatform {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn url_pattern(&self) -> &Regex {
+        &self.pattern
+    }
+
+    fn is_short_link(&self, url: &str) -> bool {
+        self.short_links.as_ref().is_some_and(|re| re.is_match(url))
+    }
+
+    fn canonicalize(&self, url: &str) -> String {
+        (self.canonicalize)(url)
+    }
+
+    fn yt_dlp_args(&self) -> Vec<String> {
+        self.yt_dlp_args.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn fallbacks(&self) -> &[Fallback] {
+        self.fallbacks
+    }
+
+    fn audio_only(&self) -> bool {
+        self.audio_only
+    }
+
+    fn supports_download_sections(&self) -> bool {
+        self.download_sections
+    }
+}
+
+/// `watch?v=` links (YouTube, Facebook) carry the video id in the query, so keep just that parameter.
+fn keep_video_param(url: &str) -> String {
+    if let Some((base, query)) = url.split_once('?')
+        && base.trim_end_matches('/').ends_with("/watch")
+        && let Some(v) = query.split(['&', '#']).find(|p| p.starts_with("v="))
+    {
+        return format!("{}?{}", base, v);
+    }
+    strip_query(url)
+}
+
+lazy_static::lazy_static! {
+    static ref TIKTOK_POST: Regex = Regex::new(r"tiktok\.com/(@[^/?#\s]+)/(video|photo)/(\d+)").unwrap();
+    static ref YOUTUBE_ID: Regex = Regex::new(
+        r"(?:youtu\.be/|youtube\.com/(?:shorts/|live/|embed/|watch\?(?:[^#\s]*&)?v=))([\w-]{11})"
+    ).unwrap();
+    static ref INSTAGRAM_POST: Regex = Regex::new(r"instagram\.com/(?:[^/?#\s]+/)?(?:reels?|p|tv)/([\w-]+)").unwrap();
+    static ref TWITTER_STATUS: Regex = Regex::new(r"/status/(\d+)").unwrap();
+    static ref VIMEO_ID: Regex = Regex::new(r"vimeo\.com/(?:video/)?(\d+)").unwrap();
+    static ref TWITCH_CLIP: Regex = Regex::new(r"(?:clips\.twitch\.tv/|twitch\.tv/[^/?#\s]+/clip/)([\w-]+)").unwrap();
+    static ref PINTEREST_PIN: Regex = Regex::new(r"pinterest\.[a-z.]+/pin/(\d+)").unwrap();
+    static ref THREADS_POST: Regex = Regex::new(r"threads\.(?:net|com)/(@[^/?#\s]+)/post/([\w-]+)").unwrap();
+}
+
+/// Rebuilds a link from the first match of `re`, or just strips the query if it doesn't match.
+fn rebuild(url: &str, re: &Regex, build: impl Fn(&regex::Captures) -> String) -> String {
+    re.captures(url).map(|c| build(&c)).unwrap_or_else(|| strip_query(url))
+}
+
+fn tiktok_canonical(url: &str) -> String {
+    rebuild(url, &TIKTOK_POST, |c| format!("https://www.tiktok.com/{}/{}/{}", &c[1], &c[2], &c[3]))
+}
+
+fn youtube_canonical(url: &str) -> String {
+    YOUTUBE_ID
+        .captures(url)
+        .map(|c| format!("https://www.youtube.com/watch?v={}", &c[1]))
+        .unwrap_or_else(|| keep_video_param(url))
+}
+
+fn instagram_canonical(url: &str) -> String {
+    rebuild(url, &INSTAGRAM_POST, |c| format!("https://www.instagram.com/p/{}", &c[1]))
+}
+
+fn twitter_canonical(url: &str) -> String {
+    rebuild(url, &TWITTER_STATUS, |c| format!("https://x.com/i/status/{}", &c[1]))
+}
+
+fn vimeo_canonical(url: &str) -> String {
+    rebuild(url, &VIMEO_ID, |c| format!("https://vimeo.com/{}", &c[1]))
+}
+
+fn twitch_canonical(url: &str) -> String {
+    rebuild(url, &TWITCH_CLIP, |c| format!("https://clips.twitch.tv/{}", &c[1]))
+}
+
+fn pinterest_canonical(url: &str) -> String {
+    rebuild(url, &PINTEREST_PIN, |c| format!("https://www.pinterest.com/pin/{}", &c[1]))
+}
+
+fn threads_canonical(url: &str) -> String {
+    rebuild(url, &THREADS_POST, |c| format!("https://www.threads.net/{}/post/{}", &c[1], &c[2]))
+}
+
+/// Same host for every mirror (old./new./m.), no query.
+fn reddit_canonical(url: &str) -> String {
+    let url = strip_query(url);
+    match url.split_once("reddit.com/") {
+        Some((_, path)) if !url.contains("v.redd.it") => format!("https://www.reddit.com/{}", path),
+        _ => url,
+    }
+}
+
+fn facebook_canonical(url: &str) -> String {
+    keep_video_param(url).replacen("://m.facebook.com/", "://www.facebook.com/", 1)
+        .replacen("://facebook.com/", "://www.facebook.com/", 1)
+}
+
+fn soundcloud_canonical(url: &str) -> String {
+    strip_query(url).replacen("://m.soundcloud.com/", "://soundcloud.com/", 1)
+        .replacen("://www.soundcloud.com/", "://soundcloud.com/", 1)
+}
+
+/// Built-in platforms in matching order.
+pub fn builtin_platforms() -> Vec<Box<dyn Platform>> {
+    vec![
+        Box::new(
+            BuiltinPlatform::new("tiktok", "TikTok", r"https?://(?:www\.|vm\.|vt\.|m\.)?tiktok\.com/[^\s]+")
+                .short_links(r"^https?://(?:(?:vm|vt)\.tiktok\.com/|(?:www\.|m\.)?tiktok\.com/t/)")
+                .canonicalize_with(tiktok_canonical)
+                .yt_dlp_args(&["--extractor-args", "tiktok:skip=feed"])
+                .fallbacks(&[Fallback::Tikwm]),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "instagram",
+                "Instagram",
+                r"https?://(?:www\.)?instagram\.com/(?:reels?|p|tv|share)/[^\s]+",
+            )
+            .short_links(r"^https?://(?:www\.)?instagram\.com/share/")
+            .canonicalize_with(instagram_canonical),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "youtube",
+                "YouTube",
+                r"https?://(?:www\.|m\.)?(?:youtube\.com/shorts/|youtube\.com/watch\?v=|youtu\.be/)[^\s]+",
+            )
+            .canonicalize_with(youtube_canonical)
+            .download_sections(),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "twitter",
+                "X / Twitter",
+                r"https?://(?:www\.|mobile\.)?(?:twitter\.com|x\.com)/[A-Za-z0-9_]+/status/\d+[^\s]*",
+            )
+            .canonicalize_with(twitter_canonical),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "reddit",
+                "Reddit",
+                r"https?://(?:(?:www|old|new)\.)?reddit\.com/r/[^\s/]+/(?:comments|s)/[^\s]+|https?://v\.redd\.it/[^\s]+",
+            )
+            .short_links(r"reddit\.com/r/[^\s/]+/s/")
+            .canonicalize_with(reddit_canonical)
+            .download_sections(),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "facebook",
+                "Facebook Reels",
+                r"https?://(?:www\.|m\.)?facebook\.com/(?:reel/|share/r/|watch/?\?v=|[^\s/]+/videos/)[^\s]+|https?://fb\.watch/[^\s]+",
+            )
+            .short_links(r"^https?://(?:fb\.watch/|(?:www\.|m\.)?facebook\.com/share/)")
+            .canonicalize_with(facebook_canonical),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "pinterest",
+                "Pinterest",
+                r"https?://(?:[a-z]{2}\.|www\.)?pinterest\.[a-z.]+/pin/[^\s]+|https?://pin\.it/[^\s]+",
+            )
+            .short_links(r"^https?://pin\.it/")
+            .canonicalize_with(pinterest_canonical),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "threads",
+                "Threads",
+                r"https?://(?:www\.)?threads\.(?:net|com)/@[^\s/]+/post/[^\s]+",
+            )
+            .canonicalize_with(threads_canonical),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "vimeo",
+                "Vimeo",
+                r"https?://(?:www\.|player\.)?vimeo\.com/(?:video/)?\d+[^\s]*",
+            )
+            .canonicalize_with(vimeo_canonical)
+            .download_sections(),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "twitch",
+                "Twitch Clips",
+                r"https?://(?:clips\.twitch\.tv/[^\s]+|(?:www\.|m\.)?twitch\.tv/[^\s/]+/clip/[^\s]+)",
+            )
+            .canonicalize_with(twitch_canonical)
+            .download_sections(),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "soundcloud",
+                "SoundCloud",
+                r"https?://(?:www\.|m\.)?soundcloud\.com/[^\s/]+/[^\s]+|https?://on\.soundcloud\.com/[^\s]+",
+            )
+            .short_links(r"^https?://on\.soundcloud\.com/")
+            .canonicalize_with(soundcloud_canonical)
+            .audio_only()
+            .download_sections(),
+        ),
+        Box::new(
+            BuiltinPlatform::new(
+                "likee",
+                "Likee",
+                r"https?://(?:www\.|l\.)?likee\.(?:video|com)/[^\s]+",
+            )
+            .short_links(r"^https?://l\.likee\.video/"),
+        ),
+    ]
+}
@@ -0,0 +1,130 @@
+//! Turns any supported link into its canonical form.
+//!
+//! Short links (vm.tiktok.com, pin.it, fb.watch, ...) are resolved by following their
+//! redirects hop by hop until a full post URL appears; the post page itself is never
+//! fetched. Resolutions are cached in memory, and a link that can't be resolved (timeout,
+//! dead shortener) falls back to its own canonical form.
+
+use reqwest::header::LOCATION;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::platforms::PlatformRegistry;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const MAX_HOPS: usize = 5;
+const MAX_CACHE_ENTRIES: usize = 10_000;
+
+pub struct Canonicalizer {
+    client: reqwest::Client,
+    cache: Mutex<HashMap<String, (String, Instant)>>,
+    ttl: Duration,
+}
+
+impl Canonicalizer {
+    /// `timeout` applies to every redirect hop; resolved links are kept for `ttl`.
+    pub fn new(timeout: Duration, ttl: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(timeout)
+            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36")
+            .build()
+            .expect("Failed to create short link client");
+        Self { client, cache: Mutex::new(HashMap::new()), ttl }
+    }
+
+    /// Reads `SHORT_LINK_TIMEOUT_SECS` (default 5).
+    pub fn from_env() -> Self {
+        let timeout = std::env::var("SHORT_LINK_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT);
+        Self::new(timeout, DEFAULT_CACHE_TTL)
+    }
+
+    /// Canonical form of `url`. Short links are resolved first; links of unknown platforms
+    /// are returned unchanged.
+    pub async fn canonicalize(&self, registry: &PlatformRegistry, url: &str) -> String {
+        let Some(platform) = registry.for_url(url) else {
+            return url.to_string();
+        };
+        if !platform.is_short_link(url) {
+            return platform.canonicalize(url);
+        }
+        if let Some(cached) = self.cached(url) {
+            return cached;
+        }
+
+        // Keep following while the link is a short link or an unknown intermediate host.
+        let resolved = self
+            .resolve_with(url, |u| registry.for_url(u).is_none_or(|p| p.is_short_link(u)))
+            .await;
+        match resolved {
+            Ok(resolved) if let Some(target) = registry.for_url(&resolved) => {
+                let canonical = target.canonicalize(&resolved);
+                self.store(url, &canonical);
+                canonical
+            }
+            other => {
+                log::warn!("Could not resolve short link {}: {:?}", url, other);
+                platform.canonicalize(url)
+            }
+        }
+    }
+
+    /// Follows redirects starting at `url` for as long as `is_short` holds, at most
+    /// `MAX_HOPS` times. Returns the first URL for which `is_short` is false.
+    pub async fn resolve_with(&self, url: &str, is_short: impl Fn(&str) -> bool) -> Result<String, anyhow::Error> {
+        let mut current = reqwest::Url::parse(url)?;
+        for _ in 0..MAX_HOPS {
+            if !is_short(current.as_str()) {
+                return Ok(current.to_string());
+            }
+            let resp = self.client.get(current.clone()).send().await?;
+            if !resp.status().is_redirection() {
+                anyhow::bail!("{} answered {} instead of a redirect", current, resp.status());
+            }
+            let location = resp
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| anyhow::anyhow!("redirect from {} has no Location", current))?;
+            // Location may be relative to the current URL.
+            current = current.join(location)?;
+        }
+        if is_short(current.as_str()) {
+            anyhow::bail!("too many redirects for {}", url);
+        }
+        Ok(current.to_string())
+    }
+
+    fn cached(&self, url: &str) -> Option<String> {
+        let cache = self.cache.lock().unwrap();
+        cache.get(url).filter(|(_, at)| at.elapsed() < self.ttl).map(|(resolved, _)| resolved.clone())
+    }
+
+    fn store(&self, url: &str, canonical: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            let ttl = self.ttl;
+            cache.retain(|_, (_, at)| at.elapsed() < ttl);
+            if cache.len() >= MAX_CACHE_ENTRIES {
+                cache.clear();
+            }
+        }
+        cache.insert(url.to_string(), (canonical.to_string(), Instant::now()));
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CANONICALIZER: Canonicalizer = Canonicalizer::from_env();
+}
+
+/// Canonical form of a link found by the built-in registry; the key used for dedupe,
+/// caching, pending downloads and stats.
+pub async fn canonical_url(url: &str) -> String {
+    CANONICALIZER.canonicalize(super::registry(), url).await
+}
@@ -0,0 +1,152 @@
+//! Links to many posts at once: profiles, channels and playlists.
+//!
+//! These aren't downloaded as one post; the handler lists their latest entries and lets the
+//! user pick how many to fetch as a batch.
+
+use regex::Regex;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectionKind {
+    TikTokProfile,
+    YoutubePlaylist,
+    YoutubeChannel,
+    InstagramProfile,
+}
+
+impl CollectionKind {
+    /// The [`crate::platforms::Platform::id`] of the posts it contains.
+    pub fn platform_id(&self) -> &'static str {
+        match self {
+            CollectionKind::TikTokProfile => "tiktok",
+            CollectionKind::YoutubePlaylist | CollectionKind::YoutubeChannel => "youtube",
+            CollectionKind::InstagramProfile => "instagram",
+        }
+    }
+
+    pub fn noun(&self) -> &'static str {
+        match self {
+            CollectionKind::TikTokProfile => "TikTok profile",
+            CollectionKind::YoutubePlaylist => "YouTube playlist",
+            CollectionKind::YoutubeChannel => "YouTube channel",
+            CollectionKind::InstagramProfile => "Instagram profile",
+        }
+    }
+}
+
+/// A profile, channel or playlist link in canonical form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionLink {
+    pub kind: CollectionKind,
+    pub url: String,
+    /// "@user" for profiles and channels, the list id for playlists.
+    pub name: String,
+}
+
+/// Instagram paths that aren't profiles.
+const INSTAGRAM_RESERVED: &[&str] = &["p", "reel", "reels", "tv", "stories", "explore", "share", "accounts", "direct"];
+
+lazy_static::lazy_static! {
+    static ref TIKTOK_PROFILE: Regex = Regex::new(
+        r"^https?://(?:www\.|m\.)?tiktok\.com/(@[\w.-]+)/?(?:[?#]\S*)?$"
+    ).unwrap();
+    static ref YOUTUBE_PLAYLIST: Regex = Regex::new(
+        r"^https?://(?:www\.|m\.|music\.)?youtube\.com/playlist\?(?:\S*&)?list=([\w-]+)"
+    ).unwrap();
+    static ref YOUTUBE_CHANNEL: Regex = Regex::new(
+        r"^https?://(?:www\.|m\.)?youtube\.com/(@[\w.-]+)(?:/(?:videos|featured)?)?/?(?:[?#]\S*)?$"
+    ).unwrap();
+    static ref INSTAGRAM_PROFILE: Regex = Regex::new(
+        r"^https?://(?:www\.)?instagram\.com/([\w.]+)/?(?:[?#]\S*)?$"
+    ).unwrap();
+}
+
+/// Canonical collection link of a single whitespace-free token.
+fn parse_collection(token: &str) -> Option<CollectionLink> {
+    if let Some(c) = TIKTOK_PROFILE.captures(token) {
+        return Some(CollectionLink {
+            kind: CollectionKind::TikTokProfile,
+            url: format!("https://www.tiktok.com/{}", &c[1]),
+            name: c[1].to_string(),
+        });
+    }
+    if let Some(c) = YOUTUBE_PLAYLIST.captures(token) {
+        return Some(CollectionLink {
+            kind: CollectionKind::YoutubePlaylist,
+            url: format!("https://www.youtube.com/playlist?list={}", &c[1]),
+            name: c[1].to_string(),
+        });
+    }
+    if let Some(c) = YOUTUBE_CHANNEL.captures(token) {
+        // The videos tab lists uploads newest first, without shorts and streams.
+        return Some(CollectionLink {
+            kind: CollectionKind::YoutubeChannel,
+            url: format!("https://www.youtube.com/{}/videos", &c[1]),
+            name: c[1].to_string(),
+        });
+    }
+    if let Some(c) = INSTAGRAM_PROFILE.captures(token)
+        && !INSTAGRAM_RESERVED.contains(&c[1].to_lowercase().as_str())
+    {
+        return Some(CollectionLink {
+            kind: CollectionKind::InstagramProfile,
+            url: format!("https://www.instagram.com/{}/", &c[1]),
+            name: format!("@{}", &c[1]),
+        });
+    }
+    None
+}
+
+/// First profile, channel or playlist link in `text`, skipping disabled platforms.
+pub fn find_collection(text: &str, disabled: &HashSet<String>) -> Option<CollectionLink> {
+    text.split_whitespace()
+        .filter_map(parse_collection)
+        .find(|link| !disabled.contains(link.kind.platform_id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str) -> Option<CollectionLink> {
+        find_collection(text, &HashSet::new())
+    }
+
+    #[test]
+    fn test_collection_links() {
+        let cases = [
+            ("https://www.tiktok.com/@some.user?lang=en", CollectionKind::TikTokProfile, "https://www.tiktok.com/@some.user"),
+            ("look https://m.tiktok.com/@user/ !", CollectionKind::TikTokProfile, "https://www.tiktok.com/@user"),
+            (
+                "https://www.youtube.com/playlist?si=x&list=PLabc-123",
+                CollectionKind::YoutubePlaylist,
+                "https://www.youtube.com/playlist?list=PLabc-123",
+            ),
+            ("https://youtube.com/@Channel/videos", CollectionKind::YoutubeChannel, "https://www.youtube.com/@Channel/videos"),
+            ("https://www.instagram.com/some_user/?hl=en", CollectionKind::InstagramProfile, "https://www.instagram.com/some_user/"),
+        ];
+        for (text, kind, url) in cases {
+            let link = find(text).unwrap_or_else(|| panic!("no collection in {}", text));
+            assert_eq!((link.kind, link.url.as_str()), (kind, url), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_single_posts_are_not_collections() {
+        for text in [
+            "https://www.tiktok.com/@user/video/123",
+            "https://www.youtube.com/watch?v=abc&list=PLabc",
+            "https://www.youtube.com/@Channel/shorts",
+            "https://www.instagram.com/reel/Cxyz/",
+            "https://www.instagram.com/p/Cxyz",
+        ] {
+            assert_eq!(find(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_disabled_platforms_are_skipped() {
+        let disabled: HashSet<String> = ["tiktok".to_string()].into_iter().collect();
+        assert_eq!(find_collection("https://www.tiktok.com/@user", &disabled), None);
+    }
+}
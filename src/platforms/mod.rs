@@ -0,0 +1,297 @@
+//! Supported link sources.
+//!
+//! Every platform knows how to spot its links in a message, how to canonicalize them (so
+//! the same post always maps to the same cache/dedup key), which extra yt-dlp arguments it
+//! needs and which non-yt-dlp sources can be tried when yt-dlp fails. Admins can switch
+//! individual platforms off from the admin panel; disabled ids are kept in the
+//! `disabled_platforms` setting.
+
+mod builtin;
+pub mod canonicalizer;
+pub mod collections;
+
+use regex::Regex;
+use std::collections::HashSet;
+
+pub use builtin::builtin_platforms;
+
+/// Non-yt-dlp sources the fetcher may fall back to for a platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// tikwm.com API: TikTok videos, photo slideshows and music.
+    Tikwm,
+}
+
+pub trait Platform: Send + Sync {
+    /// Stable identifier used in settings, e.g. "tiktok".
+    fn id(&self) -> &'static str;
+
+    /// Human readable name shown in the admin panel.
+    fn name(&self) -> &'static str;
+
+    /// Pattern matching the platform's links inside free text.
+    fn url_pattern(&self) -> &Regex;
+
+    /// First link of this platform in `text`.
+    fn find_url(&self, text: &str) -> Option<String> {
+        self.url_pattern().find(text).map(|m| m.as_str().to_string())
+    }
+
+    fn matches(&self, url: &str) -> bool {
+        self.url_pattern().is_match(url)
+    }
+
+    /// Links that only redirect to the real post and must be resolved before canonicalizing.
+    fn is_short_link(&self, _url: &str) -> bool {
+        false
+    }
+
+    /// Stable form of a link, built from the post id where possible, with tracking
+    /// parameters and fragments removed.
+    fn canonicalize(&self, url: &str) -> String {
+        strip_query(url)
+    }
+
+    /// Extra arguments passed to yt-dlp for this platform.
+    fn yt_dlp_args(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Sources tried, in order, when yt-dlp fails or returns an incomplete file.
+    fn fallbacks(&self) -> &[Fallback] {
+        &[]
+    }
+
+    /// Audio-only platforms (e.g. SoundCloud) are always downloaded in audio mode.
+    fn audio_only(&self) -> bool {
+        false
+    }
+
+    /// Whether yt-dlp can download just a part of a post (`--download-sections`). Clips of
+    /// other platforms are downloaded whole and trimmed locally.
+    fn supports_download_sections(&self) -> bool {
+        false
+    }
+}
+
+/// Drops the query string and fragment of a URL.
+pub fn strip_query(url: &str) -> String {
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    url[..end].trim_end_matches('/').to_string()
+}
+
+/// Ordered collection of platforms; earlier entries win when several patterns match.
+pub struct PlatformRegistry {
+    platforms: Vec<Box<dyn Platform>>,
+}
+
+impl PlatformRegistry {
+    pub fn new() -> Self {
+        Self { platforms: Vec::new() }
+    }
+
+    /// Registry with every built-in platform.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for platform in builtin_platforms() {
+            registry.register(platform);
+        }
+        registry
+    }
+
+    pub fn register(&mut self, platform: Box<dyn Platform>) {
+        self.platforms.push(platform);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &dyn Platform> {
+        self.platforms.iter().map(|p| p.as_ref())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn Platform> {
+        self.all().find(|p| p.id() == id)
+    }
+
+    /// Platform a (already extracted) URL belongs to.
+    pub fn for_url(&self, url: &str) -> Option<&dyn Platform> {
+        self.all().find(|p| p.matches(url))
+    }
+
+    /// Finds the first supported link in `text`, skipping disabled platforms. The link is
+    /// returned canonicalized together with its platform.
+    pub fn find_url(&self, text: &str, disabled: &HashSet<String>) -> Option<(&dyn Platform, String)> {
+        self.all()
+            .filter(|p| !disabled.contains(p.id()))
+            .filter_map(|p| p.find_url(text).map(|url| (p, url)))
+            // Prefer the link that appears first in the message.
+            .min_by_key(|(_, url)| text.find(url.as_str()).unwrap_or(usize::MAX))
+            .map(|(p, url)| (p, p.canonicalize(&url)))
+    }
+
+    /// Every supported link in `text`, in message order, canonicalized and without duplicates.
+    pub fn find_urls(&self, text: &str, disabled: &HashSet<String>) -> Vec<(&dyn Platform, String)> {
+        let mut found: Vec<(usize, usize, &dyn Platform)> = self
+            .all()
+            .filter(|p| !disabled.contains(p.id()))
+            .flat_map(|p| p.url_pattern().find_iter(text).map(move |m| (m.start(), m.end(), p)))
+            .collect();
+        // Stable sort: on equal positions the earlier registered platform wins.
+        found.sort_by_key(|(start, _, _)| *start);
+
+        let mut seen = HashSet::new();
+        let mut urls = Vec::new();
+        let mut claimed_until = 0;
+        for (start, end, platform) in found {
+            // Skip matches inside a link another platform already claimed.
+            if start < claimed_until {
+                continue;
+            }
+            claimed_until = end;
+            let canonical = platform.canonicalize(&text[start..end]);
+            if seen.insert(canonical.clone()) {
+                urls.push((platform, canonical));
+            }
+        }
+        urls
+    }
+}
+
+impl Default for PlatformRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: PlatformRegistry = PlatformRegistry::builtin();
+}
+
+/// The process-wide registry of built-in platforms.
+pub fn registry() -> &'static PlatformRegistry {
+    &REGISTRY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn none() -> HashSet<String> {
+        HashSet::new()
+    }
+
+    #[test]
+    fn test_find_url_per_platform() {
+        let registry = PlatformRegistry::builtin();
+        let cases = [
+            ("look https://vm.tiktok.com/ZMabc123/ lol", "tiktok"),
+            ("https://www.instagram.com/reel/Cxyz/?igsh=abc", "instagram"),
+            ("https://youtu.be/dQw4w9WgXcQ", "youtube"),
+            ("https://x.com/user/status/1234567890?s=20", "twitter"),
+            ("https://twitter.com/user/status/1234567890", "twitter"),
+            ("https://www.reddit.com/r/videos/comments/abc123/title/", "reddit"),
+            ("https://www.facebook.com/reel/123456789", "facebook"),
+            ("https://www.pinterest.com/pin/123456789/", "pinterest"),
+            ("https://www.threads.net/@user/post/Cabc", "threads"),
+            ("https://vimeo.com/123456789", "vimeo"),
+            ("https://clips.twitch.tv/FunnyClipName", "twitch"),
+            ("https://www.twitch.tv/streamer/clip/FunnyClipName", "twitch"),
+            ("https://soundcloud.com/artist/track-name", "soundcloud"),
+            ("https://likee.video/@user/video/123456", "likee"),
+        ];
+        for (text, id) in cases {
+            let (platform, _) = registry.find_url(text, &none()).unwrap_or_else(|| panic!("no match for {}", text));
+            assert_eq!(platform.id(), id, "{}", text);
+        }
+        assert!(registry.find_url("https://example.com/video.mp4", &none()).is_none());
+    }
+
+    #[test]
+    fn test_disabled_platforms_are_skipped() {
+        let registry = PlatformRegistry::builtin();
+        let disabled: HashSet<String> = ["reddit".to_string()].into_iter().collect();
+        assert!(registry.find_url("https://www.reddit.com/r/a/comments/b/c/", &disabled).is_none());
+        assert!(registry.find_url("https://www.tiktok.com/@u/video/1", &disabled).is_some());
+    }
+
+    #[test]
+    fn test_first_link_in_message_wins() {
+        let registry = PlatformRegistry::builtin();
+        let text = "https://vimeo.com/1 and https://www.tiktok.com/@u/video/2";
+        let (platform, url) = registry.find_url(text, &none()).unwrap();
+        assert_eq!(platform.id(), "vimeo");
+        assert_eq!(url, "https://vimeo.com/1");
+    }
+
+    #[test]
+    fn test_find_all_urls() {
+        let registry = PlatformRegistry::builtin();
+        let text = "https://vimeo.com/1 and https://www.tiktok.com/@u/video/2?x=1 and again https://www.tiktok.com/@u/video/2";
+        let urls: Vec<String> = registry.find_urls(text, &none()).into_iter().map(|(_, url)| url).collect();
+        assert_eq!(urls, vec!["https://vimeo.com/1", "https://www.tiktok.com/@u/video/2"]);
+        assert!(registry.find_urls("no links here", &none()).is_empty());
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let registry = PlatformRegistry::builtin();
+        let (_, url) = registry.find_url("https://www.tiktok.com/@u/video/7?is_from_webapp=1&sender=2", &none()).unwrap();
+        assert_eq!(url, "https://www.tiktok.com/@u/video/7");
+        let (_, url) = registry.find_url("https://www.youtube.com/watch?v=abc&t=10s&feature=share", &none()).unwrap();
+        assert_eq!(url, "https://www.youtube.com/watch?v=abc");
+        assert_eq!(strip_query("https://a.b/c/?x=1#y"), "https://a.b/c");
+    }
+
+    #[test]
+    fn test_canonical_forms_share_an_id() {
+        let registry = PlatformRegistry::builtin();
+        let same = [
+            vec![
+                "https://youtu.be/dQw4w9WgXcQ?si=abc",
+                "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+                "https://m.youtube.com/watch?v=dQw4w9WgXcQ&feature=share",
+            ],
+            vec![
+                "https://www.instagram.com/reel/Cxyz_1/?igsh=abc",
+                "https://www.instagram.com/p/Cxyz_1/",
+                "https://instagram.com/reels/Cxyz_1",
+            ],
+            vec![
+                "https://twitter.com/user/status/1234567890?s=20",
+                "https://mobile.x.com/other/status/1234567890",
+            ],
+            vec![
+                "https://m.tiktok.com/@u/video/7?is_from_webapp=1&sender_device=pc",
+                "https://www.tiktok.com/@u/video/7",
+            ],
+            vec!["https://player.vimeo.com/video/123456", "https://vimeo.com/123456#t=5"],
+            vec!["https://www.twitch.tv/streamer/clip/FunnyClip-abc", "https://clips.twitch.tv/FunnyClip-abc?tt=1"],
+            vec!["https://old.reddit.com/r/videos/comments/abc/title/", "https://www.reddit.com/r/videos/comments/abc/title?utm=1"],
+        ];
+        for group in same {
+            let canonical: Vec<String> = group.iter().map(|url| registry.find_url(url, &none()).unwrap().1).collect();
+            assert!(canonical.windows(2).all(|w| w[0] == w[1]), "{:?}", canonical);
+        }
+        let (_, url) = registry.find_url("https://youtu.be/dQw4w9WgXcQ", &none()).unwrap();
+        assert_eq!(url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+    }
+
+    #[test]
+    fn test_short_links() {
+        let registry = PlatformRegistry::builtin();
+        for url in ["https://vm.tiktok.com/ZMabc/", "https://pin.it/abc", "https://fb.watch/abc/", "https://on.soundcloud.com/abc"] {
+            assert!(registry.for_url(url).is_some_and(|p| p.is_short_link(url)), "{}", url);
+        }
+        let full = "https://www.tiktok.com/@u/video/1";
+        assert!(!registry.for_url(full).unwrap().is_short_link(full));
+    }
+
+    #[test]
+    fn test_platform_traits() {
+        let registry = PlatformRegistry::builtin();
+        assert!(registry.get("soundcloud").unwrap().audio_only());
+        assert!(registry.get("youtube").unwrap().supports_download_sections());
+        assert!(!registry.get("tiktok").unwrap().supports_download_sections());
+        assert_eq!(registry.get("tiktok").unwrap().fallbacks(), &[Fallback::Tikwm]);
+        assert!(registry.get("vimeo").unwrap().fallbacks().is_empty());
+        assert!(registry.for_url("https://www.tiktok.com/@u/photo/1").is_some());
+    }
+}
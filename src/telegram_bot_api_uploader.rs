@@ -8,6 +8,7 @@ use tokio::process::Command;
 use std::path::Path;
 use std::path::PathBuf;
 use crate::utils::temp_file::TempFileGuard;
+use crate::utils::{AudioMeta, SendOptions};
 
 async fn ensure_faststart_video(ffmpeg_path: &PathBuf, file_path: &Path) -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
     // Create a temporary file for the faststart-optimized video
@@ -37,6 +38,20 @@ async fn ensure_faststart_video(ffmpeg_path: &PathBuf, file_path: &Path) -> Resu
     Ok(temp_path)
 }
 
+/// Pulls `result.<kind>.file_id` out of a Bot API send* response, trying each kind in order
+/// (Telegram may deliver a "video" as an animation or a plain document).
+/// The send* functions return None when it's missing: the media was delivered all the same,
+/// it just can't be cached, so callers must not send it again.
+fn extract_file_id(body: &serde_json::Value, kinds: &[&str]) -> Option<String> {
+    let result = body.get("result")?;
+    kinds.iter()
+        .filter_map(|kind| result.get(*kind))
+        .filter_map(|media| media.get("file_id"))
+        .filter_map(|id| id.as_str())
+        .map(|id| id.to_string())
+        .next()
+}
+
 async fn get_video_metadata(ffprobe_path: &str, file_path: &Path) -> Result<crate::mtproto_uploader::video_metadata::Stream, Box<dyn std::error::Error + Send + Sync>> {
     // Reuse the existing function from mtproto_uploader
     crate::mtproto_uploader::metadata::get_video_metadata(ffprobe_path, file_path).await.map_err(|e| e.into())
@@ -46,9 +61,9 @@ pub async fn send_video_with_progress_botapi(
     bot_token: &str,
     chat_id: ChatId,
     file_path: &std::path::Path,
-    caption: Option<&str>,
+    options: &SendOptions,
     progress_bar: &mut ProgressBar,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Option<String>> {
     // Get paths for ffmpeg and ffprobe
     let libraries_dir = std::env::current_dir()?.join("lib");
     let ffmpeg_dir = libraries_dir.join("ffmpeg");
@@ -117,7 +132,7 @@ pub async fn send_video_with_progress_botapi(
         }
     }
 
-    if let Some(c) = caption { form = form.text("caption", c.to_string()); }
+    form = options.apply_to_form(form);
 
     let url = format!("https://api.telegram.org/bot{}/sendVideo", bot_token);
     let resp = reqwest::Client::new().post(&url).multipart(form).send().await?;
@@ -126,17 +141,24 @@ pub async fn send_video_with_progress_botapi(
         return Err(anyhow::anyhow!("Bot API sendVideo failed: {}", resp.status()));
     }
 
+    let body: serde_json::Value = resp.json().await?;
+    let file_id = extract_file_id(&body, &["video", "animation", "document"]);
+    if file_id.is_none() {
+        log::warn!("Bot API sendVideo response has no file_id");
+    }
+
     progress_bar.delete().await?;
-    Ok(())
+    Ok(file_id)
 }
 
 pub async fn send_audio_with_progress_botapi(
     bot_token: &str,
     chat_id: ChatId,
     file_path: &std::path::Path,
-    caption: Option<&str>,
+    audio: &AudioMeta,
+    options: &SendOptions,
     progress_bar: &mut ProgressBar,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Option<String>> {
     let file = File::open(file_path).await?;
     let len = file.metadata().await?.len();
     let pb_clone = progress_bar.clone();
@@ -164,15 +186,20 @@ pub async fn send_audio_with_progress_botapi(
     let stream_reader = ReaderStream::new(reader);
     let ext = file_path.extension().and_then(|s| s.to_str()).unwrap_or_default().to_lowercase();
     let mime = match ext.as_str() {
-        "mp3" => "audio/mpeg", "m4a" => "audio/mp4", "aac" => "audio/aac", "ogg" => "audio/ogg", _ => "audio/mpeg",
+        "mp3" => "audio/mpeg", "m4a" => "audio/mp4", "aac" => "audio/aac", "ogg" | "opus" => "audio/ogg", "flac" => "audio/flac", _ => "audio/mpeg",
     };
 
     let part = Part::stream_with_length(reqwest::Body::wrap_stream(stream_reader), len)
         .file_name(file_path.file_name().unwrap().to_string_lossy().to_string())
         .mime_str(mime)?;
 
-    let mut form = Form::new().text("chat_id", chat_id.0.to_string()).part("audio", part);
-    if let Some(c) = caption { form = form.text("caption", c.to_string()); }
+    let mut form = audio.apply_to_form(options.apply_to_form(Form::new().text("chat_id", chat_id.0.to_string()).part("audio", part)));
+    if let Some(thumbnail) = &audio.thumbnail {
+        match tokio::fs::read(thumbnail).await {
+            Ok(bytes) => form = form.part("thumbnail", Part::bytes(bytes).file_name("cover.jpg").mime_str("image/jpeg")?),
+            Err(e) => log::warn!("Could not read audio cover {:?}: {}", thumbnail, e),
+        }
+    }
 
     let url = format!("https://api.telegram.org/bot{}/sendAudio", bot_token);
     let resp = reqwest::Client::new().post(&url).multipart(form).send().await?;
@@ -181,6 +208,263 @@ pub async fn send_audio_with_progress_botapi(
         return Err(anyhow::anyhow!("Bot API sendAudio failed: {}", resp.status()));
     }
 
+    let body: serde_json::Value = resp.json().await?;
+    let file_id = extract_file_id(&body, &["audio", "voice", "document"]);
+    if file_id.is_none() {
+        log::warn!("Bot API sendAudio response has no file_id");
+    }
+
+    progress_bar.delete().await?;
+    Ok(file_id)
+}
+
+/// Sends an OGG/Opus file as a voice message via `sendVoice`. Voice notes are small, so the
+/// file is uploaded in one piece instead of streamed with per-chunk progress.
+pub async fn send_voice_with_progress_botapi(
+    bot_token: &str,
+    chat_id: ChatId,
+    file_path: &std::path::Path,
+    duration: u32,
+    options: &SendOptions,
+    progress_bar: &mut ProgressBar,
+) -> anyhow::Result<Option<String>> {
+    let bytes = tokio::fs::read(file_path).await?;
+    let text = format!("📤 Uploading voice message... {:.1} MB", bytes.len() as f64 / 1_048_576.0);
+    progress_bar.update(90, Some(&text)).await?;
+
+    let part = Part::bytes(bytes)
+        .file_name(file_path.file_name().unwrap_or_default().to_string_lossy().to_string())
+        .mime_str("audio/ogg")?;
+    let mut form = Form::new().text("chat_id", chat_id.0.to_string()).part("voice", part);
+    if duration > 0 {
+        form = form.text("duration", duration.to_string());
+    }
+    form = options.apply_to_form(form);
+
+    let url = format!("https://api.telegram.org/bot{}/sendVoice", bot_token);
+    let resp = reqwest::Client::new().post(&url).multipart(form).send().await?;
+
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!("Bot API sendVoice failed: {}", resp.status()));
+    }
+
+    let body: serde_json::Value = resp.json().await?;
+    let file_id = extract_file_id(&body, &["voice", "audio", "document"]);
+    if file_id.is_none() {
+        log::warn!("Bot API sendVoice response has no file_id");
+    }
+
     progress_bar.delete().await?;
+    Ok(file_id)
+}
+
+/// Multipart part streaming `file_path` that reports upload progress between 80% and 100%.
+async fn progress_part(file_path: &Path, mime: &str, progress_bar: &ProgressBar) -> anyhow::Result<Part> {
+    let file = File::open(file_path).await?;
+    let len = file.metadata().await?.len();
+    let pb_clone = progress_bar.clone();
+    let reader = ProgressReader::new(file, len, move |uploaded, total| {
+        let overall = 80.0 + (uploaded as f64 / total as f64) * 20.0;
+        let mut pb2 = pb_clone.clone();
+        let text = format!("📤 Uploading... {:.1}/{:.1} MB", uploaded as f64 / 1_048_576.0, total as f64 / 1_048_576.0);
+        tokio::spawn(async move { let _ = pb2.update(overall.min(100.0) as u8, Some(&text)).await; });
+    });
+    Ok(Part::stream_with_length(reqwest::Body::wrap_stream(ReaderStream::new(reader)), len)
+        .file_name(file_path.file_name().unwrap_or_default().to_string_lossy().to_string())
+        .mime_str(mime)?)
+}
+
+/// ffprobe/ffmpeg next to the bot, as `send_video_with_progress_botapi` uses them.
+fn ffmpeg_paths() -> anyhow::Result<(PathBuf, PathBuf)> {
+    let ffmpeg_dir = std::env::current_dir()?.join("lib").join("ffmpeg");
+    let ffmpeg_path = ffmpeg_dir.join(if cfg!(target_os = "windows") { "ffmpeg.exe" } else { "ffmpeg" });
+    let ffprobe_path = ffmpeg_dir.join(if cfg!(target_os = "windows") { "ffprobe.exe" } else { "ffprobe" });
+    Ok((ffmpeg_path, ffprobe_path))
+}
+
+/// Sends a square MP4 as a round video note via `sendVideoNote`. Video notes can't carry a
+/// caption, so `options.caption` is ignored; callers send it separately.
+pub async fn send_video_note_botapi(
+    bot_token: &str,
+    chat_id: ChatId,
+    file_path: &Path,
+    options: &SendOptions,
+    progress_bar: &mut ProgressBar,
+) -> anyhow::Result<Option<String>> {
+    let (ffmpeg_path, ffprobe_path) = ffmpeg_paths()?;
+    let meta = get_video_metadata(&ffprobe_path.to_string_lossy(), file_path).await
+        .map_err(|e| anyhow::anyhow!("Failed to probe video note: {}", e))?;
+
+    let thumbnail_path = file_path.with_extension("jpg");
+    let thumbnail_result = crate::mtproto_uploader::thumbnail::generate_thumbnail(&ffmpeg_path, file_path, &thumbnail_path).await;
+    let _thumbnail_guard = thumbnail_result.is_ok().then(|| TempFileGuard::new(thumbnail_path.clone()));
+
+    let part = progress_part(file_path, "video/mp4", progress_bar).await?;
+    let mut form = Form::new()
+        .text("chat_id", chat_id.0.to_string())
+        .part("video_note", part)
+        .text("length", meta.width.min(meta.height).to_string());
+    if meta.duration > 0.0 { form = form.text("duration", meta.duration.floor().to_string()); }
+    if thumbnail_result.is_ok() && let Ok(thumb_part) = Part::file(&thumbnail_path).await {
+        form = form.part("thumbnail", thumb_part.mime_str("image/jpeg")?);
+    }
+    form = SendOptions { caption: None, ..options.clone() }.apply_to_form(form);
+
+    let url = format!("https://api.telegram.org/bot{}/sendVideoNote", bot_token);
+    let resp = reqwest::Client::new().post(&url).multipart(form).send().await?;
+
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!("Bot API sendVideoNote failed: {}", resp.status()));
+    }
+
+    let body: serde_json::Value = resp.json().await?;
+    let file_id = extract_file_id(&body, &["video_note", "video", "document"]);
+    if file_id.is_none() {
+        log::warn!("Bot API sendVideoNote response has no file_id");
+    }
+
+    progress_bar.delete().await?;
+    Ok(file_id)
+}
+
+/// Sends a silent MP4 as an animation (GIF) via `sendAnimation`.
+pub async fn send_animation_botapi(
+    bot_token: &str,
+    chat_id: ChatId,
+    file_path: &Path,
+    options: &SendOptions,
+    progress_bar: &mut ProgressBar,
+) -> anyhow::Result<Option<String>> {
+    let (ffmpeg_path, ffprobe_path) = ffmpeg_paths()?;
+    let meta = get_video_metadata(&ffprobe_path.to_string_lossy(), file_path).await.unwrap_or_else(|e| {
+        log::warn!("Failed to get animation metadata, proceeding without: {:?}", e);
+        crate::mtproto_uploader::video_metadata::Stream { width: 0, height: 0, duration: 0.0 }
+    });
+
+    let thumbnail_path = file_path.with_extension("jpg");
+    let thumbnail_result = crate::mtproto_uploader::thumbnail::generate_thumbnail(&ffmpeg_path, file_path, &thumbnail_path).await;
+    let _thumbnail_guard = thumbnail_result.is_ok().then(|| TempFileGuard::new(thumbnail_path.clone()));
+
+    let part = progress_part(file_path, "video/mp4", progress_bar).await?;
+    let mut form = Form::new().text("chat_id", chat_id.0.to_string()).part("animation", part);
+    if meta.width > 0 { form = form.text("width", meta.width.to_string()); }
+    if meta.height > 0 { form = form.text("height", meta.height.to_string()); }
+    if meta.duration > 0.0 { form = form.text("duration", meta.duration.floor().to_string()); }
+    if thumbnail_result.is_ok() && let Ok(thumb_part) = Part::file(&thumbnail_path).await {
+        form = form.part("thumbnail", thumb_part.mime_str("image/jpeg")?);
+    }
+    form = options.apply_to_form(form);
+
+    let url = format!("https://api.telegram.org/bot{}/sendAnimation", bot_token);
+    let resp = reqwest::Client::new().post(&url).multipart(form).send().await?;
+
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!("Bot API sendAnimation failed: {}", resp.status()));
+    }
+
+    let body: serde_json::Value = resp.json().await?;
+    let file_id = extract_file_id(&body, &["animation", "video", "document"]);
+    if file_id.is_none() {
+        log::warn!("Bot API sendAnimation response has no file_id");
+    }
+
+    progress_bar.delete().await?;
+    Ok(file_id)
+}
+
+/// A Bot API album upload that failed after the first `sent` images had been delivered.
+#[derive(Debug)]
+pub struct AlbumUploadError {
+    pub sent: usize,
+    pub error: anyhow::Error,
+}
+
+impl std::fmt::Display for AlbumUploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (after {} images)", self.error, self.sent)
+    }
+}
+
+impl std::error::Error for AlbumUploadError {}
+
+/// Sends photos as albums via `sendMediaGroup`, at most `MEDIA_GROUP_LIMIT` per message.
+/// A trailing chunk with a single image is sent with `sendPhoto`, since albums need two items.
+/// The caption is attached to the first photo and only the first message replies. On failure
+/// the error tells how many images already went out, so only the rest needs resending.
+pub async fn send_photo_album_botapi(
+    bot_token: &str,
+    chat_id: ChatId,
+    images: &[PathBuf],
+    options: &SendOptions,
+    progress_bar: &mut ProgressBar,
+) -> Result<(), AlbumUploadError> {
+    use crate::yt_dlp_interface::slideshow::MEDIA_GROUP_LIMIT;
+
+    let client = reqwest::Client::new();
+    let chunk_count = images.len().div_ceil(MEDIA_GROUP_LIMIT);
+
+    for (chunk_idx, chunk) in images.chunks(MEDIA_GROUP_LIMIT).enumerate() {
+        let sent = chunk_idx * MEDIA_GROUP_LIMIT;
+        let overall = 80 + ((chunk_idx as f64 / chunk_count as f64) * 20.0) as u8;
+        let text = format!("📤 Uploading album {}/{}...", chunk_idx + 1, chunk_count);
+        if let Err(e) = progress_bar.update(overall, Some(&text)).await {
+            log::warn!("Failed to update album progress: {}", e);
+        }
+        send_album_chunk(&client, bot_token, chat_id, chunk, chunk_idx == 0, options)
+            .await
+            .map_err(|error| AlbumUploadError { sent, error })?;
+    }
+
+    if let Err(e) = progress_bar.delete().await {
+        log::warn!("Failed to delete album progress: {}", e);
+    }
+    Ok(())
+}
+
+/// Sends one chunk of `send_photo_album_botapi`; caption and reply only go with the first.
+async fn send_album_chunk(
+    client: &reqwest::Client,
+    bot_token: &str,
+    chat_id: ChatId,
+    chunk: &[PathBuf],
+    first: bool,
+    options: &SendOptions,
+) -> anyhow::Result<()> {
+    let chunk_caption = if first { options.caption.as_deref() } else { None };
+    let mut form = Form::new().text("chat_id", chat_id.0.to_string());
+    if first && let Some(reply) = options.reply_parameters_json() {
+        form = form.text("reply_parameters", reply);
+    }
+    if options.silent {
+        form = form.text("disable_notification", "true");
+    }
+
+    let method = if chunk.len() == 1 {
+        let part = Part::file(&chunk[0]).await?.mime_str("image/jpeg")?;
+        form = form.part("photo", part);
+        if let Some(c) = chunk_caption { form = form.text("caption", c.to_string()); }
+        "sendPhoto"
+    } else {
+        let mut media = Vec::with_capacity(chunk.len());
+        for (i, path) in chunk.iter().enumerate() {
+            let name = format!("photo{}", i);
+            let mut item = serde_json::json!({ "type": "photo", "media": format!("attach://{}", name) });
+            if i == 0 && let Some(c) = chunk_caption {
+                item["caption"] = serde_json::json!(c);
+            }
+            media.push(item);
+            form = form.part(name, Part::file(path).await?.mime_str("image/jpeg")?);
+        }
+        form = form.text("media", serde_json::to_string(&media)?);
+        "sendMediaGroup"
+    };
+
+    let url = format!("https://api.telegram.org/bot{}/{}", bot_token, method);
+    let resp = client.post(&url).multipart(form).send().await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("Bot API {} failed: {} {}", method, status, body));
+    }
     Ok(())
 }
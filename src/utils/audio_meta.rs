@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use crate::yt_dlp_interface::video_info::VideoInfo;
+
+/// What Telegram shows for an audio file in its player, shared by the Bot API and MTProto uploaders.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioMeta {
+    /// Whole seconds; 0 when unknown.
+    pub duration: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// JPEG cover of at most 320x320, as Telegram requires for thumbnails.
+    pub thumbnail: Option<PathBuf>,
+}
+
+impl AudioMeta {
+    /// Title and performer from the post's metadata.
+    pub fn from_info(info: &VideoInfo) -> Self {
+        Self { title: info.audio_title(), performer: info.audio_performer(), ..Default::default() }
+    }
+
+    /// Adds duration, title and performer to a Bot API `sendAudio` form. The thumbnail is a
+    /// file part and is attached by the caller.
+    pub fn apply_to_form(&self, mut form: reqwest::multipart::Form) -> reqwest::multipart::Form {
+        if self.duration > 0 {
+            form = form.text("duration", self.duration.to_string());
+        }
+        if let Some(title) = &self.title {
+            form = form.text("title", title.clone());
+        }
+        if let Some(performer) = &self.performer {
+            form = form.text("performer", performer.clone());
+        }
+        form
+    }
+}
@@ -0,0 +1,173 @@
+use crate::yt_dlp_interface::video_info::VideoInfo;
+
+/// Telegram's caption limit.
+pub const MAX_CAPTION_CHARS: usize = 1024;
+
+pub const DEFAULT_CAPTION_TEMPLATE: &str = "👤 {author}\n{description}\n{hashtags}\n🎵 {music}\n{stats}\n🔗 {url}";
+
+/// Placeholders understood by [`render_caption`], with a short explanation for the admin panel.
+pub const CAPTION_PLACEHOLDERS: &[(&str, &str)] = &[
+    ("author", "author name and handle"),
+    ("title", "post title"),
+    ("description", "description without hashtags"),
+    ("hashtags", "hashtags and tags"),
+    ("music", "artist - track"),
+    ("views", "view count"),
+    ("likes", "like count"),
+    ("comments", "comment count"),
+    ("stats", "views, likes and comments that are known"),
+    ("url", "original link"),
+];
+
+/// 1234 -> "1.2K", 5600000 -> "5.6M".
+pub fn format_count(n: u64) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=999_999 => trim_decimal(n as f64 / 1_000.0, "K"),
+        1_000_000..=999_999_999 => trim_decimal(n as f64 / 1_000_000.0, "M"),
+        _ => trim_decimal(n as f64 / 1_000_000_000.0, "B"),
+    }
+}
+
+fn trim_decimal(value: f64, suffix: &str) -> String {
+    let text = format!("{:.1}", (value * 10.0).floor() / 10.0);
+    format!("{}{}", text.trim_end_matches(".0"), suffix)
+}
+
+/// Placeholder names used in `template` that [`render_caption`] doesn't know.
+pub fn unknown_placeholders(template: &str) -> Vec<String> {
+    placeholder_names(template)
+        .filter(|name| !CAPTION_PLACEHOLDERS.iter().any(|(known, _)| known == name))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn placeholder_names(line: &str) -> impl Iterator<Item = &str> {
+    line.split('{').skip(1).filter_map(|part| part.split_once('}')).map(|(name, _)| name)
+}
+
+fn value_of(name: &str, info: &VideoInfo, url: &str, description: Option<&str>) -> Option<String> {
+    let value = match name {
+        "author" => info.author(),
+        "title" => info.title.clone(),
+        "description" => description.map(|d| d.to_string()),
+        "hashtags" => Some(info.hashtags().join(" ")),
+        "music" => info.music(),
+        "views" => info.view_count.map(format_count),
+        "likes" => info.like_count.map(format_count),
+        "comments" => info.comment_count.map(format_count),
+        "stats" => {
+            let parts: Vec<String> = [("👁", info.view_count), ("❤️", info.like_count), ("💬", info.comment_count)]
+                .into_iter()
+                .filter_map(|(icon, count)| count.map(|c| format!("{} {}", icon, format_count(c))))
+                .collect();
+            Some(parts.join("  "))
+        }
+        "url" => Some(url.to_string()),
+        _ => None,
+    };
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Fills `{placeholder}`s line by line. A line whose placeholders are all empty is dropped,
+/// so a template can carry icons and labels for fields that some posts don't have.
+fn fill(template: &str, info: &VideoInfo, url: &str, description: Option<&str>) -> String {
+    let mut lines = Vec::new();
+    for line in template.lines() {
+        let names: Vec<&str> = placeholder_names(line).collect();
+        let mut rendered = line.to_string();
+        let mut any_value = names.is_empty();
+        for name in names {
+            let value = value_of(name, info, url, description);
+            any_value |= value.is_some();
+            rendered = rendered.replace(&format!("{{{}}}", name), value.as_deref().unwrap_or(""));
+        }
+        if any_value {
+            lines.push(rendered.trim_end().to_string());
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// `text` cut to at most `max` characters, ending with "…" when it was cut.
+pub(crate) fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// Renders the caption for a post. When it exceeds Telegram's limit the description is
+/// shortened first so that the link and the other fields survive.
+pub fn render_caption(template: &str, info: &VideoInfo, url: &str) -> String {
+    let description = info.description_text();
+    let caption = fill(template, info, url, description.as_deref());
+    let length = caption.chars().count();
+    if length <= MAX_CAPTION_CHARS {
+        return caption;
+    }
+
+    if let Some(description) = &description {
+        let overflow = length - MAX_CAPTION_CHARS;
+        let keep = description.chars().count().saturating_sub(overflow);
+        if keep > 0 {
+            let shortened = truncate_chars(description, keep);
+            let caption = fill(template, info, url, Some(&shortened));
+            if caption.chars().count() <= MAX_CAPTION_CHARS {
+                return caption;
+            }
+        }
+    }
+    truncate_chars(&caption, MAX_CAPTION_CHARS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> VideoInfo {
+        VideoInfo {
+            uploader: Some("Ann".to_string()),
+            uploader_id: Some("ann".to_string()),
+            description: Some("my cat #cats".to_string()),
+            view_count: Some(1_234_567),
+            like_count: Some(8_900),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_000), "1K");
+        assert_eq!(format_count(1_250), "1.2K");
+        assert_eq!(format_count(5_600_000), "5.6M");
+        assert_eq!(format_count(2_000_000_000), "2B");
+    }
+
+    #[test]
+    fn test_default_template_drops_missing_fields() {
+        let caption = render_caption(DEFAULT_CAPTION_TEMPLATE, &info(), "https://t/1");
+        assert_eq!(caption, "👤 Ann (@ann)\nmy cat\n#cats\n👁 1.2M  ❤️ 8.9K\n🔗 https://t/1");
+
+        let empty = render_caption(DEFAULT_CAPTION_TEMPLATE, &VideoInfo::default(), "https://t/1");
+        assert_eq!(empty, "🔗 https://t/1");
+    }
+
+    #[test]
+    fn test_long_description_is_shortened_first() {
+        let mut info = info();
+        info.description = Some("a".repeat(2000));
+        let caption = render_caption("{description}\n🔗 {url}", &info, "https://t/1");
+        assert_eq!(caption.chars().count(), MAX_CAPTION_CHARS);
+        assert!(caption.ends_with("…\n🔗 https://t/1"));
+    }
+
+    #[test]
+    fn test_unknown_placeholders() {
+        assert!(unknown_placeholders(DEFAULT_CAPTION_TEMPLATE).is_empty());
+        assert_eq!(unknown_placeholders("{author} {likez}"), vec!["likez"]);
+    }
+}
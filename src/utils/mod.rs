@@ -1,7 +1,14 @@
 pub mod progress_bar;
 pub mod progress_reader;
 pub mod task_manager;
+pub mod stages;
+pub mod scheduler;
 pub mod retry;
+pub mod send_options;
+pub mod caption;
+pub mod audio_meta;
 pub mod temp_file;
 
+pub use audio_meta::AudioMeta;
+pub use send_options::SendOptions;
 pub use temp_file::TempFileGuard;
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use teloxide::{prelude::*, requests::Requester, types::{ChatId, MessageId}};
+use teloxide::{prelude::*, requests::Requester, types::{ChatId, InlineKeyboardMarkup, MessageId}};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::{Instant, Duration};
@@ -12,53 +12,123 @@ struct ProgressBarInner {
     message_id: Option<MessageId>,
     last_update: Option<Instant>,
     last_percentage: u8,
+    last_info: Option<String>,
+    /// Kept under the message on every update (the Cancel button of queued jobs).
+    keyboard: Option<InlineKeyboardMarkup>,
+    /// Silent bars track nothing and never talk to Telegram.
+    silent: bool,
+}
+
+impl ProgressBarInner {
+    fn new(bot: Bot, chat_id: ChatId, silent: bool) -> Self {
+        Self {
+            bot,
+            chat_id,
+            message_id: None,
+            last_update: None,
+            last_percentage: 0,
+            last_info: None,
+            keyboard: None,
+            silent,
+        }
+    }
+
+    /// Sends `text` as a new progress message.
+    async fn send(&self, text: &str) -> Result<MessageId, teloxide::RequestError> {
+        let mut request = self.bot.send_message(self.chat_id, text);
+        if let Some(keyboard) = &self.keyboard {
+            request = request.reply_markup(keyboard.clone());
+        }
+        Ok(request.await?.id)
+    }
 }
 
 #[derive(Clone)]
 pub struct ProgressBar {
     inner: Arc<Mutex<ProgressBarInner>>,
+    /// `(index, total)` when this handle reports the upload of one part of a split video.
+    part: Option<(usize, usize)>,
 }
 
 impl ProgressBar {
     pub fn new(bot: Bot, chat_id: ChatId) -> Self {
-        Self::create_progressbar_static(bot, chat_id)
+        Self::create_progressbar_static(bot, chat_id, false)
     }
 
     pub fn new_silent() -> Self {
-        Self::create_progressbar_static(Bot::new("DUMMY_TOKEN"), ChatId(0))
+        Self::create_progressbar_static(Bot::new("DUMMY_TOKEN"), ChatId(0), true)
+    }
+
+    /// A bar that takes over an already sent message (the queue status) instead of sending
+    /// its own, keeping `keyboard` under it.
+    pub fn for_message(bot: Bot, chat_id: ChatId, message_id: MessageId, keyboard: Option<InlineKeyboardMarkup>) -> Self {
+        let mut inner = ProgressBarInner::new(bot, chat_id, false);
+        inner.message_id = Some(message_id);
+        inner.keyboard = keyboard;
+        ProgressBar { inner: Arc::new(Mutex::new(inner)), part: None }
+    }
+
+    fn create_progressbar_static(bot: Bot, chat_id: ChatId, silent: bool) -> Self {
+        ProgressBar { inner: Arc::new(Mutex::new(ProgressBarInner::new(bot, chat_id, silent))), part: None }
+    }
+
+    /// A handle on the same message for uploading part `index` (0-based) of `total`. The
+    /// upload stage (80-100%) of every part gets its share of the overall 80-100%, and the
+    /// info line names the part.
+    pub fn for_part(&self, index: usize, total: usize) -> Self {
+        ProgressBar { inner: self.inner.clone(), part: Some((index, total.max(1))) }
     }
 
-    fn create_progressbar_static(bot: Bot, chat_id: ChatId) -> Self {
-        ProgressBar {
-            inner: Arc::new(Mutex::new(ProgressBarInner {
-                bot,
-                chat_id,
-                message_id: None,
-                last_update: None,
-                last_percentage: 0,
-            })),
+    /// Overall percentage of a part's own `percentage`.
+    fn overall_percentage(&self, percentage: u8) -> u8 {
+        match self.part {
+            Some((index, total)) => {
+                let within = percentage.saturating_sub(80).min(20) as f64 / 20.0;
+                (80.0 + 20.0 * (index as f64 + within) / total as f64).floor() as u8
+            }
+            None => percentage,
         }
     }
 
     pub async fn start(&mut self, initial_text: &str) -> Result<(), anyhow::Error> {
         let mut inner = self.inner.lock().await;
-        let msg = inner.bot.send_message(inner.chat_id, initial_text).await?;
-        inner.message_id = Some(msg.id);
+        if let Some(message_id) = inner.message_id {
+            let mut request = inner.bot.edit_message_text(inner.chat_id, message_id, initial_text);
+            if let Some(keyboard) = &inner.keyboard {
+                request = request.reply_markup(keyboard.clone());
+            }
+            if request.await.is_ok() {
+                inner.last_update = Some(Instant::now());
+                return Ok(());
+            }
+        }
+        inner.message_id = Some(inner.send(initial_text).await?);
         inner.last_update = Some(Instant::now());
         Ok(())
     }
 
     pub async fn update(&mut self, percentage: u8, extrainfo: Option<&str>) -> Result<(), anyhow::Error> {
+        let percentage = self.overall_percentage(percentage);
+        let part_info = match (self.part, extrainfo) {
+            (Some((index, total)), Some(info)) => Some(format!("Part {}/{} · {}", index + 1, total, info)),
+            _ => extrainfo.map(|s| s.to_string()),
+        };
+        let extrainfo = part_info.as_deref();
         let mut inner = self.inner.lock().await;
+        if inner.silent {
+            return Ok(());
+        }
         let now = Instant::now();
 
         // Check if an update is needed
         let should_update = if let Some(last) = inner.last_update {
             let time_passed = now.duration_since(last) >= MIN_UPDATE_INTERVAL;
             let significant_change = percentage.saturating_sub(inner.last_percentage) >= 5; // Minimum 5% change
+            // Stages squeezed into a few percent (compression) still refresh their own counter
+            let info_changed = inner.last_info.as_deref() != extrainfo;
             let is_completion = percentage == 100;
             
-            time_passed && (significant_change || is_completion) || is_completion
+            time_passed && (significant_change || info_changed || is_completion) || is_completion
         } else {
             true
         };
@@ -69,14 +139,16 @@ impl ProgressBar {
 
         inner.last_update = Some(now);
         inner.last_percentage = percentage;
+        inner.last_info = extrainfo.map(|s| s.to_string());
 
         let progresstext = ProgressBar::create_progress_bar_text(percentage, extrainfo);
 
         if let Some(message_id) = inner.message_id {
-            let result = inner
-                .bot
-                .edit_message_text(inner.chat_id, message_id, &progresstext)
-                .await;
+            let mut request = inner.bot.edit_message_text(inner.chat_id, message_id, &progresstext);
+            if let Some(keyboard) = &inner.keyboard {
+                request = request.reply_markup(keyboard.clone());
+            }
+            let result = request.await;
 
             match result {
                 Ok(_) => {},
@@ -92,8 +164,8 @@ impl ProgressBar {
                         
                         // Create a new message only if not completed
                         if percentage < 100 {
-                            if let Ok(msg) = inner.bot.send_message(inner.chat_id, progresstext).await {
-                                inner.message_id = Some(msg.id);
+                            if let Ok(message_id) = inner.send(&progresstext).await {
+                                inner.message_id = Some(message_id);
                             }
                         }
                     } else if !error_str.contains("message is not modified") {
@@ -104,8 +176,8 @@ impl ProgressBar {
             }
         } else {
             // Create a new message
-            if let Ok(msg) = inner.bot.send_message(inner.chat_id, progresstext).await {
-                inner.message_id = Some(msg.id);
+            if let Ok(message_id) = inner.send(&progresstext).await {
+                inner.message_id = Some(message_id);
             }
         }
 
@@ -148,4 +220,20 @@ impl ProgressBar {
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_progress_is_spread_over_the_upload_stage() {
+        let bar = ProgressBar::new_silent();
+        assert_eq!(bar.overall_percentage(42), 42);
+        let second = bar.for_part(1, 4);
+        assert_eq!(second.overall_percentage(10), 85);
+        assert_eq!(second.overall_percentage(90), 87);
+        assert_eq!(second.overall_percentage(100), 90);
+        assert_eq!(bar.for_part(3, 4).overall_percentage(100), 100);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+/// Priority class of a job, from its requester.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Free,
+    Premium,
+    Admin,
+}
+
+impl Priority {
+    /// Highest first, the order ties are broken in.
+    pub const ALL: [Priority; 3] = [Priority::Admin, Priority::Premium, Priority::Free];
+
+    pub fn as_i64(self) -> i64 {
+        match self {
+            Priority::Free => 0,
+            Priority::Premium => 1,
+            Priority::Admin => 2,
+        }
+    }
+
+    /// Parses a stored priority; unknown values count as free.
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            2 => Priority::Admin,
+            1 => Priority::Premium,
+            _ => Priority::Free,
+        }
+    }
+
+    /// Share of the picks while every class has jobs waiting: out of 10 picks admins get 6,
+    /// Premium users 3 and free users 1, so nobody waits forever.
+    fn weight(self) -> i64 {
+        match self {
+            Priority::Admin => 6,
+            Priority::Premium => 3,
+            Priority::Free => 1,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Priority::Admin => 0,
+            Priority::Premium => 1,
+            Priority::Free => 2,
+        }
+    }
+}
+
+/// A job waiting in the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuedJob {
+    pub id: i64,
+    pub user_id: i64,
+    pub priority: Priority,
+}
+
+/// Decides which queued job starts next: classes share the workers by weight (smooth weighted
+/// round robin), jobs of a class start oldest first, and users already running
+/// `max_per_user` jobs are skipped until one of them finishes.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    max_per_user: usize,
+    /// Round-robin credit of each class, in [`Priority::ALL`] order.
+    credit: [i64; 3],
+}
+
+impl Scheduler {
+    pub fn new(max_per_user: usize) -> Self {
+        Self { max_per_user: max_per_user.max(1), credit: [0; 3] }
+    }
+
+    /// Picks the next job of `queued` (oldest first) given the running jobs per user.
+    pub fn pick(&mut self, queued: &[QueuedJob], running: &HashMap<i64, usize>) -> Option<i64> {
+        let heads = Priority::ALL.map(|priority| {
+            queued
+                .iter()
+                .find(|job| job.priority == priority && running.get(&job.user_id).copied().unwrap_or(0) < self.max_per_user)
+                .map(|job| job.id)
+        });
+        let class = self.next_class(heads.map(|head| head.is_some()))?;
+        heads[class]
+    }
+
+    /// The order the queued jobs would start in if no running job finished in between and
+    /// nothing else was queued, ignoring the per-user cap. Used for queue positions.
+    pub fn queue_order(&self, queued: &[QueuedJob]) -> Vec<i64> {
+        let mut simulated = self.clone();
+        let mut lanes = Priority::ALL.map(|priority| queued.iter().filter(move |job| job.priority == priority).map(|job| job.id));
+        let mut order = Vec::with_capacity(queued.len());
+        let mut heads = lanes.each_mut().map(|lane| lane.next());
+        while let Some(class) = simulated.next_class(heads.map(|head| head.is_some())) {
+            order.extend(heads[class]);
+            heads[class] = lanes[class].next();
+        }
+        order
+    }
+
+    /// Smooth weighted round robin over the classes with a job ready. Classes without one
+    /// lose their credit, so an idle class can't save up picks.
+    fn next_class(&mut self, ready: [bool; 3]) -> Option<usize> {
+        let total: i64 = Priority::ALL.iter().filter(|p| ready[p.index()]).map(|p| p.weight()).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut best: Option<usize> = None;
+        for priority in Priority::ALL {
+            let idx = priority.index();
+            if !ready[idx] {
+                self.credit[idx] = 0;
+                continue;
+            }
+            self.credit[idx] += priority.weight();
+            if best.is_none_or(|b| self.credit[idx] > self.credit[b]) {
+                best = Some(idx);
+            }
+        }
+        let best = best?;
+        self.credit[best] -= total;
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs(spec: &[(i64, i64, Priority)]) -> Vec<QueuedJob> {
+        spec.iter().map(|&(id, user_id, priority)| QueuedJob { id, user_id, priority }).collect()
+    }
+
+    /// Picks until the queue is empty, as if every job finished right after starting.
+    fn drain(scheduler: &mut Scheduler, mut queued: Vec<QueuedJob>) -> Vec<i64> {
+        let mut order = Vec::new();
+        while let Some(id) = scheduler.pick(&queued, &HashMap::new()) {
+            queued.retain(|job| job.id != id);
+            order.push(id);
+        }
+        order
+    }
+
+    #[test]
+    fn test_higher_classes_go_first_without_starving_free_users() {
+        let mut queued = Vec::new();
+        for id in 1..=20 {
+            queued.push(QueuedJob { id, user_id: id, priority: Priority::Free });
+        }
+        for id in 21..=40 {
+            queued.push(QueuedJob { id, user_id: id, priority: Priority::Premium });
+        }
+        let order = drain(&mut Scheduler::new(2), queued);
+        // Premium jobs take three picks out of four while both classes wait, oldest first.
+        assert_eq!(&order[..8], &[21, 22, 1, 23, 24, 25, 2, 26]);
+        let first_twenty = &order[..20];
+        assert_eq!(first_twenty.iter().filter(|id| **id <= 20).count(), 5);
+    }
+
+    #[test]
+    fn test_weights_with_every_class_waiting() {
+        let mut queued = Vec::new();
+        for (offset, priority) in [(0, Priority::Free), (100, Priority::Premium), (200, Priority::Admin)] {
+            for id in 1..=30 {
+                queued.push(QueuedJob { id: offset + id, user_id: offset + id, priority });
+            }
+        }
+        let order = drain(&mut Scheduler::new(2), queued);
+        let count = |from: i64| order[..20].iter().filter(|id| (from..from + 100).contains(*id)).count();
+        assert_eq!((count(200), count(100), count(0)), (12, 6, 2));
+    }
+
+    #[test]
+    fn test_per_user_cap() {
+        let queued = jobs(&[(1, 7, Priority::Premium), (2, 7, Priority::Premium), (3, 8, Priority::Free)]);
+        let mut scheduler = Scheduler::new(1);
+        let running = HashMap::from([(7, 1)]);
+        assert_eq!(scheduler.pick(&queued, &running), Some(3));
+        let running = HashMap::from([(7, 1), (8, 1)]);
+        assert_eq!(scheduler.pick(&queued, &running), None);
+        assert_eq!(scheduler.pick(&queued, &HashMap::new()), Some(1));
+    }
+
+    #[test]
+    fn test_queue_order_matches_picks() {
+        let queued = jobs(&[
+            (1, 1, Priority::Free),
+            (2, 2, Priority::Free),
+            (3, 3, Priority::Premium),
+            (4, 4, Priority::Admin),
+            (5, 5, Priority::Premium),
+        ]);
+        let scheduler = Scheduler::new(2);
+        let order = scheduler.queue_order(&queued);
+        assert_eq!(order, drain(&mut scheduler.clone(), queued));
+        assert_eq!(order, vec![4, 3, 5, 1, 2]);
+    }
+
+    #[test]
+    fn test_priority_round_trip() {
+        for priority in Priority::ALL {
+            assert_eq!(Priority::from_i64(priority.as_i64()), priority);
+        }
+        assert_eq!(Priority::from_i64(9), Priority::Free);
+    }
+}
@@ -0,0 +1,52 @@
+/// How a delivered file is posted, shared by the Bot API and MTProto uploaders.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SendOptions {
+    pub caption: Option<String>,
+    /// Message the media answers (the link message in groups).
+    pub reply_to: Option<i32>,
+    /// Deliver without a notification sound.
+    pub silent: bool,
+}
+
+impl SendOptions {
+    pub fn caption_or_empty(&self) -> &str {
+        self.caption.as_deref().unwrap_or("")
+    }
+
+    /// Bot API `reply_parameters` value. Sending still succeeds if the message is gone,
+    /// e.g. when the group deletes link messages.
+    pub fn reply_parameters_json(&self) -> Option<String> {
+        self.reply_to.map(|id| {
+            serde_json::json!({ "message_id": id, "allow_sending_without_reply": true }).to_string()
+        })
+    }
+
+    /// Adds caption, reply and notification fields to a Bot API multipart form.
+    pub fn apply_to_form(&self, mut form: reqwest::multipart::Form) -> reqwest::multipart::Form {
+        if let Some(c) = &self.caption {
+            form = form.text("caption", c.clone());
+        }
+        if let Some(reply) = self.reply_parameters_json() {
+            form = form.text("reply_parameters", reply);
+        }
+        if self.silent {
+            form = form.text("disable_notification", "true");
+        }
+        form
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_parameters_json() {
+        assert_eq!(SendOptions::default().reply_parameters_json(), None);
+        let opts = SendOptions { reply_to: Some(42), ..Default::default() };
+        let value: serde_json::Value = serde_json::from_str(&opts.reply_parameters_json().unwrap()).unwrap();
+        assert_eq!(value["message_id"], 42);
+        assert_eq!(value["allow_sending_without_reply"], true);
+        assert_eq!(opts.caption_or_empty(), "");
+    }
+}
@@ -0,0 +1,261 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+/// A step of the download pipeline. Each stage has its own workers, so slow yt-dlp downloads
+/// don't keep finished files from uploading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// yt-dlp downloads.
+    Download,
+    /// ffmpeg work: conversions, compression, splitting, subtitles, slideshow rendering.
+    Ffmpeg,
+    /// Uploads of files up to 50 MB.
+    BotApiUpload,
+    /// Uploads of bigger files.
+    MtprotoUpload,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Download, Stage::Ffmpeg, Stage::BotApiUpload, Stage::MtprotoUpload];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Download => "download",
+            Stage::Ffmpeg => "ffmpeg",
+            Stage::BotApiUpload => "Bot API upload",
+            Stage::MtprotoUpload => "MTProto upload",
+        }
+    }
+
+    /// Environment variable overriding the stage's worker count.
+    pub fn env_var(self) -> &'static str {
+        match self {
+            Stage::Download => "DOWNLOAD_SLOTS",
+            Stage::Ffmpeg => "FFMPEG_SLOTS",
+            Stage::BotApiUpload => "BOTAPI_UPLOAD_SLOTS",
+            Stage::MtprotoUpload => "MTPROTO_UPLOAD_SLOTS",
+        }
+    }
+
+    fn default_limit(self) -> usize {
+        match self {
+            Stage::Download => 2,
+            Stage::Ffmpeg => 2,
+            Stage::BotApiUpload => 4,
+            Stage::MtprotoUpload => 2,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A snapshot of one stage, for the admin report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageMetrics {
+    pub stage: Stage,
+    pub limit: usize,
+    pub active: usize,
+    pub waiting: usize,
+    pub completed: u64,
+    /// Average time a worker spent on a finished step.
+    pub avg_busy: Duration,
+}
+
+/// A job's step, handed to a stage worker.
+type Step = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Default)]
+struct StageCounters {
+    active: AtomicUsize,
+    /// Callers waiting for room in the stage's channel.
+    blocked: AtomicUsize,
+    completed: AtomicU64,
+    busy_ms: AtomicU64,
+}
+
+struct StageState {
+    limit: usize,
+    steps: mpsc::Sender<Step>,
+    counters: Arc<StageCounters>,
+}
+
+/// Counts a caller as blocked until its step is in the channel or it gives up.
+struct BlockedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for BlockedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The download pipeline: every stage is a pool of workers fed by a bounded channel that
+/// holds as many steps as the stage has workers. A job submits each step to its stage and
+/// waits for the result, so it holds no worker while its step waits or while it is between
+/// stages. Counters feed `/stages`.
+pub struct Stages {
+    stages: [StageState; 4],
+}
+
+impl Stages {
+    /// Starts the workers, `limits` of them per stage in the order of [`Stage::ALL`]; a limit
+    /// of 0 counts as 1. Needs a Tokio runtime.
+    pub fn new(limits: [usize; 4]) -> Self {
+        Self {
+            stages: limits.map(|limit| {
+                let limit = limit.max(1);
+                let (steps, receiver) = mpsc::channel(limit);
+                let receiver = Arc::new(Mutex::new(receiver));
+                let counters = Arc::new(StageCounters::default());
+                for _ in 0..limit {
+                    // They end with the channel, when the stages are dropped; shutdown
+                    // doesn't stop them, so draining jobs can still finish their steps.
+                    tokio::spawn(run_stage_worker(receiver.clone(), counters.clone()));
+                }
+                StageState { limit, steps, counters }
+            }),
+        }
+    }
+
+    /// Worker counts from DOWNLOAD_SLOTS, FFMPEG_SLOTS, BOTAPI_UPLOAD_SLOTS and MTPROTO_UPLOAD_SLOTS.
+    pub fn from_env() -> Self {
+        Self::new(Stage::ALL.map(|stage| {
+            std::env::var(stage.env_var())
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(stage.default_limit())
+        }))
+    }
+
+    /// Steps the pipeline holds at once: running on a worker or waiting in a channel.
+    pub fn capacity(&self) -> usize {
+        self.stages.iter().map(|state| state.limit * 2).sum()
+    }
+
+    /// Runs `step` on a worker of `stage` and returns its output. Waits while the stage's
+    /// channel is full. Dropping the returned future drops the step, also when a worker is
+    /// already running it.
+    pub async fn run<F>(&self, stage: Stage, step: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let state = &self.stages[stage.index()];
+        let (mut done, output) = oneshot::channel();
+        let step: Step = Box::pin(async move {
+            let out = tokio::select! {
+                out = step => out,
+                _ = done.closed() => return,
+            };
+            let _ = done.send(out);
+        });
+        {
+            state.counters.blocked.fetch_add(1, Ordering::Relaxed);
+            let _blocked = BlockedGuard(&state.counters.blocked);
+            if state.steps.send(step).await.is_err() {
+                unreachable!("stage workers live as long as the stages");
+            }
+        }
+        output.await.expect("a pipeline step panicked")
+    }
+
+    pub fn metrics(&self) -> Vec<StageMetrics> {
+        Stage::ALL
+            .iter()
+            .map(|&stage| {
+                let state = &self.stages[stage.index()];
+                let counters = &state.counters;
+                let completed = counters.completed.load(Ordering::Relaxed);
+                let busy_ms = counters.busy_ms.load(Ordering::Relaxed);
+                let queued = state.steps.max_capacity() - state.steps.capacity();
+                StageMetrics {
+                    stage,
+                    limit: state.limit,
+                    active: counters.active.load(Ordering::Relaxed),
+                    waiting: queued + counters.blocked.load(Ordering::Relaxed),
+                    completed,
+                    avg_busy: Duration::from_millis(busy_ms.checked_div(completed).unwrap_or(0)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Takes the stage's steps one at a time until the channel closes.
+async fn run_stage_worker(steps: Arc<Mutex<mpsc::Receiver<Step>>>, counters: Arc<StageCounters>) {
+    loop {
+        let Some(step) = steps.lock().await.recv().await else {
+            return;
+        };
+        counters.active.fetch_add(1, Ordering::Relaxed);
+        let started = Instant::now();
+        // Spawned, so a panicking step fails only its job and the worker goes on.
+        let _ = tokio::spawn(step).await;
+        counters.active.fetch_sub(1, Ordering::Relaxed);
+        counters.completed.fetch_add(1, Ordering::Relaxed);
+        counters.busy_ms.fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Notify;
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stages_have_their_own_workers() {
+        let stages = Arc::new(Stages::new([1, 0, 2, 1]));
+        let release = Arc::new(Notify::new());
+        let blocking = {
+            let (stages, release) = (stages.clone(), release.clone());
+            tokio::spawn(async move { stages.run(Stage::Download, async move { release.notified().await }).await })
+        };
+        settle().await;
+
+        // A second download waits in the channel, uploads don't.
+        let waiter = {
+            let stages = stages.clone();
+            tokio::spawn(async move { stages.run(Stage::Download, async { 7 }).await })
+        };
+        assert_eq!(stages.run(Stage::BotApiUpload, async { 1 }).await, 1);
+        settle().await;
+        let metrics = stages.metrics();
+        assert_eq!((metrics[0].active, metrics[0].waiting), (1, 1));
+        assert_eq!((metrics[1].limit, metrics[1].active), (1, 0));
+        assert_eq!((metrics[2].limit, metrics[2].completed), (2, 1));
+        assert_eq!(stages.capacity(), 10);
+
+        release.notify_one();
+        blocking.await.unwrap();
+        assert_eq!(waiter.await.unwrap(), 7);
+        settle().await;
+        let metrics = stages.metrics();
+        assert_eq!((metrics[0].active, metrics[0].waiting, metrics[0].completed), (0, 0, 2));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_step_frees_its_worker() {
+        let stages = Arc::new(Stages::new([1, 1, 1, 1]));
+        let stuck = {
+            let stages = stages.clone();
+            tokio::spawn(async move { stages.run(Stage::Ffmpeg, std::future::pending::<()>()).await })
+        };
+        settle().await;
+        assert_eq!(stages.metrics()[1].active, 1);
+
+        // Cancelling the job drops its step, and the next one gets the worker.
+        stuck.abort();
+        assert_eq!(stages.run(Stage::Ffmpeg, async { 3 }).await, 3);
+    }
+}
@@ -1,39 +1,298 @@
-use tokio::task::JoinSet;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::{JoinError, JoinHandle};
+use tokio_util::sync::CancellationToken;
 
+/// How long cancelled jobs get to clean up (kill yt-dlp, remove temp files, tell the user)
+/// once the drain deadline passed.
+const UNWIND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    /// Bounded work (downloads, broadcasts): takes one of the concurrency slots and is
+    /// given time to finish on shutdown.
+    Job,
+    /// Long-running loops (pollers, workers, servers) and work that only waits on jobs
+    /// (update handlers, batch followers): no slot, told to stop as soon as shutdown begins.
+    Service,
+}
+
+/// A running or waiting task, for introspection.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: u64,
+    pub name: String,
+    pub kind: TaskKind,
+    /// False while a job waits for a concurrency slot.
+    pub running: bool,
+    pub age: Duration,
+}
+
+struct TaskEntry {
+    name: String,
+    kind: TaskKind,
+    started: Instant,
+    running: bool,
+    token: CancellationToken,
+}
+
+type TaskMap = Arc<Mutex<HashMap<u64, TaskEntry>>>;
+
+/// Removes a task from the registry when it ends, panics included.
+struct EntryGuard {
+    id: u64,
+    tasks: TaskMap,
+    finished: Arc<Notify>,
+}
+
+impl Drop for EntryGuard {
+    fn drop(&mut self) {
+        self.tasks.lock().unwrap().remove(&self.id);
+        self.finished.notify_waiters();
+    }
+}
+
+/// A spawned task: its cancellation token and result.
+pub struct TaskHandle<T> {
+    pub id: u64,
+    token: CancellationToken,
+    join: JoinHandle<T>,
+}
+
+impl<T> TaskHandle<T> {
+    /// The token the task was started with; cancelling it asks the task to stop.
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    pub async fn join(self) -> Result<T, JoinError> {
+        self.join.await
+    }
+}
+
+/// Runs the bot's background work. Jobs are capped at `max_concurrent` at a time; every task
+/// gets a name and its own `CancellationToken` that it is expected to watch. On shutdown no
+/// new tasks are accepted, services are told to stop and jobs get a deadline to finish.
 pub struct TaskManager {
-    tasks: JoinSet<()>,
+    slots: Arc<Semaphore>,
+    tasks: TaskMap,
+    next_id: AtomicU64,
+    finished: Arc<Notify>,
+    /// Cancelled when shutdown begins; parent of the service tokens.
+    stopping: CancellationToken,
+    /// Cancelled when the drain deadline passes; parent of the job tokens.
+    aborting: CancellationToken,
 }
 
 impl TaskManager {
-    pub fn new(_max_concurrent: usize) -> Self {
+    pub fn new(max_concurrent: usize) -> Self {
         Self {
-            tasks: JoinSet::new(),
+            slots: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(1),
+            finished: Arc::new(Notify::new()),
+            stopping: CancellationToken::new(),
+            aborting: CancellationToken::new(),
+        }
+    }
+
+    /// Starts a [`TaskKind::Job`] once a slot is free. `task` receives the job's token, which
+    /// is cancelled by [`TaskManager::cancel`] or when the shutdown deadline passes. Returns
+    /// None once shutdown began.
+    pub fn spawn_job<T, F, Fut>(&self, name: impl Into<String>, task: F) -> Option<TaskHandle<T>>
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn(name.into(), TaskKind::Job, true, task)
+    }
+
+    /// Starts a [`TaskKind::Job`] right away, without a slot, for jobs whose work is capped
+    /// elsewhere (the download pipeline's stages). Drained and cancelled like other jobs.
+    pub fn spawn_unslotted_job<T, F, Fut>(&self, name: impl Into<String>, task: F) -> Option<TaskHandle<T>>
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn(name.into(), TaskKind::Job, false, task)
+    }
+
+    /// Starts a [`TaskKind::Service`]. Its token is cancelled when shutdown begins. Returns
+    /// None once shutdown began.
+    pub fn spawn_service<T, F, Fut>(&self, name: impl Into<String>, task: F) -> Option<TaskHandle<T>>
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn(name.into(), TaskKind::Service, false, task)
+    }
+
+    fn spawn<T, F, Fut>(&self, name: String, kind: TaskKind, slotted: bool, task: F) -> Option<TaskHandle<T>>
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.is_stopping() {
+            log::warn!("Not starting {} during shutdown", name);
+            return None;
         }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let token = match kind {
+            TaskKind::Job => self.aborting.child_token(),
+            TaskKind::Service => self.stopping.child_token(),
+        };
+        self.tasks.lock().unwrap().insert(
+            id,
+            TaskEntry { name, kind, started: Instant::now(), running: !slotted, token: token.clone() },
+        );
+        let guard = EntryGuard { id, tasks: self.tasks.clone(), finished: self.finished.clone() };
+        let slots = slotted.then(|| self.slots.clone());
+        let future = task(token.clone());
+        let join = tokio::spawn(async move {
+            let guard = guard;
+            // A job cancelled while waiting still starts, sees its token cancelled and returns.
+            let _slot = match slots {
+                Some(slots) => slots.acquire_owned().await.ok(),
+                None => None,
+            };
+            if let Some(entry) = guard.tasks.lock().unwrap().get_mut(&id) {
+                entry.running = true;
+            }
+            future.await
+        });
+        Some(TaskHandle { id, token, join })
     }
 
-    /// Waits for all tasks to complete
-    pub async fn shutdown(&mut self) {
-        log::info!("Shutting down TaskManager, waiting for {} tasks", self.tasks.len());
-        while let Some(result) = self.tasks.join_next().await {
-            if let Err(e) = result {
-                log::error!("Task failed during shutdown: {:?}", e);
+    /// Asks a task to stop. Returns false when there is no such task.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.tasks.lock().unwrap().get(&id) {
+            Some(entry) => {
+                entry.token.cancel();
+                true
             }
+            None => false,
         }
-        log::info!("TaskManager shutdown complete");
     }
 
-    /// Aborts all tasks without waiting
-    pub fn abort_all(&mut self) {
-        log::warn!("Aborting all {} tasks", self.tasks.len());
-        self.tasks.abort_all();
+    /// Current tasks, oldest first.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let mut tasks: Vec<TaskInfo> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, entry)| TaskInfo {
+                id: *id,
+                name: entry.name.clone(),
+                kind: entry.kind,
+                running: entry.running,
+                age: entry.started.elapsed(),
+            })
+            .collect();
+        tasks.sort_by_key(|task| task.id);
+        tasks
     }
-}
 
-impl Drop for TaskManager {
-    fn drop(&mut self) {
-        if !self.tasks.is_empty() {
-            log::warn!("TaskManager dropped with {} active tasks, aborting them", self.tasks.len());
-            self.abort_all();
+    /// Whether shutdown began and no new tasks are accepted.
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.is_cancelled()
+    }
+
+    /// Whether the shutdown deadline passed and the remaining jobs were cancelled.
+    pub fn is_aborting(&self) -> bool {
+        self.aborting.is_cancelled()
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            let finished = self.finished.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+            if self.tasks.lock().unwrap().is_empty() {
+                return;
+            }
+            finished.await;
+        }
+    }
+
+    /// Stops accepting tasks, stops services and waits up to `grace` for jobs to finish.
+    /// Jobs still running then are cancelled and get a moment to clean up. Returns the
+    /// names of the cancelled jobs.
+    pub async fn shutdown(&self, grace: Duration) -> Vec<String> {
+        self.stopping.cancel();
+        log::info!("Shutting down TaskManager, waiting for {} tasks", self.tasks.lock().unwrap().len());
+        if tokio::time::timeout(grace, self.wait_idle()).await.is_ok() {
+            log::info!("TaskManager shutdown complete");
+            return Vec::new();
+        }
+
+        let interrupted: Vec<String> = self
+            .tasks()
+            .into_iter()
+            .filter(|task| task.kind == TaskKind::Job)
+            .map(|task| task.name)
+            .collect();
+        log::warn!("Cancelling {} jobs still running after {:?}", interrupted.len(), grace);
+        self.aborting.cancel();
+        if tokio::time::timeout(UNWIND_TIMEOUT, self.wait_idle()).await.is_err() {
+            log::error!("{} tasks did not stop in time", self.tasks.lock().unwrap().len());
         }
+        interrupted
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jobs_are_capped_and_drained() {
+        let manager = TaskManager::new(1);
+        let release = Arc::new(Notify::new());
+        let first = {
+            let release = release.clone();
+            manager.spawn_job("first", |_| async move { release.notified().await }).unwrap()
+        };
+        let second = manager.spawn_job("second", |_| async { 2 }).unwrap();
+        // Unslotted jobs don't wait for the slot.
+        let queued = manager.spawn_unslotted_job("queued", |_| async { 3 }).unwrap();
+        assert_eq!(queued.join().await.unwrap(), 3);
+        tokio::task::yield_now().await;
+        let tasks = manager.tasks();
+        assert_eq!(tasks.iter().map(|t| (t.name.as_str(), t.running)).collect::<Vec<_>>(), vec![("first", true), ("second", false)]);
+
+        release.notify_one();
+        first.join().await.unwrap();
+        assert_eq!(second.join().await.unwrap(), 2);
+        assert!(manager.tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_services_and_cancels_late_jobs() {
+        let manager = TaskManager::new(2);
+        let service = manager.spawn_service("poller", |token| async move { token.cancelled().await }).unwrap();
+        let job = manager
+            .spawn_job("slow job", |token| async move {
+                tokio::select! {
+                    _ = token.cancelled() => "cancelled",
+                    _ = tokio::time::sleep(Duration::from_secs(60)) => "done",
+                }
+            })
+            .unwrap();
+
+        let interrupted = manager.shutdown(Duration::from_millis(50)).await;
+        assert_eq!(interrupted, vec!["slow job".to_string()]);
+        assert!(manager.is_aborting());
+        assert_eq!(job.join().await.unwrap(), "cancelled");
+        service.join().await.unwrap();
+        assert!(manager.spawn_job("late", |_| async {}).is_none());
+    }
+}
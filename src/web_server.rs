@@ -7,9 +7,7 @@ use axum::{
 use tower_http::cors::CorsLayer;
 use std::sync::Arc;
 use crate::database::DatabasePool;
-use crate::yt_dlp_interface::YoutubeFetcher;
-use crate::mtproto_uploader::MTProtoUploader;
-use crate::utils::task_manager::TaskManager;
+use crate::handlers::queue::JobQueue;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use teloxide::prelude::*;
@@ -21,10 +19,7 @@ const MINI_APP_HTML: &str = include_str!("../mini-app/index.html");
 pub struct AppState {
     pub db: Arc<DatabasePool>,
     pub bot: Bot,
-    pub fetcher: Arc<YoutubeFetcher>,
-    pub mtproto_uploader: Arc<MTProtoUploader>,
-    pub task_manager: Arc<tokio::sync::Mutex<TaskManager>>,
-    pub upload_semaphore: Arc<tokio::sync::Semaphore>,
+    pub job_queue: Arc<JobQueue>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -178,23 +173,15 @@ async fn claim_video(
         Ok((user_id, url)) => {
             log::info!("Claim success! Triggering download for user {}: {}", user_id, url);
             
-            // Process in background
-            tokio::spawn(async move {
-                if let Err(e) = crate::handlers::link::process_video_request(
-                    state.bot,
-                    user_id,
-                    url,
-                    state.fetcher,
-                    state.mtproto_uploader,
-                    state.db,
-                    state.task_manager,
-                    state.upload_semaphore,
-                    None,
-                    ChatId(user_id)
-                ).await {
-                    log::error!("Error processing claimed download: {}", e);
-                }
-            });
+            // Links requested as a clip carry their range (see `ClipRange::tag_url`).
+            let (url, clip) = crate::yt_dlp_interface::ClipRange::untag_url(&url);
+            let mut request = crate::handlers::link::VideoRequest::new(user_id, ChatId(user_id), url);
+            request.clip = clip;
+
+            if let Err(e) = state.job_queue.enqueue(&request).await {
+                log::error!("Error queueing claimed download: {}", e);
+                return Json(json!({ "success": false, "error": "Could not start the download. Please try again." }));
+            }
 
             Json(json!({ "success": true }))
         },
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+/// What audio downloads are converted to before they are sent. yt-dlp extracts the source's
+/// own codec (`--audio-format best`); everything but [`AudioFormat::Original`] is re-encoded
+/// with ffmpeg afterwards, so tikwm downloads are converted the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AudioFormat {
+    /// Whatever codec the source has, usually m4a or opus.
+    #[default]
+    Original,
+    /// MP3 at the given bitrate in kbit/s.
+    Mp3(u32),
+    M4a,
+    Opus,
+    /// Mono OGG/Opus sent as a Telegram voice message.
+    Voice,
+}
+
+impl AudioFormat {
+    /// Every choice of the audio settings menu, in display order.
+    pub const ALL: [AudioFormat; 7] = [
+        AudioFormat::Original,
+        AudioFormat::Mp3(128),
+        AudioFormat::Mp3(192),
+        AudioFormat::Mp3(320),
+        AudioFormat::M4a,
+        AudioFormat::Opus,
+        AudioFormat::Voice,
+    ];
+
+    /// Value stored in `users.audio_format`.
+    pub fn id(&self) -> String {
+        match self {
+            AudioFormat::Original => "original".to_string(),
+            AudioFormat::Mp3(kbps) => format!("mp3_{}", kbps),
+            AudioFormat::M4a => "m4a".to_string(),
+            AudioFormat::Opus => "opus".to_string(),
+            AudioFormat::Voice => "voice".to_string(),
+        }
+    }
+
+    /// Parses an [`AudioFormat::id`]; unknown values fall back to the original format.
+    pub fn from_id(id: &str) -> Self {
+        Self::ALL.into_iter().find(|f| f.id() == id).unwrap_or_default()
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            AudioFormat::Original => "Original".to_string(),
+            AudioFormat::Mp3(kbps) => format!("MP3 {}k", kbps),
+            AudioFormat::M4a => "M4A (AAC)".to_string(),
+            AudioFormat::Opus => "Opus".to_string(),
+            AudioFormat::Voice => "Voice message".to_string(),
+        }
+    }
+
+    pub fn is_voice(&self) -> bool {
+        *self == AudioFormat::Voice
+    }
+
+    /// media_cache quality key, so every format is cached separately. The original format
+    /// keeps the plain "audio" key entries were stored under before formats existed.
+    pub fn cache_key(&self) -> String {
+        match self {
+            AudioFormat::Original => "audio".to_string(),
+            other => format!("audio:{}", other.id()),
+        }
+    }
+
+    /// Extension of the converted file; `None` for the original format.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            AudioFormat::Original => None,
+            AudioFormat::Mp3(_) => Some("mp3"),
+            AudioFormat::M4a => Some("m4a"),
+            AudioFormat::Opus | AudioFormat::Voice => Some("ogg"),
+        }
+    }
+
+    /// Whether the file at `path` has to be re-encoded. A source that already has the
+    /// requested codec is kept as is, except for MP3 where the bitrate was chosen.
+    pub fn needs_conversion(&self, path: &Path) -> bool {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        match self {
+            AudioFormat::Original => false,
+            AudioFormat::M4a => ext != "m4a",
+            AudioFormat::Opus => ext != "opus" && ext != "ogg",
+            AudioFormat::Mp3(_) | AudioFormat::Voice => true,
+        }
+    }
+}
+
+/// ffmpeg arguments that re-encode the audio of `input` into `output` as `format`.
+pub fn build_convert_args(input: &Path, output: &Path, format: AudioFormat) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-vn".into(),
+        "-map_metadata".into(),
+        "-1".into(),
+    ];
+    let codec: &[&str] = match format {
+        AudioFormat::Original => &["-c:a", "copy"],
+        AudioFormat::Mp3(_) => &["-c:a", "libmp3lame"],
+        AudioFormat::M4a => &["-c:a", "aac", "-b:a", "192k", "-movflags", "+faststart"],
+        AudioFormat::Opus => &["-c:a", "libopus", "-b:a", "128k"],
+        // Voice messages are mono speech-quality Opus, like the ones Telegram records.
+        AudioFormat::Voice => &["-c:a", "libopus", "-b:a", "48k", "-ac", "1", "-ar", "48000", "-application", "voip"],
+    };
+    args.extend(codec.iter().map(|s| s.to_string()));
+    if let AudioFormat::Mp3(kbps) = format {
+        args.extend(["-b:a".into(), format!("{}k", kbps)]);
+    }
+    args.push(output.to_string_lossy().into_owned());
+    args
+}
+
+impl YoutubeFetcher {
+    /// Converts a downloaded audio file to `format`. Returns the original path when no
+    /// conversion is needed; otherwise the converted file, which the caller cleans up.
+    pub async fn convert_audio(&self, input: &Path, format: AudioFormat) -> Result<PathBuf> {
+        let Some(ext) = format.extension().filter(|_| format.needs_conversion(input)) else {
+            return Ok(input.to_path_buf());
+        };
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let output = input.with_file_name(format!("{}_{}.{}", stem, format.id(), ext));
+        let mut guard = TempFileGuard::new(output.clone());
+
+        log::info!("Converting {:?} to {}", input, format.label());
+        let args = build_convert_args(input, &output, format);
+        let out = Command::new(self.ffmpeg_path()).args(&args).kill_on_drop(true).output().await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg audio conversion failed: {}", stderr.trim()));
+        }
+        guard.forget();
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_roundtrip() {
+        for format in AudioFormat::ALL {
+            assert_eq!(AudioFormat::from_id(&format.id()), format);
+        }
+        assert_eq!(AudioFormat::from_id("flac"), AudioFormat::Original);
+        assert_eq!(AudioFormat::Original.cache_key(), "audio");
+        assert_eq!(AudioFormat::Mp3(320).cache_key(), "audio:mp3_320");
+    }
+
+    #[test]
+    fn test_needs_conversion() {
+        assert!(!AudioFormat::Original.needs_conversion(Path::new("a.webm")));
+        assert!(!AudioFormat::M4a.needs_conversion(Path::new("a.m4a")));
+        assert!(AudioFormat::M4a.needs_conversion(Path::new("a.opus")));
+        assert!(!AudioFormat::Opus.needs_conversion(Path::new("a.opus")));
+        assert!(AudioFormat::Mp3(128).needs_conversion(Path::new("a.mp3")));
+        assert!(AudioFormat::Voice.needs_conversion(Path::new("a.ogg")));
+    }
+
+    #[test]
+    fn test_convert_args() {
+        let mp3 = build_convert_args(Path::new("in.m4a"), Path::new("out.mp3"), AudioFormat::Mp3(320));
+        assert!(mp3.windows(2).any(|w| w == ["-c:a", "libmp3lame"]));
+        assert!(mp3.windows(2).any(|w| w == ["-b:a", "320k"]));
+        assert_eq!(mp3.last().map(String::as_str), Some("out.mp3"));
+
+        let voice = build_convert_args(Path::new("in.m4a"), Path::new("out.ogg"), AudioFormat::Voice);
+        assert!(voice.windows(2).any(|w| w == ["-c:a", "libopus"]));
+        assert!(voice.windows(2).any(|w| w == ["-ac", "1"]));
+    }
+}
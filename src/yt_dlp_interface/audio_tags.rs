@@ -0,0 +1,194 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::mtproto_uploader::metadata::get_media_duration;
+use crate::utils::audio_meta::AudioMeta;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+use crate::yt_dlp_interface::video_info::VideoInfo;
+
+const COVER_TIMEOUT: Duration = Duration::from_secs(15);
+/// Telegram shows thumbnails of at most 320x320.
+const COVER_SIZE: u32 = 320;
+
+fn extension(path: &Path) -> String {
+    path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase()
+}
+
+/// Containers ffmpeg can write an attached cover picture into.
+pub fn supports_cover(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "mp3" | "m4a" | "mp4" | "flac")
+}
+
+/// Containers that carry title/artist tags at all (raw AAC and WAV streams don't, reliably).
+pub fn supports_tags(path: &Path) -> bool {
+    supports_cover(path) || matches!(extension(path).as_str(), "ogg" | "opus" | "webm")
+}
+
+/// ffmpeg arguments that copy the audio of `input` into `output` with title/artist tags and,
+/// when given, `cover` as the embedded front cover.
+pub fn build_tag_args(input: &Path, cover: Option<&Path>, meta: &AudioMeta, output: &Path) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-y".into(), "-i".into(), input.to_string_lossy().into_owned()];
+    if let Some(cover) = cover {
+        args.extend(["-i".into(), cover.to_string_lossy().into_owned()]);
+    }
+    args.extend(["-map".into(), "0:a".into()]);
+    if cover.is_some() {
+        args.extend([
+            "-map".into(),
+            "1:v".into(),
+            "-disposition:v:0".into(),
+            "attached_pic".into(),
+            "-metadata:s:v".into(),
+            "title=Album cover".into(),
+            "-metadata:s:v".into(),
+            "comment=Cover (front)".into(),
+        ]);
+    }
+    args.extend(["-c".into(), "copy".into(), "-map_metadata".into(), "-1".into()]);
+    if let Some(title) = &meta.title {
+        args.extend(["-metadata".into(), format!("title={}", title)]);
+    }
+    if let Some(performer) = &meta.performer {
+        args.extend(["-metadata".into(), format!("artist={}", performer)]);
+    }
+    if extension(output) == "mp3" {
+        args.extend(["-id3v2_version".into(), "3".into()]);
+    }
+    args.push(output.to_string_lossy().into_owned());
+    args
+}
+
+impl YoutubeFetcher {
+    /// Everything Telegram needs to show an audio file properly: duration from ffprobe,
+    /// title/performer from the post's metadata and a square cover. The tags and cover are
+    /// also written into the file itself, so they survive when the file is saved. Every
+    /// step is best effort; the audio is delivered even when none of them works out.
+    pub async fn prepare_audio(&self, path: &Path, info: Option<&VideoInfo>) -> AudioMeta {
+        let mut meta = info.map(AudioMeta::from_info).unwrap_or_default();
+
+        match get_media_duration(self.ffprobe_path().to_string_lossy().as_ref(), path).await {
+            Ok(duration) => meta.duration = duration.round().max(0.0) as u32,
+            Err(e) => log::warn!("Could not probe the duration of {:?}: {}", path, e),
+        }
+
+        if let Some(url) = info.and_then(|i| i.thumbnail.as_deref()) {
+            match self.fetch_cover(url, path).await {
+                Ok(cover) => meta.thumbnail = Some(cover),
+                Err(e) => log::warn!("Could not fetch the cover {}: {}", url, e),
+            }
+        }
+
+        if supports_tags(path) && (meta.title.is_some() || meta.performer.is_some() || meta.thumbnail.is_some()) {
+            let cover = meta.thumbnail.as_deref().filter(|_| supports_cover(path));
+            if let Err(e) = self.write_tags(path, cover, &meta).await {
+                log::warn!("Could not tag {:?}: {}", path, e);
+            }
+        }
+        meta
+    }
+
+    /// Downloads the cover and crops it to a square JPEG thumbnail saved next to `audio`.
+    async fn fetch_cover(&self, url: &str, audio: &Path) -> Result<PathBuf> {
+        let client = reqwest::Client::builder().timeout(COVER_TIMEOUT).build()?;
+        let bytes = client
+            .get(url)
+            .header("User-Agent", "Mozilla/5.0")
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let stem = audio.file_stem().unwrap_or_default().to_string_lossy();
+        let source = audio.with_file_name(format!("{}_cover_src", stem));
+        let _source_guard = TempFileGuard::new(source.clone());
+        tokio::fs::write(&source, &bytes).await?;
+
+        let cover = audio.with_file_name(format!("{}_cover.jpg", stem));
+        let mut guard = TempFileGuard::new(cover.clone());
+        let filter = format!(
+            "scale={size}:{size}:force_original_aspect_ratio=increase,crop={size}:{size}",
+            size = COVER_SIZE
+        );
+        let out = Command::new(self.ffmpeg_path())
+            .arg("-y")
+            .arg("-i")
+            .arg(&source)
+            .arg("-vf")
+            .arg(filter)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-q:v")
+            .arg("4")
+            .arg(&cover)
+            .output()
+            .await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg cover conversion failed: {}", stderr.trim()));
+        }
+        guard.forget();
+        Ok(cover)
+    }
+
+    /// Rewrites `path` in place with the tags (and cover) of `meta`.
+    async fn write_tags(&self, path: &Path, cover: Option<&Path>, meta: &AudioMeta) -> Result<()> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let tagged = path.with_file_name(format!("{}_tagged.{}", stem, extension(path)));
+        let mut guard = TempFileGuard::new(tagged.clone());
+
+        let args = build_tag_args(path, cover, meta, &tagged);
+        let out = Command::new(self.ffmpeg_path()).args(&args).output().await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg tagging failed: {}", stderr.trim()));
+        }
+
+        if let Err(e) = tokio::fs::rename(&tagged, path).await {
+            tokio::fs::copy(&tagged, path).await?;
+            log::debug!("Copied tagged audio over the original (rename failed: {})", e);
+        } else {
+            guard.forget();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> AudioMeta {
+        AudioMeta { title: Some("Song".to_string()), performer: Some("DJ".to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_tag_args_with_cover() {
+        let args = build_tag_args(Path::new("a.mp3"), Some(Path::new("c.jpg")), &meta(), Path::new("out.mp3"));
+        assert!(args.windows(2).any(|w| w == ["-map", "1:v"]));
+        assert!(args.windows(2).any(|w| w == ["-disposition:v:0", "attached_pic"]));
+        assert!(args.windows(2).any(|w| w == ["-metadata", "title=Song"]));
+        assert!(args.windows(2).any(|w| w == ["-metadata", "artist=DJ"]));
+        assert!(args.windows(2).any(|w| w == ["-id3v2_version", "3"]));
+        assert_eq!(args.last().map(String::as_str), Some("out.mp3"));
+    }
+
+    #[test]
+    fn test_tag_args_without_cover() {
+        let args = build_tag_args(Path::new("a.opus"), None, &meta(), Path::new("out.opus"));
+        assert!(!args.contains(&"1:v".to_string()));
+        assert!(!args.contains(&"-id3v2_version".to_string()));
+        assert_eq!(args.iter().filter(|a| *a == "-i").count(), 1);
+    }
+
+    #[test]
+    fn test_container_support() {
+        assert!(supports_cover(Path::new("x.M4A")));
+        assert!(!supports_cover(Path::new("x.opus")));
+        assert!(supports_tags(Path::new("x.opus")));
+        assert!(!supports_tags(Path::new("x.aac")));
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+/// Longest clip free users can request, unless FREE_MAX_CLIP_SECS says otherwise.
+const DEFAULT_FREE_MAX_CLIP_SECS: u32 = 60;
+/// Longest clip Premium users can request, unless PREMIUM_MAX_CLIP_SECS says otherwise.
+const DEFAULT_PREMIUM_MAX_CLIP_SECS: u32 = 600;
+/// Section downloads start at the nearest keyframe; anything this much longer than the
+/// requested range is trimmed locally.
+const SECTION_TOLERANCE_SECS: f64 = 2.0;
+/// Fragment a clip range is carried in when the link is stored for later (pending ad downloads).
+const CLIP_FRAGMENT: &str = "#clip=";
+
+/// A part of a video, in whole seconds: `start` inclusive, `end` exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClipRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Why a clip range can't be downloaded, worded for the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipError {
+    /// The range starts at or after the end of the video.
+    PastEnd { duration: u32 },
+    /// The range is longer than the user's limit.
+    TooLong { max: u32, premium: bool },
+}
+
+impl std::fmt::Display for ClipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClipError::PastEnd { duration } => {
+                write!(f, "The video is only {} long.", format_timestamp(*duration))
+            }
+            ClipError::TooLong { max, premium: true } => {
+                write!(f, "Clips can be at most {} long.", format_timestamp(*max))
+            }
+            ClipError::TooLong { max, premium: false } => {
+                write!(f, "Clips can be at most {} long. Premium users get longer clips.", format_timestamp(*max))
+            }
+        }
+    }
+}
+
+/// Parses `SS`, `M:SS` or `H:MM:SS` into seconds.
+pub fn parse_timestamp(s: &str) -> Option<u32> {
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    let mut seconds: u32 = 0;
+    for (idx, part) in parts.iter().enumerate() {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let value: u32 = part.parse().ok()?;
+        // Minutes and seconds after a colon stay below 60.
+        if idx > 0 && (part.len() != 2 || value >= 60) {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(value)?;
+    }
+    Some(seconds)
+}
+
+/// `80` -> "1:20", `3725` -> "1:02:05".
+pub fn format_timestamp(seconds: u32) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 { format!("{}:{:02}:{:02}", h, m, s) } else { format!("{}:{:02}", m, s) }
+}
+
+impl ClipRange {
+    /// Parses `1:20-2:05` (also with an en dash); the end has to be after the start.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (start, end) = s.split_once('-').or_else(|| s.split_once('–'))?;
+        let range = ClipRange { start: parse_timestamp(start.trim())?, end: parse_timestamp(end.trim())? };
+        (range.end > range.start).then_some(range)
+    }
+
+    pub fn duration(&self) -> u32 {
+        self.end - self.start
+    }
+
+    /// "1:20-2:05"
+    pub fn label(&self) -> String {
+        format!("{}-{}", format_timestamp(self.start), format_timestamp(self.end))
+    }
+
+    /// Appended to the media_cache quality key, so every clip of a post is cached separately.
+    pub fn cache_suffix(&self) -> String {
+        format!("@{}-{}", self.start, self.end)
+    }
+
+    /// Value of yt-dlp's `--download-sections`.
+    pub fn section_arg(&self) -> String {
+        format!("*{}-{}", self.start, self.end)
+    }
+
+    /// Checks the range against the video's duration (when it is known) and the user's limit.
+    pub fn validate(&self, duration: Option<f64>, max_len: u32, premium: bool) -> Result<(), ClipError> {
+        if let Some(duration) = duration
+            && self.start as f64 >= duration
+        {
+            return Err(ClipError::PastEnd { duration: duration.round() as u32 });
+        }
+        if self.duration() > max_len {
+            return Err(ClipError::TooLong { max: max_len, premium });
+        }
+        Ok(())
+    }
+
+    /// `url` with the range attached, for storing the request as a single string.
+    pub fn tag_url(&self, url: &str) -> String {
+        format!("{}{}{}-{}", url, CLIP_FRAGMENT, self.start, self.end)
+    }
+
+    /// Splits a [`ClipRange::tag_url`] string back into the link and its range.
+    pub fn untag_url(url: &str) -> (String, Option<ClipRange>) {
+        match url.rsplit_once(CLIP_FRAGMENT) {
+            Some((base, range)) => match ClipRange::parse(range) {
+                Some(range) => (base.to_string(), Some(range)),
+                None => (url.to_string(), None),
+            },
+            None => (url.to_string(), None),
+        }
+    }
+}
+
+/// The range written right after a link, as in `https://youtu.be/abc 1:20-2:05`.
+pub fn find_clip_range(text: &str) -> Option<ClipRange> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    tokens
+        .windows(2)
+        .filter(|w| w[0].starts_with("http://") || w[0].starts_with("https://"))
+        .find_map(|w| ClipRange::parse(w[1]))
+}
+
+/// Longest clip the user may request, from FREE_MAX_CLIP_SECS / PREMIUM_MAX_CLIP_SECS.
+pub fn max_clip_seconds(premium: bool) -> u32 {
+    let (var, default) = if premium {
+        ("PREMIUM_MAX_CLIP_SECS", DEFAULT_PREMIUM_MAX_CLIP_SECS)
+    } else {
+        ("FREE_MAX_CLIP_SECS", DEFAULT_FREE_MAX_CLIP_SECS)
+    };
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(default)
+}
+
+/// ffmpeg arguments that cut `range` out of `input`. Video is re-encoded so the clip starts
+/// exactly at `start` instead of the previous keyframe; audio-only files are copied.
+pub fn build_trim_args(input: &Path, output: &Path, range: ClipRange, has_video: bool) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-ss".into(),
+        range.start.to_string(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-t".into(),
+        range.duration().to_string(),
+    ];
+    if has_video {
+        args.extend(
+            [
+                "-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p", "-c:a", "aac", "-b:a",
+                "128k", "-movflags", "+faststart",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+    } else {
+        args.extend(["-vn".into(), "-c:a".into(), "copy".into()]);
+    }
+    args.push(output.to_string_lossy().into_owned());
+    args
+}
+
+impl YoutubeFetcher {
+    /// Whether the file at `path` is longer than `range` and still has to be trimmed, i.e. the
+    /// download came from a source that ignored `--download-sections`.
+    pub async fn needs_trim(&self, path: &Path, range: ClipRange) -> bool {
+        let ffprobe = self.ffprobe_path();
+        match crate::mtproto_uploader::metadata::get_media_duration(ffprobe.to_string_lossy().as_ref(), path).await {
+            Ok(duration) => duration > range.duration() as f64 + SECTION_TOLERANCE_SECS,
+            Err(e) => {
+                log::warn!("Could not probe the duration of {:?} ({}); trimming anyway", path, e);
+                true
+            }
+        }
+    }
+
+    /// Cuts `range` out of the file at `input`. The caller cleans up the returned file.
+    pub async fn trim_clip(&self, input: &Path, range: ClipRange) -> Result<PathBuf> {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = input.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+        let output = input.with_file_name(format!("{}_clip.{}", stem, ext));
+        let mut guard = TempFileGuard::new(output.clone());
+
+        log::info!("Trimming {:?} to {}", input, range.label());
+        let has_video = self.file_has_video(input).await;
+        let args = build_trim_args(input, &output, range, has_video);
+        let out = Command::new(self.ffmpeg_path()).args(&args).kill_on_drop(true).output().await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg trim failed: {}", stderr.trim()));
+        }
+        guard.forget();
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(ClipRange::parse("1:20-2:05"), Some(ClipRange { start: 80, end: 125 }));
+        assert_eq!(ClipRange::parse("80-125"), Some(ClipRange { start: 80, end: 125 }));
+        assert_eq!(ClipRange::parse("1:00:00–1:00:30"), Some(ClipRange { start: 3600, end: 3630 }));
+        assert_eq!(ClipRange::parse("2:05-1:20"), None);
+        assert_eq!(ClipRange::parse("1:75-2:00"), None);
+        assert_eq!(ClipRange::parse("1:5-2:00"), None);
+        assert_eq!(ClipRange::parse("a-b"), None);
+        assert_eq!(format_timestamp(3725), "1:02:05");
+    }
+
+    #[test]
+    fn test_find_clip_range() {
+        let range = find_clip_range("look https://youtu.be/abc 1:20-2:05 nice");
+        assert_eq!(range, Some(ClipRange { start: 80, end: 125 }));
+        // Ranges that don't follow a link are ordinary text.
+        assert_eq!(find_clip_range("1:20-2:05 https://youtu.be/abc"), None);
+        assert_eq!(find_clip_range("https://youtu.be/abc"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let range = ClipRange { start: 80, end: 125 };
+        assert!(range.validate(Some(300.0), 60, false).is_ok());
+        assert!(range.validate(None, 60, false).is_ok());
+        assert_eq!(range.validate(Some(70.0), 60, false), Err(ClipError::PastEnd { duration: 70 }));
+        assert_eq!(range.validate(Some(300.0), 30, true), Err(ClipError::TooLong { max: 30, premium: true }));
+    }
+
+    #[test]
+    fn test_tagged_url_roundtrip() {
+        let range = ClipRange { start: 5, end: 20 };
+        let tagged = range.tag_url("https://youtu.be/abc");
+        assert_eq!(ClipRange::untag_url(&tagged), ("https://youtu.be/abc".to_string(), Some(range)));
+        assert_eq!(ClipRange::untag_url("https://youtu.be/abc"), ("https://youtu.be/abc".to_string(), None));
+    }
+
+    #[test]
+    fn test_trim_args() {
+        let range = ClipRange { start: 80, end: 125 };
+        let video = build_trim_args(Path::new("in.mp4"), Path::new("out.mp4"), range, true);
+        assert!(video.windows(2).any(|w| w == ["-ss", "80"]));
+        assert!(video.windows(2).any(|w| w == ["-t", "45"]));
+        assert!(video.windows(2).any(|w| w == ["-c:v", "libx264"]));
+        let audio = build_trim_args(Path::new("in.m4a"), Path::new("out.m4a"), range, false);
+        assert!(audio.windows(2).any(|w| w == ["-c:a", "copy"]));
+    }
+}
@@ -1,22 +1,31 @@
 use anyhow::Result;
 use regex::Regex;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 
+use crate::platforms;
 use crate::utils::progress_bar::ProgressBar;
 use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::clip::ClipRange;
+use crate::yt_dlp_interface::providers::{DownloadJob, ProviderRegistry, Tikwm};
+use crate::yt_dlp_interface::formats::{parse_format_options, FormatOption, FORMAT_QUALITY_PREFIX};
+use crate::yt_dlp_interface::video_info::VideoInfo;
 
 #[derive(Clone)]
 pub struct YoutubeFetcher {
     pub yt_dlp_path: PathBuf,
     pub output_dir: PathBuf,
     pub ffmpeg_dir: PathBuf,
+    /// Download sources tried for every link, with their health.
+    pub providers: Arc<ProviderRegistry>,
+    pub tikwm: Tikwm,
 }
 
 impl YoutubeFetcher {
     /// Returns the platform-specific ffprobe binary path inside the ffmpeg directory.
-    fn ffprobe_path(&self) -> PathBuf {
+    pub(crate) fn ffprobe_path(&self) -> PathBuf {
         self.ffmpeg_dir.join(if cfg!(target_os = "windows") {
             "ffprobe.exe"
         } else {
@@ -25,13 +34,65 @@ impl YoutubeFetcher {
     }
 
     /// Returns the platform-specific ffmpeg binary path inside the ffmpeg directory.
-    fn ffmpeg_path(&self) -> PathBuf {
+    pub(crate) fn ffmpeg_path(&self) -> PathBuf {
         self.ffmpeg_dir.join(if cfg!(target_os = "windows") {
             "ffmpeg.exe"
         } else {
             "ffmpeg"
         })
     }
+
+    /// Where yt-dlp writes the info JSON of a download (`--write-info-json`).
+    pub(crate) fn info_json_path(&self, filename_stem: &str) -> PathBuf {
+        self.output_dir.join(format!("{}.info.json", filename_stem))
+    }
+
+    /// Reads and removes the metadata yt-dlp saved for `filename_stem`. `None` when the file
+    /// came from an alternate source or the JSON couldn't be parsed.
+    pub async fn take_video_info(&self, filename_stem: &str) -> Option<VideoInfo> {
+        let path = self.info_json_path(filename_stem);
+        let _guard = TempFileGuard::new(path.clone());
+        let json = tokio::fs::read_to_string(&path).await.ok()?;
+        VideoInfo::from_json(&json)
+            .map_err(|e| log::warn!("Could not parse {:?}: {}", path, e))
+            .ok()
+    }
+
+    /// Lists the formats of a post (`yt-dlp -J`) as picker entries, without downloading it.
+    pub async fn list_formats(&self, url: &str, fingerprint: Option<String>) -> Result<Vec<FormatOption>> {
+        let info = self.probe_info(url, fingerprint).await?;
+        Ok(parse_format_options(&info))
+    }
+
+    /// Duration of a post in seconds, without downloading it. `None` when the extractor
+    /// doesn't report one.
+    pub async fn probe_duration(&self, url: &str, fingerprint: Option<String>) -> Result<Option<f64>> {
+        let info = self.probe_info(url, fingerprint).await?;
+        Ok(info.get("duration").and_then(|v| v.as_f64()))
+    }
+
+    /// The post's metadata as printed by `yt-dlp -J`.
+    pub(crate) async fn probe_info(&self, url: &str, fingerprint: Option<String>) -> Result<serde_json::Value> {
+        let mut cmd = Command::new(&self.yt_dlp_path);
+        cmd.kill_on_drop(true);
+        if let Some(platform) = platforms::registry().for_url(url) {
+            cmd.args(platform.yt_dlp_args());
+        }
+        cmd.arg("-J").arg("--no-playlist").arg("--no-warnings");
+        if let Some(fp) = fingerprint {
+            cmd.arg(format!("--impersonate={}", fp));
+        }
+        cmd.arg(url);
+
+        let output = tokio::time::timeout(std::time::Duration::from_secs(60), cmd.output())
+            .await
+            .map_err(|_| anyhow::anyhow!("yt-dlp -J timed out for {}", url))??;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("yt-dlp -J failed: {}", stderr.trim()));
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
 }
 
 impl YoutubeFetcher {
@@ -40,9 +101,28 @@ impl YoutubeFetcher {
             yt_dlp_path,
             output_dir,
             ffmpeg_dir,
+            providers: Arc::new(ProviderRegistry::builtin()),
+            tikwm: Tikwm::from_env(),
         })
     }
 
+    /// Replaces the download sources, e.g. with stubs in tests.
+    pub fn with_providers(mut self, providers: ProviderRegistry) -> Self {
+        self.providers = Arc::new(providers);
+        self
+    }
+
+    pub fn with_tikwm(mut self, tikwm: Tikwm) -> Self {
+        self.tikwm = tikwm;
+        self
+    }
+
+    /// Tries the providers of the link's chain in order until one returns a usable file.
+    ///
+    /// In video modes a file without a video stream (TikTok served only the music, e.g. the
+    /// post's video is restricted on the web API) doesn't end the chain: the next provider
+    /// gets a chance, and the audio-only file is only returned when none does better, so
+    /// that [`YoutubeFetcher::download`] can still recognise photo posts.
     pub async fn download_video_from_url(
         &self,
         url: String,
@@ -51,91 +131,102 @@ impl YoutubeFetcher {
         fingerprint: Option<String>,
         progress_bar: &mut ProgressBar,
     ) -> Result<std::path::PathBuf> {
+        self.download_with_providers(url, filename_stem, quality, fingerprint, None, progress_bar).await
+    }
+
+    /// Downloads the `range` part of a post. Platforms that support `--download-sections`
+    /// get only that part from yt-dlp; anything that still comes back longer (other
+    /// platforms, alternate sources) is trimmed locally with ffmpeg.
+    pub async fn download_clip(
+        &self,
+        url: String,
+        filename_stem: &str,
+        quality: &str,
+        fingerprint: Option<String>,
+        range: ClipRange,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<PathBuf> {
+        let sections = platforms::registry().for_url(&url).is_some_and(|p| p.supports_download_sections());
+        let section = sections.then_some(range);
+        let path = self
+            .download_with_providers(url, filename_stem, quality, fingerprint, section, progress_bar)
+            .await?;
+        if !self.needs_trim(&path, range).await {
+            return Ok(path);
+        }
+
+        // The whole download is only needed until the clip is cut out of it.
+        let _guard = TempFileGuard::new(path.clone());
+        progress_bar.update(80, Some("✂️ Cutting the clip...")).await?;
+        self.trim_clip(&path, range).await
+    }
+
+    async fn download_with_providers(
+        &self,
+        url: String,
+        filename_stem: &str,
+        quality: &str,
+        fingerprint: Option<String>,
+        section: Option<ClipRange>,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<PathBuf> {
         log::info!("Starting download for URL: {} (quality: {})", url, quality);
 
-        // Audio mode: download directly using the full 0-80% range.
-        // If yt-dlp fails, try tikwm music fallback.
-        if quality == "audio" {
-            let audio_result = self
-                .run_yt_dlp(&url, filename_stem, quality, fingerprint.clone(), progress_bar, 0, 80)
-                .await;
-            return match audio_result {
-                Ok(path) => Ok(path),
-                Err(e) => {
-                    log::warn!("yt-dlp failed for audio URL: {} ({}); trying tikwm music fallback", url, e);
-                    progress_bar
-                        .update(40, Some("🔧 yt-dlp failed — trying alternate audio source..."))
-                        .await?;
-                    self.tikwm_music_fallback(&url, filename_stem, progress_bar).await
-                }
-            };
+        let wants_video = quality != "audio";
+        let chain = self.providers.chain_for(&url);
+        if chain.is_empty() {
+            return Err(anyhow::anyhow!("No download source is available right now"));
         }
 
-        // Video modes (h264 / h265 / best): download using 0-60% of the progress bar.
-        // This reserves 60-80% for fallback steps if the result is incomplete:
-        //   - missing video (TikTok hid the video, only audio served) -> tikwm fallback
-        //   - missing audio (yt-dlp HEVC video-only bug, #16950)        -> H.264 audio mux
-        let primary_path = match self
-            .run_yt_dlp(&url, filename_stem, quality, fingerprint.clone(), progress_bar, 0, 60)
-            .await
-        {
-            Ok(path) => path,
-            Err(e) => {
-                log::warn!(
-                    "yt-dlp failed for URL: {} ({}); trying tikwm fallback",
-                    url, e
-                );
-                progress_bar
-                    .update(60, Some("🔧 yt-dlp failed — trying alternate source..."))
-                    .await?;
-                let result = self
-                    .tikwm_video_fallback(&url, filename_stem, None, progress_bar)
-                    .await;
-                return match result {
-                    Ok(path) => {
-                        progress_bar.update(80, Some("⬇️ Download completed")).await?;
-                        Ok(path)
-                    }
-                    Err(_fallback_err) => {
-                        log::error!("Both yt-dlp and tikwm fallback failed for URL: {}", url);
-                        Err(anyhow::anyhow!("Failed to download: {}", e))
-                    }
-                };
+        let mut last_error = None;
+        let mut audio_only: Option<PathBuf> = None;
+        for (idx, provider) in chain.iter().enumerate() {
+            // The first provider gets 0-60% in video modes, keeping 60-80% for later
+            // attempts and repairs; audio downloads need no repairs and use 0-80%.
+            let progress = match (idx, wants_video) {
+                (0, true) => (0, 60),
+                (0, false) => (0, 80),
+                _ => (60, 80),
+            };
+            if idx > 0 {
+                log::warn!("Trying {} for {}", provider.name(), url);
+                progress_bar.update(progress.0, Some("🔧 Trying alternate source...")).await?;
             }
-        };
 
-        let has_video = self.file_has_video(&primary_path).await;
-        let has_audio = matches!(file_has_audio(&self.ffprobe_path(), &primary_path).await, Ok(true));
+            let job = DownloadJob { url: &url, filename_stem, quality, fingerprint: fingerprint.clone(), section, progress };
+            let path = match provider.download(self, &job, progress_bar).await {
+                Ok(path) => {
+                    self.providers.record_success(provider.as_ref());
+                    path
+                }
+                Err(e) => {
+                    log::warn!("{} failed for {}: {}", provider.name(), url, e);
+                    self.providers.record_failure(provider.as_ref(), &e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
 
-        // Case 1: no video stream at all — TikTok served an audio-only file (e.g. the post's
-        // video is restricted on the web API, playAddr is empty). The user asked for a video,
-        // so fall back to the tikwm API which exposes the real CDN video URL in these cases.
-        if !has_video {
-            log::warn!(
-                "Downloaded file has no video stream (audio-only); attempting tikwm fallback: {:?}",
-                primary_path
-            );
-            return self
-                .tikwm_video_fallback(&url, filename_stem, Some(&primary_path), progress_bar)
-                .await;
+            if wants_video && !self.file_has_video(&path).await {
+                log::warn!("{} returned a file without a video stream: {:?}", provider.name(), path);
+                if let Some(previous) = audio_only.replace(path) {
+                    let _ = tokio::fs::remove_file(previous).await;
+                }
+                continue;
+            }
+            if let Some(previous) = audio_only.take() {
+                let _ = tokio::fs::remove_file(previous).await;
+            }
+            progress_bar.update(80, Some("⬇️ Download completed")).await?;
+            return Ok(path);
         }
 
-        // Case 2: video present but audio missing — HEVC video-only stream (yt-dlp #16950).
-        // Recover the audio from an H.264 variant and mux it in.
-        if !has_audio {
-            log::warn!(
-                "Downloaded file is missing audio, attempting H.264 audio fallback: {:?}",
-                primary_path
-            );
-            return self
-                .audio_fallback(&url, filename_stem, fingerprint.clone(), &primary_path, progress_bar)
-                .await;
+        if let Some(path) = audio_only {
+            progress_bar.update(80, Some("⬇️ Download completed")).await?;
+            return Ok(path);
         }
-
-        // Both streams present — deliver as-is.
-        log::info!("Downloaded file has both video and audio, no fallback needed: {:?}", primary_path);
-        progress_bar.update(80, Some("⬇️ Download completed")).await?;
-        Ok(primary_path)
+        let error = last_error.map(|e| e.to_string()).unwrap_or_default();
+        Err(anyhow::anyhow!("Failed to download: {}", error))
     }
 
     /// Checks whether a downloaded file actually contains a video stream.
@@ -160,239 +251,15 @@ impl YoutubeFetcher {
         }
     }
 
-    /// Fallback used when yt-dlp could only obtain an audio-only file for a post whose
-    /// video is hidden from the TikTok web API, or when yt-dlp failed entirely.
-    /// Queries the tikwm public API, which exposes the real CDN video URL, downloads
-    /// that file (with audio) and returns it.
-    async fn tikwm_video_fallback(
-        &self,
-        url: &str,
-        filename_stem: &str,
-        primary_path: Option<&Path>,
-        progress_bar: &mut ProgressBar,
-    ) -> Result<PathBuf> {
-        progress_bar
-            .update(60, Some("🔧 Video unavailable via TikTok API — trying alternate source..."))
-            .await?;
-
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
-
-        let api_url = format!("https://www.tikwm.com/api/?url={}", url);
-        log::info!("Querying tikwm API: {}", api_url);
-
-        let resp = client
-            .get(&api_url)
-            .header("User-Agent", "Mozilla/5.0")
-            .header("Referer", "https://www.tikwm.com/")
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            log::error!("tikwm API returned HTTP {}, delivering original file", resp.status());
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            return Ok(primary_path.map(|p| p.to_path_buf()).unwrap_or_else(|| self.output_dir.join(format!("{}_fallback.mp4", filename_stem))));
-        }
-
-        // Parse { "code": 0, "data": { "play": "<url>", "wmplay": "<url>", ... } }
-        let body: serde_json::Value = resp.json().await?;
-        if body.get("code").and_then(|v| v.as_i64()) != Some(0) {
-            log::error!(
-                "tikwm API returned non-zero code: {:?}; delivering original file",
-                body.get("code")
-            );
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            return Ok(primary_path.map(|p| p.to_path_buf()).unwrap_or_else(|| self.output_dir.join(format!("{}_fallback.mp4", filename_stem))));
-        }
-
-        // Prefer the no-watermark "play" URL; fall back to "wmplay" if absent.
-        let video_url = body
-            .get("data")
-            .and_then(|d| d.get("play"))
-            .and_then(|v| v.as_str())
-            .filter(|s| !s.is_empty())
-            .or_else(|| {
-                body.get("data")
-                    .and_then(|d| d.get("wmplay"))
-                    .and_then(|v| v.as_str())
-                    .filter(|s| !s.is_empty())
-            });
-
-        let video_url = match video_url {
-            Some(u) => {
-                log::info!("tikwm provided video URL: {}", u);
-                u.to_string()
-            }
-            None => {
-                log::error!("tikwm API returned no video URL in data.play/wmplay; delivering original file");
-                progress_bar.update(80, Some("⬇️ Download completed")).await?;
-                return Ok(primary_path.map(|p| p.to_path_buf()).unwrap_or_else(|| self.output_dir.join(format!("{}_fallback.mp4", filename_stem))));
-            }
-        };
-
-        // Download the alternate-source video into a fresh .mp4 file.
-        progress_bar.update(70, Some("⬇️ Downloading from alternate source...")).await?;
-        let fallback_path = self.output_dir.join(format!("{}_alt.mp4", filename_stem));
-        let mut fallback_guard = TempFileGuard::new(fallback_path.clone());
-
-        let download_resp = client
-            .get(&video_url)
-            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36")
-            .header("Referer", "https://www.tikwm.com/")
-            .header("Accept", "*/*")
-            .send()
-            .await?;
-
-        if !download_resp.status().is_success() {
-            log::error!(
-                "tikwm CDN returned HTTP {}, delivering original file",
-                download_resp.status()
-            );
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            return Ok(primary_path.map(|p| p.to_path_buf()).unwrap_or_else(|| self.output_dir.join(format!("{}_fallback.mp4", filename_stem))));
-        }
-
-        let bytes = download_resp.bytes().await?;
-        if bytes.is_empty() {
-            log::error!("tikwm CDN returned empty body, delivering original file");
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            return Ok(primary_path.map(|p| p.to_path_buf()).unwrap_or_else(|| self.output_dir.join(format!("{}_fallback.mp4", filename_stem))));
-        }
-        tokio::fs::write(&fallback_path, &bytes).await?;
-        log::info!("tikwm fallback downloaded {} bytes to {:?}", bytes.len(), fallback_path);
-
-        // Verify the alternate file actually has a video stream before using it.
-        if !self.file_has_video(&fallback_path).await {
-            log::error!("tikwm fallback file has no video stream, delivering original file");
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            return Ok(primary_path.map(|p| p.to_path_buf()).unwrap_or_else(|| self.output_dir.join(format!("{}_fallback.mp4", filename_stem))));
-        }
-
-        // If we have a primary_path, try to replace it (rename/copy) so callers see the same file.
-        // Otherwise return the fallback path directly.
-        if let Some(primary) = primary_path {
-            let final_path = primary.with_extension("mp4");
-            let cleanup_old = if final_path != *primary && primary.exists() {
-                Some(primary.to_path_buf())
-            } else {
-                None
-            };
-
-            if let Err(e) = tokio::fs::rename(&fallback_path, &final_path).await {
-                tokio::fs::copy(&fallback_path, &final_path).await?;
-                fallback_guard.forget();
-                log::debug!("Copied tikwm fallback to primary (rename failed: {})", e);
-            } else {
-                fallback_guard.forget();
-            }
-
-            // Remove the now-replaced audio-only file if it had a different extension.
-            if let Some(old) = cleanup_old {
-                if old.exists() {
-                    let _ = tokio::fs::remove_file(&old).await;
-                }
-            }
-
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            Ok(final_path)
-        } else {
-            // No primary_path: return the fallback file as-is.
-            fallback_guard.forget();
-            progress_bar.update(80, Some("⬇️ Download completed")).await?;
-            Ok(fallback_path)
-        }
-    }
-
-    /// Fallback used when yt-dlp fails to download audio.
-    /// Queries the tikwm public API for the `music` field and downloads it.
-    async fn tikwm_music_fallback(
-        &self,
-        url: &str,
-        filename_stem: &str,
-        progress_bar: &mut ProgressBar,
-    ) -> Result<PathBuf> {
-        progress_bar
-            .update(50, Some("🔧 Fetching audio from alternate source..."))
-            .await?;
-
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
-
-        let api_url = format!("https://www.tikwm.com/api/?url={}", url);
-        log::info!("Querying tikwm API for audio: {}", api_url);
-
-        let resp = client
-            .get(&api_url)
-            .header("User-Agent", "Mozilla/5.0")
-            .header("Referer", "https://www.tikwm.com/")
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            return Err(anyhow::anyhow!("tikwm API returned HTTP {}", resp.status()));
-        }
-
-        let body: serde_json::Value = resp.json().await?;
-        if body.get("code").and_then(|v| v.as_i64()) != Some(0) {
-            return Err(anyhow::anyhow!("tikwm API returned non-zero code: {:?}", body.get("code")));
-        }
-
-        // Extract the music URL from the response.
-        let music_url = body
-            .get("data")
-            .and_then(|d| d.get("music"))
-            .and_then(|v| v.as_str())
-            .filter(|s| !s.is_empty());
-
-        let music_url = match music_url {
-            Some(u) => {
-                log::info!("tikwm provided music URL: {}", u);
-                u.to_string()
-            }
-            None => {
-                return Err(anyhow::anyhow!("tikwm API returned no music URL in response"));
-            }
-        };
-
-        // Download the audio file.
-        progress_bar.update(60, Some("⬇️ Downloading audio...")).await?;
-        let audio_path = self.output_dir.join(format!("{}.m4a", filename_stem));
-        let mut audio_guard = TempFileGuard::new(audio_path.clone());
-
-        let download_resp = client
-            .get(&music_url)
-            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36")
-            .header("Referer", "https://www.tikwm.com/")
-            .header("Accept", "*/*")
-            .send()
-            .await?;
-
-        if !download_resp.status().is_success() {
-            return Err(anyhow::anyhow!("tikwm CDN returned HTTP {} for audio", download_resp.status()));
-        }
-
-        let bytes = download_resp.bytes().await?;
-        if bytes.is_empty() {
-            return Err(anyhow::anyhow!("tikwm CDN returned empty body for audio"));
-        }
-        tokio::fs::write(&audio_path, &bytes).await?;
-        log::info!("tikwm music fallback downloaded {} bytes to {:?}", bytes.len(), audio_path);
-
-        audio_guard.forget();
-        progress_bar.update(80, Some("⬇️ Download completed")).await?;
-        Ok(audio_path)
-    }
-
     /// Downloads a fresh H.264 (avc) copy of the video, extracts its audio track, and
     /// muxes that audio into the existing `primary_path` (typically an HEVC stream that
     /// TikTok served without audio). On success returns the muxed file path.
-    async fn audio_fallback(
+    pub(crate) async fn audio_fallback(
         &self,
         url: &str,
         filename_stem: &str,
         fingerprint: Option<String>,
+        section: Option<ClipRange>,
         primary_path: &Path,
         progress_bar: &mut ProgressBar,
     ) -> Result<PathBuf> {
@@ -402,8 +269,9 @@ impl YoutubeFetcher {
             .await?;
 
         let audio_src_stem = format!("{}_audio_src", filename_stem);
+        let _info_guard = TempFileGuard::new(self.info_json_path(&audio_src_stem));
         let h264_path = match self
-            .run_yt_dlp(url, &audio_src_stem, "h264", fingerprint, progress_bar, 60, 78)
+            .run_yt_dlp(url, &audio_src_stem, "h264", fingerprint, section, progress_bar, 60, 78)
             .await
         {
             Ok(path) => path,
@@ -491,13 +359,16 @@ impl YoutubeFetcher {
     }
 
     /// Runs yt-dlp for the given quality and reports download progress into the slice
-    /// of the progress bar defined by [start_pct, end_pct]. Returns the produced file path.
-    async fn run_yt_dlp(
+    /// of the progress bar defined by [start_pct, end_pct]. With a `section`, only that part
+    /// of the post is downloaded. Returns the produced file path.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn run_yt_dlp(
         &self,
         url: &str,
         filename_stem: &str,
         quality: &str,
         fingerprint: Option<String>,
+        section: Option<ClipRange>,
         progress_bar: &mut ProgressBar,
         start_pct: u8,
         end_pct: u8,
@@ -513,9 +384,11 @@ impl YoutubeFetcher {
 
         let mut cmd = Command::new(&self.yt_dlp_path);
         cmd.kill_on_drop(true); // Guarantee process termination on drop
-        cmd.arg("--extractor-args")
-            .arg("tiktok:skip=feed")
-            .arg("--output")
+        // Platform-specific arguments (e.g. TikTok's `--extractor-args tiktok:skip=feed`).
+        if let Some(platform) = platforms::registry().for_url(url) {
+            cmd.args(platform.yt_dlp_args());
+        }
+        cmd.arg("--output")
             .arg(&output_template)
             .arg("--no-part")
             .arg("--no-mtime")
@@ -523,6 +396,8 @@ impl YoutubeFetcher {
             .arg(&self.ffmpeg_dir)
             .arg("--progress")
             .arg("--newline")
+            // Metadata for captions; read back with `take_video_info`.
+            .arg("--write-info-json")
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped());
 
@@ -541,7 +416,10 @@ impl YoutubeFetcher {
         //   * h264: force avc via -S (correctly returns H.264 with audio)
         //   * h265 / best: allow HEVC; a post-download audio check + mux fallback
         //     (see audio_fallback) recovers missing audio.
-        if quality == "h264" {
+        //   * format:<selector>: the exact format the user picked (see formats.rs)
+        if let Some(selector) = quality.strip_prefix(FORMAT_QUALITY_PREFIX) {
+            cmd.arg("-f").arg(selector).arg("--merge-output-format").arg("mp4");
+        } else if quality == "h264" {
             cmd.arg("-S").arg("vcodec:avc,mres");
         } else if quality == "audio" {
             cmd.arg("-x").arg("--audio-format").arg("best");
@@ -550,6 +428,11 @@ impl YoutubeFetcher {
             cmd.arg("-f").arg("bestvideo+bestaudio/best");
         }
 
+        if let Some(section) = section {
+            // Cut at the exact timestamps instead of the surrounding keyframes.
+            cmd.arg("--download-sections").arg(section.section_arg()).arg("--force-keyframes-at-cuts");
+        }
+
         cmd.arg(url);
 
         log::info!("🔍 Full yt-dlp command: {:?}", cmd);
@@ -634,7 +517,7 @@ impl YoutubeFetcher {
                     if let Ok(file_type) = file.file_type().await {
                         if file_type.is_file() {
                             if let Some(filename) = file.file_name().to_str() {
-                                if filename.starts_with(&*stem.to_string_lossy()) {
+                                if filename.starts_with(&*stem.to_string_lossy()) && !filename.ends_with(".info.json") {
                                     let path = parent.join(filename);
                                     log::info!(
                                         "Found downloaded file for {}: {:?}",
@@ -693,6 +576,7 @@ impl YoutubeFetcher {
 }
 
 /// Scales a 0-100 yt-dlp download percentage into the [start_pct, start_pct+span] window.
+/// Whether the platform the URL belongs to lists `fallback` among its alternate sources.
 fn scale_to_range(percentage: f64, start_pct: u8, span: f64) -> u8 {
     let scaled = start_pct as f64 + (percentage / 100.0) * span;
     scaled.round().clamp(0.0, 100.0) as u8
@@ -734,7 +618,7 @@ async fn probe_stream_present(
 }
 
 /// Uses ffprobe to determine whether the given file contains at least one audio stream.
-async fn file_has_audio(ffprobe_path: &Path, file_path: &Path) -> Result<bool> {
+pub(crate) async fn file_has_audio(ffprobe_path: &Path, file_path: &Path) -> Result<bool> {
     probe_stream_present(ffprobe_path, "a", file_path).await
 }
 
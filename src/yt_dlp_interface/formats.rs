@@ -0,0 +1,185 @@
+use serde_json::Value;
+
+/// Quality strings starting with this carry an explicit yt-dlp `-f` selector picked by the user
+/// (see [`format_quality`]) instead of one of the fixed h264/h265/best/audio modes.
+pub const FORMAT_QUALITY_PREFIX: &str = "format:";
+
+/// At most this many video choices are offered, highest resolutions first.
+const MAX_VIDEO_OPTIONS: usize = 8;
+
+pub fn format_quality(selector: &str) -> String {
+    format!("{}{}", FORMAT_QUALITY_PREFIX, selector)
+}
+
+/// One entry of the format picker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOption {
+    /// yt-dlp `-f` selector; video-only formats are merged with the best audio.
+    pub selector: String,
+    /// `None` for the audio-only choice.
+    pub height: Option<u32>,
+    /// "H.264", "H.265", "VP9", "AV1", ...; empty for audio.
+    pub codec: String,
+    /// Estimated size in bytes, audio included.
+    pub size: Option<u64>,
+}
+
+impl FormatOption {
+    pub fn is_audio(&self) -> bool {
+        self.height.is_none()
+    }
+
+    /// "1080p H.264 · ~24.1 MB" / "🎵 Audio · ~3.2 MB"
+    pub fn label(&self) -> String {
+        let name = match self.height {
+            Some(height) => format!("{}p {}", height, self.codec),
+            None => "🎵 Audio".to_string(),
+        };
+        match self.size {
+            Some(size) => format!("{} · ~{:.1} MB", name, size as f64 / 1_048_576.0),
+            None => name,
+        }
+    }
+}
+
+fn codec_name(vcodec: &str) -> String {
+    let lower = vcodec.to_lowercase();
+    if lower.starts_with("avc") || lower.starts_with("h264") {
+        "H.264".to_string()
+    } else if ["hev", "hvc", "h265", "bytevc1"].iter().any(|p| lower.starts_with(p)) {
+        "H.265".to_string()
+    } else if lower.starts_with("vp9") || lower.starts_with("vp09") {
+        "VP9".to_string()
+    } else if lower.starts_with("av01") {
+        "AV1".to_string()
+    } else {
+        lower.split('.').next().unwrap_or_default().to_uppercase()
+    }
+}
+
+/// Codecs that play everywhere are listed first within a resolution.
+fn codec_rank(codec: &str) -> u8 {
+    match codec {
+        "H.264" => 0,
+        "H.265" => 1,
+        "VP9" => 2,
+        "AV1" => 3,
+        _ => 4,
+    }
+}
+
+fn has_codec(format: &Value, key: &str) -> bool {
+    format.get(key).and_then(|v| v.as_str()).is_some_and(|c| c != "none")
+}
+
+/// Size from yt-dlp's exact or approximate size, else from the bitrate (kbit/s) and duration.
+fn estimated_size(format: &Value, duration: Option<f64>) -> Option<u64> {
+    format.get("filesize").and_then(|v| v.as_u64())
+        .or_else(|| format.get("filesize_approx").and_then(|v| v.as_u64()))
+        .or_else(|| {
+            let tbr = format.get("tbr").and_then(|v| v.as_f64())?;
+            Some((tbr * 1000.0 / 8.0 * duration?) as u64)
+        })
+}
+
+/// Builds the picker entries from `yt-dlp -J` output: the best format per resolution and
+/// codec, plus an audio-only entry when the post has separate audio.
+pub fn parse_format_options(info: &Value) -> Vec<FormatOption> {
+    let duration = info.get("duration").and_then(|v| v.as_f64());
+    let formats: &[Value] = info.get("formats").and_then(|v| v.as_array()).map(|v| v.as_slice()).unwrap_or_default();
+
+    let best_audio = formats
+        .iter()
+        .filter(|f| has_codec(f, "acodec") && !has_codec(f, "vcodec"))
+        .max_by_key(|f| (f.get("abr").or(f.get("tbr")).and_then(|v| v.as_f64()).unwrap_or(0.0) * 1000.0) as u64);
+    let audio_id = best_audio.and_then(|f| f.get("format_id")).and_then(|v| v.as_str());
+    let audio_size = best_audio.and_then(|f| estimated_size(f, duration));
+
+    let mut videos: Vec<(FormatOption, f64)> = Vec::new();
+    for format in formats.iter().filter(|f| has_codec(f, "vcodec")) {
+        let (Some(id), Some(height)) = (
+            format.get("format_id").and_then(|v| v.as_str()),
+            format.get("height").and_then(|v| v.as_u64()),
+        ) else {
+            continue;
+        };
+        let codec = codec_name(format.get("vcodec").and_then(|v| v.as_str()).unwrap_or_default());
+        let video_size = estimated_size(format, duration);
+        let (selector, size) = match audio_id {
+            Some(audio_id) if !has_codec(format, "acodec") => {
+                (format!("{}+{}", id, audio_id), video_size.map(|v| v + audio_size.unwrap_or(0)))
+            }
+            _ => (id.to_string(), video_size),
+        };
+        let bitrate = format.get("tbr").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let option = FormatOption { selector, height: Some(height as u32), codec, size };
+
+        match videos.iter_mut().find(|(o, _)| o.height == option.height && o.codec == option.codec) {
+            Some(existing) if existing.1 < bitrate => *existing = (option, bitrate),
+            Some(_) => {}
+            None => videos.push((option, bitrate)),
+        }
+    }
+
+    let mut options: Vec<FormatOption> = videos.into_iter().map(|(o, _)| o).collect();
+    options.sort_by_key(|o| (std::cmp::Reverse(o.height), codec_rank(&o.codec)));
+    options.truncate(MAX_VIDEO_OPTIONS);
+
+    if let Some(audio_id) = audio_id {
+        options.push(FormatOption { selector: audio_id.to_string(), height: None, codec: String::new(), size: audio_size });
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> Value {
+        serde_json::json!({
+            "duration": 10.0,
+            "formats": [
+                { "format_id": "sb0", "vcodec": "none", "acodec": "none", "ext": "mhtml" },
+                { "format_id": "140", "vcodec": "none", "acodec": "mp4a.40.2", "abr": 128.0, "filesize": 160000 },
+                { "format_id": "139", "vcodec": "none", "acodec": "mp4a.40.5", "abr": 48.0, "filesize": 60000 },
+                { "format_id": "137", "vcodec": "avc1.640028", "acodec": "none", "height": 1080, "tbr": 4000.0, "filesize": 5000000 },
+                { "format_id": "248", "vcodec": "vp9", "acodec": "none", "height": 1080, "tbr": 2500.0 },
+                { "format_id": "136", "vcodec": "avc1.4d401f", "acodec": "none", "height": 720, "tbr": 1500.0, "filesize_approx": 1900000 },
+                { "format_id": "135", "vcodec": "avc1.4d401e", "acodec": "none", "height": 720, "tbr": 900.0 },
+                { "format_id": "18", "vcodec": "avc1.42001E", "acodec": "mp4a.40.2", "height": 360, "tbr": 500.0 }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_parse_format_options() {
+        let options = parse_format_options(&info());
+        let summary: Vec<(Option<u32>, &str, &str)> =
+            options.iter().map(|o| (o.height, o.codec.as_str(), o.selector.as_str())).collect();
+        assert_eq!(summary, vec![
+            (Some(1080), "H.264", "137+140"),
+            (Some(1080), "VP9", "248+140"),
+            (Some(720), "H.264", "136+140"),
+            (Some(360), "H.264", "18"),
+            (None, "", "140"),
+        ]);
+        // Video size plus the merged audio; VP9 size estimated from the bitrate.
+        assert_eq!(options[0].size, Some(5_160_000));
+        assert_eq!(options[1].size, Some(3_125_000 + 160_000));
+        assert_eq!(options[3].size, Some(625_000));
+    }
+
+    #[test]
+    fn test_labels() {
+        let options = parse_format_options(&info());
+        assert_eq!(options[0].label(), "1080p H.264 · ~4.9 MB");
+        assert!(options.last().unwrap().is_audio());
+        assert_eq!(options.last().unwrap().label(), "🎵 Audio · ~0.2 MB");
+        assert_eq!(format_quality("137+140"), "format:137+140");
+    }
+
+    #[test]
+    fn test_no_formats() {
+        assert!(parse_format_options(&serde_json::json!({ "title": "x" })).is_empty());
+    }
+}
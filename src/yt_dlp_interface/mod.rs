@@ -3,8 +3,23 @@ pub mod utils;
 pub mod urls;
 pub mod downloader;
 pub mod ensure;
+pub mod slideshow;
+pub mod video_info;
+pub mod formats;
+pub mod transcode;
+pub mod audio_tags;
+pub mod audio_format;
+pub mod video_modes;
+pub mod clip;
+pub mod split;
+pub mod subtitles;
+pub mod playlist;
+pub mod providers;
 
 pub use fetcher::YoutubeFetcher;
+pub use video_info::VideoInfo;
+pub use audio_format::AudioFormat;
+pub use clip::ClipRange;
 pub use utils::is_executable_present;
 pub use ensure::ensure_binaries;
 
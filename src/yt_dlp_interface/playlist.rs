@@ -0,0 +1,112 @@
+use anyhow::Result;
+use tokio::process::Command;
+
+use crate::platforms;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+/// One post of a profile, channel or playlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistEntry {
+    /// The extractor's id of the post; the canonical link when there is none.
+    pub id: String,
+    /// Canonical link of the post.
+    pub url: String,
+}
+
+/// Entries of a `yt-dlp -J --flat-playlist` result that belong to a supported platform, with
+/// canonical links, in listing order and without duplicates.
+pub fn parse_entries(info: &serde_json::Value) -> Vec<PlaylistEntry> {
+    let Some(entries) = info.get("entries").and_then(|v| v.as_array()) else {
+        return Vec::new();
+    };
+    let mut parsed: Vec<PlaylistEntry> = Vec::new();
+    for entry in entries {
+        let link = ["url", "webpage_url"]
+            .iter()
+            .filter_map(|field| entry.get(*field).and_then(|v| v.as_str()))
+            .find(|link| link.starts_with("http"))
+            .map(str::to_string)
+            // Flat YouTube entries sometimes carry just the video id.
+            .or_else(|| match (entry.get("ie_key").and_then(|v| v.as_str()), entry.get("id").and_then(|v| v.as_str())) {
+                (Some("Youtube"), Some(id)) => Some(format!("https://www.youtube.com/watch?v={}", id)),
+                _ => None,
+            });
+        let Some(link) = link else {
+            continue;
+        };
+        // Nested playlists and tabs aren't posts.
+        let Some(platform) = platforms::registry().for_url(&link) else {
+            continue;
+        };
+        let url = platform.canonicalize(&link);
+        if parsed.iter().any(|e| e.url == url) {
+            continue;
+        }
+        let id = entry.get("id").and_then(|v| v.as_str()).map(str::to_string).unwrap_or_else(|| url.clone());
+        parsed.push(PlaylistEntry { id, url });
+    }
+    parsed
+}
+
+impl YoutubeFetcher {
+    /// The latest (at most `limit`) posts of a profile, channel or playlist, newest first,
+    /// listed with `--flat-playlist` so nothing is downloaded.
+    pub async fn list_entries(&self, url: &str, limit: usize, fingerprint: Option<String>) -> Result<Vec<PlaylistEntry>> {
+        let mut cmd = Command::new(&self.yt_dlp_path);
+        cmd.kill_on_drop(true)
+            .arg("-J")
+            .arg("--flat-playlist")
+            .arg("--playlist-end")
+            .arg(limit.to_string())
+            .arg("--no-warnings");
+        if let Some(fp) = fingerprint {
+            cmd.arg(format!("--impersonate={}", fp));
+        }
+        cmd.arg(url);
+
+        let output = tokio::time::timeout(std::time::Duration::from_secs(120), cmd.output())
+            .await
+            .map_err(|_| anyhow::anyhow!("yt-dlp --flat-playlist timed out for {}", url))??;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("yt-dlp --flat-playlist failed: {}", stderr.trim()));
+        }
+        let info: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        let mut entries = parse_entries(&info);
+        entries.truncate(limit);
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_entries() {
+        let info = json!({
+            "entries": [
+                {"id": "1", "url": "https://www.tiktok.com/@u/video/1?lang=en"},
+                {"id": "1", "url": "https://www.tiktok.com/@u/video/1"},
+                {"url": "abc", "webpage_url": "https://www.instagram.com/p/Cxyz/"},
+                {"ie_key": "Youtube", "id": "dQw4w9WgXcQ", "url": "dQw4w9WgXcQ"},
+                {"url": "https://www.youtube.com/playlist?list=PLnested"},
+                {"title": "no link"}
+            ]
+        });
+        let entries = parse_entries(&info);
+        let urls: Vec<&str> = entries.iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.tiktok.com/@u/video/1",
+                "https://www.instagram.com/p/Cxyz",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            ]
+        );
+        let ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "https://www.instagram.com/p/Cxyz", "dQw4w9WgXcQ"]);
+        assert!(parse_entries(&json!({"title": "single video"})).is_empty());
+    }
+}
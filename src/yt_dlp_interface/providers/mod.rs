@@ -0,0 +1,320 @@
+//! Download sources. Every link is tried against an ordered chain of providers (yt-dlp first,
+//! then tikwm for TikTok by default); admins can reorder the chain per platform. Providers
+//! that talk to a remote endpoint have a circuit breaker, so an endpoint that keeps failing
+//! is skipped for a cooldown instead of costing every download a timeout.
+
+pub mod tikwm;
+pub mod ytdlp;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::platforms::{self, Fallback};
+use crate::utils::progress_bar::ProgressBar;
+use crate::yt_dlp_interface::clip::ClipRange;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+pub use tikwm::{Tikwm, TikwmProvider};
+pub use ytdlp::YtDlpProvider;
+
+/// Consecutive endpoint failures after which a provider's circuit opens.
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+/// How long an open circuit keeps a provider out of the chain, unless PROVIDER_COOLDOWN_SECS says otherwise.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(300);
+
+pub type ProviderFuture<'a> = Pin<Box<dyn Future<Output = Result<PathBuf>> + Send + 'a>>;
+
+/// One download attempt handed to a provider.
+pub struct DownloadJob<'a> {
+    pub url: &'a str,
+    pub filename_stem: &'a str,
+    pub quality: &'a str,
+    pub fingerprint: Option<String>,
+    /// Part of the post to download, for providers that can; others return the whole file.
+    pub section: Option<ClipRange>,
+    /// Slice of the progress bar, in percent, the attempt reports into.
+    pub progress: (u8, u8),
+}
+
+impl DownloadJob<'_> {
+    pub fn wants_audio(&self) -> bool {
+        self.quality == "audio"
+    }
+}
+
+pub trait DownloadProvider: Send + Sync {
+    /// Stable identifier stored in the per-platform order, e.g. "tikwm".
+    fn id(&self) -> &'static str;
+
+    /// Human readable name shown in the admin panel.
+    fn name(&self) -> &'static str;
+
+    /// Whether the provider can download links of this platform.
+    fn supports(&self, platform_id: &str) -> bool;
+
+    /// Providers backed by a remote endpoint are skipped for a while after repeated failures.
+    /// Local tools whose failures depend on the link (yt-dlp) opt out.
+    fn has_circuit_breaker(&self) -> bool {
+        true
+    }
+
+    fn download<'a>(
+        &'a self,
+        fetcher: &'a YoutubeFetcher,
+        job: &'a DownloadJob<'a>,
+        progress_bar: &'a mut ProgressBar,
+    ) -> ProviderFuture<'a>;
+}
+
+/// Marks an error as a failure of the endpoint itself (bad HTTP status, broken response)
+/// rather than of the link, so it counts towards the circuit breaker.
+#[derive(Debug)]
+pub struct EndpointError(pub String);
+
+impl std::fmt::Display for EndpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for EndpointError {}
+
+/// Network errors and [`EndpointError`]s trip the breaker; "this post has no video" doesn't.
+pub fn is_endpoint_failure(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| e.is::<reqwest::Error>() || e.is::<EndpointError>())
+}
+
+/// Success/failure counters and circuit state of one provider.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderHealth {
+    pub successes: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub open_until: Option<Instant>,
+    pub last_error: Option<String>,
+}
+
+impl ProviderHealth {
+    pub fn is_open(&self, now: Instant) -> bool {
+        self.open_until.is_some_and(|until| now < until)
+    }
+}
+
+/// The available providers, their order for every platform and their health.
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn DownloadProvider>>,
+    orders: RwLock<HashMap<String, Vec<String>>>,
+    health: Mutex<HashMap<&'static str, ProviderHealth>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl ProviderRegistry {
+    pub fn new(providers: Vec<Arc<dyn DownloadProvider>>) -> Self {
+        Self {
+            providers,
+            orders: RwLock::new(HashMap::new()),
+            health: Mutex::new(HashMap::new()),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// yt-dlp and tikwm, with the cooldown from PROVIDER_COOLDOWN_SECS.
+    pub fn builtin() -> Self {
+        let cooldown = std::env::var("PROVIDER_COOLDOWN_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_COOLDOWN);
+        Self::new(vec![Arc::new(YtDlpProvider), Arc::new(TikwmProvider)]).with_circuit_breaker(DEFAULT_FAILURE_THRESHOLD, cooldown)
+    }
+
+    pub fn with_circuit_breaker(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Arc<dyn DownloadProvider>> {
+        self.providers.iter().find(|p| p.id() == id)
+    }
+
+    /// Providers that can serve the platform, in registration order.
+    pub fn supporting(&self, platform_id: &str) -> Vec<&Arc<dyn DownloadProvider>> {
+        self.providers.iter().filter(|p| p.supports(platform_id)).collect()
+    }
+
+    /// Order used when no admin set one: yt-dlp, then the platform's fallbacks.
+    pub fn default_order(&self, platform_id: &str) -> Vec<String> {
+        let fallbacks = platforms::registry().get(platform_id).map(|p| p.fallbacks()).unwrap_or_default();
+        std::iter::once(YtDlpProvider::ID)
+            .chain(fallbacks.iter().map(|f| match f {
+                Fallback::Tikwm => TikwmProvider::ID,
+            }))
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    pub fn order(&self, platform_id: &str) -> Vec<String> {
+        self.orders
+            .read()
+            .unwrap()
+            .get(platform_id)
+            .cloned()
+            .unwrap_or_else(|| self.default_order(platform_id))
+    }
+
+    /// Replaces the order of a platform; an empty list restores the default.
+    pub fn set_order(&self, platform_id: &str, order: Vec<String>) {
+        let mut orders = self.orders.write().unwrap();
+        if order.is_empty() {
+            orders.remove(platform_id);
+        } else {
+            orders.insert(platform_id.to_string(), order);
+        }
+    }
+
+    /// Providers to try for `url`, in order, without the ones whose circuit is open.
+    pub fn chain_for(&self, url: &str) -> Vec<Arc<dyn DownloadProvider>> {
+        let platform = platforms::registry().for_url(url);
+        let order = match platform {
+            Some(platform) => self.order(platform.id()),
+            None => vec![YtDlpProvider::ID.to_string()],
+        };
+        let now = Instant::now();
+        let health = self.health.lock().unwrap();
+        order
+            .iter()
+            .filter_map(|id| self.get(id))
+            .filter(|p| platform.is_none_or(|platform| p.supports(platform.id())))
+            .filter(|p| {
+                let open = health.get(p.id()).is_some_and(|h| h.is_open(now));
+                if open {
+                    log::debug!("Skipping {}: circuit open", p.name());
+                }
+                !open
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn record_success(&self, provider: &dyn DownloadProvider) {
+        let mut health = self.health.lock().unwrap();
+        let entry = health.entry(provider.id()).or_default();
+        entry.successes += 1;
+        entry.consecutive_failures = 0;
+        entry.open_until = None;
+    }
+
+    pub fn record_failure(&self, provider: &dyn DownloadProvider, error: &anyhow::Error) {
+        let mut health = self.health.lock().unwrap();
+        let entry = health.entry(provider.id()).or_default();
+        entry.failures += 1;
+        entry.last_error = Some(error.to_string());
+        if !provider.has_circuit_breaker() || !is_endpoint_failure(error) {
+            return;
+        }
+        entry.consecutive_failures += 1;
+        // Also re-opens right away when the single trial after a cooldown fails.
+        if entry.consecutive_failures >= self.failure_threshold {
+            log::warn!(
+                "{} failed {} times in a row, skipping it for {}s",
+                provider.name(),
+                entry.consecutive_failures,
+                self.cooldown.as_secs()
+            );
+            entry.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    pub fn health(&self, provider_id: &str) -> ProviderHealth {
+        self.health.lock().unwrap().get(provider_id).cloned().unwrap_or_default()
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Arc<dyn DownloadProvider>> {
+        self.providers.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Remote;
+
+    impl DownloadProvider for Remote {
+        fn id(&self) -> &'static str {
+            "remote"
+        }
+        fn name(&self) -> &'static str {
+            "Remote"
+        }
+        fn supports(&self, _platform_id: &str) -> bool {
+            true
+        }
+        fn download<'a>(&'a self, _: &'a YoutubeFetcher, _: &'a DownloadJob<'a>, _: &'a mut ProgressBar) -> ProviderFuture<'a> {
+            Box::pin(async { Err(anyhow::anyhow!("unused")) })
+        }
+    }
+
+    fn endpoint_error() -> anyhow::Error {
+        EndpointError("HTTP 502".to_string()).into()
+    }
+
+    #[test]
+    fn test_default_chain_follows_platform_fallbacks() {
+        let registry = ProviderRegistry::builtin();
+        assert_eq!(registry.default_order("tiktok"), vec!["ytdlp", "tikwm"]);
+        assert_eq!(registry.default_order("vimeo"), vec!["ytdlp"]);
+
+        let ids = |url: &str| registry.chain_for(url).iter().map(|p| p.id()).collect::<Vec<_>>();
+        assert_eq!(ids("https://www.tiktok.com/@u/video/1"), vec!["ytdlp", "tikwm"]);
+        assert_eq!(ids("https://vimeo.com/1"), vec!["ytdlp"]);
+
+        registry.set_order("tiktok", vec!["tikwm".to_string(), "ytdlp".to_string()]);
+        assert_eq!(ids("https://www.tiktok.com/@u/video/1"), vec!["tikwm", "ytdlp"]);
+        // tikwm only knows TikTok, even when an admin lists it elsewhere.
+        registry.set_order("vimeo", vec!["tikwm".to_string(), "ytdlp".to_string()]);
+        assert_eq!(ids("https://vimeo.com/1"), vec!["ytdlp"]);
+        registry.set_order("tiktok", Vec::new());
+        assert_eq!(registry.order("tiktok"), vec!["ytdlp", "tikwm"]);
+    }
+
+    #[test]
+    fn test_circuit_opens_after_repeated_endpoint_failures() {
+        let registry = ProviderRegistry::new(vec![Arc::new(Remote)]).with_circuit_breaker(2, Duration::from_secs(60));
+        registry.set_order("vimeo", vec!["remote".to_string()]);
+        let url = "https://vimeo.com/1";
+
+        // Link-specific errors never open the circuit.
+        for _ in 0..5 {
+            registry.record_failure(&Remote, &anyhow::anyhow!("post has no video"));
+        }
+        assert_eq!(registry.chain_for(url).len(), 1);
+
+        registry.record_failure(&Remote, &endpoint_error());
+        assert_eq!(registry.chain_for(url).len(), 1);
+        registry.record_failure(&Remote, &endpoint_error().context("tikwm API"));
+        assert!(registry.chain_for(url).is_empty());
+        assert_eq!(registry.health("remote").failures, 7);
+
+        registry.record_success(&Remote);
+        assert_eq!(registry.chain_for(url).len(), 1);
+    }
+
+    #[test]
+    fn test_circuit_closes_after_cooldown() {
+        let registry = ProviderRegistry::new(vec![Arc::new(Remote)]).with_circuit_breaker(1, Duration::from_millis(20));
+        registry.set_order("vimeo", vec!["remote".to_string()]);
+        registry.record_failure(&Remote, &endpoint_error());
+        assert!(registry.chain_for("https://vimeo.com/1").is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(registry.chain_for("https://vimeo.com/1").len(), 1);
+    }
+}
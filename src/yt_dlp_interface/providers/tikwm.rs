@@ -0,0 +1,198 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+use crate::yt_dlp_interface::providers::{DownloadJob, DownloadProvider, EndpointError, ProviderFuture};
+use crate::yt_dlp_interface::slideshow::tikwm_music_url;
+use crate::yt_dlp_interface::video_info::VideoInfo;
+
+pub const DEFAULT_TIKWM_URL: &str = "https://www.tikwm.com";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
+
+/// Client of the tikwm.com API, which exposes TikTok posts (no-watermark video, music and
+/// photo carousels) that the TikTok web API hides from yt-dlp.
+#[derive(Clone)]
+pub struct Tikwm {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Tikwm {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self { base_url: base_url.into().trim_end_matches('/').to_string(), client }
+    }
+
+    /// Uses TIKWM_API_URL (e.g. a mirror) when set.
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("TIKWM_API_URL")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_TIKWM_URL.to_string());
+        Self::new(base_url)
+    }
+
+    fn referer(&self) -> String {
+        format!("{}/", self.base_url)
+    }
+
+    /// Media links tikwm returns are sometimes relative to its own host.
+    pub fn absolute_url(&self, media_url: &str) -> String {
+        if media_url.starts_with('/') {
+            format!("{}{}", self.base_url, media_url)
+        } else {
+            media_url.to_string()
+        }
+    }
+
+    /// The `data` object tikwm reports for a post.
+    pub async fn post_data(&self, url: &str) -> Result<serde_json::Value> {
+        log::info!("Querying tikwm API for {}", url);
+        let resp = self
+            .client
+            .get(format!("{}/api/", self.base_url))
+            .query(&[("url", url)])
+            .header("User-Agent", "Mozilla/5.0")
+            .header("Referer", self.referer())
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(EndpointError(format!("tikwm API returned HTTP {}", resp.status())).into());
+        }
+
+        // { "code": 0, "data": { "play": "<url>", "wmplay": "<url>", "music": "<url>", ... } }
+        let body: serde_json::Value = resp.json().await?;
+        if body.get("code").and_then(|v| v.as_i64()) != Some(0) {
+            return Err(anyhow::anyhow!("tikwm API returned non-zero code: {:?}", body.get("code")));
+        }
+        Ok(body.get("data").cloned().unwrap_or_default())
+    }
+
+    /// Downloads a media file tikwm pointed to.
+    pub async fn fetch(&self, media_url: &str) -> Result<Vec<u8>> {
+        let resp = self
+            .client
+            .get(self.absolute_url(media_url))
+            .header("User-Agent", BROWSER_USER_AGENT)
+            .header("Referer", self.referer())
+            .header("Accept", "*/*")
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(EndpointError(format!("tikwm CDN returned HTTP {}", resp.status())).into());
+        }
+        let bytes = resp.bytes().await?;
+        if bytes.is_empty() {
+            return Err(anyhow::anyhow!("tikwm CDN returned an empty body"));
+        }
+        Ok(bytes.into())
+    }
+
+    async fn fetch_to(&self, media_url: &str, path: &Path) -> Result<()> {
+        let bytes = self.fetch(media_url).await?;
+        tokio::fs::write(path, &bytes).await?;
+        log::info!("tikwm downloaded {} bytes to {:?}", bytes.len(), path);
+        Ok(())
+    }
+}
+
+/// The no-watermark "play" URL, or "wmplay" when that is missing.
+pub fn tikwm_video_url(data: &serde_json::Value) -> Option<String> {
+    ["play", "wmplay"]
+        .iter()
+        .filter_map(|key| data.get(*key).and_then(|v| v.as_str()))
+        .find(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+/// Videos and music of TikTok posts through [`Tikwm`].
+pub struct TikwmProvider;
+
+impl TikwmProvider {
+    pub const ID: &'static str = "tikwm";
+}
+
+impl DownloadProvider for TikwmProvider {
+    fn id(&self) -> &'static str {
+        Self::ID
+    }
+
+    fn name(&self) -> &'static str {
+        "tikwm"
+    }
+
+    fn supports(&self, platform_id: &str) -> bool {
+        platform_id == "tiktok"
+    }
+
+    fn download<'a>(
+        &'a self,
+        fetcher: &'a YoutubeFetcher,
+        job: &'a DownloadJob<'a>,
+        progress_bar: &'a mut ProgressBar,
+    ) -> ProviderFuture<'a> {
+        Box::pin(async move {
+            let (start, end) = job.progress;
+            progress_bar.update(start, Some("🔧 Fetching from alternate source...")).await?;
+            let data = fetcher.tikwm.post_data(job.url).await?;
+
+            let (media_url, path) = if job.wants_audio() {
+                let music_url = tikwm_music_url(&data)
+                    .ok_or_else(|| anyhow::anyhow!("tikwm API returned no music URL"))?;
+                (music_url, fetcher.output_dir.join(format!("{}.m4a", job.filename_stem)))
+            } else {
+                let video_url = tikwm_video_url(&data)
+                    .ok_or_else(|| anyhow::anyhow!("tikwm API returned no video URL in data.play/wmplay"))?;
+                (video_url, fetcher.output_dir.join(format!("{}_alt.mp4", job.filename_stem)))
+            };
+            log::info!("tikwm provided media URL: {}", media_url);
+
+            let middle = start + (end.saturating_sub(start)) / 2;
+            progress_bar.update(middle, Some("⬇️ Downloading from alternate source...")).await?;
+            let mut guard = TempFileGuard::new(path.clone());
+            fetcher.tikwm.fetch_to(&media_url, &path).await?;
+            guard.forget();
+
+            // Same place yt-dlp leaves its info JSON, so captions and audio tags work either way.
+            match serde_json::to_string(&VideoInfo::from_tikwm(&data)) {
+                Ok(json) => {
+                    if let Err(e) = tokio::fs::write(fetcher.info_json_path(job.filename_stem), json).await {
+                        log::warn!("Could not save tikwm metadata: {}", e);
+                    }
+                }
+                Err(e) => log::warn!("Could not serialize tikwm metadata: {}", e),
+            }
+            Ok(path)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_video_url_prefers_play() {
+        assert_eq!(tikwm_video_url(&json!({ "play": "https://cdn/p.mp4", "wmplay": "https://cdn/w.mp4" })).as_deref(), Some("https://cdn/p.mp4"));
+        assert_eq!(tikwm_video_url(&json!({ "play": "", "wmplay": "https://cdn/w.mp4" })).as_deref(), Some("https://cdn/w.mp4"));
+        assert_eq!(tikwm_video_url(&json!({ "play": "" })), None);
+    }
+
+    #[test]
+    fn test_absolute_url() {
+        let tikwm = Tikwm::new("http://127.0.0.1:9000/");
+        assert_eq!(tikwm.absolute_url("/video/1.mp4"), "http://127.0.0.1:9000/video/1.mp4");
+        assert_eq!(tikwm.absolute_url("https://cdn/1.mp4"), "https://cdn/1.mp4");
+    }
+}
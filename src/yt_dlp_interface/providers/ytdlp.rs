@@ -0,0 +1,58 @@
+use crate::utils::progress_bar::ProgressBar;
+use crate::yt_dlp_interface::fetcher::{file_has_audio, YoutubeFetcher};
+use crate::yt_dlp_interface::providers::{DownloadJob, DownloadProvider, ProviderFuture};
+
+/// The bundled yt-dlp binary; knows every platform.
+pub struct YtDlpProvider;
+
+impl YtDlpProvider {
+    pub const ID: &'static str = "ytdlp";
+}
+
+impl DownloadProvider for YtDlpProvider {
+    fn id(&self) -> &'static str {
+        Self::ID
+    }
+
+    fn name(&self) -> &'static str {
+        "yt-dlp"
+    }
+
+    fn supports(&self, _platform_id: &str) -> bool {
+        true
+    }
+
+    /// yt-dlp failures are almost always about the link (private, removed, geo-blocked).
+    fn has_circuit_breaker(&self) -> bool {
+        false
+    }
+
+    fn download<'a>(
+        &'a self,
+        fetcher: &'a YoutubeFetcher,
+        job: &'a DownloadJob<'a>,
+        progress_bar: &'a mut ProgressBar,
+    ) -> ProviderFuture<'a> {
+        Box::pin(async move {
+            let (start, end) = job.progress;
+            let path = fetcher
+                .run_yt_dlp(job.url, job.filename_stem, job.quality, job.fingerprint.clone(), job.section, progress_bar, start, end)
+                .await?;
+            if job.wants_audio() {
+                return Ok(path);
+            }
+
+            // Video present but audio missing: HEVC video-only stream (yt-dlp #16950).
+            // Recover the audio from an H.264 variant and mux it in. Files without any video
+            // are left to the next provider in the chain.
+            let has_audio = matches!(file_has_audio(&fetcher.ffprobe_path(), &path).await, Ok(true));
+            if !has_audio && fetcher.file_has_video(&path).await {
+                log::warn!("Downloaded file is missing audio, attempting H.264 audio fallback: {:?}", path);
+                return fetcher
+                    .audio_fallback(job.url, job.filename_stem, job.fingerprint.clone(), job.section, &path, progress_bar)
+                    .await;
+            }
+            Ok(path)
+        })
+    }
+}
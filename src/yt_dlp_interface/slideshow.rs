@@ -0,0 +1,447 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+use crate::yt_dlp_interface::providers::TikwmProvider;
+use crate::yt_dlp_interface::video_info::VideoInfo;
+
+/// Telegram accepts at most 10 items per media group.
+pub const MEDIA_GROUP_LIMIT: usize = 10;
+
+/// A TikTok photo post: every image of the carousel plus the post's background music.
+#[derive(Clone)]
+pub struct Slideshow {
+    pub images: Vec<PathBuf>,
+    pub audio: Option<PathBuf>,
+    /// Post metadata reported by tikwm, for the caption.
+    pub info: VideoInfo,
+}
+
+impl Slideshow {
+    /// Guards that delete every downloaded file once the slideshow has been delivered.
+    pub fn guards(&self) -> Vec<TempFileGuard> {
+        self.images
+            .iter()
+            .chain(self.audio.iter())
+            .map(|p| TempFileGuard::new(p.clone()))
+            .collect()
+    }
+}
+
+/// Result of a download: either a single media file or a photo carousel.
+pub enum Downloaded {
+    Media(PathBuf),
+    Slideshow(Box<Slideshow>),
+}
+
+pub fn is_tiktok_url(url: &str) -> bool {
+    url.contains("tiktok.com/")
+}
+
+/// `/photo/` URLs are always slideshows; short links have to be resolved through tikwm.
+pub fn is_photo_url(url: &str) -> bool {
+    is_tiktok_url(url) && url.contains("/photo/")
+}
+
+/// Extracts the carousel image URLs from a tikwm `data` object.
+pub fn tikwm_image_urls(data: &serde_json::Value) -> Vec<String> {
+    data.get("images")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Extracts the background music URL from a tikwm `data` object.
+pub fn tikwm_music_url(data: &serde_json::Value) -> Option<String> {
+    data.get("music")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .or_else(|| {
+            data.get("music_info")
+                .and_then(|m| m.get("play"))
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+        })
+        .map(|s| s.to_string())
+}
+
+/// Frame size of rendered slideshows; images are letterboxed into a vertical 9:16 frame.
+const RENDER_WIDTH: u32 = 1080;
+const RENDER_HEIGHT: u32 = 1920;
+
+/// How a photo carousel is turned into an MP4.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlideshowRenderOptions {
+    /// How long every image stays on screen.
+    pub seconds_per_image: f64,
+    /// Length of the crossfade between images; 0 disables it.
+    pub crossfade_seconds: f64,
+}
+
+impl Default for SlideshowRenderOptions {
+    fn default() -> Self {
+        Self { seconds_per_image: 3.0, crossfade_seconds: 0.5 }
+    }
+}
+
+impl SlideshowRenderOptions {
+    /// Reads SLIDESHOW_SECONDS_PER_IMAGE and SLIDESHOW_CROSSFADE_SECONDS, falling back to the defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |key: &str| std::env::var(key).ok().and_then(|v| v.trim().parse::<f64>().ok());
+        let seconds_per_image = read("SLIDESHOW_SECONDS_PER_IMAGE")
+            .filter(|v| *v > 0.0)
+            .unwrap_or(defaults.seconds_per_image);
+        // A crossfade longer than the image itself would eat the whole frame.
+        let crossfade_seconds = read("SLIDESHOW_CROSSFADE_SECONDS")
+            .filter(|v| *v >= 0.0)
+            .unwrap_or(defaults.crossfade_seconds)
+            .min(seconds_per_image / 2.0);
+        Self { seconds_per_image, crossfade_seconds }
+    }
+
+    /// Total length of the rendered video for `image_count` images.
+    pub fn total_duration(&self, image_count: usize) -> f64 {
+        image_count as f64 * self.seconds_per_image + self.crossfade_seconds_for(image_count)
+    }
+
+    fn crossfade_seconds_for(&self, image_count: usize) -> f64 {
+        if image_count > 1 { self.crossfade_seconds } else { 0.0 }
+    }
+}
+
+/// Builds the ffmpeg arguments that render the images (and optional music) into `output`.
+///
+/// Each image is looped for `seconds_per_image` (+ crossfade), scaled and padded into the
+/// same frame, then either concatenated or chained through `xfade`. The music is looped
+/// if it is shorter than the slideshow and cut if it is longer.
+pub fn build_slideshow_ffmpeg_args(
+    images: &[PathBuf],
+    audio: Option<&Path>,
+    output: &Path,
+    options: &SlideshowRenderOptions,
+) -> Vec<String> {
+    let crossfade = options.crossfade_seconds_for(images.len());
+    let clip_len = options.seconds_per_image + crossfade;
+    let total = options.total_duration(images.len());
+
+    let mut args: Vec<String> = vec!["-y".into()];
+    for image in images {
+        args.extend([
+            "-loop".into(), "1".into(),
+            "-t".into(), format!("{:.3}", clip_len),
+            "-i".into(), image.to_string_lossy().into_owned(),
+        ]);
+    }
+    if let Some(audio) = audio {
+        args.extend([
+            "-stream_loop".into(), "-1".into(),
+            "-i".into(), audio.to_string_lossy().into_owned(),
+        ]);
+    }
+
+    let mut filter = String::new();
+    for i in 0..images.len() {
+        filter.push_str(&format!(
+            "[{i}:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=30,format=yuv420p[v{i}];",
+            i = i, w = RENDER_WIDTH, h = RENDER_HEIGHT,
+        ));
+    }
+    if crossfade > 0.0 {
+        let mut last = "v0".to_string();
+        for i in 1..images.len() {
+            let out = format!("x{}", i);
+            filter.push_str(&format!(
+                "[{}][v{}]xfade=transition=fade:duration={:.3}:offset={:.3}[{}];",
+                last, i, crossfade, i as f64 * options.seconds_per_image, out
+            ));
+            last = out;
+        }
+        filter.push_str(&format!("[{}]null[vout]", last));
+    } else {
+        for i in 0..images.len() {
+            filter.push_str(&format!("[v{}]", i));
+        }
+        filter.push_str(&format!("concat=n={}:v=1:a=0[vout]", images.len()));
+    }
+
+    args.extend(["-filter_complex".into(), filter, "-map".into(), "[vout]".into()]);
+    if audio.is_some() {
+        args.extend([
+            "-map".into(), format!("{}:a:0", images.len()),
+            "-c:a".into(), "aac".into(),
+            "-b:a".into(), "128k".into(),
+        ]);
+    }
+    args.extend([
+        "-c:v".into(), "libx264".into(),
+        "-preset".into(), "veryfast".into(),
+        "-crf".into(), "23".into(),
+        "-pix_fmt".into(), "yuv420p".into(),
+        "-t".into(), format!("{:.3}", total),
+        "-movflags".into(), "+faststart".into(),
+        output.to_string_lossy().into_owned(),
+    ]);
+    args
+}
+
+fn is_jpeg_or_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xFF, 0xD8]) || bytes.starts_with(&[0x89, b'P', b'N', b'G'])
+}
+
+impl YoutubeFetcher {
+    /// Downloads the URL and reports whether it turned out to be a TikTok photo carousel.
+    ///
+    /// yt-dlp only sees the music track of photo posts, so slideshows are recognised either
+    /// by a `/photo/` URL or by a video request that still has no video stream after the
+    /// regular fallbacks; in both cases the images come from tikwm's `images` array.
+    pub async fn download(
+        &self,
+        url: String,
+        filename_stem: &str,
+        quality: &str,
+        fingerprint: Option<String>,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Downloaded> {
+        let wants_video = quality != "audio";
+
+        if wants_video
+            && is_photo_url(&url)
+            && let Some(show) = self.fetch_tikwm_slideshow(&url, filename_stem, progress_bar).await?
+        {
+            return Ok(Downloaded::Slideshow(Box::new(show)));
+        }
+
+        match self
+            .download_video_from_url(url.clone(), filename_stem, quality, fingerprint, progress_bar)
+            .await
+        {
+            Ok(path) => {
+                if wants_video
+                    && is_tiktok_url(&url)
+                    && !self.file_has_video(&path).await
+                    && let Ok(Some(show)) = self.fetch_tikwm_slideshow(&url, filename_stem, progress_bar).await
+                {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return Ok(Downloaded::Slideshow(Box::new(show)));
+                }
+                Ok(Downloaded::Media(path))
+            }
+            Err(e) => {
+                if wants_video
+                    && is_tiktok_url(&url)
+                    && let Ok(Some(show)) = self.fetch_tikwm_slideshow(&url, filename_stem, progress_bar).await
+                {
+                    return Ok(Downloaded::Slideshow(Box::new(show)));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Queries tikwm for the post and, if it is a photo carousel, downloads every image and
+    /// the background music. Returns `Ok(None)` for regular video posts.
+    pub async fn fetch_tikwm_slideshow(
+        &self,
+        url: &str,
+        filename_stem: &str,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Option<Slideshow>> {
+        let Some(provider) = self.providers.get(TikwmProvider::ID).cloned() else {
+            return Ok(None);
+        };
+        if self.providers.health(TikwmProvider::ID).is_open(std::time::Instant::now()) {
+            log::debug!("Skipping the slideshow lookup for {}: tikwm circuit open", url);
+            return Ok(None);
+        }
+        let data = match self.tikwm.post_data(url).await {
+            Ok(data) => {
+                self.providers.record_success(provider.as_ref());
+                data
+            }
+            Err(e) => {
+                self.providers.record_failure(provider.as_ref(), &e);
+                return Err(e);
+            }
+        };
+        let image_urls = tikwm_image_urls(&data);
+        if image_urls.is_empty() {
+            return Ok(None);
+        }
+
+        log::info!("tikwm reports a slideshow with {} images for {}", image_urls.len(), url);
+        let total = image_urls.len();
+        let mut images = Vec::with_capacity(total);
+        // Keep guards until everything is downloaded so a failure mid-way leaves no files behind.
+        let mut guards = Vec::with_capacity(total + 1);
+
+        for (idx, image_url) in image_urls.iter().enumerate() {
+            let pct = 10 + ((idx as f64 / total as f64) * 60.0) as u8;
+            let info = format!("🖼 Downloading images {}/{}", idx + 1, total);
+            progress_bar.update(pct, Some(&info)).await?;
+
+            let bytes = self.tikwm.fetch(image_url).await?;
+
+            let path = self.output_dir.join(format!("{}_img{:02}.jpg", filename_stem, idx));
+            guards.push(TempFileGuard::new(path.clone()));
+
+            if is_jpeg_or_png(&bytes) {
+                tokio::fs::write(&path, &bytes).await?;
+            } else {
+                // WebP/HEIC images are not accepted as Telegram photos; convert them to JPEG.
+                let raw_path = self.output_dir.join(format!("{}_img{:02}.raw", filename_stem, idx));
+                let _raw_guard = TempFileGuard::new(raw_path.clone());
+                tokio::fs::write(&raw_path, &bytes).await?;
+                self.convert_to_jpeg(&raw_path, &path).await?;
+            }
+            images.push(path);
+        }
+
+        progress_bar.update(75, Some("🎵 Downloading music...")).await?;
+        let audio = match tikwm_music_url(&data) {
+            Some(music_url) => {
+                let path = self.output_dir.join(format!("{}_music.mp3", filename_stem));
+                match self.tikwm.fetch(&music_url).await {
+                    Ok(bytes) => {
+                        guards.push(TempFileGuard::new(path.clone()));
+                        tokio::fs::write(&path, &bytes).await?;
+                        Some(path)
+                    }
+                    Err(e) => {
+                        log::warn!("tikwm music download failed ({}), sending images only", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        for mut guard in guards {
+            guard.forget();
+        }
+        progress_bar.update(80, Some("⬇️ Download completed")).await?;
+        Ok(Some(Slideshow { images, audio, info: VideoInfo::from_tikwm(&data) }))
+    }
+
+    /// Renders the carousel into a single MP4 with the bundled ffmpeg. The returned file is a
+    /// regular H.264/AAC video and goes through the usual upload path (thumbnail, faststart).
+    pub async fn render_slideshow_video(
+        &self,
+        show: &Slideshow,
+        filename_stem: &str,
+        options: &SlideshowRenderOptions,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<PathBuf> {
+        if show.images.is_empty() {
+            return Err(anyhow::anyhow!("Slideshow has no images to render"));
+        }
+        progress_bar.update(82, Some("🎞 Rendering slideshow video...")).await?;
+
+        let output = self.output_dir.join(format!("{}_slideshow.mp4", filename_stem));
+        let mut guard = TempFileGuard::new(output.clone());
+        let args = build_slideshow_ffmpeg_args(&show.images, show.audio.as_deref(), &output, options);
+        log::info!("Rendering slideshow with {} images into {:?}", show.images.len(), output);
+
+        let out = Command::new(self.ffmpeg_path()).args(&args).output().await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg slideshow rendering failed: {}", stderr.trim()));
+        }
+
+        guard.forget();
+        Ok(output)
+    }
+
+    async fn convert_to_jpeg(&self, input: &Path, output: &Path) -> Result<()> {
+        let out = Command::new(self.ffmpeg_path())
+            .arg("-y")
+            .arg("-i")
+            .arg(input)
+            .arg("-q:v")
+            .arg("2")
+            .arg(output)
+            .output()
+            .await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg image conversion failed: {}", stderr.trim()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_photo_url() {
+        assert!(is_photo_url("https://www.tiktok.com/@user/photo/7300000000000000000"));
+        assert!(!is_photo_url("https://www.tiktok.com/@user/video/7300000000000000000"));
+        assert!(!is_photo_url("https://example.com/photo/1"));
+    }
+
+    fn images(n: usize) -> Vec<PathBuf> {
+        (0..n).map(|i| PathBuf::from(format!("img{}.jpg", i))).collect()
+    }
+
+    #[test]
+    fn test_slideshow_args_with_crossfade() {
+        let options = SlideshowRenderOptions { seconds_per_image: 3.0, crossfade_seconds: 0.5 };
+        let args = build_slideshow_ffmpeg_args(&images(3), Some(Path::new("music.mp3")), Path::new("out.mp4"), &options);
+        let filter = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+
+        assert!(filter.contains("[v0][v1]xfade=transition=fade:duration=0.500:offset=3.000[x1]"));
+        assert!(filter.contains("[x1][v2]xfade=transition=fade:duration=0.500:offset=6.000[x2]"));
+        assert!(filter.ends_with("[x2]null[vout]"));
+        // Every clip covers its crossfade, music is input #3 and the output is cut to 3*3+0.5s.
+        assert_eq!(args.iter().filter(|a| *a == "3.500").count(), 3);
+        assert!(args.windows(2).any(|w| w[0] == "-map" && w[1] == "3:a:0"));
+        assert!(args.windows(2).any(|w| w[0] == "-t" && w[1] == "9.500"));
+        assert_eq!(args.last().unwrap(), "out.mp4");
+    }
+
+    #[test]
+    fn test_slideshow_args_without_crossfade_or_music() {
+        let options = SlideshowRenderOptions { seconds_per_image: 2.0, crossfade_seconds: 0.0 };
+        let args = build_slideshow_ffmpeg_args(&images(2), None, Path::new("out.mp4"), &options);
+        let filter = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+
+        assert!(filter.ends_with("[v0][v1]concat=n=2:v=1:a=0[vout]"));
+        assert!(!args.iter().any(|a| a == "-stream_loop" || a == "-c:a"));
+        assert!(args.windows(2).any(|w| w[0] == "-t" && w[1] == "4.000"));
+    }
+
+    #[test]
+    fn test_single_image_has_no_crossfade() {
+        let options = SlideshowRenderOptions::default();
+        assert_eq!(options.total_duration(1), 3.0);
+        let args = build_slideshow_ffmpeg_args(&images(1), None, Path::new("out.mp4"), &options);
+        assert!(args.iter().any(|a| a.ends_with("concat=n=1:v=1:a=0[vout]")));
+    }
+
+    #[test]
+    fn test_tikwm_image_and_music_urls() {
+        let data = json!({
+            "play": "https://cdn/music.mp3",
+            "images": ["https://cdn/1.jpg", "", "https://cdn/2.jpg"],
+            "music_info": { "play": "https://cdn/music_info.mp3" }
+        });
+        assert_eq!(tikwm_image_urls(&data), vec!["https://cdn/1.jpg", "https://cdn/2.jpg"]);
+        assert_eq!(tikwm_music_url(&data), Some("https://cdn/music_info.mp3".to_string()));
+
+        let video = json!({ "play": "https://cdn/video.mp4", "music": "https://cdn/m.mp3" });
+        assert!(tikwm_image_urls(&video).is_empty());
+        assert_eq!(tikwm_music_url(&video), Some("https://cdn/m.mp3".to_string()));
+    }
+}
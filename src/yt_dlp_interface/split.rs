@@ -0,0 +1,208 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::mtproto_uploader::metadata::get_video_metadata;
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+use crate::yt_dlp_interface::transcode::plan_transcode;
+use crate::yt_dlp_interface::video_info::Chapter;
+
+/// Parts are planned to this share of the upload limit, since cuts land on keyframes and
+/// bitrates aren't constant.
+const PART_FILL: f64 = 0.9;
+/// More parts than this aren't worth sending; such videos fail as too large instead.
+const MAX_PARTS: usize = 20;
+
+/// Start and end, in seconds, of every part.
+pub type SplitPlan = Vec<(f64, f64)>;
+
+/// `count` parts of equal duration.
+fn equal_parts(duration: f64, count: usize) -> SplitPlan {
+    let length = duration / count as f64;
+    (0..count)
+        .map(|i| (i as f64 * length, if i + 1 == count { duration } else { (i + 1) as f64 * length }))
+        .collect()
+}
+
+/// Consecutive chapters grouped into parts that each stay under `part_limit` bytes at the
+/// file's average bitrate. `None` when a single chapter is already too large.
+fn chapter_parts(chapters: &[Chapter], duration: f64, bytes_per_sec: f64, part_limit: f64) -> Option<SplitPlan> {
+    let mut parts: SplitPlan = Vec::new();
+    let mut current: Option<(f64, f64)> = None;
+    for chapter in chapters {
+        let end = chapter.end_time.min(duration);
+        if end <= chapter.start_time {
+            continue;
+        }
+        if (end - chapter.start_time) * bytes_per_sec > part_limit {
+            return None;
+        }
+        current = match current {
+            Some((start, _)) if (end - start) * bytes_per_sec <= part_limit => Some((start, end)),
+            Some(part) => {
+                parts.push(part);
+                Some((chapter.start_time, end))
+            }
+            None => Some((chapter.start_time, end)),
+        };
+    }
+    parts.extend(current);
+    // Chapters that don't cover the whole video can't be trusted for splitting.
+    let covers = parts.first().is_some_and(|p| p.0 <= 1.0) && parts.last().is_some_and(|p| p.1 >= duration - 1.0);
+    covers.then_some(parts)
+}
+
+/// Splits a `size` bytes, `duration` seconds video into parts below `limit` bytes: along the
+/// chapters when they allow it, otherwise into `min_parts` or more equal segments.
+pub fn plan_split(duration: f64, size: u64, limit: u64, chapters: &[Chapter], min_parts: usize) -> SplitPlan {
+    let part_limit = limit as f64 * PART_FILL;
+    if !chapters.is_empty()
+        && duration > 0.0
+        && let Some(parts) = chapter_parts(chapters, duration, size as f64 / duration, part_limit)
+        && parts.len() >= min_parts
+    {
+        return parts;
+    }
+    let count = ((size as f64 / part_limit).ceil() as usize).max(min_parts).max(1);
+    equal_parts(duration, count)
+}
+
+/// ffmpeg arguments that copy `[start, end)` of `input` into `output`. Seeking before the
+/// input snaps the cut to the previous keyframe, so no re-encode is needed.
+pub fn build_part_args(input: &Path, output: &Path, start: f64, end: f64) -> Vec<String> {
+    vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-ss".into(),
+        format!("{:.3}", start),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-t".into(),
+        format!("{:.3}", end - start),
+        "-map".into(),
+        "0".into(),
+        "-c".into(),
+        "copy".into(),
+        "-avoid_negative_ts".into(),
+        "make_zero".into(),
+        "-movflags".into(),
+        "+faststart".into(),
+        output.to_string_lossy().into_owned(),
+    ]
+}
+
+impl YoutubeFetcher {
+    /// Whether a video over `limit` bytes is better split than compressed: compression would
+    /// have to scale it below a watchable resolution, or it can't fit at all.
+    pub async fn should_split(&self, input: &Path, limit: u64) -> bool {
+        match get_video_metadata(self.ffprobe_path().to_string_lossy().as_ref(), input).await {
+            Ok(metadata) => plan_transcode(limit, metadata.duration, metadata.height).is_none_or(|p| !p.is_reasonable()),
+            Err(e) => {
+                log::warn!("Could not probe {:?} ({}); compressing instead of splitting", input, e);
+                false
+            }
+        }
+    }
+
+    /// Cuts the video at `input` into parts of at most `limit` bytes, following `chapters`
+    /// where possible. Progress is reported between 80% and 85%. The caller cleans up the
+    /// returned files.
+    pub async fn split_video(
+        &self,
+        input: &Path,
+        limit: u64,
+        chapters: &[Chapter],
+        progress_bar: &mut ProgressBar,
+    ) -> Result<Vec<PathBuf>> {
+        let metadata = get_video_metadata(self.ffprobe_path().to_string_lossy().as_ref(), input)
+            .await
+            .map_err(|e| anyhow::anyhow!("ffprobe failed: {}", e))?;
+        let size = tokio::fs::metadata(input).await?.len();
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+
+        let mut min_parts = 1;
+        loop {
+            let plan = plan_split(metadata.duration, size, limit, chapters, min_parts);
+            if plan.len() > MAX_PARTS {
+                return Err(anyhow::anyhow!("Video would need more than {} parts", MAX_PARTS));
+            }
+            log::info!("Splitting {:?} ({:.0}s, {} bytes) into {} parts", input, metadata.duration, size, plan.len());
+
+            let mut guards = Vec::with_capacity(plan.len());
+            let mut parts = Vec::with_capacity(plan.len());
+            for (idx, (start, end)) in plan.iter().enumerate() {
+                let pct = 80 + (idx * 5 / plan.len()) as u8;
+                let info = format!("✂️ Splitting into parts {}/{}", idx + 1, plan.len());
+                progress_bar.update(pct, Some(&info)).await?;
+
+                let output = self.output_dir.join(format!("{}_part{:02}.mp4", stem, idx + 1));
+                guards.push(TempFileGuard::new(output.clone()));
+                let args = build_part_args(input, &output, *start, *end);
+                let out = Command::new(self.ffmpeg_path()).args(&args).kill_on_drop(true).output().await?;
+                if !out.status.success() {
+                    let stderr = String::from_utf8_lossy(&out.stderr);
+                    return Err(anyhow::anyhow!("ffmpeg split failed: {}", stderr.trim()));
+                }
+                parts.push(output);
+            }
+
+            // Bitrate peaks can still push a part over the limit; retry with more, shorter parts.
+            let mut oversized = false;
+            for part in &parts {
+                oversized |= tokio::fs::metadata(part).await?.len() > limit;
+            }
+            if !oversized {
+                guards.iter_mut().for_each(TempFileGuard::forget);
+                return Ok(parts);
+            }
+            min_parts = plan.len() + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn chapter(start: f64, end: f64) -> Chapter {
+        Chapter { start_time: start, end_time: end, title: None }
+    }
+
+    #[test]
+    fn test_equal_parts_without_chapters() {
+        // 100 MB into 48 MB parts (43.2 MB planned): 3 parts of 200s.
+        let plan = plan_split(600.0, 100 * MB, 48 * MB, &[], 1);
+        assert_eq!(plan, vec![(0.0, 200.0), (200.0, 400.0), (400.0, 600.0)]);
+        assert_eq!(plan_split(600.0, 100 * MB, 48 * MB, &[], 5).len(), 5);
+    }
+
+    #[test]
+    fn test_parts_follow_chapters() {
+        // 1 MB per 10 seconds; parts hold up to 43.2 MB, i.e. 432 seconds.
+        let chapters = [chapter(0.0, 200.0), chapter(200.0, 400.0), chapter(400.0, 600.0), chapter(600.0, 1000.0)];
+        let plan = plan_split(1000.0, 100 * MB, 48 * MB, &chapters, 1);
+        assert_eq!(plan, vec![(0.0, 400.0), (400.0, 600.0), (600.0, 1000.0)]);
+    }
+
+    #[test]
+    fn test_oversized_chapter_falls_back_to_equal_parts() {
+        let chapters = [chapter(0.0, 100.0), chapter(100.0, 1000.0)];
+        let plan = plan_split(1000.0, 100 * MB, 48 * MB, &chapters, 1);
+        assert_eq!(plan.len(), 3);
+        assert_eq!(plan[0], (0.0, 1000.0 / 3.0));
+    }
+
+    #[test]
+    fn test_part_args_copy_streams() {
+        let args = build_part_args(Path::new("in.mp4"), Path::new("out.mp4"), 10.0, 70.5);
+        assert!(args.windows(2).any(|w| w == ["-ss", "10.000"]));
+        assert!(args.windows(2).any(|w| w == ["-t", "60.500"]));
+        assert!(args.windows(2).any(|w| w == ["-c", "copy"]));
+    }
+}
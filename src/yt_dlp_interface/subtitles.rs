@@ -0,0 +1,298 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::platforms;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+/// Subtitles are looked up in the user's language first, then in this one.
+pub const FALLBACK_SUBTITLE_LANGUAGE: &str = "en";
+
+/// How subtitles are delivered with a video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SubtitleMode {
+    #[default]
+    Off,
+    /// An .srt document sent right after the video.
+    File,
+    /// Rendered into the picture with ffmpeg's subtitles filter.
+    Burn,
+}
+
+impl SubtitleMode {
+    pub const ALL: [SubtitleMode; 3] = [SubtitleMode::Off, SubtitleMode::File, SubtitleMode::Burn];
+
+    /// Value stored in `users.subtitles`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            SubtitleMode::Off => "off",
+            SubtitleMode::File => "srt",
+            SubtitleMode::Burn => "burn",
+        }
+    }
+
+    /// Parses a [`SubtitleMode::id`]; unknown values turn subtitles off.
+    pub fn from_id(id: &str) -> Self {
+        Self::ALL.into_iter().find(|m| m.id() == id).unwrap_or_default()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubtitleMode::Off => "Off",
+            SubtitleMode::File => ".srt file",
+            SubtitleMode::Burn => "Burned in",
+        }
+    }
+
+    /// The mode after this one in the settings button's cycle.
+    pub fn next(&self) -> Self {
+        match self {
+            SubtitleMode::Off => SubtitleMode::File,
+            SubtitleMode::File => SubtitleMode::Burn,
+            SubtitleMode::Burn => SubtitleMode::Off,
+        }
+    }
+}
+
+/// A subtitle track yt-dlp can fetch for a post.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleTrack {
+    /// Language key as yt-dlp lists it, e.g. "pt-BR".
+    pub lang: String,
+    /// Auto-generated captions rather than subtitles uploaded by the author.
+    pub automatic: bool,
+}
+
+/// "pt-BR" -> "pt", "en_US" -> "en".
+fn base_language(lang: &str) -> String {
+    lang.split(['-', '_']).next().unwrap_or(lang).to_lowercase()
+}
+
+/// The best entry of `available` for `wanted`: the exact language, else one of the same base
+/// language (YouTube's original-language captions are listed as e.g. "en-orig"). yt-dlp's
+/// live chat "subtitles" are never picked.
+fn find_language<'a>(wanted: &str, available: &[&'a str]) -> Option<&'a str> {
+    let usable: Vec<&str> = available.iter().copied().filter(|lang| *lang != "live_chat").collect();
+    usable
+        .iter()
+        .find(|lang| lang.eq_ignore_ascii_case(wanted))
+        .or_else(|| usable.iter().find(|lang| base_language(lang) == base_language(wanted)))
+        .copied()
+}
+
+/// Picks the subtitle track for a user whose Telegram language is `preferred`: the
+/// preferred language, author subtitles before auto-captions, then English the same way.
+pub fn select_subtitle_track(preferred: Option<&str>, manual: &[&str], automatic: &[&str]) -> Option<SubtitleTrack> {
+    let wanted = preferred.map(str::trim).filter(|l| !l.is_empty()).into_iter().chain([FALLBACK_SUBTITLE_LANGUAGE]);
+    for lang in wanted {
+        for (available, automatic) in [(manual, false), (automatic, true)] {
+            if let Some(found) = find_language(lang, available) {
+                return Some(SubtitleTrack { lang: found.to_string(), automatic });
+            }
+        }
+    }
+    None
+}
+
+/// Language keys of a `subtitles` / `automatic_captions` object of yt-dlp's info JSON.
+fn language_keys(info: &serde_json::Value, field: &str) -> Vec<String> {
+    info.get(field)
+        .and_then(|v| v.as_object())
+        .map(|tracks| tracks.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// yt-dlp arguments that fetch only `track` of the post as SRT.
+pub fn build_subtitle_args(track: &SubtitleTrack, output_template: &Path) -> Vec<String> {
+    vec![
+        "--skip-download".into(),
+        if track.automatic { "--write-auto-subs" } else { "--write-subs" }.into(),
+        "--sub-langs".into(),
+        track.lang.clone(),
+        "--sub-format".into(),
+        "srt/vtt/best".into(),
+        "--convert-subs".into(),
+        "srt".into(),
+        "--output".into(),
+        output_template.to_string_lossy().into_owned(),
+    ]
+}
+
+/// Escapes a path for use inside an ffmpeg filter argument, where `\`, `'`, `:` and `,`
+/// have a meaning of their own.
+pub fn escape_filter_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        if matches!(c, '\\' | '\'' | ':' | ',' | '[' | ']' | ';') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// ffmpeg arguments that render `subtitles` into the video of `input`.
+pub fn build_burn_args(input: &Path, subtitles: &Path, output: &Path) -> Vec<String> {
+    vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-vf".into(),
+        format!("subtitles={}", escape_filter_path(subtitles)),
+        "-c:v".into(),
+        "libx264".into(),
+        "-preset".into(),
+        "veryfast".into(),
+        "-crf".into(),
+        "23".into(),
+        "-pix_fmt".into(),
+        "yuv420p".into(),
+        "-c:a".into(),
+        "copy".into(),
+        "-movflags".into(),
+        "+faststart".into(),
+        output.to_string_lossy().into_owned(),
+    ]
+}
+
+impl YoutubeFetcher {
+    /// Downloads the post's subtitles in `preferred` language (or English) as an .srt file
+    /// next to the download. `Ok(None)` when the post has no subtitles in either language.
+    /// The caller cleans up the returned file.
+    pub async fn fetch_subtitles(
+        &self,
+        url: &str,
+        filename_stem: &str,
+        preferred: Option<&str>,
+        fingerprint: Option<String>,
+    ) -> Result<Option<PathBuf>> {
+        let info = self.probe_info(url, fingerprint.clone()).await?;
+        let manual = language_keys(&info, "subtitles");
+        let automatic = language_keys(&info, "automatic_captions");
+        let manual: Vec<&str> = manual.iter().map(String::as_str).collect();
+        let automatic: Vec<&str> = automatic.iter().map(String::as_str).collect();
+        let Some(track) = select_subtitle_track(preferred, &manual, &automatic) else {
+            log::info!("No subtitles in {} or {} for {}", preferred.unwrap_or("-"), FALLBACK_SUBTITLE_LANGUAGE, url);
+            return Ok(None);
+        };
+
+        let stem = format!("{}_subs", filename_stem);
+        let output = self.output_dir.join(format!("{}.{}.srt", stem, track.lang));
+        let mut guard = TempFileGuard::new(output.clone());
+
+        let mut cmd = Command::new(&self.yt_dlp_path);
+        cmd.kill_on_drop(true);
+        if let Some(platform) = platforms::registry().for_url(url) {
+            cmd.args(platform.yt_dlp_args());
+        }
+        cmd.args(build_subtitle_args(&track, &self.output_dir.join(format!("{}.%(ext)s", stem))))
+            .arg("--ffmpeg-location")
+            .arg(&self.ffmpeg_dir)
+            .arg("--no-warnings");
+        if let Some(fp) = fingerprint {
+            cmd.arg(format!("--impersonate={}", fp));
+        }
+        cmd.arg(url);
+
+        let out = tokio::time::timeout(std::time::Duration::from_secs(60), cmd.output())
+            .await
+            .map_err(|_| anyhow::anyhow!("yt-dlp subtitle download timed out for {}", url))??;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("yt-dlp subtitle download failed: {}", stderr.trim()));
+        }
+        if !output.exists() {
+            return Err(anyhow::anyhow!("yt-dlp wrote no {} subtitles", track.lang));
+        }
+        guard.forget();
+        Ok(Some(output))
+    }
+
+    /// Renders `subtitles` into the video at `input`. The caller cleans up the returned file.
+    pub async fn burn_subtitles(&self, input: &Path, subtitles: &Path) -> Result<PathBuf> {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let output = input.with_file_name(format!("{}_subbed.mp4", stem));
+        let mut guard = TempFileGuard::new(output.clone());
+
+        let args = build_burn_args(input, subtitles, &output);
+        let out = Command::new(self.ffmpeg_path()).args(&args).kill_on_drop(true).output().await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg subtitle burn failed: {}", stderr.trim()));
+        }
+        guard.forget();
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(lang: &str, automatic: bool) -> Option<SubtitleTrack> {
+        Some(SubtitleTrack { lang: lang.to_string(), automatic })
+    }
+
+    #[test]
+    fn test_exact_language_wins() {
+        let manual = ["en", "pt-BR", "pt-PT"];
+        assert_eq!(select_subtitle_track(Some("pt-BR"), &manual, &[]), track("pt-BR", false));
+        assert_eq!(select_subtitle_track(Some("PT-pt"), &manual, &[]), track("pt-PT", false));
+    }
+
+    #[test]
+    fn test_base_language_matches_regional_tracks() {
+        assert_eq!(select_subtitle_track(Some("de"), &["en", "de-DE"], &[]), track("de-DE", false));
+        assert_eq!(select_subtitle_track(Some("es-MX"), &["es"], &[]), track("es", false));
+    }
+
+    #[test]
+    fn test_author_subtitles_before_auto_captions() {
+        assert_eq!(select_subtitle_track(Some("fr"), &["fr"], &["fr", "en"]), track("fr", false));
+        // Auto-captions in the user's language still beat English subtitles.
+        assert_eq!(select_subtitle_track(Some("fr"), &["en"], &["fr", "en"]), track("fr", true));
+    }
+
+    #[test]
+    fn test_falls_back_to_english() {
+        assert_eq!(select_subtitle_track(Some("uk"), &["en", "de"], &[]), track("en", false));
+        assert_eq!(select_subtitle_track(Some("uk"), &[], &["en-orig"]), track("en-orig", true));
+        assert_eq!(select_subtitle_track(None, &["de", "en"], &[]), track("en", false));
+        assert_eq!(select_subtitle_track(Some(""), &["en"], &[]), track("en", false));
+    }
+
+    #[test]
+    fn test_no_usable_track() {
+        assert_eq!(select_subtitle_track(Some("ru"), &["de"], &["ja"]), None);
+        assert_eq!(select_subtitle_track(Some("en"), &["live_chat"], &[]), None);
+    }
+
+    #[test]
+    fn test_subtitle_args() {
+        let args = build_subtitle_args(&SubtitleTrack { lang: "en".to_string(), automatic: true }, Path::new("o/x.%(ext)s"));
+        assert!(args.contains(&"--write-auto-subs".to_string()));
+        assert!(args.windows(2).any(|w| w == ["--sub-langs", "en"]));
+        assert!(args.windows(2).any(|w| w == ["--convert-subs", "srt"]));
+    }
+
+    #[test]
+    fn test_burn_args_escape_the_path() {
+        assert_eq!(escape_filter_path(Path::new("C:\\out\\it's.srt")), "C\\:\\\\out\\\\it\\'s.srt");
+        let args = build_burn_args(Path::new("in.mp4"), Path::new("o/a.en.srt"), Path::new("out.mp4"));
+        assert!(args.windows(2).any(|w| w == ["-vf", "subtitles=o/a.en.srt"]));
+    }
+
+    #[test]
+    fn test_mode_ids() {
+        for mode in SubtitleMode::ALL {
+            assert_eq!(SubtitleMode::from_id(mode.id()), mode);
+        }
+        assert_eq!(SubtitleMode::from_id("bogus"), SubtitleMode::Off);
+        assert_eq!(SubtitleMode::Burn.next(), SubtitleMode::Off);
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::mtproto_uploader::metadata::get_video_metadata;
+use crate::utils::progress_bar::ProgressBar;
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+/// Audio bitrate of compressed videos, in bits per second.
+const AUDIO_BITRATE: u64 = 128_000;
+/// Share of the size budget kept free for the MP4 container and rate control overshoot.
+const SIZE_MARGIN: f64 = 0.05;
+/// Resolutions a video may be scaled down to, with the lowest video bitrate (bit/s) each
+/// one still looks acceptable at. Largest first.
+const LADDER: &[(u32, u64)] = &[
+    (2160, 8_000_000),
+    (1440, 4_000_000),
+    (1080, 2_000_000),
+    (720, 1_000_000),
+    (480, 500_000),
+    (360, 300_000),
+    (240, 100_000),
+];
+/// Below this there is no point in encoding, the video would be unwatchable.
+const MIN_VIDEO_BITRATE: u64 = 50_000;
+/// Users who split long videos get parts instead of a compressed video below this height.
+const MIN_REASONABLE_HEIGHT: u32 = 480;
+
+/// How a video is re-encoded to fit under a size limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TranscodePlan {
+    /// Target video bitrate in bits per second.
+    pub video_bitrate: u64,
+    /// Output height, when the video has to be scaled down.
+    pub scale_height: Option<u32>,
+}
+
+impl TranscodePlan {
+    /// Whether the compressed video still looks decent: it isn't scaled below 480p.
+    pub fn is_reasonable(&self) -> bool {
+        self.scale_height.is_none_or(|h| h >= MIN_REASONABLE_HEIGHT)
+    }
+}
+
+/// Picks the video bitrate that lands the file under `limit` bytes, and the largest resolution
+/// that still looks fine at that bitrate. `None` when the video is too long to fit at all.
+pub fn plan_transcode(limit: u64, duration: f64, height: u32) -> Option<TranscodePlan> {
+    if duration <= 0.0 {
+        return None;
+    }
+    let budget_bits = limit as f64 * 8.0 * (1.0 - SIZE_MARGIN);
+    let video_bitrate = (budget_bits / duration) as u64;
+    let video_bitrate = video_bitrate.checked_sub(AUDIO_BITRATE).filter(|b| *b >= MIN_VIDEO_BITRATE)?;
+
+    let rung = LADDER
+        .iter()
+        .find(|(h, min)| *h <= height && *min <= video_bitrate)
+        .map(|(h, _)| *h)
+        .unwrap_or(LADDER[LADDER.len() - 1].0);
+    let scale_height = (rung < height).then_some(rung);
+    Some(TranscodePlan { video_bitrate, scale_height })
+}
+
+/// ffmpeg arguments for one pass of the two-pass encode. The first pass only writes the
+/// rate control log to `passlog`, so its output goes to the null device.
+pub fn build_transcode_args(input: &Path, output: &Path, passlog: &Path, plan: &TranscodePlan, pass: u8) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        // stderr is only read after exit, keep it small.
+        "-loglevel".into(),
+        "error".into(),
+        "-nostats".into(),
+        "-progress".into(),
+        "pipe:1".into(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-c:v".into(),
+        "libx264".into(),
+        "-preset".into(),
+        "medium".into(),
+        "-b:v".into(),
+        plan.video_bitrate.to_string(),
+        "-pix_fmt".into(),
+        "yuv420p".into(),
+        "-pass".into(),
+        pass.to_string(),
+        "-passlogfile".into(),
+        passlog.to_string_lossy().into_owned(),
+    ];
+    if let Some(height) = plan.scale_height {
+        args.push("-vf".into());
+        args.push(format!("scale=-2:{}", height));
+    }
+    if pass == 1 {
+        args.extend(["-an".into(), "-f".into(), "mp4".into()]);
+        args.push(if cfg!(target_os = "windows") { "NUL" } else { "/dev/null" }.into());
+    } else {
+        args.extend([
+            "-c:a".into(),
+            "aac".into(),
+            "-b:a".into(),
+            AUDIO_BITRATE.to_string(),
+            "-movflags".into(),
+            "+faststart".into(),
+        ]);
+        args.push(output.to_string_lossy().into_owned());
+    }
+    args
+}
+
+/// Position in seconds from a `-progress` line (`out_time_us=...`; `out_time_ms` is also
+/// in microseconds despite its name).
+pub fn parse_progress_seconds(line: &str) -> Option<f64> {
+    let (key, value) = line.trim().split_once('=')?;
+    if key != "out_time_us" && key != "out_time_ms" {
+        return None;
+    }
+    let micros: i64 = value.trim().parse().ok()?;
+    Some(micros.max(0) as f64 / 1_000_000.0)
+}
+
+impl YoutubeFetcher {
+    /// Re-encodes the video at `input` so that it fits in `limit` bytes: a two-pass H.264
+    /// encode at the bitrate the duration allows, scaled down when that bitrate is too low
+    /// for the source resolution. Progress is reported between 80% and 85%.
+    pub async fn fit_to_size(&self, input: &Path, limit: u64, progress_bar: &mut ProgressBar) -> Result<PathBuf> {
+        let metadata = get_video_metadata(self.ffprobe_path().to_string_lossy().as_ref(), input)
+            .await
+            .map_err(|e| anyhow::anyhow!("ffprobe failed: {}", e))?;
+        let plan = plan_transcode(limit, metadata.duration, metadata.height)
+            .ok_or_else(|| anyhow::anyhow!("Video is too long to fit in {} MB", limit / 1_048_576))?;
+        log::info!(
+            "Compressing {:?} ({:.0}s, {}p) to {} kbit/s{}",
+            input,
+            metadata.duration,
+            metadata.height,
+            plan.video_bitrate / 1000,
+            plan.scale_height.map(|h| format!(" at {}p", h)).unwrap_or_default()
+        );
+
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let output = self.output_dir.join(format!("{}_compressed.mp4", stem));
+        let passlog = self.output_dir.join(format!("{}_2pass", stem));
+        let mut guard = TempFileGuard::new(output.clone());
+        let _log_guards = [
+            TempFileGuard::new(PathBuf::from(format!("{}-0.log", passlog.display()))),
+            TempFileGuard::new(PathBuf::from(format!("{}-0.log.mbtree", passlog.display()))),
+        ];
+
+        for pass in 1..=2u8 {
+            let args = build_transcode_args(input, &output, &passlog, &plan, pass);
+            self.run_ffmpeg_with_progress(&args, metadata.duration, pass, progress_bar).await?;
+        }
+
+        let size = tokio::fs::metadata(&output).await?.len();
+        if size > limit {
+            return Err(anyhow::anyhow!("Compressed file is still {:.1} MB", size as f64 / 1_048_576.0));
+        }
+        guard.forget();
+        Ok(output)
+    }
+
+    async fn run_ffmpeg_with_progress(
+        &self,
+        args: &[String],
+        duration: f64,
+        pass: u8,
+        progress_bar: &mut ProgressBar,
+    ) -> Result<()> {
+        let mut child = Command::new(self.ffmpeg_path())
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout not captured");
+        let mut lines = BufReader::new(stdout).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(seconds) = parse_progress_seconds(&line) {
+                let done = (seconds / duration).clamp(0.0, 1.0);
+                // Pass 1 covers 80-82%, pass 2 82-85%.
+                let overall = if pass == 1 { 80.0 + done * 2.0 } else { 82.0 + done * 3.0 };
+                let info = format!("🗜 Compressing (pass {}/2): {:.0}%", pass, done * 100.0);
+                progress_bar.update(overall.round() as u8, Some(&info)).await?;
+            }
+        }
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let tail: Vec<&str> = stderr.lines().rev().take(5).collect();
+            return Err(anyhow::anyhow!("ffmpeg pass {} failed: {}", pass, tail.into_iter().rev().collect::<Vec<_>>().join("\n")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_plan_keeps_resolution_when_bitrate_allows() {
+        // 10 minutes into 2000 MB leaves plenty of bitrate for 1080p.
+        let plan = plan_transcode(2000 * MB, 600.0, 1080).unwrap();
+        assert_eq!(plan.scale_height, None);
+        assert!(plan.video_bitrate > 20_000_000);
+    }
+
+    #[test]
+    fn test_plan_downscales_low_bitrates() {
+        // 10 minutes into 48 MB: ~540 kbit/s video, enough for 480p only.
+        let plan = plan_transcode(48 * MB, 600.0, 1080).unwrap();
+        assert_eq!(plan.scale_height, Some(480));
+        let total_bits = (plan.video_bitrate + AUDIO_BITRATE) as f64 * 600.0;
+        assert!(total_bits / 8.0 < 48.0 * MB as f64);
+
+        // Never scaled up.
+        assert_eq!(plan_transcode(48 * MB, 600.0, 360).unwrap().scale_height, None);
+
+        assert!(plan.is_reasonable());
+        assert!(!plan_transcode(48 * MB, 1800.0, 1080).unwrap().is_reasonable());
+    }
+
+    #[test]
+    fn test_plan_rejects_videos_that_cannot_fit() {
+        assert_eq!(plan_transcode(48 * MB, 3.0 * 3600.0, 1080), None);
+        assert_eq!(plan_transcode(48 * MB, 0.0, 1080), None);
+    }
+
+    #[test]
+    fn test_transcode_args() {
+        let plan = TranscodePlan { video_bitrate: 500_000, scale_height: Some(480) };
+        let first = build_transcode_args(Path::new("in.mp4"), Path::new("out.mp4"), Path::new("log"), &plan, 1);
+        assert!(first.windows(2).any(|w| w == ["-pass", "1"]));
+        assert!(first.contains(&"-an".to_string()));
+        assert!(!first.contains(&"out.mp4".to_string()));
+
+        let second = build_transcode_args(Path::new("in.mp4"), Path::new("out.mp4"), Path::new("log"), &plan, 2);
+        assert!(second.windows(2).any(|w| w == ["-vf", "scale=-2:480"]));
+        assert!(second.windows(2).any(|w| w == ["-b:v", "500000"]));
+        assert_eq!(second.last().map(String::as_str), Some("out.mp4"));
+    }
+
+    #[test]
+    fn test_parse_progress_seconds() {
+        assert_eq!(parse_progress_seconds("out_time_us=12500000"), Some(12.5));
+        assert_eq!(parse_progress_seconds("out_time_ms=1000000"), Some(1.0));
+        assert_eq!(parse_progress_seconds("out_time=00:00:12.500000"), None);
+        assert_eq!(parse_progress_seconds("out_time_us=N/A"), None);
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+/// Post metadata used for captions: the subset of yt-dlp's info JSON (`--write-info-json`)
+/// that the bot shows. Every field is optional since extractors fill in different ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoInfo {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Display name of the author.
+    pub uploader: Option<String>,
+    /// Handle of the author (`@name` without the `@` on TikTok).
+    pub uploader_id: Option<String>,
+    pub channel: Option<String>,
+    pub track: Option<String>,
+    pub artist: Option<String>,
+    pub artists: Option<Vec<String>>,
+    pub view_count: Option<u64>,
+    pub like_count: Option<u64>,
+    pub comment_count: Option<u64>,
+    pub tags: Option<Vec<String>>,
+    pub webpage_url: Option<String>,
+    /// Cover image URL (video thumbnail, or the album cover of the post's music on tikwm).
+    pub thumbnail: Option<String>,
+    /// Chapters of long videos (YouTube); long videos are split along them.
+    pub chapters: Option<Vec<Chapter>>,
+}
+
+/// One chapter of a video, in seconds from the start.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Chapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: Option<String>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|s| !s.is_empty())
+}
+
+lazy_static::lazy_static! {
+    static ref HASHTAG: regex::Regex = regex::Regex::new(r"#[\p{L}\p{N}_]+").unwrap();
+}
+
+impl VideoInfo {
+    /// Parses the contents of a `.info.json` file.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Builds the info from a tikwm `data` object (slideshows and tikwm fallbacks).
+    pub fn from_tikwm(data: &serde_json::Value) -> Self {
+        let str_at = |ptr: &str| data.pointer(ptr).and_then(|v| v.as_str()).map(|s| s.to_string());
+        let count_at = |ptr: &str| data.pointer(ptr).and_then(|v| v.as_u64());
+        Self {
+            title: str_at("/title"),
+            description: str_at("/title"),
+            uploader: str_at("/author/nickname"),
+            uploader_id: str_at("/author/unique_id"),
+            track: str_at("/music_info/title"),
+            artist: str_at("/music_info/author"),
+            view_count: count_at("/play_count"),
+            like_count: count_at("/digg_count"),
+            comment_count: count_at("/comment_count"),
+            thumbnail: str_at("/music_info/cover").or_else(|| str_at("/cover")),
+            ..Default::default()
+        }
+    }
+
+    /// `Name (@handle)`, whichever parts are known.
+    pub fn author(&self) -> Option<String> {
+        let name = non_empty(&self.uploader).or_else(|| non_empty(&self.channel));
+        let handle = non_empty(&self.uploader_id)
+            .filter(|id| !id.starts_with("UC") || id.len() != 24) // YouTube channel ids aren't handles
+            .map(|id| format!("@{}", id.trim_start_matches('@')));
+        match (name, handle) {
+            (Some(name), Some(handle)) if handle[1..] != *name => Some(format!("{} ({})", name, handle)),
+            (Some(name), _) => Some(name.to_string()),
+            (None, handle) => handle,
+        }
+    }
+
+    /// `Artist - Track`, or just the track title.
+    pub fn music(&self) -> Option<String> {
+        let track = non_empty(&self.track)?;
+        let artist = non_empty(&self.artist)
+            .map(|a| a.to_string())
+            .or_else(|| self.artists.as_ref().filter(|a| !a.is_empty()).map(|a| a.join(", ")));
+        Some(match artist {
+            Some(artist) => format!("{} - {}", artist, track),
+            None => track.to_string(),
+        })
+    }
+
+    /// Track name for audio players: the music track, else the post title.
+    pub fn audio_title(&self) -> Option<String> {
+        non_empty(&self.track).or_else(|| non_empty(&self.title)).map(|s| s.to_string())
+    }
+
+    /// Performer for audio players: the music's artist, else the author.
+    pub fn audio_performer(&self) -> Option<String> {
+        non_empty(&self.artist)
+            .map(|a| a.to_string())
+            .or_else(|| self.artists.as_ref().filter(|a| !a.is_empty()).map(|a| a.join(", ")))
+            .or_else(|| non_empty(&self.uploader).or_else(|| non_empty(&self.channel)).map(|s| s.to_string()))
+    }
+
+    /// Description (or title when there is none) with its hashtags removed; they are
+    /// listed separately by [`VideoInfo::hashtags`].
+    pub fn description_text(&self) -> Option<String> {
+        let text = non_empty(&self.description).or_else(|| non_empty(&self.title))?;
+        let stripped = HASHTAG.replace_all(text, "");
+        let lines: Vec<&str> = stripped
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        let joined = lines
+            .iter()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        (!joined.is_empty()).then_some(joined)
+    }
+
+    /// Hashtags from the description followed by the post's tags, without duplicates.
+    pub fn hashtags(&self) -> Vec<String> {
+        let text = non_empty(&self.description).or_else(|| non_empty(&self.title)).unwrap_or_default();
+        let from_text = HASHTAG.find_iter(text).map(|m| m.as_str().to_string());
+        let from_tags = self
+            .tags
+            .iter()
+            .flatten()
+            .map(|t| t.trim().replace(' ', ""))
+            .filter(|t| !t.is_empty())
+            .map(|t| format!("#{}", t.trim_start_matches('#')));
+
+        let mut seen = std::collections::HashSet::new();
+        from_text
+            .chain(from_tags)
+            .filter(|tag| seen.insert(tag.to_lowercase()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ytdlp_info() {
+        let info = VideoInfo::from_json(r##"{
+            "id": "7", "title": "dance #fyp", "description": "new dance\n#fyp #Dance",
+            "uploader": "Ann", "uploader_id": "ann.dances", "track": "Song", "artists": ["DJ"],
+            "view_count": 1200, "like_count": 30, "comment_count": null, "tags": null,
+            "formats": [{"format_id": "x"}],
+            "chapters": [{"start_time": 0.0, "end_time": 60.5, "title": "Intro"}]
+        }"##).unwrap();
+        assert_eq!(info.author().as_deref(), Some("Ann (@ann.dances)"));
+        assert_eq!(info.music().as_deref(), Some("DJ - Song"));
+        assert_eq!(info.description_text().as_deref(), Some("new dance"));
+        assert_eq!(info.hashtags(), vec!["#fyp", "#Dance"]);
+        assert_eq!(info.comment_count, None);
+        assert_eq!(info.audio_title().as_deref(), Some("Song"));
+        assert_eq!(info.audio_performer().as_deref(), Some("DJ"));
+        assert_eq!(info.chapters.unwrap()[0].end_time, 60.5);
+    }
+
+    #[test]
+    fn test_hashtags_merge_tags_without_duplicates() {
+        let info = VideoInfo {
+            description: Some("hello #Cats".to_string()),
+            tags: Some(vec!["cats".to_string(), "funny animals".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(info.hashtags(), vec!["#Cats", "#funnyanimals"]);
+    }
+
+    #[test]
+    fn test_from_tikwm() {
+        let data = serde_json::json!({
+            "title": "photo dump #summer",
+            "author": { "unique_id": "ann", "nickname": "Ann" },
+            "music_info": { "title": "original sound", "author": "Ann" },
+            "play_count": 10, "digg_count": 2
+        });
+        let info = VideoInfo::from_tikwm(&data);
+        assert_eq!(info.author().as_deref(), Some("Ann (@ann)"));
+        assert_eq!(info.music().as_deref(), Some("Ann - original sound"));
+        assert_eq!((info.view_count, info.like_count), (Some(10), Some(2)));
+        assert_eq!(info.audio_title().as_deref(), Some("original sound"));
+        assert_eq!(info.audio_performer().as_deref(), Some("Ann"));
+    }
+}
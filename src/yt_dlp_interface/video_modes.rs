@@ -0,0 +1,152 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::utils::temp_file::TempFileGuard;
+use crate::yt_dlp_interface::fetcher::YoutubeFetcher;
+
+/// Quality preference that delivers links as round video notes.
+pub const QUALITY_VIDEO_NOTE: &str = "video_note";
+/// Quality preference that delivers links as silent looping animations.
+pub const QUALITY_ANIMATION: &str = "animation";
+
+/// Telegram plays video notes up to 640x640 and one minute long.
+pub const VIDEO_NOTE_MAX_SIZE: u32 = 640;
+pub const VIDEO_NOTE_MAX_SECONDS: u32 = 60;
+
+/// Output modes that re-encode the downloaded video into another kind of Telegram message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoMode {
+    VideoNote,
+    Animation,
+}
+
+impl VideoMode {
+    pub fn from_quality(quality: &str) -> Option<Self> {
+        match quality {
+            QUALITY_VIDEO_NOTE => Some(VideoMode::VideoNote),
+            QUALITY_ANIMATION => Some(VideoMode::Animation),
+            _ => None,
+        }
+    }
+
+    /// media_cache media_type of the delivered message.
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            VideoMode::VideoNote => "video_note",
+            VideoMode::Animation => "animation",
+        }
+    }
+}
+
+/// What yt-dlp downloads for a quality preference. Video notes and animations are
+/// re-encoded anyway, so they start from the H.264 copy that every platform has.
+pub fn download_quality(quality: &str) -> &str {
+    if VideoMode::from_quality(quality).is_some() { "h264" } else { quality }
+}
+
+fn output_args(output: &Path) -> Vec<String> {
+    [
+        "-c:v", "libx264", "-preset", "veryfast", "-crf", "26", "-pix_fmt", "yuv420p", "-movflags", "+faststart",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .chain(std::iter::once(output.to_string_lossy().into_owned()))
+    .collect()
+}
+
+/// ffmpeg arguments that crop the video to a centered square of at most 640px, cut after
+/// a minute, for a video note.
+pub fn build_video_note_args(input: &Path, output: &Path) -> Vec<String> {
+    let filter = format!(
+        "crop='min(iw,ih)':'min(iw,ih)',scale='trunc(min({max},iw)/2)*2':'trunc(min({max},ih)/2)*2'",
+        max = VIDEO_NOTE_MAX_SIZE
+    );
+    let mut args: Vec<String> = vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-t".into(),
+        VIDEO_NOTE_MAX_SECONDS.to_string(),
+        "-vf".into(),
+        filter,
+        "-c:a".into(),
+        "aac".into(),
+        "-b:a".into(),
+        "96k".into(),
+    ];
+    args.extend(output_args(output));
+    args
+}
+
+/// ffmpeg arguments that drop the audio track, which makes Telegram treat an MP4 as an
+/// animation (GIF).
+pub fn build_animation_args(input: &Path, output: &Path) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-y".into(),
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-an".into(),
+        "-vf".into(),
+        "scale='trunc(iw/2)*2':'trunc(ih/2)*2'".into(),
+    ];
+    args.extend(output_args(output));
+    args
+}
+
+impl YoutubeFetcher {
+    /// Re-encodes the video at `input` for `mode`. The caller cleans up the returned file.
+    pub async fn apply_video_mode(&self, input: &Path, mode: VideoMode) -> Result<PathBuf> {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let output = input.with_file_name(format!("{}_{}.mp4", stem, mode.media_type()));
+        let mut guard = TempFileGuard::new(output.clone());
+
+        let args = match mode {
+            VideoMode::VideoNote => build_video_note_args(input, &output),
+            VideoMode::Animation => build_animation_args(input, &output),
+        };
+        let out = Command::new(self.ffmpeg_path()).args(&args).kill_on_drop(true).output().await?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(anyhow::anyhow!("ffmpeg {} encode failed: {}", mode.media_type(), stderr.trim()));
+        }
+        guard.forget();
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modes_from_quality() {
+        assert_eq!(VideoMode::from_quality(QUALITY_VIDEO_NOTE), Some(VideoMode::VideoNote));
+        assert_eq!(VideoMode::from_quality(QUALITY_ANIMATION), Some(VideoMode::Animation));
+        assert_eq!(VideoMode::from_quality("h264"), None);
+        assert_eq!(download_quality(QUALITY_ANIMATION), "h264");
+        assert_eq!(download_quality("h265"), "h265");
+    }
+
+    #[test]
+    fn test_video_note_args() {
+        let args = build_video_note_args(Path::new("in.mp4"), Path::new("out.mp4"));
+        assert!(args.windows(2).any(|w| w == ["-t", "60"]));
+        let filter = args.iter().find(|a| a.starts_with("crop=")).unwrap();
+        assert!(filter.contains("min(640,iw)"));
+        assert_eq!(args.last().map(String::as_str), Some("out.mp4"));
+    }
+
+    #[test]
+    fn test_animation_args_drop_audio() {
+        let args = build_animation_args(Path::new("in.mp4"), Path::new("out.mp4"));
+        assert!(args.contains(&"-an".to_string()));
+        assert!(!args.contains(&"aac".to_string()));
+    }
+}
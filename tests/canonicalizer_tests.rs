@@ -0,0 +1,138 @@
+use axum::extract::State;
+use axum::http::{header, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tiktokdownloader::platforms::canonicalizer::Canonicalizer;
+use tiktokdownloader::platforms::{Platform, PlatformRegistry};
+
+#[derive(Deserialize, Clone)]
+struct Fixture {
+    path: String,
+    status: u16,
+    location: String,
+    expected: Option<String>,
+}
+
+struct Stub {
+    base: String,
+    fixtures: Vec<Fixture>,
+    hits: AtomicUsize,
+}
+
+/// Plays back the recorded redirects; `/slow` never answers in time and anything else is a 404.
+async fn serve(State(stub): State<Arc<Stub>>, uri: Uri) -> Response {
+    stub.hits.fetch_add(1, Ordering::SeqCst);
+    if uri.path() == "/slow" {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+    match stub.fixtures.iter().find(|f| f.path == uri.path()) {
+        Some(f) => (
+            StatusCode::from_u16(f.status).unwrap(),
+            [(header::LOCATION, f.location.replace("{base}", &stub.base))],
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn start_stub() -> Arc<Stub> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let fixtures: Vec<Fixture> =
+        serde_json::from_str(include_str!("fixtures/short_link_redirects.json")).unwrap();
+    let stub = Arc::new(Stub { base, fixtures, hits: AtomicUsize::new(0) });
+    let app = Router::new().fallback(serve).with_state(stub.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    stub
+}
+
+/// Stands in for the real shorteners: every link on the stub host is a short link.
+struct StubShortener {
+    pattern: Regex,
+}
+
+impl Platform for StubShortener {
+    fn id(&self) -> &'static str {
+        "stub"
+    }
+
+    fn name(&self) -> &'static str {
+        "Stub shortener"
+    }
+
+    fn url_pattern(&self) -> &Regex {
+        &self.pattern
+    }
+
+    fn is_short_link(&self, _url: &str) -> bool {
+        true
+    }
+}
+
+fn registry_with_stub() -> PlatformRegistry {
+    let mut registry = PlatformRegistry::builtin();
+    registry.register(Box::new(StubShortener { pattern: Regex::new(r"http://127\.0\.0\.1:\d+/[^\s]*").unwrap() }));
+    registry
+}
+
+#[tokio::test]
+async fn test_recorded_short_links_resolve_to_canonical_urls() {
+    let stub = start_stub().await;
+    let registry = registry_with_stub();
+    let canonicalizer = Canonicalizer::new(Duration::from_secs(2), Duration::from_secs(60));
+
+    for fixture in stub.fixtures.iter().filter(|f| f.expected.is_some()) {
+        let short = format!("{}{}", stub.base, fixture.path);
+        let canonical = canonicalizer.canonicalize(&registry, &short).await;
+        assert_eq!(Some(&canonical), fixture.expected.as_ref(), "{}", fixture.path);
+    }
+}
+
+#[tokio::test]
+async fn test_resolved_links_are_cached() {
+    let stub = start_stub().await;
+    let registry = registry_with_stub();
+    let canonicalizer = Canonicalizer::new(Duration::from_secs(2), Duration::from_secs(60));
+    let short = format!("{}/t/ZT8aBcDeF/", stub.base);
+
+    let first = canonicalizer.canonicalize(&registry, &short).await;
+    let hits = stub.hits.load(Ordering::SeqCst);
+    assert_eq!(hits, 2, "two recorded hops");
+
+    let second = canonicalizer.canonicalize(&registry, &short).await;
+    assert_eq!(first, second);
+    assert_eq!(stub.hits.load(Ordering::SeqCst), hits, "second lookup must not hit the network");
+}
+
+#[tokio::test]
+async fn test_unresolvable_links_fall_back_to_themselves() {
+    let stub = start_stub().await;
+    let registry = registry_with_stub();
+    let canonicalizer = Canonicalizer::new(Duration::from_millis(300), Duration::from_secs(60));
+
+    // Dead shortener
+    let dead = format!("{}/gone?x=1", stub.base);
+    assert_eq!(canonicalizer.canonicalize(&registry, &dead).await, format!("{}/gone", stub.base));
+
+    // Slow shortener: the per-hop timeout kicks in
+    let started = std::time::Instant::now();
+    let slow = format!("{}/slow", stub.base);
+    assert_eq!(canonicalizer.canonicalize(&registry, &slow).await, slow);
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_full_links_are_canonicalized_without_requests() {
+    let stub = start_stub().await;
+    let registry = registry_with_stub();
+    let canonicalizer = Canonicalizer::new(Duration::from_secs(2), Duration::from_secs(60));
+
+    let url = canonicalizer.canonicalize(&registry, "https://youtu.be/dQw4w9WgXcQ?si=tracking").await;
+    assert_eq!(url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+    assert_eq!(stub.hits.load(Ordering::SeqCst), 0);
+}
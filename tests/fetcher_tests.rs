@@ -318,3 +318,132 @@ async fn test_fetcher_with_real_ffprobe() {
     
     // The method should handle errors gracefully and return true by default
 }
+
+mod provider_chain {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use tempfile::tempdir;
+    use tiktokdownloader::utils::progress_bar::ProgressBar;
+    use tiktokdownloader::yt_dlp_interface::fetcher::YoutubeFetcher;
+    use tiktokdownloader::yt_dlp_interface::providers::{
+        DownloadJob, DownloadProvider, ProviderFuture, ProviderRegistry, Tikwm, TikwmProvider,
+    };
+
+    const TIKTOK_URL: &str = "https://www.tiktok.com/@user/video/7300000000000000000";
+    const VIDEO_BYTES: &[u8] = b"stub video";
+
+    /// Stands in for yt-dlp: fails every link without touching the network.
+    struct BrokenProvider;
+
+    impl DownloadProvider for BrokenProvider {
+        fn id(&self) -> &'static str {
+            "broken"
+        }
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+        fn supports(&self, _platform_id: &str) -> bool {
+            true
+        }
+        fn has_circuit_breaker(&self) -> bool {
+            false
+        }
+        fn download<'a>(&'a self, _: &'a YoutubeFetcher, _: &'a DownloadJob<'a>, _: &'a mut ProgressBar) -> ProviderFuture<'a> {
+            Box::pin(async { Err(anyhow::anyhow!("ERROR: Unable to extract video data")) })
+        }
+    }
+
+    /// Local tikwm stub counting API calls; answers with `api_status`.
+    async fn start_tikwm_stub(api_status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/api/",
+                get(move |State(hits): State<Arc<AtomicUsize>>| async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    let body = serde_json::json!({ "code": 0, "data": { "play": "", "wmplay": "/media/video.mp4" } });
+                    (api_status, Json(body))
+                }),
+            )
+            .route("/media/video.mp4", get(|| async { VIDEO_BYTES }))
+            .with_state(hits.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), hits)
+    }
+
+    fn fetcher(output_dir: PathBuf, tikwm_url: &str, threshold: u32) -> YoutubeFetcher {
+        let registry = ProviderRegistry::new(vec![Arc::new(BrokenProvider), Arc::new(TikwmProvider)])
+            .with_circuit_breaker(threshold, Duration::from_secs(60));
+        registry.set_order("tiktok", vec!["broken".to_string(), TikwmProvider::ID.to_string()]);
+        YoutubeFetcher::new(PathBuf::from("yt-dlp"), output_dir.clone(), output_dir.join("ffmpeg"))
+            .unwrap()
+            .with_providers(registry)
+            .with_tikwm(Tikwm::new(tikwm_url))
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_tikwm_when_first_provider_fails() {
+        let (url, hits) = start_tikwm_stub(StatusCode::OK).await;
+        let dir = tempdir().unwrap();
+        let fetcher = fetcher(dir.path().to_path_buf(), &url, 3);
+
+        let path = fetcher
+            .download_video_from_url(TIKTOK_URL.to_string(), "clip", "best", None, &mut ProgressBar::new_silent())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), VIDEO_BYTES);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert_eq!(fetcher.providers.health(TikwmProvider::ID).successes, 1);
+        assert_eq!(fetcher.providers.health("broken").failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dead_tikwm_is_skipped_after_repeated_failures() {
+        let (url, hits) = start_tikwm_stub(StatusCode::BAD_GATEWAY).await;
+        let dir = tempdir().unwrap();
+        let fetcher = fetcher(dir.path().to_path_buf(), &url, 2);
+
+        for _ in 0..3 {
+            let result = fetcher
+                .download_video_from_url(TIKTOK_URL.to_string(), "clip", "best", None, &mut ProgressBar::new_silent())
+                .await;
+            assert!(result.is_err());
+        }
+
+        // The third download no longer asks tikwm.
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(fetcher.providers.health(TikwmProvider::ID).is_open(std::time::Instant::now()));
+        let chain: Vec<&str> = fetcher.providers.chain_for(TIKTOK_URL).iter().map(|p| p.id()).collect();
+        assert_eq!(chain, vec!["broken"]);
+    }
+
+    #[tokio::test]
+    async fn test_posts_tikwm_cannot_serve_do_not_trip_the_breaker() {
+        let app = Router::new().route("/api/", get(|| async { Json(serde_json::json!({ "code": -1, "msg": "Url parsing is failed!" })) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempdir().unwrap();
+        let fetcher = fetcher(dir.path().to_path_buf(), &url, 1);
+        for _ in 0..2 {
+            let result = fetcher
+                .download_video_from_url(TIKTOK_URL.to_string(), "clip", "best", None, &mut ProgressBar::new_silent())
+                .await;
+            assert!(result.is_err());
+        }
+        assert!(!fetcher.providers.health(TikwmProvider::ID).is_open(std::time::Instant::now()));
+        assert_eq!(fetcher.providers.chain_for(TIKTOK_URL).len(), 2);
+    }
+}